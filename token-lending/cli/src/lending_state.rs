@@ -90,6 +90,7 @@ impl SolendState {
             self.lending_program_id,
             self.obligation_pubkey,
             reserve_pubkeys,
+            self.obligation.lending_market,
         ));
 
         instructions
@@ -34,9 +34,11 @@ use {
     },
     solend_program::{
         self,
-        instruction::{init_lending_market, init_reserve, update_reserve_config},
+        instruction::{
+            init_lending_market, init_reserve, queue_reserve_config_update, update_reserve_config,
+        },
         math::WAD,
-        state::{LendingMarket, Reserve, ReserveConfig, ReserveFees},
+        state::{LendingMarket, Reserve, ReserveConfig, ReserveFees, ReserveType},
     },
     spl_token::{
         amount_to_ui_amount,
@@ -87,6 +89,64 @@ struct PartialReserveConfig {
     pub protocol_liquidation_fee: Option<u8>,
     /// Protocol take rate is the amount borrowed interest protocol recieves, as a percentage  
     pub protocol_take_rate: Option<u8>,
+    /// Number of slots over which a decrease of the liquidation threshold is phased in,
+    /// 0 to apply it immediately
+    pub liquidation_threshold_ramp_slots: Option<u64>,
+    /// Maximum accumulated protocol fees as a percentage of the reserve liquidity, 0 for no cap
+    pub protocol_fees_cap: Option<u8>,
+    /// Only obligations whitelisted by the lending market owner can borrow from this reserve
+    pub permissioned: Option<bool>,
+    /// Accept a zero oracle price instead of rejecting the refresh
+    pub allow_zero_price: Option<bool>,
+    /// Refreshes fail once the oracle price has not changed for more than this many slots, 0 to
+    /// disable the check
+    pub max_unchanged_price_slots: Option<u64>,
+    /// Borrows supplied the reserve's oracle accounts refresh the reserve inline when it is stale
+    /// instead of failing
+    pub auto_refresh: Option<bool>,
+    /// Whether the reserve's collateral tokens can be deposited into obligations
+    pub usable_as_collateral: Option<bool>,
+    /// Maximum percent the collateral price used for new borrows may rise over the previous
+    /// refresh's price within a slot, 0 for no cap
+    pub max_borrow_price_increase_pct: Option<u8>,
+    /// Tranche the reserve belongs to
+    pub tranche: Option<u8>,
+    /// Utilization rate above which the borrow rate climbs to the extreme borrow rate
+    pub extreme_utilization_rate: Option<u8>,
+    /// Borrow APY at full utilization when the jump segment is enabled
+    pub extreme_borrow_rate: Option<u16>,
+    /// Whether the reserve is priced from Pyth's exponential moving average
+    pub use_ema_price: Option<bool>,
+    /// Percentage of an obligation's borrowed value repayable in a single liquidation
+    pub liquidation_close_factor: Option<u8>,
+    /// Whether suppliers are credited accrued interest rounded up to whole units
+    pub round_supplier_interest_up: Option<bool>,
+    /// Maximum switchboard v2 std deviation as basis points of the price, 0 to disable
+    pub max_price_confidence_bps: Option<u16>,
+    /// Whether obligations can't borrow from a reserve they have collateral deposited in
+    pub reject_self_borrow: Option<bool>,
+    /// Slots the reserve keeps its cached price through an oracle outage, 0 to disable
+    pub max_stale_price_slots: Option<u64>,
+    /// Valuation decay of a cached price in basis points per slot
+    pub stale_price_decay_bps: Option<u16>,
+    /// Price the liquidity is valued at in place of its oracles, as a wad, 0 to use the oracles
+    pub fixed_price: Option<u64>,
+    /// Liquidation bonus once an obligation is insolvent, 0 to keep the bonus flat
+    pub max_liquidation_bonus: Option<u8>,
+    /// Hold interest accrual while the oracles are unavailable
+    pub pause_interest_on_oracle_error: Option<bool>,
+    /// Whether the liquidity can be borrowed alongside other assets
+    pub reserve_type: Option<ReserveType>,
+    /// Token account of the strategy idle liquidity can be deployed to
+    pub strategy_vault: Option<Pubkey>,
+    /// Max percentage of the liquidity that isn't lent out which can be deployed
+    pub max_deployed_pct: Option<u8>,
+    /// Slots after which a Pyth price is too old to use
+    pub max_price_age_slots: Option<u64>,
+    /// Slots after the oracles were last read that the reserve can't be liquidated against
+    pub max_liquidation_price_age_slots: Option<u64>,
+    /// Deposit and borrow limits are basis points of the reserve's total supply
+    pub fractional_limits: Option<bool>,
 }
 
 /// Reserve Fees with optional fields
@@ -521,6 +581,275 @@ fn main() {
                         .default_value("18446744073709551615")
                         .help("Borrow limit"),
                 )
+                .arg(
+                    Arg::with_name("liquidation_threshold_ramp_slots")
+                        .long("liquidation-threshold-ramp-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Slots over which a liquidation threshold decrease is phased in"),
+                )
+                .arg(
+                    Arg::with_name("protocol_fees_cap")
+                        .long("protocol-fees-cap")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Cap on accumulated protocol fees as a share of reserve liquidity, 0 for none: [0, 100]"),
+                )
+                .arg(
+                    Arg::with_name("permissioned")
+                        .long("permissioned")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("false")
+                        .help("Restrict borrowing to obligations whitelisted by the market owner"),
+                )
+                .arg(
+                    Arg::with_name("allow_zero_price")
+                        .long("allow-zero-price")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("false")
+                        .help("Accept a zero oracle price instead of rejecting the refresh"),
+                )
+                .arg(
+                    Arg::with_name("max_unchanged_price_slots")
+                        .long("max-unchanged-price-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("SLOTS")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Slots the oracle price may stay unchanged before refreshes are rejected, 0 to disable."),
+                )
+                .arg(
+                    Arg::with_name("auto_refresh")
+                        .long("auto-refresh")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("false")
+                        .help("Let borrows supplied the oracle accounts refresh a stale reserve inline"),
+                )
+                .arg(
+                    Arg::with_name("usable_as_collateral")
+                        .long("usable-as-collateral")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("true")
+                        .help("Allow the reserve's collateral tokens to be deposited into obligations"),
+                )
+                .arg(
+                    Arg::with_name("max_borrow_price_increase_pct")
+                        .long("max-borrow-price-increase-pct")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Cap on how far the collateral price backing new borrows may rise over the previous refresh within a slot, 0 for none"),
+                )
+                .arg(
+                    Arg::with_name("tranche")
+                        .long("tranche")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Tranche of the reserve, only obligations of the same tranche can use it"),
+                )
+                .arg(
+                    Arg::with_name("extreme_utilization_rate")
+                        .long("extreme-utilization-rate")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("100")
+                        .help("Utilization rate above which the borrow rate jumps towards the extreme borrow rate, 100 to disable"),
+                )
+                .arg(
+                    Arg::with_name("extreme_borrow_rate")
+                        .long("extreme-borrow-rate")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Borrow rate at full utilization when the extreme utilization rate is below 100: [max borrow rate, 65535]"),
+                )
+                .arg(
+                    Arg::with_name("use_ema_price")
+                        .long("use-ema-price")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("false")
+                        .help("Price the reserve from the Pyth EMA price instead of the aggregate price"),
+                )
+                .arg(
+                    Arg::with_name("liquidation_close_factor")
+                        .long("liquidation-close-factor")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("20")
+                        .help("Percentage of an obligation's borrowed value that can be repaid in a single liquidation: (0, 100]"),
+                )
+                .arg(
+                    Arg::with_name("round_supplier_interest_up")
+                        .long("round-supplier-interest-up")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("false")
+                        .help("Round the suppliers' share of accrued interest up to whole units, paid for out of protocol fees"),
+                )
+                .arg(
+                    Arg::with_name("max_price_confidence_bps")
+                        .long("max-price-confidence-bps")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER_BPS")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Maximum switchboard v2 std deviation as basis points of the price, 0 to disable: [0, 10000]"),
+                )
+                .arg(
+                    Arg::with_name("reject_self_borrow")
+                        .long("reject-self-borrow")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("false")
+                        .help("Reject borrows by obligations that have collateral deposited in the same reserve"),
+                )
+                .arg(
+                    Arg::with_name("max_stale_price_slots")
+                        .long("max-stale-price-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER_SLOTS")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Slots the reserve keeps refreshing on its cached price while its oracles are unavailable, 0 to disable"),
+                )
+                .arg(
+                    Arg::with_name("stale_price_decay_bps")
+                        .long("stale-price-decay-bps")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER_BPS")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Discount on collateral and markup on debt valued at a cached price, in basis points per slot since the last oracle price: [0, 10000]"),
+                )
+                .arg(
+                    Arg::with_name("fixed_price")
+                        .long("fixed-price")
+                        .validator(is_parsable::<f64>)
+                        .value_name("DECIMAL_PRICE")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Price the liquidity is valued at in place of its oracles, exposing the reserve to depegs, 0 to use the oracles"),
+                )
+                .arg(
+                    Arg::with_name("max_liquidation_bonus")
+                        .long("max-liquidation-bonus")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Bonus a liquidator gets once an obligation's borrows are worth as much as its deposits, ramping up from the liquidation bonus at the liquidation threshold, 0 to keep the bonus flat: [liquidation bonus, 100]"),
+                )
+                .arg(
+                    Arg::with_name("pause_interest_on_oracle_error")
+                        .long("pause-interest-on-oracle-error")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("false")
+                        .help("Hold interest accrual while the oracles are unavailable, resuming when they recover"),
+                )
+                .arg(
+                    Arg::with_name("reserve_type")
+                        .long("reserve-type")
+                        .possible_values(&["regular", "isolated"])
+                        .value_name("TYPE")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("regular")
+                        .help("Isolated assets can only be borrowed by obligations that borrow nothing else"),
+                )
+                .arg(
+                    Arg::with_name("strategy_vault")
+                        .long("strategy-vault")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Token account of the strategy the market owner can deploy idle liquidity to"),
+                )
+                .arg(
+                    Arg::with_name("max_deployed_pct")
+                        .long("max-deployed-pct")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Max percentage of the liquidity that isn't lent out which can be deployed to the strategy vault: [0, 100]"),
+                )
+                .arg(
+                    Arg::with_name("max_price_age_slots")
+                        .long("max-price-age-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER_SLOTS")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Slots after which a Pyth price is too old to use, 0 for the default of 240"),
+                )
+                .arg(
+                    Arg::with_name("max_liquidation_price_age_slots")
+                        .long("max-liquidation-price-age-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER_SLOTS")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("0")
+                        .help("Slots after the oracles were last read that the reserve can't be liquidated against, 0 for no bound beyond refreshing"),
+                )
+                .arg(
+                    Arg::with_name("fractional_limits")
+                        .long("fractional-limits")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("false")
+                        .help("Deposit and borrow limits are basis points of the reserve's total supply instead of token amounts"),
+                )
         )
         .subcommand(
             SubCommand::with_name("update-reserve")
@@ -688,6 +1017,249 @@ fn main() {
                         .required(false)
                         .help("Fee receiver address"),
                 )
+                .arg(
+                    Arg::with_name("liquidation_threshold_ramp_slots")
+                        .long("liquidation-threshold-ramp-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slots over which a liquidation threshold decrease is phased in"),
+                )
+                .arg(
+                    Arg::with_name("protocol_fees_cap")
+                        .long("protocol-fees-cap")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Cap on accumulated protocol fees as a share of reserve liquidity, 0 for none: [0, 100]"),
+                )
+                .arg(
+                    Arg::with_name("permissioned")
+                        .long("permissioned")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Restrict borrowing to obligations whitelisted by the market owner"),
+                )
+                .arg(
+                    Arg::with_name("allow_zero_price")
+                        .long("allow-zero-price")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Accept a zero oracle price instead of rejecting the refresh"),
+                )
+                .arg(
+                    Arg::with_name("max_unchanged_price_slots")
+                        .long("max-unchanged-price-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("SLOTS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slots the oracle price may stay unchanged before refreshes are rejected, 0 to disable."),
+                )
+                .arg(
+                    Arg::with_name("auto_refresh")
+                        .long("auto-refresh")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Let borrows supplied the oracle accounts refresh a stale reserve inline"),
+                )
+                .arg(
+                    Arg::with_name("usable_as_collateral")
+                        .long("usable-as-collateral")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Allow the reserve's collateral tokens to be deposited into obligations"),
+                )
+                .arg(
+                    Arg::with_name("max_borrow_price_increase_pct")
+                        .long("max-borrow-price-increase-pct")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Cap on how far the collateral price backing new borrows may rise over the previous refresh within a slot, 0 for none"),
+                )
+                .arg(
+                    Arg::with_name("tranche")
+                        .long("tranche")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Tranche of the reserve, only obligations of the same tranche can use it"),
+                )
+                .arg(
+                    Arg::with_name("extreme_utilization_rate")
+                        .long("extreme-utilization-rate")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Utilization rate above which the borrow rate jumps towards the extreme borrow rate, 100 to disable"),
+                )
+                .arg(
+                    Arg::with_name("extreme_borrow_rate")
+                        .long("extreme-borrow-rate")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Borrow rate at full utilization when the extreme utilization rate is below 100: [max borrow rate, 65535]"),
+                )
+                .arg(
+                    Arg::with_name("use_ema_price")
+                        .long("use-ema-price")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Price the reserve from the Pyth EMA price instead of the aggregate price"),
+                )
+                .arg(
+                    Arg::with_name("liquidation_close_factor")
+                        .long("liquidation-close-factor")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Percentage of an obligation's borrowed value that can be repaid in a single liquidation: (0, 100]"),
+                )
+                .arg(
+                    Arg::with_name("round_supplier_interest_up")
+                        .long("round-supplier-interest-up")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Round the suppliers' share of accrued interest up to whole units, paid for out of protocol fees"),
+                )
+                .arg(
+                    Arg::with_name("max_price_confidence_bps")
+                        .long("max-price-confidence-bps")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER_BPS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Maximum switchboard v2 std deviation as basis points of the price, 0 to disable: [0, 10000]"),
+                )
+                .arg(
+                    Arg::with_name("reject_self_borrow")
+                        .long("reject-self-borrow")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Reject borrows by obligations that have collateral deposited in the same reserve"),
+                )
+                .arg(
+                    Arg::with_name("max_stale_price_slots")
+                        .long("max-stale-price-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER_SLOTS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slots the reserve keeps refreshing on its cached price while its oracles are unavailable, 0 to disable"),
+                )
+                .arg(
+                    Arg::with_name("stale_price_decay_bps")
+                        .long("stale-price-decay-bps")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER_BPS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Discount on collateral and markup on debt valued at a cached price, in basis points per slot since the last oracle price: [0, 10000]"),
+                )
+                .arg(
+                    Arg::with_name("fixed_price")
+                        .long("fixed-price")
+                        .validator(is_parsable::<f64>)
+                        .value_name("DECIMAL_PRICE")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Price the liquidity is valued at in place of its oracles, exposing the reserve to depegs, 0 to use the oracles"),
+                )
+                .arg(
+                    Arg::with_name("max_liquidation_bonus")
+                        .long("max-liquidation-bonus")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Bonus a liquidator gets once an obligation's borrows are worth as much as its deposits, ramping up from the liquidation bonus at the liquidation threshold, 0 to keep the bonus flat: [liquidation bonus, 100]"),
+                )
+                .arg(
+                    Arg::with_name("pause_interest_on_oracle_error")
+                        .long("pause-interest-on-oracle-error")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Hold interest accrual while the oracles are unavailable, resuming when they recover"),
+                )
+                .arg(
+                    Arg::with_name("reserve_type")
+                        .long("reserve-type")
+                        .possible_values(&["regular", "isolated"])
+                        .value_name("TYPE")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Isolated assets can only be borrowed by obligations that borrow nothing else"),
+                )
+                .arg(
+                    Arg::with_name("strategy_vault")
+                        .long("strategy-vault")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Token account of the strategy the market owner can deploy idle liquidity to"),
+                )
+                .arg(
+                    Arg::with_name("max_deployed_pct")
+                        .long("max-deployed-pct")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Max percentage of the liquidity that isn't lent out which can be deployed to the strategy vault: [0, 100]"),
+                )
+                .arg(
+                    Arg::with_name("max_price_age_slots")
+                        .long("max-price-age-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER_SLOTS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slots after which a Pyth price is too old to use, 0 for the default of 240"),
+                )
+                .arg(
+                    Arg::with_name("max_liquidation_price_age_slots")
+                        .long("max-liquidation-price-age-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER_SLOTS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slots after the oracles were last read that the reserve can't be liquidated against, 0 for no bound beyond refreshing"),
+                )
+                .arg(
+                    Arg::with_name("fractional_limits")
+                        .long("fractional-limits")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Deposit and borrow limits are basis points of the reserve's total supply instead of token amounts"),
+                )
                 .arg(
                     Arg::with_name("pyth_product")
                         .long("pyth-product")
@@ -821,8 +1393,8 @@ fn main() {
             let borrow_fee = value_of::<f64>(arg_matches, "borrow_fee").unwrap();
             let flash_loan_fee = value_of::<f64>(arg_matches, "flash_loan_fee").unwrap();
             let host_fee_percentage = value_of(arg_matches, "host_fee_percentage").unwrap();
-            let deposit_limit = value_of(arg_matches, "deposit_limit").unwrap();
-            let borrow_limit = value_of(arg_matches, "borrow_limit").unwrap();
+            let deposit_limit: f64 = value_of(arg_matches, "deposit_limit").unwrap();
+            let borrow_limit: f64 = value_of(arg_matches, "borrow_limit").unwrap();
 
             let borrow_fee_wad = (borrow_fee * WAD as f64) as u64;
             let flash_loan_fee_wad = (flash_loan_fee * WAD as f64) as u64;
@@ -831,6 +1403,43 @@ fn main() {
             let protocol_liquidation_fee =
                 value_of(arg_matches, "protocol_liquidation_fee").unwrap();
             let protocol_take_rate = value_of(arg_matches, "protocol_take_rate").unwrap();
+            let liquidation_threshold_ramp_slots =
+                value_of(arg_matches, "liquidation_threshold_ramp_slots").unwrap();
+            let protocol_fees_cap = value_of(arg_matches, "protocol_fees_cap").unwrap();
+            let permissioned = value_of(arg_matches, "permissioned").unwrap();
+            let allow_zero_price = value_of(arg_matches, "allow_zero_price").unwrap();
+            let max_unchanged_price_slots =
+                value_of(arg_matches, "max_unchanged_price_slots").unwrap();
+            let auto_refresh = value_of(arg_matches, "auto_refresh").unwrap();
+            let usable_as_collateral = value_of(arg_matches, "usable_as_collateral").unwrap();
+            let max_borrow_price_increase_pct =
+                value_of(arg_matches, "max_borrow_price_increase_pct").unwrap();
+            let tranche = value_of(arg_matches, "tranche").unwrap();
+            let extreme_utilization_rate =
+                value_of(arg_matches, "extreme_utilization_rate").unwrap();
+            let extreme_borrow_rate = value_of(arg_matches, "extreme_borrow_rate").unwrap();
+            let use_ema_price = value_of(arg_matches, "use_ema_price").unwrap();
+            let liquidation_close_factor =
+                value_of(arg_matches, "liquidation_close_factor").unwrap();
+            let round_supplier_interest_up =
+                value_of(arg_matches, "round_supplier_interest_up").unwrap();
+            let max_price_confidence_bps =
+                value_of(arg_matches, "max_price_confidence_bps").unwrap();
+            let reject_self_borrow = value_of(arg_matches, "reject_self_borrow").unwrap();
+            let max_stale_price_slots = value_of(arg_matches, "max_stale_price_slots").unwrap();
+            let stale_price_decay_bps = value_of(arg_matches, "stale_price_decay_bps").unwrap();
+            let fixed_price = value_of::<f64>(arg_matches, "fixed_price").unwrap();
+            let fixed_price = (fixed_price * WAD as f64) as u64;
+            let max_liquidation_bonus = value_of(arg_matches, "max_liquidation_bonus").unwrap();
+            let pause_interest_on_oracle_error =
+                value_of(arg_matches, "pause_interest_on_oracle_error").unwrap();
+            let reserve_type = reserve_type_of(arg_matches, "reserve_type").unwrap();
+            let strategy_vault = pubkey_of(arg_matches, "strategy_vault").unwrap_or_default();
+            let max_deployed_pct = value_of(arg_matches, "max_deployed_pct").unwrap();
+            let max_price_age_slots = value_of(arg_matches, "max_price_age_slots").unwrap();
+            let max_liquidation_price_age_slots =
+                value_of(arg_matches, "max_liquidation_price_age_slots").unwrap();
+            let fractional_limits = value_of(arg_matches, "fractional_limits").unwrap();
 
             let source_liquidity_account = config
                 .rpc_client
@@ -846,8 +1455,14 @@ fn main() {
                 Mint::unpack_from_slice(source_liquidity_mint_account.data.borrow()).unwrap();
 
             let liquidity_amount = ui_amount_to_amount(ui_amount, source_liquidity_mint.decimals);
-            let deposit_limit = ui_amount_to_amount(deposit_limit, source_liquidity_mint.decimals);
-            let borrow_limit = ui_amount_to_amount(borrow_limit, source_liquidity_mint.decimals);
+            let (deposit_limit, borrow_limit) = if fractional_limits {
+                (deposit_limit as u64, borrow_limit as u64)
+            } else {
+                (
+                    ui_amount_to_amount(deposit_limit, source_liquidity_mint.decimals),
+                    ui_amount_to_amount(borrow_limit, source_liquidity_mint.decimals),
+                )
+            };
 
             command_add_reserve(
                 &mut config,
@@ -870,6 +1485,33 @@ fn main() {
                     fee_receiver: liquidity_fee_receiver_keypair.pubkey(),
                     protocol_liquidation_fee,
                     protocol_take_rate,
+                    liquidation_threshold_ramp_slots,
+                    protocol_fees_cap,
+                    permissioned,
+                    allow_zero_price,
+                    max_unchanged_price_slots,
+                    auto_refresh,
+                    usable_as_collateral,
+                    max_borrow_price_increase_pct,
+                    tranche,
+                    extreme_utilization_rate,
+                    extreme_borrow_rate,
+                    use_ema_price,
+                    liquidation_close_factor,
+                    round_supplier_interest_up,
+                    max_price_confidence_bps,
+                    reject_self_borrow,
+                    max_stale_price_slots,
+                    stale_price_decay_bps,
+                    fixed_price,
+                    max_liquidation_bonus,
+                    pause_interest_on_oracle_error,
+                    reserve_type,
+                    strategy_vault,
+                    max_deployed_pct,
+                    max_price_age_slots,
+                    max_liquidation_price_age_slots,
+                    fractional_limits,
                 },
                 source_liquidity_pubkey,
                 source_liquidity_owner_keypair,
@@ -902,6 +1544,38 @@ fn main() {
             let fee_receiver = pubkey_of(arg_matches, "fee_receiver");
             let protocol_liquidation_fee = value_of(arg_matches, "protocol_liquidation_fee");
             let protocol_take_rate = value_of(arg_matches, "protocol_take_rate");
+            let liquidation_threshold_ramp_slots =
+                value_of(arg_matches, "liquidation_threshold_ramp_slots");
+            let protocol_fees_cap = value_of(arg_matches, "protocol_fees_cap");
+            let permissioned = value_of(arg_matches, "permissioned");
+            let allow_zero_price = value_of(arg_matches, "allow_zero_price");
+            let max_unchanged_price_slots = value_of(arg_matches, "max_unchanged_price_slots");
+            let auto_refresh = value_of(arg_matches, "auto_refresh");
+            let usable_as_collateral = value_of(arg_matches, "usable_as_collateral");
+            let max_borrow_price_increase_pct =
+                value_of(arg_matches, "max_borrow_price_increase_pct");
+            let tranche = value_of(arg_matches, "tranche");
+            let extreme_utilization_rate = value_of(arg_matches, "extreme_utilization_rate");
+            let extreme_borrow_rate = value_of(arg_matches, "extreme_borrow_rate");
+            let use_ema_price = value_of(arg_matches, "use_ema_price");
+            let liquidation_close_factor = value_of(arg_matches, "liquidation_close_factor");
+            let round_supplier_interest_up = value_of(arg_matches, "round_supplier_interest_up");
+            let max_price_confidence_bps = value_of(arg_matches, "max_price_confidence_bps");
+            let reject_self_borrow = value_of(arg_matches, "reject_self_borrow");
+            let max_stale_price_slots = value_of(arg_matches, "max_stale_price_slots");
+            let stale_price_decay_bps = value_of(arg_matches, "stale_price_decay_bps");
+            let fixed_price = value_of::<f64>(arg_matches, "fixed_price")
+                .map(|price| (price * WAD as f64) as u64);
+            let max_liquidation_bonus = value_of(arg_matches, "max_liquidation_bonus");
+            let pause_interest_on_oracle_error =
+                value_of(arg_matches, "pause_interest_on_oracle_error");
+            let reserve_type = reserve_type_of(arg_matches, "reserve_type");
+            let strategy_vault = pubkey_of(arg_matches, "strategy_vault");
+            let max_deployed_pct = value_of(arg_matches, "max_deployed_pct");
+            let max_price_age_slots = value_of(arg_matches, "max_price_age_slots");
+            let max_liquidation_price_age_slots =
+                value_of(arg_matches, "max_liquidation_price_age_slots");
+            let fractional_limits = value_of(arg_matches, "fractional_limits");
             let pyth_product_pubkey = pubkey_of(arg_matches, "pyth_product");
             let pyth_price_pubkey = pubkey_of(arg_matches, "pyth_price");
             let switchboard_feed_pubkey = pubkey_of(arg_matches, "switchboard_feed");
@@ -929,6 +1603,33 @@ fn main() {
                     fee_receiver,
                     protocol_liquidation_fee,
                     protocol_take_rate,
+                    liquidation_threshold_ramp_slots,
+                    protocol_fees_cap,
+                    permissioned,
+                    allow_zero_price,
+                    max_unchanged_price_slots,
+                    auto_refresh,
+                    usable_as_collateral,
+                    max_borrow_price_increase_pct,
+                    tranche,
+                    extreme_utilization_rate,
+                    extreme_borrow_rate,
+                    use_ema_price,
+                    liquidation_close_factor,
+                    round_supplier_interest_up,
+                    max_price_confidence_bps,
+                    reject_self_borrow,
+                    max_stale_price_slots,
+                    stale_price_decay_bps,
+                    fixed_price,
+                    max_liquidation_bonus,
+                    pause_interest_on_oracle_error,
+                    reserve_type,
+                    strategy_vault,
+                    max_deployed_pct,
+                    max_price_age_slots,
+                    max_liquidation_price_age_slots,
+                    fractional_limits,
                 },
                 pyth_product_pubkey,
                 pyth_price_pubkey,
@@ -1187,6 +1888,7 @@ fn command_liquidate_obligation(
         config.lending_program_id,
         obligation_pubkey,
         reserve_pubkeys,
+        obligation_state.lending_market,
     ));
 
     instructions.push(liquidate_obligation_and_redeem_reserve_collateral(
@@ -1366,6 +2068,7 @@ fn command_add_reserve(
                 config.lending_program_id,
                 liquidity_amount,
                 reserve_config,
+                0,
                 source_liquidity_pubkey,
                 user_collateral_keypair.pubkey(),
                 reserve_keypair.pubkey(),
@@ -1448,7 +2151,8 @@ fn command_update_reserve(
     lending_market_owner_keypair: Keypair,
 ) -> CommandResult {
     let reserve_info = config.rpc_client.get_account(&reserve_pubkey)?;
-    let mut reserve = Reserve::unpack_from_slice(reserve_info.data.borrow())?;
+    let mut reserve = Reserve::unpack(reserve_info.data.borrow())?;
+    let current_config = reserve.config;
     let mut no_change = true;
     if reserve_config.optimal_utilization_rate.is_some()
         && reserve.config.optimal_utilization_rate
@@ -1468,7 +2172,7 @@ fn command_update_reserve(
     {
         no_change = false;
         println!(
-            "Updating loan_to_value_ratio from {} to {}",
+            "Queueing update of loan_to_value_ratio from {} to {}",
             reserve.config.loan_to_value_ratio,
             reserve_config.loan_to_value_ratio.unwrap(),
         );
@@ -1492,7 +2196,7 @@ fn command_update_reserve(
     {
         no_change = false;
         println!(
-            "Updating liquidation_threshold from {} to {}",
+            "Queueing update of liquidation_threshold from {} to {}",
             reserve.config.liquidation_threshold,
             reserve_config.liquidation_threshold.unwrap(),
         );
@@ -1572,6 +2276,11 @@ fn command_update_reserve(
         reserve.config.fees.host_fee_percentage = reserve_config.fees.host_fee_percentage.unwrap();
     }
 
+    // fractional limits are basis points of the total supply rather than token amounts
+    let fractional_limits = reserve_config
+        .fractional_limits
+        .unwrap_or(reserve.config.fractional_limits);
+
     if reserve_config.deposit_limit.is_some()
         && reserve.config.deposit_limit != reserve_config.deposit_limit.unwrap()
     {
@@ -1584,10 +2293,14 @@ fn command_update_reserve(
             ),
             reserve_config.deposit_limit.unwrap(),
         );
-        reserve.config.deposit_limit = ui_amount_to_amount(
-            reserve_config.deposit_limit.unwrap() as f64,
-            reserve.liquidity.mint_decimals,
-        )
+        reserve.config.deposit_limit = if fractional_limits {
+            reserve_config.deposit_limit.unwrap()
+        } else {
+            ui_amount_to_amount(
+                reserve_config.deposit_limit.unwrap() as f64,
+                reserve.liquidity.mint_decimals,
+            )
+        };
     }
 
     if reserve_config.borrow_limit.is_some()
@@ -1599,10 +2312,14 @@ fn command_update_reserve(
             amount_to_ui_amount(reserve.config.borrow_limit, reserve.liquidity.mint_decimals),
             reserve_config.borrow_limit.unwrap(),
         );
-        reserve.config.borrow_limit = ui_amount_to_amount(
-            reserve_config.borrow_limit.unwrap() as f64,
-            reserve.liquidity.mint_decimals,
-        )
+        reserve.config.borrow_limit = if fractional_limits {
+            reserve_config.borrow_limit.unwrap()
+        } else {
+            ui_amount_to_amount(
+                reserve_config.borrow_limit.unwrap() as f64,
+                reserve.liquidity.mint_decimals,
+            )
+        };
     }
 
     if reserve_config.fee_receiver.is_some()
@@ -1635,13 +2352,351 @@ fn command_update_reserve(
     {
         no_change = false;
         println!(
-            "Updating protocol_take_rate from {} to {}",
+            "Queueing update of protocol_take_rate from {} to {}",
             reserve.config.protocol_take_rate,
             reserve_config.protocol_take_rate.unwrap(),
         );
         reserve.config.protocol_take_rate = reserve_config.protocol_take_rate.unwrap();
     }
 
+    if reserve_config.liquidation_threshold_ramp_slots.is_some()
+        && reserve.config.liquidation_threshold_ramp_slots
+            != reserve_config.liquidation_threshold_ramp_slots.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating liquidation_threshold_ramp_slots from {} to {}",
+            reserve.config.liquidation_threshold_ramp_slots,
+            reserve_config.liquidation_threshold_ramp_slots.unwrap(),
+        );
+        reserve.config.liquidation_threshold_ramp_slots =
+            reserve_config.liquidation_threshold_ramp_slots.unwrap();
+    }
+
+    if reserve_config.protocol_fees_cap.is_some()
+        && reserve.config.protocol_fees_cap != reserve_config.protocol_fees_cap.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating protocol_fees_cap from {} to {}",
+            reserve.config.protocol_fees_cap,
+            reserve_config.protocol_fees_cap.unwrap(),
+        );
+        reserve.config.protocol_fees_cap = reserve_config.protocol_fees_cap.unwrap();
+    }
+
+    if reserve_config.permissioned.is_some()
+        && reserve.config.permissioned != reserve_config.permissioned.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating permissioned from {} to {}",
+            reserve.config.permissioned,
+            reserve_config.permissioned.unwrap(),
+        );
+        reserve.config.permissioned = reserve_config.permissioned.unwrap();
+    }
+
+    if reserve_config.allow_zero_price.is_some()
+        && reserve.config.allow_zero_price != reserve_config.allow_zero_price.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating allow_zero_price from {} to {}",
+            reserve.config.allow_zero_price,
+            reserve_config.allow_zero_price.unwrap(),
+        );
+        reserve.config.allow_zero_price = reserve_config.allow_zero_price.unwrap();
+    }
+
+    if reserve_config.max_unchanged_price_slots.is_some()
+        && reserve.config.max_unchanged_price_slots
+            != reserve_config.max_unchanged_price_slots.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_unchanged_price_slots from {} to {}",
+            reserve.config.max_unchanged_price_slots,
+            reserve_config.max_unchanged_price_slots.unwrap(),
+        );
+        reserve.config.max_unchanged_price_slots =
+            reserve_config.max_unchanged_price_slots.unwrap();
+    }
+
+    if reserve_config.auto_refresh.is_some()
+        && reserve.config.auto_refresh != reserve_config.auto_refresh.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating auto_refresh from {} to {}",
+            reserve.config.auto_refresh,
+            reserve_config.auto_refresh.unwrap(),
+        );
+        reserve.config.auto_refresh = reserve_config.auto_refresh.unwrap();
+    }
+
+    if reserve_config.usable_as_collateral.is_some()
+        && reserve.config.usable_as_collateral != reserve_config.usable_as_collateral.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating usable_as_collateral from {} to {}",
+            reserve.config.usable_as_collateral,
+            reserve_config.usable_as_collateral.unwrap(),
+        );
+        reserve.config.usable_as_collateral = reserve_config.usable_as_collateral.unwrap();
+    }
+
+    if reserve_config.max_borrow_price_increase_pct.is_some()
+        && reserve.config.max_borrow_price_increase_pct
+            != reserve_config.max_borrow_price_increase_pct.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_borrow_price_increase_pct from {} to {}",
+            reserve.config.max_borrow_price_increase_pct,
+            reserve_config.max_borrow_price_increase_pct.unwrap(),
+        );
+        reserve.config.max_borrow_price_increase_pct =
+            reserve_config.max_borrow_price_increase_pct.unwrap();
+    }
+
+    if reserve_config.tranche.is_some() && reserve.config.tranche != reserve_config.tranche.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating tranche from {} to {}",
+            reserve.config.tranche,
+            reserve_config.tranche.unwrap(),
+        );
+        reserve.config.tranche = reserve_config.tranche.unwrap();
+    }
+
+    if reserve_config.extreme_utilization_rate.is_some()
+        && reserve.config.extreme_utilization_rate
+            != reserve_config.extreme_utilization_rate.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating extreme_utilization_rate from {} to {}",
+            reserve.config.extreme_utilization_rate,
+            reserve_config.extreme_utilization_rate.unwrap(),
+        );
+        reserve.config.extreme_utilization_rate = reserve_config.extreme_utilization_rate.unwrap();
+    }
+
+    if reserve_config.extreme_borrow_rate.is_some()
+        && reserve.config.extreme_borrow_rate != reserve_config.extreme_borrow_rate.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating extreme_borrow_rate from {} to {}",
+            reserve.config.extreme_borrow_rate,
+            reserve_config.extreme_borrow_rate.unwrap(),
+        );
+        reserve.config.extreme_borrow_rate = reserve_config.extreme_borrow_rate.unwrap();
+    }
+
+    if reserve_config.use_ema_price.is_some()
+        && reserve.config.use_ema_price != reserve_config.use_ema_price.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating use_ema_price from {} to {}",
+            reserve.config.use_ema_price,
+            reserve_config.use_ema_price.unwrap(),
+        );
+        reserve.config.use_ema_price = reserve_config.use_ema_price.unwrap();
+    }
+
+    if reserve_config.liquidation_close_factor.is_some()
+        && reserve.config.liquidation_close_factor
+            != reserve_config.liquidation_close_factor.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating liquidation_close_factor from {} to {}",
+            reserve.config.liquidation_close_factor,
+            reserve_config.liquidation_close_factor.unwrap(),
+        );
+        reserve.config.liquidation_close_factor = reserve_config.liquidation_close_factor.unwrap();
+    }
+
+    if reserve_config.round_supplier_interest_up.is_some()
+        && reserve.config.round_supplier_interest_up
+            != reserve_config.round_supplier_interest_up.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating round_supplier_interest_up from {} to {}",
+            reserve.config.round_supplier_interest_up,
+            reserve_config.round_supplier_interest_up.unwrap(),
+        );
+        reserve.config.round_supplier_interest_up =
+            reserve_config.round_supplier_interest_up.unwrap();
+    }
+
+    if reserve_config.max_price_confidence_bps.is_some()
+        && reserve.config.max_price_confidence_bps
+            != reserve_config.max_price_confidence_bps.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_price_confidence_bps from {} to {}",
+            reserve.config.max_price_confidence_bps,
+            reserve_config.max_price_confidence_bps.unwrap(),
+        );
+        reserve.config.max_price_confidence_bps = reserve_config.max_price_confidence_bps.unwrap();
+    }
+
+    if reserve_config.reject_self_borrow.is_some()
+        && reserve.config.reject_self_borrow != reserve_config.reject_self_borrow.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating reject_self_borrow from {} to {}",
+            reserve.config.reject_self_borrow,
+            reserve_config.reject_self_borrow.unwrap(),
+        );
+        reserve.config.reject_self_borrow = reserve_config.reject_self_borrow.unwrap();
+    }
+
+    if reserve_config.max_stale_price_slots.is_some()
+        && reserve.config.max_stale_price_slots != reserve_config.max_stale_price_slots.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_stale_price_slots from {} to {}",
+            reserve.config.max_stale_price_slots,
+            reserve_config.max_stale_price_slots.unwrap(),
+        );
+        reserve.config.max_stale_price_slots = reserve_config.max_stale_price_slots.unwrap();
+    }
+
+    if reserve_config.stale_price_decay_bps.is_some()
+        && reserve.config.stale_price_decay_bps != reserve_config.stale_price_decay_bps.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating stale_price_decay_bps from {} to {}",
+            reserve.config.stale_price_decay_bps,
+            reserve_config.stale_price_decay_bps.unwrap(),
+        );
+        reserve.config.stale_price_decay_bps = reserve_config.stale_price_decay_bps.unwrap();
+    }
+
+    if reserve_config.fixed_price.is_some()
+        && reserve.config.fixed_price != reserve_config.fixed_price.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating fixed_price from {} to {}",
+            reserve.config.fixed_price,
+            reserve_config.fixed_price.unwrap(),
+        );
+        reserve.config.fixed_price = reserve_config.fixed_price.unwrap();
+    }
+
+    if reserve_config.max_liquidation_bonus.is_some()
+        && reserve.config.max_liquidation_bonus != reserve_config.max_liquidation_bonus.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_liquidation_bonus from {} to {}",
+            reserve.config.max_liquidation_bonus,
+            reserve_config.max_liquidation_bonus.unwrap(),
+        );
+        reserve.config.max_liquidation_bonus = reserve_config.max_liquidation_bonus.unwrap();
+    }
+
+    if reserve_config.pause_interest_on_oracle_error.is_some()
+        && reserve.config.pause_interest_on_oracle_error
+            != reserve_config.pause_interest_on_oracle_error.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating pause_interest_on_oracle_error from {} to {}",
+            reserve.config.pause_interest_on_oracle_error,
+            reserve_config.pause_interest_on_oracle_error.unwrap(),
+        );
+        reserve.config.pause_interest_on_oracle_error =
+            reserve_config.pause_interest_on_oracle_error.unwrap();
+    }
+
+    if reserve_config.reserve_type.is_some()
+        && reserve.config.reserve_type != reserve_config.reserve_type.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating reserve_type from {:?} to {:?}",
+            reserve.config.reserve_type,
+            reserve_config.reserve_type.unwrap(),
+        );
+        reserve.config.reserve_type = reserve_config.reserve_type.unwrap();
+    }
+
+    if reserve_config.strategy_vault.is_some()
+        && reserve.config.strategy_vault != reserve_config.strategy_vault.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating strategy_vault from {} to {}",
+            reserve.config.strategy_vault,
+            reserve_config.strategy_vault.unwrap(),
+        );
+        reserve.config.strategy_vault = reserve_config.strategy_vault.unwrap();
+    }
+
+    if reserve_config.max_deployed_pct.is_some()
+        && reserve.config.max_deployed_pct != reserve_config.max_deployed_pct.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_deployed_pct from {} to {}",
+            reserve.config.max_deployed_pct,
+            reserve_config.max_deployed_pct.unwrap(),
+        );
+        reserve.config.max_deployed_pct = reserve_config.max_deployed_pct.unwrap();
+    }
+
+    if reserve_config.max_price_age_slots.is_some()
+        && reserve.config.max_price_age_slots != reserve_config.max_price_age_slots.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_price_age_slots from {} to {}",
+            reserve.config.max_price_age_slots,
+            reserve_config.max_price_age_slots.unwrap(),
+        );
+        reserve.config.max_price_age_slots = reserve_config.max_price_age_slots.unwrap();
+    }
+
+    if reserve_config.max_liquidation_price_age_slots.is_some()
+        && reserve.config.max_liquidation_price_age_slots
+            != reserve_config.max_liquidation_price_age_slots.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_liquidation_price_age_slots from {} to {}",
+            reserve.config.max_liquidation_price_age_slots,
+            reserve_config.max_liquidation_price_age_slots.unwrap(),
+        );
+        reserve.config.max_liquidation_price_age_slots =
+            reserve_config.max_liquidation_price_age_slots.unwrap();
+    }
+
+    if reserve_config.fractional_limits.is_some()
+        && reserve.config.fractional_limits != reserve_config.fractional_limits.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating fractional_limits from {} to {}",
+            reserve.config.fractional_limits,
+            reserve_config.fractional_limits.unwrap(),
+        );
+        reserve.config.fractional_limits = reserve_config.fractional_limits.unwrap();
+    }
+
     let mut new_pyth_product_pubkey = solend_program::NULL_PUBKEY;
     if pyth_price_pubkey.is_some() {
         no_change = false;
@@ -1668,19 +2723,45 @@ fn command_update_reserve(
         return Ok(());
     }
 
-    let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
-
-    let message = Message::new_with_blockhash(
-        &[update_reserve_config(
+    // risk parameters only change once the queued update is applied after the timelock
+    let queue_instruction = if reserve.config.loan_to_value_ratio
+        != current_config.loan_to_value_ratio
+        || reserve.config.liquidation_threshold != current_config.liquidation_threshold
+        || reserve.config.protocol_take_rate != current_config.protocol_take_rate
+    {
+        let instruction = queue_reserve_config_update(
             config.lending_program_id,
-            reserve.config,
+            reserve.config.loan_to_value_ratio,
+            reserve.config.liquidation_threshold,
+            reserve.config.protocol_take_rate,
             reserve_pubkey,
             lending_market_pubkey,
             lending_market_owner_keypair.pubkey(),
-            new_pyth_product_pubkey,
-            reserve.liquidity.pyth_oracle_pubkey,
-            reserve.liquidity.switchboard_oracle_pubkey,
-        )],
+        );
+        reserve.config.loan_to_value_ratio = current_config.loan_to_value_ratio;
+        reserve.config.liquidation_threshold = current_config.liquidation_threshold;
+        reserve.config.protocol_take_rate = current_config.protocol_take_rate;
+        Some(instruction)
+    } else {
+        None
+    };
+
+    let mut instructions = vec![update_reserve_config(
+        config.lending_program_id,
+        reserve.config,
+        reserve_pubkey,
+        lending_market_pubkey,
+        lending_market_owner_keypair.pubkey(),
+        new_pyth_product_pubkey,
+        reserve.liquidity.pyth_oracle_pubkey,
+        reserve.liquidity.switchboard_oracle_pubkey,
+    )];
+    instructions.extend(queue_instruction);
+
+    let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
+
+    let message = Message::new_with_blockhash(
+        &instructions,
         Some(&config.fee_payer.pubkey()),
         &recent_blockhash,
     );
@@ -1753,6 +2834,14 @@ fn quote_currency_of(matches: &ArgMatches<'_>, name: &str) -> Option<[u8; 32]> {
     }
 }
 
+fn reserve_type_of(matches: &ArgMatches<'_>, name: &str) -> Option<ReserveType> {
+    match matches.value_of(name) {
+        Some("isolated") => Some(ReserveType::Isolated),
+        Some(_) => Some(ReserveType::Regular),
+        None => None,
+    }
+}
+
 fn get_or_create_associated_token_address(config: &Config, mint: &Pubkey) -> Pubkey {
     let ata = get_associated_token_address(&config.fee_payer.pubkey(), mint);
 
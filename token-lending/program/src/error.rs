@@ -190,6 +190,76 @@ pub enum LendingError {
     /// Deprecated instruction
     #[error("Instruction is deprecated")]
     DeprecatedInstruction,
+    /// Too many price sources supplied
+    #[error("Too many price sources supplied to refresh reserve")]
+    TooManyPriceSources,
+
+    // 55
+    /// Not authorized to perform the operation
+    #[error("Not authorized to perform this operation")]
+    Unauthorized,
+    /// Oracle price has not changed within the configured window
+    #[error("Oracle price has not changed within the configured window")]
+    OraclePriceStuck,
+    /// Reserve has no queued config update
+    #[error("Reserve has no queued config update")]
+    NoPendingConfigUpdate,
+    /// Queued config update timelock has not elapsed
+    #[error("Queued config update timelock has not elapsed")]
+    ConfigUpdateTimelocked,
+    /// Lending market is deleveraging
+    #[error("Lending market is deleveraging, only unwinding operations are allowed")]
+    MarketDeleveraging,
+
+    // 60
+    /// Obligation was liquidated too recently
+    #[error("Obligation was liquidated too recently and is in its liquidation cooldown")]
+    LiquidationCooldown,
+    /// Reserve collateral can't be used as obligation collateral
+    #[error("Reserve is supply-only and its collateral can't be deposited into obligations")]
+    CollateralNotAllowed,
+    /// Reserve accounting disagrees with on-chain token state
+    #[error("Reserve collateral supply does not match the collateral mint supply")]
+    ReserveAccountingInconsistent,
+    /// Obligation can still be liquidated
+    #[error("Obligation is unhealthy and can still be liquidated")]
+    ObligationUnhealthy,
+    /// New oracle price is too far from the stored market price
+    #[error("New oracle price diverges from the reserve market price by more than allowed")]
+    OraclePriceDivergence,
+    /// Reserve and obligation tranches differ
+    #[error("Reserve belongs to a different tranche than the obligation")]
+    TrancheMismatch,
+    /// Borrowing from a reserve the obligation has collateral deposited in
+    #[error("Borrow reserve is also a deposit reserve of the obligation")]
+    SelfBorrow,
+    /// Deposit would take the reserve's total liquidity above its deposit limit
+    #[error("Deposit exceeds the reserve deposit limit")]
+    DepositLimitExceeded,
+    /// Borrow would take the reserve's borrowed liquidity above its borrow limit
+    #[error("Borrow exceeds the reserve borrow limit")]
+    BorrowLimitExceeded,
+    /// Borrow would take the obligation's borrowed value above the market's cap
+    #[error("Borrow exceeds the lending market's maximum borrowed value per obligation")]
+    ObligationValueLimit,
+    /// Borrow would mix an isolated asset with other borrows
+    #[error("Isolated assets can't be borrowed alongside any other asset")]
+    IsolatedBorrowViolation,
+    /// Deploy would leave too little of the reserve's liquidity available
+    #[error("Deploy amount exceeds the reserve's max deployed percentage")]
+    DeployLimitExceeded,
+    /// Reserve is at a version obligations can't be refreshed against
+    #[error("Reserve version mismatch, the reserve must be migrated")]
+    ReserveVersionMismatch,
+    /// Reserve usable as collateral is configured below the market's minimum loan to value ratio
+    #[error("Loan to value ratio is below the lending market's minimum for collateral")]
+    CollateralLtvTooLow,
+    /// Fee receiver provided isn't the one configured on the reserve
+    #[error("Fee receiver does not match the reserve's fee receiver")]
+    InvalidFeeReceiver,
+    /// Risk parameter change that has to go through the reserve config timelock
+    #[error("Loan to value ratio, liquidation threshold and protocol take rate can only change through a queued config update")]
+    RiskParameterTimelocked,
 }
 
 impl From<LendingError> for ProgramError {
@@ -2,16 +2,20 @@
 
 use crate::{
     error::LendingError,
-    state::{ReserveConfig, ReserveFees},
+    state::{LendingMarketConfigUpdate, ReserveConfig, ReserveFees, ReserveType},
 };
 use solana_program::{
     instruction::{AccountMeta, Instruction},
     msg,
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::{Pubkey, PUBKEY_BYTES},
-    sysvar,
+    system_program, sysvar,
+};
+use std::{
+    convert::{TryFrom, TryInto},
+    mem::size_of,
 };
-use std::{convert::TryInto, mem::size_of};
 
 /// Instructions supported by the lending program.
 #[derive(Clone, Debug, PartialEq)]
@@ -49,6 +53,10 @@ pub enum LendingInstruction {
     // 2
     /// Initializes a new lending market reserve.
     ///
+    /// The liquidity supply, fee receiver, collateral mint and collateral supply must be new
+    /// accounts. They are initialized under the lending market's derived authority, so reserves
+    /// for the same liquidity mint in different markets never share a vault.
+    ///
     /// Accounts expected by this instruction:
     ///
     ///   0. `[writable]` Source liquidity token account.
@@ -76,6 +84,9 @@ pub enum LendingInstruction {
         liquidity_amount: u64,
         /// Reserve configuration values
         config: ReserveConfig,
+        /// Collateral tokens minted per liquidity token for the initial deposit, scaled by WAD.
+        /// 0 for the default initial collateral ratio
+        collateral_exchange_rate_wads: u64,
     },
 
     // 3
@@ -89,6 +100,13 @@ pub enum LendingInstruction {
     ///   2. `[]` Switchboard Reserve liquidity oracle account.
     ///             Must be the Switchboard price feed account specified at InitReserve.
     ///   3. `[]` Clock sysvar (optional, will be removed soon).
+    ///   4. `[]` Extra Pyth price accounts, in the order set by SetReserveOracles, if any.
+    ///
+    /// No further oracle accounts may be supplied, at most `MAX_PRICE_SOURCES` feeds are read.
+    /// The market price is the median of the Pyth feeds that aren't stale.
+    /// Reserves with a fixed price ignore their oracles, which can then be left out.
+    /// Reserves that pause interest on oracle errors still refresh while their oracles are
+    /// unavailable, but are left stale once their cached price can't be used.
     RefreshReserve,
 
     // 4
@@ -145,7 +163,10 @@ pub enum LendingInstruction {
     ///   3. `[]` Clock sysvar (optional, will be removed soon).
     ///   4. `[]` Rent sysvar.
     ///   5. `[]` Token program id.
-    InitObligation,
+    InitObligation {
+        /// Tranche of the reserves the obligation can deposit into and borrow from
+        tranche: u8,
+    },
 
     // 7
     /// Refresh an obligation's accrued interest and collateral and liquidity prices. Requires
@@ -158,6 +179,8 @@ pub enum LendingInstruction {
     ///   1. `[]` Clock sysvar (optional, will be removed soon).
     ///   .. `[]` Collateral deposit reserve accounts - refreshed, all, in order.
     ///   .. `[]` Liquidity borrow reserve accounts - refreshed, all, in order.
+    ///   .. `[]` Lending market account - optional, applies the market's valuation policies.
+    ///                     Required when a reserve uses the market's confidence pricing.
     RefreshObligation,
 
     // 8
@@ -172,7 +195,9 @@ pub enum LendingInstruction {
     ///   2. `[writable]` Deposit reserve account.
     ///   3. `[writable]` Obligation account.
     ///   4. `[]` Lending market account.
-    ///   5. `[signer]` Obligation owner.
+    ///   5. `[signer]` Obligation owner or delegate.
+    ///             Need not sign if the lending market allows sponsored deposits and the
+    ///             obligation already has collateral from the deposit reserve.
     ///   6. `[signer]` User transfer authority ($authority).
     ///   7. `[]` Clock sysvar (optional, will be removed soon).
     ///   8. `[]` Token program id.
@@ -210,7 +235,8 @@ pub enum LendingInstruction {
     ///   0. `[writable]` Source borrow reserve liquidity supply SPL Token account.
     ///   1. `[writable]` Destination liquidity token account.
     ///                     Minted by borrow reserve liquidity mint.
-    ///   2. `[writable]` Borrow reserve account - refreshed.
+    ///   2. `[writable]` Borrow reserve account - refreshed, unless it enables auto refresh
+    ///                     and its oracle accounts are supplied.
     ///   3. `[writable]` Borrow reserve liquidity fee receiver account.
     ///                     Must be the fee account specified at InitReserve.
     ///   4. `[writable]` Obligation account - refreshed.
@@ -219,7 +245,10 @@ pub enum LendingInstruction {
     ///   7. `[signer]` Obligation owner.
     ///   8. `[]` Clock sysvar (optional, will be removed soon).
     ///   9. `[]` Token program id.
-    ///   10 `[optional, writable]` Host fee receiver account.
+    ///   10 `[optional]` Borrow reserve liquidity pyth oracle account.
+    ///   11 `[optional]` Borrow reserve liquidity switchboard oracle account, followed by the
+    ///                     borrow reserve's extra pyth oracle accounts, if it has any.
+    ///   12 `[optional, writable]` Host fee receiver account.
     BorrowObligationLiquidity {
         /// Amount of liquidity to borrow - u64::MAX for 100% of borrowing power
         liquidity_amount: u64,
@@ -329,7 +358,9 @@ pub enum LendingInstruction {
     ///   6. `[]` Derived lending market authority.
     ///   7. `[writable]` Destination deposit reserve collateral supply SPL Token account.
     ///   8. `[writable]` Obligation account.
-    ///   9. `[signer]` Obligation owner.
+    ///   9. `[signer]` Obligation owner or delegate.
+    ///             Need not sign if the lending market allows sponsored deposits and the
+    ///             obligation already has collateral from the deposit reserve.
     ///   10 `[]` Pyth price oracle account.
     ///   11 `[]` Switchboard price feed oracle account.
     ///   12 `[signer]` User transfer authority ($authority).
@@ -365,7 +396,9 @@ pub enum LendingInstruction {
     },
 
     // 16
-    /// Updates a reserves config and a reserve price oracle pubkeys
+    /// Updates a reserves config and a reserve price oracle pubkeys. The loan to value ratio,
+    /// liquidation threshold and protocol take rate must be left unchanged, they can only be
+    /// changed with QueueReserveConfigUpdate.
     ///
     /// Accounts expected by this instruction:
     ///
@@ -411,6 +444,12 @@ pub enum LendingInstruction {
     },
 
     // 18
+    /// Transfer the whole-token part of the reserve's accumulated protocol fees from the reserve
+    /// liquidity supply to its fee receiver. Requires a reserve refreshed in the current slot, so
+    /// that the fees include all accrued interest.
+    ///
+    /// Accounts expected by this instruction:
+    ///
     ///   0. `[writable]` Reserve account.
     ///   1. `[writable]` Borrow reserve liquidity fee receiver account.
     ///                     Must be the fee account specified at InitReserve.
@@ -448,7 +487,7 @@ pub enum LendingInstruction {
     ///   1. `[writable]` Destination liquidity token account.
     ///   2. `[writable]` Flash loan fee receiver account.
     ///                     Must match the reserve liquidity fee receiver.
-    ///   3. `[writable]` Host fee receiver.
+    ///   3. `[writable]` Host fee receiver, or the fee receiver again if there is no host.
     ///   4. `[writable]` Reserve account.
     ///   5. `[]` Lending market account.
     ///   6. `[signer]` User transfer authority ($authority).
@@ -460,6 +499,367 @@ pub enum LendingInstruction {
         /// Index of FlashBorrowReserveLiquidity instruction
         borrow_instruction_index: u8,
     },
+
+    // 21
+    /// Compute the state a RefreshReserve would produce without persisting it.
+    ///
+    /// The refreshed reserve is packed and written to the program return data, so the reserve
+    /// can be passed read-only, e.g. from an RPC simulation or a CPI. ViewObligation does the
+    /// same for an obligation's health.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Reserve account.
+    ///   1. `[]` Pyth Reserve liquidity oracle account.
+    ///             Must be the Pyth price account specified at InitReserve.
+    ///   2. `[]` Switchboard Reserve liquidity oracle account.
+    ///             Must be the Switchboard price feed account specified at InitReserve.
+    ViewReserve,
+
+    // 22
+    /// Allow or disallow an obligation to borrow from permissioned reserves.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Lending market owner.
+    SetObligationWhitelisted {
+        /// Whether the obligation may borrow from permissioned reserves
+        whitelisted: bool,
+    },
+
+    // 23
+    /// Repay borrowed liquidity to a reserve and withdraw collateral from the same obligation,
+    /// checking the obligation's health only once the debt has been repaid. Requires a refreshed
+    /// obligation and reserves.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Source liquidity token account.
+    ///                     Minted by repay reserve liquidity mint.
+    ///                     $authority can transfer $liquidity_amount.
+    ///   1. `[writable]` Destination repay reserve liquidity supply SPL Token account.
+    ///   2. `[writable]` Repay reserve account - refreshed.
+    ///   3. `[writable]` Source withdraw reserve collateral supply SPL Token account.
+    ///   4. `[writable]` Destination collateral token account.
+    ///                     Minted by withdraw reserve collateral mint.
+    ///   5. `[]` Withdraw reserve account - refreshed.
+    ///   6. `[writable]` Obligation account - refreshed.
+    ///   7. `[]` Lending market account.
+    ///   8. `[]` Derived lending market authority.
+    ///   9. `[signer]` Obligation owner.
+    ///   10 `[signer]` User transfer authority ($authority).
+    ///   11 `[]` Token program id.
+    RepayAndWithdraw {
+        /// Amount of liquidity to repay - u64::MAX for 100% of borrowed amount
+        liquidity_amount: u64,
+        /// Amount of collateral tokens to withdraw - u64::MAX for up to 100% of deposited amount
+        collateral_amount: u64,
+    },
+
+    // 24
+    /// Updates the market-wide settings of a lending market. Only the settings the update sets
+    /// change, the rest keep their current value.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[signer]` Lending market owner.
+    ///   .. `[writable]` Reserve accounts of the market - optional, each given a copy of the
+    ///                     confidence pricing policy. Pass every reserve when changing it.
+    UpdateLendingMarket {
+        /// Settings to change
+        update: LendingMarketConfigUpdate,
+    },
+
+    // 25
+    /// Set aside part of an obligation's collateral as liquidation protection. Protected
+    /// collateral still counts toward the obligation's liquidation threshold but gives it no
+    /// extra borrowing power, and it can't be withdrawn until the protection is lowered.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[]` Deposit reserve account.
+    ///   2. `[]` Lending market account.
+    ///   3. `[signer]` Obligation owner.
+    SetObligationCollateralProtection {
+        /// Amount of deposited collateral tokens to protect, replacing the previous amount
+        protected_amount: u64,
+    },
+
+    // 26
+    /// Returns the health of up to MAX_OBLIGATION_SUMMARIES obligations as packed
+    /// ObligationSummary entries in the return data, in the order the obligations were passed.
+    /// Every obligation must be refreshed in the current slot.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0.. `[]` Obligation accounts - refreshed.
+    BatchObligationSummary,
+
+    // 27
+    /// Queue a change of a reserve's risk parameters. The change can be applied with
+    /// ApplyReserveConfigUpdate once RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS have passed, and
+    /// replaces any change that is already queued.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Lending market owner.
+    QueueReserveConfigUpdate {
+        /// Loan to value ratio, as a percentage
+        loan_to_value_ratio: u8,
+        /// Liquidation threshold, as a percentage
+        liquidation_threshold: u8,
+        /// Protocol take rate, as a percentage
+        protocol_take_rate: u8,
+    },
+
+    // 28
+    /// Apply a reserve's queued risk parameter change once its timelock has elapsed.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ApplyReserveConfigUpdate,
+
+    // 29
+    /// Set a manual price that caps how deposits of a reserve are valued when refreshing an
+    /// obligation. Collateral is valued at the lower of the market price and this price.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Lending market owner.
+    SetReserveCollateralFloorPrice {
+        /// Collateral floor price in quote currency as a wad, 0 to value collateral at spot
+        floor_price_wads: u128,
+    },
+
+    // 30
+    /// Check whether borrowing an amount of reserve liquidity would succeed and keep the
+    /// obligation healthy, without borrowing. A packed BorrowCheck with the outcome, the error
+    /// the borrow would fail with and the resulting loan to value ratio is written to the return
+    /// data.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Obligation account - refreshed.
+    ///   1. `[]` Borrow reserve account - refreshed.
+    ///   2. `[]` Lending market account.
+    CanBorrow {
+        /// Amount of liquidity to borrow, excluding fees
+        liquidity_amount: u64,
+    },
+
+    // 31
+    /// Designate a delegate authority which can deposit collateral into the obligation on the
+    /// owner's behalf. Borrowing and withdrawing still require the owner.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Obligation owner.
+    SetObligationDelegate {
+        /// Delegate authority, the default pubkey to remove the delegate
+        delegate: Pubkey,
+    },
+
+    // 32
+    /// Returns the accounts needed to refresh an obligation and its reserves as packed
+    /// ReserveRefreshAccounts entries in the return data, one per reserve account expected by
+    /// RefreshObligation and in the same order. Fails if the entries don't fit in the return data,
+    /// which only happens when an obligation's many reserves also have extra pyth oracles.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Obligation account.
+    ///   .. `[]` Collateral deposit reserve accounts - all, in order.
+    ///   .. `[]` Liquidity borrow reserve accounts - all, in order.
+    GetRefreshAccounts,
+
+    // 33
+    /// Deposit collateral into an obligation like DepositObligationCollateral, then fail unless
+    /// the deposit leaves the obligation healthy enough that it can't be liquidated. Requires a
+    /// refreshed obligation and deposit reserve.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Source collateral token account.
+    ///                     Minted by deposit reserve collateral mint.
+    ///                     $authority can transfer $collateral_amount.
+    ///   1. `[writable]` Destination deposit reserve collateral supply SPL Token account.
+    ///   2. `[]` Deposit reserve account - refreshed.
+    ///   3. `[writable]` Obligation account - refreshed.
+    ///   4. `[]` Lending market account.
+    ///   5. `[signer]` Obligation owner or delegate.
+    ///             Need not sign if the lending market allows sponsored deposits and the
+    ///             obligation already has collateral from the deposit reserve.
+    ///   6. `[signer]` User transfer authority ($authority).
+    ///   7. `[]` Token program id.
+    DepositCollateralAndAssertHealthy {
+        /// Amount of collateral tokens to deposit
+        collateral_amount: u64,
+    },
+
+    // 34
+    /// Switch a live reserve to new price oracles, failing if their price diverges from the
+    /// reserve's stored market price by more than the given tolerance. A reserve without a market
+    /// price must be refreshed first, or can't be switched if it allows a zero price. The reserve
+    /// is left stale so that its next refresh reads the new oracles. The extra Pyth feeds, whose
+    /// median with the Pyth price account is the market price, are replaced by the pairs given.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Lending market owner.
+    ///   3. `[]` Pyth product account.
+    ///   4. `[]` Pyth price account.
+    ///   5. `[]` Switchboard price feed account.
+    ///   6. `[]` Extra Pyth product and price accounts, a pair for each, up to two pairs.
+    SetReserveOracles {
+        /// Maximum difference between the new oracles' price and the stored market price, in
+        /// basis points of the stored market price, up to `MAX_ORACLE_PRICE_DIVERGENCE_BPS`
+        max_price_divergence_bps: u16,
+    },
+
+    // 35
+    /// Returns the largest liquidation currently allowed for a pair of the obligation's borrow
+    /// and deposit as a packed MaxLiquidation in the return data: the liquidity a liquidation of
+    /// u64::MAX would repay and the collateral it would seize. Liquidating exactly the returned
+    /// repay amount moves the same amounts.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Obligation account - refreshed.
+    ///   1. `[]` Repay reserve account - refreshed.
+    ///   2. `[]` Withdraw reserve account - refreshed.
+    ///   3. `[]` Lending market account.
+    GetMaxLiquidation,
+
+    // 36
+    /// Returns the key risk parameters of up to MAX_RESERVE_CONFIG_SUMMARIES reserves of a
+    /// lending market as packed ReserveConfigSummary entries in the return data, in the order the
+    /// reserves were passed.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1.. `[]` Reserve accounts of the lending market.
+    GetMarketReserves,
+
+    // 37
+    /// Records the full state of a refreshed obligation in the transaction log, as a packed
+    /// ObligationSnapshot emitted with `sol_log_data`, so its deposits, borrows, values and
+    /// health at the current slot are kept on record.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Obligation account - refreshed.
+    ///   1. `[]` Lending market account.
+    SnapshotObligation,
+
+    // 38
+    /// Moves idle reserve liquidity to the reserve's strategy vault, up to the reserve's max
+    /// deployed percentage of the liquidity that isn't lent out. Deployed liquidity still counts
+    /// towards the reserve's total supply, so the collateral exchange rate is unchanged.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account - refreshed.
+    ///   1. `[writable]` Reserve liquidity supply SPL Token account.
+    ///   2. `[writable]` Strategy vault SPL Token account.
+    ///                     Must be the strategy vault specified in the reserve config.
+    ///   3. `[]` Lending market account.
+    ///   4. `[]` Derived lending market authority.
+    ///   5. `[signer]` Lending market owner.
+    ///   6. `[]` Token program id.
+    DeployReserveLiquidity {
+        /// Amount of liquidity to deploy
+        liquidity_amount: u64,
+    },
+
+    // 39
+    /// Returns liquidity from the reserve's strategy vault to the reserve liquidity supply.
+    /// Returning more than was deployed credits the difference to suppliers as yield.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account - refreshed.
+    ///   1. `[writable]` Strategy vault SPL Token account.
+    ///                     Must be the strategy vault specified in the reserve config.
+    ///   2. `[writable]` Reserve liquidity supply SPL Token account.
+    ///   3. `[]` Lending market account.
+    ///   4. `[signer]` Lending market owner.
+    ///   5. `[signer]` Strategy vault transfer authority ($authority).
+    ///   6. `[]` Token program id.
+    RecallReserveLiquidity {
+        /// Amount of liquidity to return
+        liquidity_amount: u64,
+    },
+
+    // 40
+    /// Accrue interest and update market price of several reserves in one instruction, as
+    /// RefreshReserve does for each. Stops at the first reserve that can't be refreshed,
+    /// logging its index.
+    ///
+    /// Accounts expected by this instruction, repeated for each reserve:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Pyth Reserve liquidity oracle account.
+    ///             Must be the Pyth price account specified at InitReserve.
+    ///   2. `[]` Switchboard Reserve liquidity oracle account.
+    ///             Must be the Switchboard price feed account specified at InitReserve.
+    ///   3. `[]` Extra Pyth price accounts, as many as the reserve has extra pyth oracles.
+    ///             Must be the extra Pyth price accounts set by SetReserveOracles, in order.
+    RefreshReserves,
+
+    // 41
+    /// Permanently gives up ownership of a lending market. The owner is set to the null pubkey,
+    /// which no one can sign for, so every owner-gated instruction fails from then on.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[signer]` Current owner.
+    RenounceLendingMarketOwner {
+        /// Must be the null pubkey, confirming the owner is given up for good
+        confirmation: Pubkey,
+    },
+
+    // 42
+    /// Grows a reserve account created before extra Pyth oracles were added to the reserve
+    /// layout to the current reserve size, leaving it without any. Reserves with the older layout
+    /// keep working without it, but need migrating before extra Pyth oracles can be set. Anyone
+    /// can migrate a reserve, topping up its lamports to stay rent exempt at the new size.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[signer, writable]` Payer.
+    ///   2. `[]` System program.
+    MigrateReserve,
+
+    // 43
+    /// Compute the health a RefreshObligation would give an obligation, refreshing each of its
+    /// reserves as ViewReserve does, without persisting anything.
+    ///
+    /// The obligation is summarized as a packed ObligationSummary in the program return data, so
+    /// neither the obligation nor its reserves need to be writable or refreshed beforehand.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Obligation account.
+    ///   1. `[]` Lending market account.
+    ///   .. `[]` Deposit reserve accounts in order, then borrow reserve accounts in order, each
+    ///             followed by its oracle accounts as laid out for RefreshReserves.
+    ViewObligation,
 }
 
 impl LendingInstruction {
@@ -483,6 +883,7 @@ impl LendingInstruction {
             }
             2 => {
                 let (liquidity_amount, rest) = Self::unpack_u64(rest)?;
+                let (collateral_exchange_rate_wads, rest) = Self::unpack_u64(rest)?;
                 let (optimal_utilization_rate, rest) = Self::unpack_u8(rest)?;
                 let (loan_to_value_ratio, rest) = Self::unpack_u8(rest)?;
                 let (liquidation_bonus, rest) = Self::unpack_u8(rest)?;
@@ -497,7 +898,35 @@ impl LendingInstruction {
                 let (borrow_limit, rest) = Self::unpack_u64(rest)?;
                 let (fee_receiver, rest) = Self::unpack_pubkey(rest)?;
                 let (protocol_liquidation_fee, rest) = Self::unpack_u8(rest)?;
-                let (protocol_take_rate, _rest) = Self::unpack_u8(rest)?;
+                let (protocol_take_rate, rest) = Self::unpack_u8(rest)?;
+                let (liquidation_threshold_ramp_slots, rest) = Self::unpack_u64(rest)?;
+                let (protocol_fees_cap, rest) = Self::unpack_u8(rest)?;
+                let (permissioned, rest) = Self::unpack_bool(rest)?;
+                let (allow_zero_price, rest) = Self::unpack_bool(rest)?;
+                let (max_unchanged_price_slots, rest) = Self::unpack_u64(rest)?;
+                let (auto_refresh, rest) = Self::unpack_bool(rest)?;
+                let (usable_as_collateral, rest) = Self::unpack_bool(rest)?;
+                let (max_borrow_price_increase_pct, rest) = Self::unpack_u8(rest)?;
+                let (tranche, rest) = Self::unpack_u8(rest)?;
+                let (extreme_utilization_rate, rest) = Self::unpack_u8(rest)?;
+                let (extreme_borrow_rate, rest) = Self::unpack_u16(rest)?;
+                let (use_ema_price, rest) = Self::unpack_bool(rest)?;
+                let (liquidation_close_factor, rest) = Self::unpack_u8(rest)?;
+                let (round_supplier_interest_up, rest) = Self::unpack_bool(rest)?;
+                let (max_price_confidence_bps, rest) = Self::unpack_u16(rest)?;
+                let (reject_self_borrow, rest) = Self::unpack_bool(rest)?;
+                let (max_stale_price_slots, rest) = Self::unpack_u64(rest)?;
+                let (stale_price_decay_bps, rest) = Self::unpack_u16(rest)?;
+                let (fixed_price, rest) = Self::unpack_u64(rest)?;
+                let (max_liquidation_bonus, rest) = Self::unpack_u8(rest)?;
+                let (pause_interest_on_oracle_error, rest) = Self::unpack_bool(rest)?;
+                let (reserve_type, rest) = Self::unpack_u8(rest)?;
+                let reserve_type = ReserveType::try_from(reserve_type)?;
+                let (strategy_vault, rest) = Self::unpack_pubkey(rest)?;
+                let (max_deployed_pct, rest) = Self::unpack_u8(rest)?;
+                let (max_price_age_slots, rest) = Self::unpack_u64(rest)?;
+                let (max_liquidation_price_age_slots, rest) = Self::unpack_u64(rest)?;
+                let (fractional_limits, _rest) = Self::unpack_bool(rest)?;
                 Self::InitReserve {
                     liquidity_amount,
                     config: ReserveConfig {
@@ -518,7 +947,35 @@ impl LendingInstruction {
                         fee_receiver,
                         protocol_liquidation_fee,
                         protocol_take_rate,
+                        liquidation_threshold_ramp_slots,
+                        protocol_fees_cap,
+                        permissioned,
+                        allow_zero_price,
+                        max_unchanged_price_slots,
+                        auto_refresh,
+                        usable_as_collateral,
+                        max_borrow_price_increase_pct,
+                        tranche,
+                        extreme_utilization_rate,
+                        extreme_borrow_rate,
+                        use_ema_price,
+                        liquidation_close_factor,
+                        round_supplier_interest_up,
+                        max_price_confidence_bps,
+                        reject_self_borrow,
+                        max_stale_price_slots,
+                        stale_price_decay_bps,
+                        fixed_price,
+                        max_liquidation_bonus,
+                        pause_interest_on_oracle_error,
+                        reserve_type,
+                        strategy_vault,
+                        max_deployed_pct,
+                        max_price_age_slots,
+                        max_liquidation_price_age_slots,
+                        fractional_limits,
                     },
+                    collateral_exchange_rate_wads,
                 }
             }
             3 => Self::RefreshReserve,
@@ -530,7 +987,11 @@ impl LendingInstruction {
                 let (collateral_amount, _rest) = Self::unpack_u64(rest)?;
                 Self::RedeemReserveCollateral { collateral_amount }
             }
-            6 => Self::InitObligation,
+            6 => {
+                // clients that predate tranches don't send one and get the default tranche
+                let tranche = rest.first().copied().unwrap_or_default();
+                Self::InitObligation { tranche }
+            }
             7 => Self::RefreshObligation,
             8 => {
                 let (collateral_amount, _rest) = Self::unpack_u64(rest)?;
@@ -579,7 +1040,35 @@ impl LendingInstruction {
                 let (borrow_limit, rest) = Self::unpack_u64(rest)?;
                 let (fee_receiver, rest) = Self::unpack_pubkey(rest)?;
                 let (protocol_liquidation_fee, rest) = Self::unpack_u8(rest)?;
-                let (protocol_take_rate, _rest) = Self::unpack_u8(rest)?;
+                let (protocol_take_rate, rest) = Self::unpack_u8(rest)?;
+                let (liquidation_threshold_ramp_slots, rest) = Self::unpack_u64(rest)?;
+                let (protocol_fees_cap, rest) = Self::unpack_u8(rest)?;
+                let (permissioned, rest) = Self::unpack_bool(rest)?;
+                let (allow_zero_price, rest) = Self::unpack_bool(rest)?;
+                let (max_unchanged_price_slots, rest) = Self::unpack_u64(rest)?;
+                let (auto_refresh, rest) = Self::unpack_bool(rest)?;
+                let (usable_as_collateral, rest) = Self::unpack_bool(rest)?;
+                let (max_borrow_price_increase_pct, rest) = Self::unpack_u8(rest)?;
+                let (tranche, rest) = Self::unpack_u8(rest)?;
+                let (extreme_utilization_rate, rest) = Self::unpack_u8(rest)?;
+                let (extreme_borrow_rate, rest) = Self::unpack_u16(rest)?;
+                let (use_ema_price, rest) = Self::unpack_bool(rest)?;
+                let (liquidation_close_factor, rest) = Self::unpack_u8(rest)?;
+                let (round_supplier_interest_up, rest) = Self::unpack_bool(rest)?;
+                let (max_price_confidence_bps, rest) = Self::unpack_u16(rest)?;
+                let (reject_self_borrow, rest) = Self::unpack_bool(rest)?;
+                let (max_stale_price_slots, rest) = Self::unpack_u64(rest)?;
+                let (stale_price_decay_bps, rest) = Self::unpack_u16(rest)?;
+                let (fixed_price, rest) = Self::unpack_u64(rest)?;
+                let (max_liquidation_bonus, rest) = Self::unpack_u8(rest)?;
+                let (pause_interest_on_oracle_error, rest) = Self::unpack_bool(rest)?;
+                let (reserve_type, rest) = Self::unpack_u8(rest)?;
+                let reserve_type = ReserveType::try_from(reserve_type)?;
+                let (strategy_vault, rest) = Self::unpack_pubkey(rest)?;
+                let (max_deployed_pct, rest) = Self::unpack_u8(rest)?;
+                let (max_price_age_slots, rest) = Self::unpack_u64(rest)?;
+                let (max_liquidation_price_age_slots, rest) = Self::unpack_u64(rest)?;
+                let (fractional_limits, _rest) = Self::unpack_bool(rest)?;
                 Self::UpdateReserveConfig {
                     config: ReserveConfig {
                         optimal_utilization_rate,
@@ -599,6 +1088,33 @@ impl LendingInstruction {
                         fee_receiver,
                         protocol_liquidation_fee,
                         protocol_take_rate,
+                        liquidation_threshold_ramp_slots,
+                        protocol_fees_cap,
+                        permissioned,
+                        allow_zero_price,
+                        max_unchanged_price_slots,
+                        auto_refresh,
+                        usable_as_collateral,
+                        max_borrow_price_increase_pct,
+                        tranche,
+                        extreme_utilization_rate,
+                        extreme_borrow_rate,
+                        use_ema_price,
+                        liquidation_close_factor,
+                        round_supplier_interest_up,
+                        max_price_confidence_bps,
+                        reject_self_borrow,
+                        max_stale_price_slots,
+                        stale_price_decay_bps,
+                        fixed_price,
+                        max_liquidation_bonus,
+                        pause_interest_on_oracle_error,
+                        reserve_type,
+                        strategy_vault,
+                        max_deployed_pct,
+                        max_price_age_slots,
+                        max_liquidation_price_age_slots,
+                        fractional_limits,
                     },
                 }
             }
@@ -619,6 +1135,80 @@ impl LendingInstruction {
                     borrow_instruction_index,
                 }
             }
+            21 => Self::ViewReserve,
+            22 => {
+                let (whitelisted, _rest) = Self::unpack_bool(rest)?;
+                Self::SetObligationWhitelisted { whitelisted }
+            }
+            23 => {
+                let (liquidity_amount, rest) = Self::unpack_u64(rest)?;
+                let (collateral_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::RepayAndWithdraw {
+                    liquidity_amount,
+                    collateral_amount,
+                }
+            }
+            24 => {
+                let (update, _rest) = Self::unpack_lending_market_config_update(rest)?;
+                Self::UpdateLendingMarket { update }
+            }
+            25 => {
+                let (protected_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::SetObligationCollateralProtection { protected_amount }
+            }
+            26 => Self::BatchObligationSummary,
+            27 => {
+                let (loan_to_value_ratio, rest) = Self::unpack_u8(rest)?;
+                let (liquidation_threshold, rest) = Self::unpack_u8(rest)?;
+                let (protocol_take_rate, _rest) = Self::unpack_u8(rest)?;
+                Self::QueueReserveConfigUpdate {
+                    loan_to_value_ratio,
+                    liquidation_threshold,
+                    protocol_take_rate,
+                }
+            }
+            28 => Self::ApplyReserveConfigUpdate,
+            29 => {
+                let (floor_price_wads, _rest) = Self::unpack_u128(rest)?;
+                Self::SetReserveCollateralFloorPrice { floor_price_wads }
+            }
+            30 => {
+                let (liquidity_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::CanBorrow { liquidity_amount }
+            }
+            31 => {
+                let (delegate, _rest) = Self::unpack_pubkey(rest)?;
+                Self::SetObligationDelegate { delegate }
+            }
+            32 => Self::GetRefreshAccounts,
+            33 => {
+                let (collateral_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::DepositCollateralAndAssertHealthy { collateral_amount }
+            }
+            34 => {
+                let (max_price_divergence_bps, _rest) = Self::unpack_u16(rest)?;
+                Self::SetReserveOracles {
+                    max_price_divergence_bps,
+                }
+            }
+            35 => Self::GetMaxLiquidation,
+            36 => Self::GetMarketReserves,
+            37 => Self::SnapshotObligation,
+            38 => {
+                let (liquidity_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::DeployReserveLiquidity { liquidity_amount }
+            }
+            39 => {
+                let (liquidity_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::RecallReserveLiquidity { liquidity_amount }
+            }
+            40 => Self::RefreshReserves,
+            41 => {
+                let (confirmation, _rest) = Self::unpack_pubkey(rest)?;
+                Self::RenounceLendingMarketOwner { confirmation }
+            }
+            42 => Self::MigrateReserve,
+            43 => Self::ViewObligation,
             _ => {
                 msg!("Instruction cannot be unpacked");
                 return Err(LendingError::InstructionUnpackError.into());
@@ -626,6 +1216,20 @@ impl LendingInstruction {
         })
     }
 
+    fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+        if input.len() < 2 {
+            msg!("u16 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(2);
+        let value = bytes
+            .get(..2)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u16::from_le_bytes)
+            .ok_or(LendingError::InstructionUnpackError)?;
+        Ok((value, rest))
+    }
+
     fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
         if input.len() < 8 {
             msg!("u64 cannot be unpacked");
@@ -640,6 +1244,20 @@ impl LendingInstruction {
         Ok((value, rest))
     }
 
+    fn unpack_u128(input: &[u8]) -> Result<(u128, &[u8]), ProgramError> {
+        if input.len() < 16 {
+            msg!("u128 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(16);
+        let value = bytes
+            .get(..16)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u128::from_le_bytes)
+            .ok_or(LendingError::InstructionUnpackError)?;
+        Ok((value, rest))
+    }
+
     fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
         if input.is_empty() {
             msg!("u8 cannot be unpacked");
@@ -654,6 +1272,18 @@ impl LendingInstruction {
         Ok((value, rest))
     }
 
+    fn unpack_bool(input: &[u8]) -> Result<(bool, &[u8]), ProgramError> {
+        let (value, rest) = Self::unpack_u8(input)?;
+        match value {
+            0 => Ok((false, rest)),
+            1 => Ok((true, rest)),
+            _ => {
+                msg!("Boolean cannot be unpacked");
+                Err(LendingError::InstructionUnpackError.into())
+            }
+        }
+    }
+
     fn unpack_bytes32(input: &[u8]) -> Result<(&[u8; 32], &[u8]), ProgramError> {
         if input.len() < 32 {
             msg!("32 bytes cannot be unpacked");
@@ -678,6 +1308,19 @@ impl LendingInstruction {
         Ok((pk, rest))
     }
 
+    fn unpack_lending_market_config_update(
+        input: &[u8],
+    ) -> Result<(LendingMarketConfigUpdate, &[u8]), ProgramError> {
+        if input.len() < LendingMarketConfigUpdate::LEN {
+            msg!("Lending market config update cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(LendingMarketConfigUpdate::LEN);
+        let update = LendingMarketConfigUpdate::unpack_from_slice(bytes)
+            .map_err(|_| LendingError::InstructionUnpackError)?;
+        Ok((update, rest))
+    }
+
     /// Packs a [LendingInstruction](enum.LendingInstruction.html) into a byte buffer.
     pub fn pack(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(size_of::<Self>());
@@ -716,10 +1359,39 @@ impl LendingInstruction {
                         fee_receiver,
                         protocol_liquidation_fee,
                         protocol_take_rate,
+                        liquidation_threshold_ramp_slots,
+                        protocol_fees_cap,
+                        permissioned,
+                        allow_zero_price,
+                        max_unchanged_price_slots,
+                        auto_refresh,
+                        usable_as_collateral,
+                        max_borrow_price_increase_pct,
+                        tranche,
+                        extreme_utilization_rate,
+                        extreme_borrow_rate,
+                        use_ema_price,
+                        liquidation_close_factor,
+                        round_supplier_interest_up,
+                        max_price_confidence_bps,
+                        reject_self_borrow,
+                        max_stale_price_slots,
+                        stale_price_decay_bps,
+                        fixed_price,
+                        max_liquidation_bonus,
+                        pause_interest_on_oracle_error,
+                        reserve_type,
+                        strategy_vault,
+                        max_deployed_pct,
+                        max_price_age_slots,
+                        max_liquidation_price_age_slots,
+                        fractional_limits,
                     },
+                collateral_exchange_rate_wads,
             } => {
                 buf.push(2);
                 buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+                buf.extend_from_slice(&collateral_exchange_rate_wads.to_le_bytes());
                 buf.extend_from_slice(&optimal_utilization_rate.to_le_bytes());
                 buf.extend_from_slice(&loan_to_value_ratio.to_le_bytes());
                 buf.extend_from_slice(&liquidation_bonus.to_le_bytes());
@@ -735,6 +1407,33 @@ impl LendingInstruction {
                 buf.extend_from_slice(&fee_receiver.to_bytes());
                 buf.extend_from_slice(&protocol_liquidation_fee.to_le_bytes());
                 buf.extend_from_slice(&protocol_take_rate.to_le_bytes());
+                buf.extend_from_slice(&liquidation_threshold_ramp_slots.to_le_bytes());
+                buf.extend_from_slice(&protocol_fees_cap.to_le_bytes());
+                buf.push(permissioned as u8);
+                buf.push(allow_zero_price as u8);
+                buf.extend_from_slice(&max_unchanged_price_slots.to_le_bytes());
+                buf.push(auto_refresh as u8);
+                buf.push(usable_as_collateral as u8);
+                buf.extend_from_slice(&max_borrow_price_increase_pct.to_le_bytes());
+                buf.extend_from_slice(&tranche.to_le_bytes());
+                buf.extend_from_slice(&extreme_utilization_rate.to_le_bytes());
+                buf.extend_from_slice(&extreme_borrow_rate.to_le_bytes());
+                buf.push(use_ema_price as u8);
+                buf.extend_from_slice(&liquidation_close_factor.to_le_bytes());
+                buf.push(round_supplier_interest_up as u8);
+                buf.extend_from_slice(&max_price_confidence_bps.to_le_bytes());
+                buf.push(reject_self_borrow as u8);
+                buf.extend_from_slice(&max_stale_price_slots.to_le_bytes());
+                buf.extend_from_slice(&stale_price_decay_bps.to_le_bytes());
+                buf.extend_from_slice(&fixed_price.to_le_bytes());
+                buf.extend_from_slice(&max_liquidation_bonus.to_le_bytes());
+                buf.push(pause_interest_on_oracle_error as u8);
+                buf.extend_from_slice(&(reserve_type as u8).to_le_bytes());
+                buf.extend_from_slice(&strategy_vault.to_bytes());
+                buf.extend_from_slice(&max_deployed_pct.to_le_bytes());
+                buf.extend_from_slice(&max_price_age_slots.to_le_bytes());
+                buf.extend_from_slice(&max_liquidation_price_age_slots.to_le_bytes());
+                buf.push(fractional_limits as u8);
             }
             Self::RefreshReserve => {
                 buf.push(3);
@@ -747,8 +1446,9 @@ impl LendingInstruction {
                 buf.push(5);
                 buf.extend_from_slice(&collateral_amount.to_le_bytes());
             }
-            Self::InitObligation => {
+            Self::InitObligation { tranche } => {
                 buf.push(6);
+                buf.extend_from_slice(&tranche.to_le_bytes());
             }
             Self::RefreshObligation => {
                 buf.push(7);
@@ -802,6 +1502,33 @@ impl LendingInstruction {
                 buf.extend_from_slice(&config.fee_receiver.to_bytes());
                 buf.extend_from_slice(&config.protocol_liquidation_fee.to_le_bytes());
                 buf.extend_from_slice(&config.protocol_take_rate.to_le_bytes());
+                buf.extend_from_slice(&config.liquidation_threshold_ramp_slots.to_le_bytes());
+                buf.extend_from_slice(&config.protocol_fees_cap.to_le_bytes());
+                buf.push(config.permissioned as u8);
+                buf.push(config.allow_zero_price as u8);
+                buf.extend_from_slice(&config.max_unchanged_price_slots.to_le_bytes());
+                buf.push(config.auto_refresh as u8);
+                buf.push(config.usable_as_collateral as u8);
+                buf.extend_from_slice(&config.max_borrow_price_increase_pct.to_le_bytes());
+                buf.extend_from_slice(&config.tranche.to_le_bytes());
+                buf.extend_from_slice(&config.extreme_utilization_rate.to_le_bytes());
+                buf.extend_from_slice(&config.extreme_borrow_rate.to_le_bytes());
+                buf.push(config.use_ema_price as u8);
+                buf.extend_from_slice(&config.liquidation_close_factor.to_le_bytes());
+                buf.push(config.round_supplier_interest_up as u8);
+                buf.extend_from_slice(&config.max_price_confidence_bps.to_le_bytes());
+                buf.push(config.reject_self_borrow as u8);
+                buf.extend_from_slice(&config.max_stale_price_slots.to_le_bytes());
+                buf.extend_from_slice(&config.stale_price_decay_bps.to_le_bytes());
+                buf.extend_from_slice(&config.fixed_price.to_le_bytes());
+                buf.extend_from_slice(&config.max_liquidation_bonus.to_le_bytes());
+                buf.push(config.pause_interest_on_oracle_error as u8);
+                buf.extend_from_slice(&(config.reserve_type as u8).to_le_bytes());
+                buf.extend_from_slice(&config.strategy_vault.to_bytes());
+                buf.extend_from_slice(&config.max_deployed_pct.to_le_bytes());
+                buf.extend_from_slice(&config.max_price_age_slots.to_le_bytes());
+                buf.extend_from_slice(&config.max_liquidation_price_age_slots.to_le_bytes());
+                buf.push(config.fractional_limits as u8);
             }
             Self::LiquidateObligationAndRedeemReserveCollateral { liquidity_amount } => {
                 buf.push(17);
@@ -822,29 +1549,125 @@ impl LendingInstruction {
                 buf.extend_from_slice(&liquidity_amount.to_le_bytes());
                 buf.extend_from_slice(&borrow_instruction_index.to_le_bytes());
             }
-        }
-        buf
-    }
-}
-
-/// Creates an 'InitLendingMarket' instruction.
-pub fn init_lending_market(
-    program_id: Pubkey,
-    owner: Pubkey,
-    quote_currency: [u8; 32],
-    lending_market_pubkey: Pubkey,
-    oracle_program_id: Pubkey,
-    switchboard_oracle_program_id: Pubkey,
-) -> Instruction {
-    Instruction {
-        program_id,
-        accounts: vec![
-            AccountMeta::new(lending_market_pubkey, false),
-            AccountMeta::new_readonly(sysvar::rent::id(), false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-            AccountMeta::new_readonly(oracle_program_id, false),
-            AccountMeta::new_readonly(switchboard_oracle_program_id, false),
-        ],
+            Self::ViewReserve => {
+                buf.push(21);
+            }
+            Self::SetObligationWhitelisted { whitelisted } => {
+                buf.push(22);
+                buf.push(whitelisted as u8);
+            }
+            Self::RepayAndWithdraw {
+                liquidity_amount,
+                collateral_amount,
+            } => {
+                buf.push(23);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+                buf.extend_from_slice(&collateral_amount.to_le_bytes());
+            }
+            Self::UpdateLendingMarket { update } => {
+                buf.push(24);
+                let mut data = [0; LendingMarketConfigUpdate::LEN];
+                update.pack_into_slice(&mut data);
+                buf.extend_from_slice(&data);
+            }
+            Self::SetObligationCollateralProtection { protected_amount } => {
+                buf.push(25);
+                buf.extend_from_slice(&protected_amount.to_le_bytes());
+            }
+            Self::BatchObligationSummary => {
+                buf.push(26);
+            }
+            Self::QueueReserveConfigUpdate {
+                loan_to_value_ratio,
+                liquidation_threshold,
+                protocol_take_rate,
+            } => {
+                buf.push(27);
+                buf.extend_from_slice(&loan_to_value_ratio.to_le_bytes());
+                buf.extend_from_slice(&liquidation_threshold.to_le_bytes());
+                buf.extend_from_slice(&protocol_take_rate.to_le_bytes());
+            }
+            Self::ApplyReserveConfigUpdate => {
+                buf.push(28);
+            }
+            Self::SetReserveCollateralFloorPrice { floor_price_wads } => {
+                buf.push(29);
+                buf.extend_from_slice(&floor_price_wads.to_le_bytes());
+            }
+            Self::CanBorrow { liquidity_amount } => {
+                buf.push(30);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+            }
+            Self::SetObligationDelegate { delegate } => {
+                buf.push(31);
+                buf.extend_from_slice(delegate.as_ref());
+            }
+            Self::GetRefreshAccounts => {
+                buf.push(32);
+            }
+            Self::DepositCollateralAndAssertHealthy { collateral_amount } => {
+                buf.push(33);
+                buf.extend_from_slice(&collateral_amount.to_le_bytes());
+            }
+            Self::SetReserveOracles {
+                max_price_divergence_bps,
+            } => {
+                buf.push(34);
+                buf.extend_from_slice(&max_price_divergence_bps.to_le_bytes());
+            }
+            Self::GetMaxLiquidation => {
+                buf.push(35);
+            }
+            Self::GetMarketReserves => {
+                buf.push(36);
+            }
+            Self::SnapshotObligation => {
+                buf.push(37);
+            }
+            Self::DeployReserveLiquidity { liquidity_amount } => {
+                buf.push(38);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+            }
+            Self::RecallReserveLiquidity { liquidity_amount } => {
+                buf.push(39);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+            }
+            Self::RefreshReserves => {
+                buf.push(40);
+            }
+            Self::RenounceLendingMarketOwner { confirmation } => {
+                buf.push(41);
+                buf.extend_from_slice(confirmation.as_ref());
+            }
+            Self::MigrateReserve => {
+                buf.push(42);
+            }
+            Self::ViewObligation => {
+                buf.push(43);
+            }
+        }
+        buf
+    }
+}
+
+/// Creates an 'InitLendingMarket' instruction.
+pub fn init_lending_market(
+    program_id: Pubkey,
+    owner: Pubkey,
+    quote_currency: [u8; 32],
+    lending_market_pubkey: Pubkey,
+    oracle_program_id: Pubkey,
+    switchboard_oracle_program_id: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(oracle_program_id, false),
+            AccountMeta::new_readonly(switchboard_oracle_program_id, false),
+        ],
         data: LendingInstruction::InitLendingMarket {
             owner,
             quote_currency,
@@ -876,6 +1699,7 @@ pub fn init_reserve(
     program_id: Pubkey,
     liquidity_amount: u64,
     config: ReserveConfig,
+    collateral_exchange_rate_wads: u64,
     source_liquidity_pubkey: Pubkey,
     destination_collateral_pubkey: Pubkey,
     reserve_pubkey: Pubkey,
@@ -919,6 +1743,7 @@ pub fn init_reserve(
         data: LendingInstruction::InitReserve {
             liquidity_amount,
             config,
+            collateral_exchange_rate_wads,
         }
         .pack(),
     }
@@ -1015,6 +1840,7 @@ pub fn redeem_reserve_collateral(
 #[allow(clippy::too_many_arguments)]
 pub fn init_obligation(
     program_id: Pubkey,
+    tranche: u8,
     obligation_pubkey: Pubkey,
     lending_market_pubkey: Pubkey,
     obligation_owner_pubkey: Pubkey,
@@ -1028,7 +1854,7 @@ pub fn init_obligation(
             AccountMeta::new_readonly(sysvar::rent::id(), false),
             AccountMeta::new_readonly(spl_token::id(), false),
         ],
-        data: LendingInstruction::InitObligation.pack(),
+        data: LendingInstruction::InitObligation { tranche }.pack(),
     }
 }
 
@@ -1038,6 +1864,7 @@ pub fn refresh_obligation(
     program_id: Pubkey,
     obligation_pubkey: Pubkey,
     reserve_pubkeys: Vec<Pubkey>,
+    lending_market_pubkey: Pubkey,
 ) -> Instruction {
     let mut accounts = vec![AccountMeta::new(obligation_pubkey, false)];
     accounts.extend(
@@ -1045,6 +1872,7 @@ pub fn refresh_obligation(
             .into_iter()
             .map(|pubkey| AccountMeta::new_readonly(pubkey, false)),
     );
+    accounts.push(AccountMeta::new_readonly(lending_market_pubkey, false));
     Instruction {
         program_id,
         accounts,
@@ -1454,7 +2282,7 @@ pub fn flash_repay_reserve_liquidity(
     source_liquidity_pubkey: Pubkey,
     destination_liquidity_pubkey: Pubkey,
     reserve_liquidity_fee_receiver_pubkey: Pubkey,
-    host_fee_receiver_pubkey: Pubkey,
+    host_fee_receiver_pubkey: Option<Pubkey>,
     reserve_pubkey: Pubkey,
     lending_market_pubkey: Pubkey,
     user_transfer_authority_pubkey: Pubkey,
@@ -1465,7 +2293,10 @@ pub fn flash_repay_reserve_liquidity(
             AccountMeta::new(source_liquidity_pubkey, false),
             AccountMeta::new(destination_liquidity_pubkey, false),
             AccountMeta::new(reserve_liquidity_fee_receiver_pubkey, false),
-            AccountMeta::new(host_fee_receiver_pubkey, false),
+            AccountMeta::new(
+                host_fee_receiver_pubkey.unwrap_or(reserve_liquidity_fee_receiver_pubkey),
+                false,
+            ),
             AccountMeta::new(reserve_pubkey, false),
             AccountMeta::new_readonly(lending_market_pubkey, false),
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
@@ -1479,3 +2310,533 @@ pub fn flash_repay_reserve_liquidity(
         .pack(),
     }
 }
+
+/// Creates a 'ViewReserve' instruction.
+pub fn view_reserve(
+    program_id: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_pyth_oracle_pubkey: Pubkey,
+    reserve_liquidity_switchboard_oracle_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(reserve_pubkey, false),
+            AccountMeta::new_readonly(reserve_liquidity_pyth_oracle_pubkey, false),
+            AccountMeta::new_readonly(reserve_liquidity_switchboard_oracle_pubkey, false),
+        ],
+        data: LendingInstruction::ViewReserve.pack(),
+    }
+}
+
+/// Creates a 'SetObligationWhitelisted' instruction.
+pub fn set_obligation_whitelisted(
+    program_id: Pubkey,
+    whitelisted: bool,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_owner_pubkey, true),
+        ],
+        data: LendingInstruction::SetObligationWhitelisted { whitelisted }.pack(),
+    }
+}
+
+/// Creates a 'RepayAndWithdraw' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn repay_and_withdraw(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    collateral_amount: u64,
+    source_liquidity_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    repay_reserve_pubkey: Pubkey,
+    source_collateral_pubkey: Pubkey,
+    destination_collateral_pubkey: Pubkey,
+    withdraw_reserve_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+) -> Instruction {
+    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        &program_id,
+    );
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(source_liquidity_pubkey, false),
+            AccountMeta::new(destination_liquidity_pubkey, false),
+            AccountMeta::new(repay_reserve_pubkey, false),
+            AccountMeta::new(source_collateral_pubkey, false),
+            AccountMeta::new(destination_collateral_pubkey, false),
+            AccountMeta::new_readonly(withdraw_reserve_pubkey, false),
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+            AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: LendingInstruction::RepayAndWithdraw {
+            liquidity_amount,
+            collateral_amount,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an 'UpdateLendingMarket' instruction.
+pub fn update_lending_market(
+    program_id: Pubkey,
+    update: LendingMarketConfigUpdate,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+    reserve_pubkeys: Vec<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(lending_market_pubkey, false),
+        AccountMeta::new_readonly(lending_market_owner, true),
+    ];
+    accounts.extend(
+        reserve_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new(pubkey, false)),
+    );
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::UpdateLendingMarket { update }.pack(),
+    }
+}
+
+/// Creates a 'SetObligationCollateralProtection' instruction.
+pub fn set_obligation_collateral_protection(
+    program_id: Pubkey,
+    protected_amount: u64,
+    obligation_pubkey: Pubkey,
+    deposit_reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(deposit_reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        ],
+        data: LendingInstruction::SetObligationCollateralProtection { protected_amount }.pack(),
+    }
+}
+
+/// Creates a 'BatchObligationSummary' instruction.
+pub fn batch_obligation_summary(
+    program_id: Pubkey,
+    obligation_pubkeys: Vec<Pubkey>,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: obligation_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new_readonly(pubkey, false))
+            .collect(),
+        data: LendingInstruction::BatchObligationSummary.pack(),
+    }
+}
+
+/// Creates a `QueueReserveConfigUpdate` instruction
+pub fn queue_reserve_config_update(
+    program_id: Pubkey,
+    loan_to_value_ratio: u8,
+    liquidation_threshold: u8,
+    protocol_take_rate: u8,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_owner_pubkey, true),
+        ],
+        data: LendingInstruction::QueueReserveConfigUpdate {
+            loan_to_value_ratio,
+            liquidation_threshold,
+            protocol_take_rate,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `ApplyReserveConfigUpdate` instruction
+pub fn apply_reserve_config_update(program_id: Pubkey, reserve_pubkey: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![AccountMeta::new(reserve_pubkey, false)],
+        data: LendingInstruction::ApplyReserveConfigUpdate.pack(),
+    }
+}
+
+/// Creates a `SetReserveCollateralFloorPrice` instruction
+pub fn set_reserve_collateral_floor_price(
+    program_id: Pubkey,
+    floor_price_wads: u128,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_owner_pubkey, true),
+        ],
+        data: LendingInstruction::SetReserveCollateralFloorPrice { floor_price_wads }.pack(),
+    }
+}
+
+/// Creates a `CanBorrow` instruction
+pub fn can_borrow(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    obligation_pubkey: Pubkey,
+    borrow_reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(obligation_pubkey, false),
+            AccountMeta::new_readonly(borrow_reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+        ],
+        data: LendingInstruction::CanBorrow { liquidity_amount }.pack(),
+    }
+}
+
+/// Creates a `SetObligationDelegate` instruction
+pub fn set_obligation_delegate(
+    program_id: Pubkey,
+    delegate: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        ],
+        data: LendingInstruction::SetObligationDelegate { delegate }.pack(),
+    }
+}
+
+/// Creates a `GetRefreshAccounts` instruction
+pub fn get_refresh_accounts(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    reserve_pubkeys: Vec<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![AccountMeta::new_readonly(obligation_pubkey, false)];
+    accounts.extend(
+        reserve_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new_readonly(pubkey, false)),
+    );
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::GetRefreshAccounts.pack(),
+    }
+}
+
+/// Creates a `DepositCollateralAndAssertHealthy` instruction
+#[allow(clippy::too_many_arguments)]
+pub fn deposit_collateral_and_assert_healthy(
+    program_id: Pubkey,
+    collateral_amount: u64,
+    source_collateral_pubkey: Pubkey,
+    destination_collateral_pubkey: Pubkey,
+    deposit_reserve_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(source_collateral_pubkey, false),
+            AccountMeta::new(destination_collateral_pubkey, false),
+            AccountMeta::new_readonly(deposit_reserve_pubkey, false),
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+            AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: LendingInstruction::DepositCollateralAndAssertHealthy { collateral_amount }.pack(),
+    }
+}
+
+/// Creates a `SetReserveOracles` instruction, with the product and price pubkeys of each extra
+/// Pyth feed
+#[allow(clippy::too_many_arguments)]
+pub fn set_reserve_oracles(
+    program_id: Pubkey,
+    max_price_divergence_bps: u16,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+    pyth_product_pubkey: Pubkey,
+    pyth_price_pubkey: Pubkey,
+    switchboard_feed_pubkey: Pubkey,
+    extra_pyth_oracles: Vec<(Pubkey, Pubkey)>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(reserve_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(lending_market_owner_pubkey, true),
+        AccountMeta::new_readonly(pyth_product_pubkey, false),
+        AccountMeta::new_readonly(pyth_price_pubkey, false),
+        AccountMeta::new_readonly(switchboard_feed_pubkey, false),
+    ];
+    for (extra_pyth_product_pubkey, extra_pyth_price_pubkey) in extra_pyth_oracles {
+        accounts.push(AccountMeta::new_readonly(extra_pyth_product_pubkey, false));
+        accounts.push(AccountMeta::new_readonly(extra_pyth_price_pubkey, false));
+    }
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::SetReserveOracles {
+            max_price_divergence_bps,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `GetMaxLiquidation` instruction
+pub fn get_max_liquidation(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    repay_reserve_pubkey: Pubkey,
+    withdraw_reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(obligation_pubkey, false),
+            AccountMeta::new_readonly(repay_reserve_pubkey, false),
+            AccountMeta::new_readonly(withdraw_reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+        ],
+        data: LendingInstruction::GetMaxLiquidation.pack(),
+    }
+}
+
+/// Creates a `GetMarketReserves` instruction
+pub fn get_market_reserves(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+    reserve_pubkeys: Vec<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![AccountMeta::new_readonly(lending_market_pubkey, false)];
+    accounts.extend(
+        reserve_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new_readonly(pubkey, false)),
+    );
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::GetMarketReserves.pack(),
+    }
+}
+
+/// Creates a `SnapshotObligation` instruction
+pub fn snapshot_obligation(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(obligation_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+        ],
+        data: LendingInstruction::SnapshotObligation.pack(),
+    }
+}
+
+/// Creates a `DeployReserveLiquidity` instruction
+pub fn deploy_reserve_liquidity(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_supply_pubkey: Pubkey,
+    strategy_vault_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        &program_id,
+    );
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new(reserve_liquidity_supply_pubkey, false),
+            AccountMeta::new(strategy_vault_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+            AccountMeta::new_readonly(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: LendingInstruction::DeployReserveLiquidity { liquidity_amount }.pack(),
+    }
+}
+
+/// Creates a `RecallReserveLiquidity` instruction
+#[allow(clippy::too_many_arguments)]
+pub fn recall_reserve_liquidity(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    reserve_pubkey: Pubkey,
+    strategy_vault_pubkey: Pubkey,
+    reserve_liquidity_supply_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+    strategy_vault_authority_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new(strategy_vault_pubkey, false),
+            AccountMeta::new(reserve_liquidity_supply_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(strategy_vault_authority_pubkey, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: LendingInstruction::RecallReserveLiquidity { liquidity_amount }.pack(),
+    }
+}
+
+/// Creates a `RefreshReserves` instruction, refreshing each reserve given as its
+/// (reserve, pyth oracle, switchboard oracle, extra pyth oracles) pubkeys
+pub fn refresh_reserves(
+    program_id: Pubkey,
+    reserves: Vec<(Pubkey, Pubkey, Pubkey, Vec<Pubkey>)>,
+) -> Instruction {
+    let accounts = reserves
+        .into_iter()
+        .flat_map(
+            |(
+                reserve_pubkey,
+                pyth_oracle_pubkey,
+                switchboard_oracle_pubkey,
+                extra_pyth_oracle_pubkeys,
+            )| {
+                vec![
+                    AccountMeta::new(reserve_pubkey, false),
+                    AccountMeta::new_readonly(pyth_oracle_pubkey, false),
+                    AccountMeta::new_readonly(switchboard_oracle_pubkey, false),
+                ]
+                .into_iter()
+                .chain(
+                    extra_pyth_oracle_pubkeys
+                        .into_iter()
+                        .map(|pubkey| AccountMeta::new_readonly(pubkey, false)),
+                )
+            },
+        )
+        .collect();
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::RefreshReserves.pack(),
+    }
+}
+
+/// Creates a `RenounceLendingMarketOwner` instruction. `confirmation` must be the null pubkey.
+pub fn renounce_lending_market_owner(
+    program_id: Pubkey,
+    confirmation: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_owner, true),
+        ],
+        data: LendingInstruction::RenounceLendingMarketOwner { confirmation }.pack(),
+    }
+}
+
+/// Creates a `MigrateReserve` instruction
+pub fn migrate_reserve(program_id: Pubkey, reserve_pubkey: Pubkey, payer: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::MigrateReserve.pack(),
+    }
+}
+
+/// Creates a 'ViewObligation' instruction. `reserves` holds the obligation's deposit reserves
+/// followed by its borrow reserves, each with its pyth, switchboard and extra pyth oracles.
+pub fn view_obligation(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    reserves: Vec<(Pubkey, Pubkey, Pubkey, Vec<Pubkey>)>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(obligation_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+    ];
+    for (
+        reserve_pubkey,
+        pyth_oracle_pubkey,
+        switchboard_oracle_pubkey,
+        extra_pyth_oracle_pubkeys,
+    ) in reserves
+    {
+        accounts.push(AccountMeta::new_readonly(reserve_pubkey, false));
+        accounts.push(AccountMeta::new_readonly(pyth_oracle_pubkey, false));
+        accounts.push(AccountMeta::new_readonly(switchboard_oracle_pubkey, false));
+        accounts.extend(
+            extra_pyth_oracle_pubkeys
+                .into_iter()
+                .map(|pubkey| AccountMeta::new_readonly(pubkey, false)),
+        );
+    }
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::ViewObligation.pack(),
+    }
+}
@@ -96,6 +96,38 @@ impl Decimal {
             .ok_or(LendingError::MathOverflow)?;
         Ok(u64::try_from(ceil_val).map_err(|_| LendingError::MathOverflow)?)
     }
+
+    /// Calculates base^exp by squaring, in O(log exp) multiplications
+    pub fn try_pow(&self, mut exp: u64) -> Result<Decimal, ProgramError> {
+        let mut base = *self;
+        let mut ret = Self::one();
+
+        while exp > 0 {
+            if exp % 2 != 0 {
+                ret = ret.try_mul(base)?;
+            }
+            exp /= 2;
+            // the last square would go unused, and could overflow when the result doesn't
+            if exp > 0 {
+                base = base.try_mul(base)?;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Approximate the decimal as a float, for display only. Floats lose precision past 15
+    /// significant digits and must never feed back into on-chain math.
+    pub fn to_f64_lossy(&self) -> f64 {
+        let integer = self.0 / Self::wad();
+        let fraction = (self.0 % Self::wad()).as_u64();
+        let integer = integer
+            .0
+            .iter()
+            .rev()
+            .fold(0f64, |acc, word| acc * 2f64.powi(64) + *word as f64);
+        integer + fraction as f64 / WAD as f64
+    }
 }
 
 impl fmt::Display for Decimal {
@@ -161,7 +193,14 @@ impl TryDiv<u64> for Decimal {
 
 impl TryDiv<Rate> for Decimal {
     fn try_div(self, rhs: Rate) -> Result<Self, ProgramError> {
-        self.try_div(Self::from(rhs))
+        // rates share the decimal's WAD scale, so the scaled value divides directly
+        Ok(Self(
+            self.0
+                .checked_mul(Self::wad())
+                .ok_or(LendingError::MathOverflow)?
+                .checked_div(U192::from(rhs.to_scaled_val()))
+                .ok_or(LendingError::MathOverflow)?,
+        ))
     }
 }
 
@@ -189,7 +228,13 @@ impl TryMul<u64> for Decimal {
 
 impl TryMul<Rate> for Decimal {
     fn try_mul(self, rhs: Rate) -> Result<Self, ProgramError> {
-        self.try_mul(Self::from(rhs))
+        Ok(Self(
+            self.0
+                .checked_mul(U192::from(rhs.to_scaled_val()))
+                .ok_or(LendingError::MathOverflow)?
+                .checked_div(Self::wad())
+                .ok_or(LendingError::MathOverflow)?,
+        ))
     }
 }
 
@@ -208,6 +253,7 @@ impl TryMul<Decimal> for Decimal {
 #[cfg(test)]
 mod test {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_scaler() {
@@ -271,6 +317,57 @@ mod test {
         assert_eq!(val.try_ceil_u64().unwrap(), 2);
     }
 
+    #[test]
+    fn test_try_pow() {
+        let base = Decimal::from(3u64).try_div(Decimal::from(2u64)).unwrap();
+        assert_eq!(base.try_pow(0).unwrap(), Decimal::one());
+        assert_eq!(Decimal::zero().try_pow(0).unwrap(), Decimal::one());
+        assert_eq!(base.try_pow(1).unwrap(), base);
+
+        // matches multiplying it out while it's exact, 1.5^n has n decimal places
+        let mut expected = Decimal::one();
+        for exp in 1..=18 {
+            expected = expected.try_mul(base).unwrap();
+            assert_eq!(base.try_pow(exp).unwrap(), expected);
+        }
+
+        assert_eq!(Decimal::one().try_pow(u64::MAX).unwrap(), Decimal::one());
+        assert_eq!(
+            Decimal::from(2u64).try_pow(63).unwrap(),
+            Decimal::from(1u64 << 63)
+        );
+        assert_eq!(
+            Decimal::from(2u64).try_pow(1_000),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn test_to_f64_lossy() {
+        let assert_close = |decimal: Decimal, expected: f64| {
+            let actual = decimal.to_f64_lossy();
+            assert!(
+                (actual - expected).abs() <= expected.abs() * 1e-15,
+                "{} != {}",
+                actual,
+                expected
+            );
+        };
+
+        assert_close(Decimal::zero(), 0.0);
+        assert_close(Decimal::one(), 1.0);
+        assert_close(Decimal::from_scaled_val(1u128), 1e-18);
+        assert_close(Decimal::from_percent(25), 0.25);
+        assert_close(
+            Decimal::from(20u64).try_div(Decimal::from(3u64)).unwrap(),
+            20.0 / 3.0,
+        );
+        assert_close(Decimal::from(u64::MAX), u64::MAX as f64);
+        assert_close(Decimal::from_scaled_val(u128::MAX), u128::MAX as f64 / 1e18);
+        // the full u192 range doesn't panic
+        assert_close(Decimal(U192::MAX), 2f64.powi(192) / 1e18);
+    }
+
     #[test]
     fn test_display() {
         assert_eq!(Decimal::from(1u64).to_string(), "1.000000000000000000");
@@ -279,4 +376,17 @@ mod test {
             "0.000000000000000001"
         );
     }
+
+    proptest! {
+        #[test]
+        fn rate_ops_match_decimal_ops(
+            decimal in 0..=u128::MAX,
+            rate in 0..=u128::MAX,
+        ) {
+            let decimal = Decimal::from_scaled_val(decimal);
+            let rate = Rate::try_from(Decimal::from_scaled_val(rate))?;
+            prop_assert_eq!(decimal.try_mul(rate).ok(), decimal.try_mul(Decimal::from(rate)).ok());
+            prop_assert_eq!(decimal.try_div(rate).ok(), decimal.try_div(Decimal::from(rate)).ok());
+        }
+    }
 }
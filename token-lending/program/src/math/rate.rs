@@ -53,7 +53,17 @@ impl Rate {
 
     /// Create scaled decimal from percent value
     pub fn from_percent(percent: u8) -> Self {
-        Self(U128::from(percent as u64 * PERCENT_SCALER))
+        Self::from_percent_u16(percent as u16)
+    }
+
+    /// Create scaled decimal from a percent value wider than u8
+    pub fn from_percent_u16(percent: u16) -> Self {
+        Self(U128::from(percent) * U128::from(PERCENT_SCALER))
+    }
+
+    /// Create scaled decimal from basis points value
+    pub fn from_bps(bps: u16) -> Self {
+        Self(U128::from(bps) * U128::from(BPS_SCALER))
     }
 
     /// Return raw scaled value
@@ -67,26 +77,32 @@ impl Rate {
         Self(U128::from(scaled_val))
     }
 
-    /// Calculates base^exp
+    /// Calculates base^exp by squaring, in O(log exp) multiplications
     pub fn try_pow(&self, mut exp: u64) -> Result<Rate, ProgramError> {
         let mut base = *self;
-        let mut ret = if exp % 2 != 0 {
-            base
-        } else {
-            Rate(Self::wad())
-        };
+        let mut ret = Self::one();
 
         while exp > 0 {
-            exp /= 2;
-            base = base.try_mul(base)?;
-
             if exp % 2 != 0 {
                 ret = ret.try_mul(base)?;
             }
+            exp /= 2;
+            // the last square would go unused, and could overflow when the result doesn't
+            if exp > 0 {
+                base = base.try_mul(base)?;
+            }
         }
 
         Ok(ret)
     }
+
+    /// Approximate the rate as a float, for display only. Floats lose precision past 15
+    /// significant digits and must never feed back into on-chain math.
+    pub fn to_f64_lossy(&self) -> f64 {
+        let integer = (self.0 / Self::wad()).as_u128();
+        let fraction = (self.0 % Self::wad()).as_u64();
+        integer as f64 + fraction as f64 / WAD as f64
+    }
 }
 
 impl fmt::Display for Rate {
@@ -181,6 +197,30 @@ mod test {
     #[test]
     fn test_scaled_val() {
         assert_eq!(Rate::from_percent(50).to_scaled_val(), HALF_WAD as u128);
+        assert_eq!(Rate::from_bps(5_000), Rate::from_percent(50));
+    }
+
+    #[test]
+    fn test_percent_and_bps_scaling() {
+        assert_eq!(
+            Rate::from_bps(8_250),
+            Rate::from_percent(82).try_add(Rate::from_bps(50)).unwrap()
+        );
+        assert_eq!(
+            Rate::from_bps(8_250).to_scaled_val(),
+            825_000_000_000_000_000
+        );
+        assert_eq!(Rate::from_percent_u16(82), Rate::from_percent(82));
+        assert_eq!(Rate::from_percent_u16(255), Rate::from_percent(255));
+        assert_eq!(Rate::from_percent_u16(300), Rate::from_bps(30_000));
+        assert_eq!(
+            Rate::from_percent_u16(u16::MAX).to_scaled_val(),
+            u16::MAX as u128 * PERCENT_SCALER as u128
+        );
+        assert_eq!(
+            Rate::from_bps(u16::MAX).to_scaled_val(),
+            u16::MAX as u128 * BPS_SCALER as u128
+        );
     }
 
     #[test]
@@ -190,6 +230,45 @@ mod test {
             Rate::from_percent(200).try_pow(7).unwrap(),
             Decimal::from(128u64).try_into().unwrap()
         );
+        // 256 fits, squaring it once more wouldn't
+        assert_eq!(
+            Rate::from_percent(200).try_pow(8).unwrap(),
+            Decimal::from(256u64).try_into().unwrap()
+        );
+        assert_eq!(
+            Rate::from_percent(200).try_pow(9),
+            Err(LendingError::MathOverflow.into())
+        );
+
+        // compounding a slot's interest matches multiplying it out slot by slot
+        let slot_rate = Rate::one().try_add(Rate::from_scaled_val(1_000)).unwrap();
+        let mut expected = Rate::one();
+        for exp in 1..=5 {
+            expected = expected.try_mul(slot_rate).unwrap();
+            assert_eq!(slot_rate.try_pow(exp).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_to_f64_lossy() {
+        let assert_close = |rate: Rate, expected: f64| {
+            let actual = rate.to_f64_lossy();
+            assert!(
+                (actual - expected).abs() <= expected.abs() * 1e-15,
+                "{} != {}",
+                actual,
+                expected
+            );
+        };
+
+        assert_close(Rate::zero(), 0.0);
+        assert_close(Rate::one(), 1.0);
+        assert_close(Rate::from_bps(1), 0.0001);
+        assert_close(Rate::from_percent(5), 0.05);
+        assert_close(Rate::one().try_div(3u64).unwrap(), 1.0 / 3.0);
+        assert_close(Rate::from_scaled_val(1), 1e-18);
+        // the full u128 range doesn't panic
+        assert_close(Rate(U128::MAX), u128::MAX as f64 / 1e18);
     }
 
     #[test]
@@ -3,19 +3,45 @@ use crate::{
     self as solend_program,
     error::LendingError,
     math::{Decimal, TryDiv, TryMul},
+    state::MAX_EXTRA_PYTH_ORACLES,
+};
+use pyth_sdk_solana::{
+    self,
+    state::{PriceAccount, PriceStatus},
+    Price,
 };
-use pyth_sdk_solana;
 use solana_program::{
     account_info::AccountInfo, msg, program_error::ProgramError, sysvar::clock::Clock,
 };
-use std::{convert::TryInto, result::Result};
+use std::{
+    convert::{TryFrom, TryInto},
+    result::Result,
+};
+
+/// Maximum number of oracle accounts a single reserve refresh will read. Each feed costs a
+/// deserialization and validation pass, so this bounds refresh compute regardless of how many
+/// accounts the caller appends. A reserve reads its pyth and switchboard oracles along with its
+/// extra pyth oracles.
+pub const MAX_PRICE_SOURCES: usize = 2 + MAX_EXTRA_PYTH_ORACLES;
 
 pub fn get_pyth_price(
     pyth_price_info: &AccountInfo,
     clock: &Clock,
+    use_ema_price: bool,
+    max_price_age_slots: u64,
 ) -> Result<Decimal, ProgramError> {
+    get_pyth_price_and_confidence(pyth_price_info, clock, use_ema_price, max_price_age_slots)
+        .map(|(market_price, _)| market_price)
+}
+
+/// The pyth price along with its confidence interval, both in the quote currency
+pub fn get_pyth_price_and_confidence(
+    pyth_price_info: &AccountInfo,
+    clock: &Clock,
+    use_ema_price: bool,
+    max_price_age_slots: u64,
+) -> Result<(Decimal, Decimal), ProgramError> {
     const PYTH_CONFIDENCE_RATIO: u64 = 10;
-    const STALE_AFTER_SLOTS_ELAPSED: u64 = 240; // roughly 2 min
 
     if *pyth_price_info.key == solend_program::NULL_PUBKEY {
         return Err(LendingError::NullOracleConfig.into());
@@ -26,12 +52,20 @@ pub fn get_pyth_price(
         msg!("Couldn't load price feed from account info: {:?}", e);
         LendingError::InvalidOracleConfig
     })?;
-    let pyth_price = price_account
-        .get_price_no_older_than(clock, STALE_AFTER_SLOTS_ELAPSED)
-        .ok_or_else(|| {
-            msg!("Pyth oracle price is too stale!");
-            LendingError::InvalidOracleConfig
-        })?;
+    let ema_price = if use_ema_price {
+        get_pyth_ema_price(price_account, clock, max_price_age_slots)
+    } else {
+        None
+    };
+    let pyth_price = match ema_price {
+        Some(ema_price) => ema_price,
+        None => price_account
+            .get_price_no_older_than(clock, max_price_age_slots)
+            .ok_or_else(|| {
+                msg!("Pyth oracle price is too stale!");
+                LendingError::InvalidOracleConfig
+            })?,
+    };
 
     let price: u64 = pyth_price.price.try_into().map_err(|_| {
         msg!("Oracle price cannot be negative");
@@ -50,18 +84,22 @@ pub fn get_pyth_price(
         return Err(LendingError::InvalidOracleConfig.into());
     }
 
-    let market_price = if pyth_price.expo >= 0 {
-        let exponent = pyth_price
-            .expo
-            .try_into()
-            .map_err(|_| LendingError::MathOverflow)?;
+    let market_price = scale_pyth_value(price, pyth_price.expo)?;
+    let confidence = scale_pyth_value(pyth_price.conf, pyth_price.expo)?;
+
+    Ok((market_price, confidence))
+}
+
+/// A raw pyth value in the quote currency, given the price account's exponent
+fn scale_pyth_value(value: u64, expo: i32) -> Result<Decimal, ProgramError> {
+    if expo >= 0 {
+        let exponent = expo.try_into().map_err(|_| LendingError::MathOverflow)?;
         let zeros = 10u64
             .checked_pow(exponent)
             .ok_or(LendingError::MathOverflow)?;
-        Decimal::from(price).try_mul(zeros)?
+        Decimal::from(value).try_mul(zeros)
     } else {
-        let exponent = pyth_price
-            .expo
+        let exponent = expo
             .checked_abs()
             .ok_or(LendingError::MathOverflow)?
             .try_into()
@@ -69,19 +107,45 @@ pub fn get_pyth_price(
         let decimals = 10u64
             .checked_pow(exponent)
             .ok_or(LendingError::MathOverflow)?;
-        Decimal::from(price).try_div(decimals)?
+        Decimal::from(value).try_div(decimals)
+    }
+}
+
+/// Pyth's exponential moving average price, if it is positive and was updated within
+/// `slot_threshold` slots of the current slot
+fn get_pyth_ema_price(
+    price_account: &PriceAccount,
+    clock: &Clock,
+    slot_threshold: u64,
+) -> Option<Price> {
+    // the average moves with every trading aggregate, so it was last published along with the
+    // latest trading price
+    let (ema_pub_slot, publish_time) = if price_account.agg.status == PriceStatus::Trading {
+        (price_account.agg.pub_slot, price_account.timestamp)
+    } else {
+        (price_account.prev_slot, price_account.prev_timestamp)
     };
+    if price_account.ema_price.val <= 0 || clock.slot.saturating_sub(ema_pub_slot) > slot_threshold
+    {
+        return None;
+    }
 
-    Ok(market_price)
+    Some(Price {
+        price: price_account.ema_price.val,
+        conf: u64::try_from(price_account.ema_conf.val).ok()?,
+        expo: price_account.expo,
+        publish_time,
+    })
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::state::DEFAULT_MAX_PRICE_AGE_SLOTS;
     use bytemuck::bytes_of_mut;
     use proptest::prelude::*;
     use pyth_sdk_solana::state::{
-        AccountType, CorpAction, PriceAccount, PriceInfo, PriceStatus, PriceType, MAGIC, VERSION_2,
+        AccountType, CorpAction, PriceInfo, PriceType, Rational, MAGIC, VERSION_2,
     };
     use solana_program::pubkey::Pubkey;
 
@@ -89,6 +153,7 @@ mod test {
     struct PythPriceTestCase {
         price_account: PriceAccount,
         clock: Clock,
+        use_ema_price: bool,
         expected_result: Result<Decimal, ProgramError>,
     }
 
@@ -115,6 +180,7 @@ mod test {
                     slot: 4,
                     ..Clock::default()
                 },
+                use_ema_price: false,
                 // PythError::InvalidAccountData.
                 expected_result: Err(LendingError::InvalidOracleConfig.into()),
             }),
@@ -139,6 +205,7 @@ mod test {
                     slot: 4,
                     ..Clock::default()
                 },
+                use_ema_price: false,
                 expected_result: Err(LendingError::InvalidOracleConfig.into()),
             }),
             // case 4: failure. bad account type
@@ -162,6 +229,7 @@ mod test {
                     slot: 4,
                     ..Clock::default()
                 },
+                use_ema_price: false,
                 expected_result: Err(LendingError::InvalidOracleConfig.into()),
             }),
             // case 5: ignore. bad price type is fine. not testing this
@@ -187,6 +255,7 @@ mod test {
                     slot: 240,
                     ..Clock::default()
                 },
+                use_ema_price: false,
                 expected_result: Ok(Decimal::from(2000_u64))
             }),
             // case 7: success. most recent price has status == unknown, previous price not stale
@@ -214,6 +283,7 @@ mod test {
                     slot: 240,
                     ..Clock::default()
                 },
+                use_ema_price: false,
                 expected_result: Ok(Decimal::from(1900_u64))
             }),
             // case 8: failure. most recent price is stale
@@ -239,6 +309,7 @@ mod test {
                     slot: 242,
                     ..Clock::default()
                 },
+                use_ema_price: false,
                 expected_result: Err(LendingError::InvalidOracleConfig.into())
             }),
             // case 9: failure. most recent price has status == unknown and previous price is stale
@@ -266,6 +337,7 @@ mod test {
                     slot: 241,
                     ..Clock::default()
                 },
+                use_ema_price: false,
                 expected_result: Err(LendingError::InvalidOracleConfig.into())
             }),
             // case 10: failure. price is negative
@@ -290,6 +362,7 @@ mod test {
                     slot: 240,
                     ..Clock::default()
                 },
+                use_ema_price: false,
                 expected_result: Err(LendingError::InvalidOracleConfig.into())
             }),
             // case 11: failure. confidence interval is too wide
@@ -314,11 +387,189 @@ mod test {
                     slot: 240,
                     ..Clock::default()
                 },
+                use_ema_price: false,
+                expected_result: Err(LendingError::InvalidOracleConfig.into())
+            }),
+            // case 12: success. ema price is used instead of the diverging aggregate
+            Just(PythPriceTestCase {
+                price_account: PriceAccount {
+                    magic: MAGIC,
+                    ver: VERSION_2,
+                    atype: AccountType::Price as u32,
+                    ptype: PriceType::Price,
+                    expo: 1,
+                    timestamp: 0,
+                    agg: PriceInfo {
+                        price: 200,
+                        conf: 1,
+                        status: PriceStatus::Trading,
+                        corp_act: CorpAction::NoCorpAct,
+                        pub_slot: 0
+                    },
+                    ema_price: Rational {
+                        val: 150,
+                        numer: 150,
+                        denom: 1
+                    },
+                    ema_conf: Rational {
+                        val: 1,
+                        numer: 1,
+                        denom: 1
+                    },
+                    ..PriceAccount::default()
+                },
+                clock: Clock {
+                    slot: 240,
+                    ..Clock::default()
+                },
+                use_ema_price: true,
+                expected_result: Ok(Decimal::from(1500_u64))
+            }),
+            // case 13: success. a zero ema price falls back to the aggregate
+            Just(PythPriceTestCase {
+                price_account: PriceAccount {
+                    magic: MAGIC,
+                    ver: VERSION_2,
+                    atype: AccountType::Price as u32,
+                    ptype: PriceType::Price,
+                    expo: 1,
+                    timestamp: 0,
+                    agg: PriceInfo {
+                        price: 200,
+                        conf: 1,
+                        status: PriceStatus::Trading,
+                        corp_act: CorpAction::NoCorpAct,
+                        pub_slot: 0
+                    },
+                    ..PriceAccount::default()
+                },
+                clock: Clock {
+                    slot: 240,
+                    ..Clock::default()
+                },
+                use_ema_price: true,
+                expected_result: Ok(Decimal::from(2000_u64))
+            }),
+            // case 14: failure. the ema price is as stale as the aggregate it was published with
+            Just(PythPriceTestCase {
+                price_account: PriceAccount {
+                    magic: MAGIC,
+                    ver: VERSION_2,
+                    atype: AccountType::Price as u32,
+                    ptype: PriceType::Price,
+                    expo: 1,
+                    timestamp: 0,
+                    agg: PriceInfo {
+                        price: 200,
+                        conf: 1,
+                        status: PriceStatus::Trading,
+                        corp_act: CorpAction::NoCorpAct,
+                        pub_slot: 1
+                    },
+                    ema_price: Rational {
+                        val: 150,
+                        numer: 150,
+                        denom: 1
+                    },
+                    ema_conf: Rational {
+                        val: 1,
+                        numer: 1,
+                        denom: 1
+                    },
+                    ..PriceAccount::default()
+                },
+                clock: Clock {
+                    slot: 242,
+                    ..Clock::default()
+                },
+                use_ema_price: true,
+                expected_result: Err(LendingError::InvalidOracleConfig.into())
+            }),
+            // case 15: failure. ema confidence interval is too wide
+            Just(PythPriceTestCase {
+                price_account: PriceAccount {
+                    magic: MAGIC,
+                    ver: VERSION_2,
+                    atype: AccountType::Price as u32,
+                    ptype: PriceType::Price,
+                    expo: 1,
+                    timestamp: 0,
+                    agg: PriceInfo {
+                        price: 200,
+                        conf: 1,
+                        status: PriceStatus::Trading,
+                        corp_act: CorpAction::NoCorpAct,
+                        pub_slot: 0
+                    },
+                    ema_price: Rational {
+                        val: 150,
+                        numer: 150,
+                        denom: 1
+                    },
+                    ema_conf: Rational {
+                        val: 30,
+                        numer: 30,
+                        denom: 1
+                    },
+                    ..PriceAccount::default()
+                },
+                clock: Clock {
+                    slot: 240,
+                    ..Clock::default()
+                },
+                use_ema_price: true,
                 expected_result: Err(LendingError::InvalidOracleConfig.into())
             }),
         ]
     }
 
+    #[test]
+    fn test_pyth_price_confidence() {
+        let mut price_account = PriceAccount {
+            magic: MAGIC,
+            ver: VERSION_2,
+            atype: AccountType::Price as u32,
+            ptype: PriceType::Price,
+            expo: -2,
+            agg: PriceInfo {
+                price: 2_000,
+                conf: 150,
+                status: PriceStatus::Trading,
+                corp_act: CorpAction::NoCorpAct,
+                pub_slot: 240,
+            },
+            ..PriceAccount::default()
+        };
+        let mut lamports = 20;
+        let pubkey = Pubkey::new_unique();
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            bytes_of_mut(&mut price_account),
+            &pubkey,
+            false,
+            0,
+        );
+
+        assert_eq!(
+            get_pyth_price_and_confidence(
+                &account_info,
+                &Clock {
+                    slot: 240,
+                    ..Clock::default()
+                },
+                false,
+                DEFAULT_MAX_PRICE_AGE_SLOTS,
+            ),
+            Ok((
+                Decimal::from(20u64),
+                Decimal::from_scaled_val(1_500_000_000_000_000_000)
+            ))
+        );
+    }
+
     proptest! {
         #[test]
         fn test_pyth_price(mut test_case in pyth_price_cases()) {
@@ -336,7 +587,12 @@ mod test {
                 0,
             );
 
-            let result = get_pyth_price(&account_info, &test_case.clock);
+            let result = get_pyth_price(
+                &account_info,
+                &test_case.clock,
+                test_case.use_ema_price,
+                DEFAULT_MAX_PRICE_AGE_SLOTS,
+            );
             assert_eq!(
                 result,
                 test_case.expected_result,
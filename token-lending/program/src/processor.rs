@@ -5,12 +5,17 @@ use crate::{
     error::LendingError,
     instruction::LendingInstruction,
     math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub, WAD},
-    oracles::get_pyth_price,
+    oracles::{get_pyth_price_and_confidence, MAX_PRICE_SOURCES},
     state::{
-        CalculateBorrowResult, CalculateLiquidationResult, CalculateRepayResult,
-        InitLendingMarketParams, InitObligationParams, InitReserveParams, LendingMarket,
-        NewReserveCollateralParams, NewReserveLiquidityParams, Obligation, Reserve,
-        ReserveCollateral, ReserveConfig, ReserveLiquidity,
+        BorrowCheck, CalculateBorrowResult, CalculateLiquidationResult, CalculateRepayResult,
+        FeeCalculation, InitLendingMarketParams, InitObligationParams, InitReserveParams,
+        LendingMarket, LendingMarketConfigUpdate, MaxLiquidation, NewReserveCollateralParams,
+        NewReserveLiquidityParams, Obligation, ObligationSnapshot, ObligationSummary, Reserve,
+        ReserveCollateral, ReserveConfig, ReserveConfigSummary, ReserveLiquidity,
+        ReserveRefreshAccounts, ReserveType, LIQUIDATION_CLOSE_AMOUNT, MAX_INITIAL_COLLATERAL_RATE,
+        MAX_OBLIGATION_SUMMARIES, MAX_ORACLE_PRICE_DIVERGENCE_BPS, MAX_RESERVE_CONFIG_SUMMARIES,
+        MIN_INITIAL_COLLATERAL_RATE, MIN_RESERVE_VERSION, PROGRAM_VERSION, SLOTS_PER_SECOND,
+        UNINITIALIZED_VERSION,
     },
 };
 use num_traits::FromPrimitive;
@@ -20,11 +25,13 @@ use solana_program::{
     decode_error::DecodeError,
     entrypoint::ProgramResult,
     instruction::{get_stack_height, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT},
+    log::{sol_log, sol_log_data},
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data, MAX_RETURN_DATA},
     program_error::{PrintProgramError, ProgramError},
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
+    system_instruction, system_program,
     sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
     sysvar::{
         clock::{self, Clock},
@@ -32,8 +39,15 @@ use solana_program::{
         Sysvar,
     },
 };
-use spl_token::state::Mint;
-use std::{cmp::min, result::Result};
+use spl_token::state::{Account as Token, Mint};
+use std::{
+    cmp::min,
+    convert::TryFrom,
+    fmt::{self, Write},
+    iter::Peekable,
+    result::Result,
+    str,
+};
 use switchboard_program::{
     get_aggregator, get_aggregator_result, AggregatorState, RoundResult, SwitchboardAccountType,
 };
@@ -71,9 +85,16 @@ pub fn process_instruction(
         LendingInstruction::InitReserve {
             liquidity_amount,
             config,
+            collateral_exchange_rate_wads,
         } => {
             msg!("Instruction: Init Reserve");
-            process_init_reserve(program_id, liquidity_amount, config, accounts)
+            process_init_reserve(
+                program_id,
+                liquidity_amount,
+                config,
+                collateral_exchange_rate_wads,
+                accounts,
+            )
         }
         LendingInstruction::RefreshReserve => {
             msg!("Instruction: Refresh Reserve");
@@ -87,9 +108,9 @@ pub fn process_instruction(
             msg!("Instruction: Redeem Reserve Collateral");
             process_redeem_reserve_collateral(program_id, collateral_amount, accounts)
         }
-        LendingInstruction::InitObligation => {
+        LendingInstruction::InitObligation { tranche } => {
             msg!("Instruction: Init Obligation");
-            process_init_obligation(program_id, accounts)
+            process_init_obligation(program_id, tranche, accounts)
         }
         LendingInstruction::RefreshObligation => {
             msg!("Instruction: Refresh Obligation");
@@ -171,6 +192,113 @@ pub fn process_instruction(
                 accounts,
             )
         }
+        LendingInstruction::ViewReserve => {
+            msg!("Instruction: View Reserve");
+            process_view_reserve(program_id, accounts)
+        }
+        LendingInstruction::SetObligationWhitelisted { whitelisted } => {
+            msg!("Instruction: Set Obligation Whitelisted");
+            process_set_obligation_whitelisted(program_id, whitelisted, accounts)
+        }
+        LendingInstruction::RepayAndWithdraw {
+            liquidity_amount,
+            collateral_amount,
+        } => {
+            msg!("Instruction: Repay And Withdraw");
+            process_repay_and_withdraw(program_id, liquidity_amount, collateral_amount, accounts)
+        }
+        LendingInstruction::UpdateLendingMarket { update } => {
+            msg!("Instruction: Update Lending Market");
+            process_update_lending_market(program_id, update, accounts)
+        }
+        LendingInstruction::SetObligationCollateralProtection { protected_amount } => {
+            msg!("Instruction: Set Obligation Collateral Protection");
+            process_set_obligation_collateral_protection(program_id, protected_amount, accounts)
+        }
+        LendingInstruction::BatchObligationSummary => {
+            msg!("Instruction: Batch Obligation Summary");
+            process_batch_obligation_summary(program_id, accounts)
+        }
+        LendingInstruction::QueueReserveConfigUpdate {
+            loan_to_value_ratio,
+            liquidation_threshold,
+            protocol_take_rate,
+        } => {
+            msg!("Instruction: Queue Reserve Config Update");
+            process_queue_reserve_config_update(
+                program_id,
+                loan_to_value_ratio,
+                liquidation_threshold,
+                protocol_take_rate,
+                accounts,
+            )
+        }
+        LendingInstruction::ApplyReserveConfigUpdate => {
+            msg!("Instruction: Apply Reserve Config Update");
+            process_apply_reserve_config_update(program_id, accounts)
+        }
+        LendingInstruction::SetReserveCollateralFloorPrice { floor_price_wads } => {
+            msg!("Instruction: Set Reserve Collateral Floor Price");
+            process_set_reserve_collateral_floor_price(program_id, floor_price_wads, accounts)
+        }
+        LendingInstruction::CanBorrow { liquidity_amount } => {
+            msg!("Instruction: Can Borrow");
+            process_can_borrow(program_id, liquidity_amount, accounts)
+        }
+        LendingInstruction::SetObligationDelegate { delegate } => {
+            msg!("Instruction: Set Obligation Delegate");
+            process_set_obligation_delegate(program_id, delegate, accounts)
+        }
+        LendingInstruction::GetRefreshAccounts => {
+            msg!("Instruction: Get Refresh Accounts");
+            process_get_refresh_accounts(program_id, accounts)
+        }
+        LendingInstruction::DepositCollateralAndAssertHealthy { collateral_amount } => {
+            msg!("Instruction: Deposit Collateral And Assert Healthy");
+            process_deposit_collateral_and_assert_healthy(program_id, collateral_amount, accounts)
+        }
+        LendingInstruction::SetReserveOracles {
+            max_price_divergence_bps,
+        } => {
+            msg!("Instruction: Set Reserve Oracles");
+            process_set_reserve_oracles(program_id, max_price_divergence_bps, accounts)
+        }
+        LendingInstruction::GetMaxLiquidation => {
+            msg!("Instruction: Get Max Liquidation");
+            process_get_max_liquidation(program_id, accounts)
+        }
+        LendingInstruction::GetMarketReserves => {
+            msg!("Instruction: Get Market Reserves");
+            process_get_market_reserves(program_id, accounts)
+        }
+        LendingInstruction::SnapshotObligation => {
+            msg!("Instruction: Snapshot Obligation");
+            process_snapshot_obligation(program_id, accounts)
+        }
+        LendingInstruction::DeployReserveLiquidity { liquidity_amount } => {
+            msg!("Instruction: Deploy Reserve Liquidity");
+            process_deploy_reserve_liquidity(program_id, liquidity_amount, accounts)
+        }
+        LendingInstruction::RecallReserveLiquidity { liquidity_amount } => {
+            msg!("Instruction: Recall Reserve Liquidity");
+            process_recall_reserve_liquidity(program_id, liquidity_amount, accounts)
+        }
+        LendingInstruction::RefreshReserves => {
+            msg!("Instruction: Refresh Reserves");
+            process_refresh_reserves(program_id, accounts)
+        }
+        LendingInstruction::RenounceLendingMarketOwner { confirmation } => {
+            msg!("Instruction: Renounce Lending Market Owner");
+            process_renounce_lending_market_owner(program_id, confirmation, accounts)
+        }
+        LendingInstruction::MigrateReserve => {
+            msg!("Instruction: Migrate Reserve");
+            process_migrate_reserve(program_id, accounts)
+        }
+        LendingInstruction::ViewObligation => {
+            msg!("Instruction: View Obligation");
+            process_view_obligation(program_id, accounts)
+        }
     }
 }
 
@@ -222,18 +350,213 @@ fn process_set_lending_market_owner(
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
-    if &lending_market.owner != lending_market_owner_info.key {
-        msg!("Lending market owner does not match the lending market owner provided");
-        return Err(LendingError::InvalidMarketOwner.into());
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+
+    lending_market.owner = new_owner;
+    LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_renounce_lending_market_owner(
+    program_id: &Pubkey,
+    confirmation: Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if confirmation != solend_program::NULL_PUBKEY {
+        msg!("The null pubkey must be passed to confirm renouncing the lending market owner");
+        return Err(LendingError::InvalidConfig.into());
     }
-    if !lending_market_owner_info.is_signer {
-        msg!("Lending market owner provided must be a signer");
-        return Err(LendingError::InvalidSigner.into());
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
     }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
 
-    lending_market.owner = new_owner;
+    lending_market.owner = solend_program::NULL_PUBKEY;
+    LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_update_lending_market(
+    program_id: &Pubkey,
+    update: LendingMarketConfigUpdate,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+
+    let mut config = lending_market.config;
+    config.apply(&update);
+    if config.liquidation_bonus_clawback_pct > 100 {
+        msg!("Liquidation bonus clawback percent must be in range [0, 100]");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.borrow_health_buffer_bps > 10_000 {
+        msg!("Borrow health buffer must be in range [0, 10000] basis points");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.min_collateral_ltv_pct >= 100 {
+        msg!("Minimum collateral loan to value ratio must be in range [0, 100)");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.post_liquidation_target_ltv >= 100 {
+        msg!("Post liquidation target loan to value ratio must be in range [0, 100)");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    lending_market.config = config;
     LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
 
+    // reserves keep a copy of the confidence pricing policy for refreshes without the market
+    for reserve_info in account_info_iter {
+        if reserve_info.owner != program_id {
+            msg!("Reserve provided is not owned by the lending program");
+            return Err(LendingError::InvalidAccountOwner.into());
+        }
+        let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+        if &reserve.lending_market != lending_market_info.key {
+            msg!("Reserve lending market does not match the lending market provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        reserve.use_price_confidence = config.use_price_confidence;
+        Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    }
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_obligation_whitelisted(
+    program_id: &Pubkey,
+    whitelisted: bool,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    obligation.whitelisted = whitelisted;
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_obligation_collateral_protection(
+    program_id: &Pubkey,
+    protected_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let deposit_reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let collateral_index = obligation
+        .find_collateral_in_deposits(*deposit_reserve_info.key)?
+        .1;
+    obligation.deposits[collateral_index].protect(protected_amount)?;
+    // allowed borrow value is out of date until the next refresh
+    obligation.last_update.mark_stale();
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_obligation_delegate(
+    program_id: &Pubkey,
+    delegate: Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    obligation.delegate = delegate;
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
     Ok(())
 }
 
@@ -241,13 +564,21 @@ fn process_init_reserve(
     program_id: &Pubkey,
     liquidity_amount: u64,
     config: ReserveConfig,
+    collateral_exchange_rate_wads: u64,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     if liquidity_amount == 0 {
         msg!("Reserve must be initialized with liquidity");
         return Err(LendingError::InvalidAmount.into());
     }
-    validate_reserve_config(config)?;
+    if collateral_exchange_rate_wads != 0
+        && !(MIN_INITIAL_COLLATERAL_RATE..=MAX_INITIAL_COLLATERAL_RATE)
+            .contains(&collateral_exchange_rate_wads)
+    {
+        msg!("Initial collateral exchange rate is out of bounds");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    config.validate()?;
     let account_info_iter = &mut accounts.iter().peekable();
     let source_liquidity_info = next_account_info(account_info_iter)?;
     let destination_collateral_info = next_account_info(account_info_iter)?;
@@ -289,6 +620,12 @@ fn process_init_reserve(
         msg!("Reserve liquidity supply cannot be used as the source liquidity provided");
         return Err(LendingError::InvalidAccountInput.into());
     }
+    // vaults must be fresh accounts so that reserves for the same liquidity mint in different
+    // markets can never share one, each market's vaults belonging to its own derived authority
+    assert_uninitialized::<Token>(reserve_liquidity_supply_info)?;
+    assert_uninitialized::<Token>(reserve_liquidity_fee_receiver_info)?;
+    assert_uninitialized::<Mint>(reserve_collateral_mint_info)?;
+    assert_uninitialized::<Token>(reserve_collateral_supply_info)?;
 
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -303,14 +640,8 @@ fn process_init_reserve(
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
     }
-    if &lending_market.owner != lending_market_owner_info.key {
-        msg!("Lending market owner does not match the lending market owner provided");
-        return Err(LendingError::InvalidMarketOwner.into());
-    }
-    if !lending_market_owner_info.is_signer {
-        msg!("Lending market owner provided must be a signer");
-        return Err(LendingError::InvalidSigner.into());
-    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+    validate_collateral_ltv(&config, &lending_market)?;
     if *switchboard_feed_info.key == solend_program::NULL_PUBKEY
         && (*pyth_price_info.key == solend_program::NULL_PUBKEY
             || *pyth_product_info.key == solend_program::NULL_PUBKEY)
@@ -321,7 +652,16 @@ fn process_init_reserve(
     validate_pyth_keys(&lending_market, pyth_product_info, pyth_price_info)?;
     validate_switchboard_keys(&lending_market, switchboard_feed_info)?;
 
-    let market_price = get_price(Some(switchboard_feed_info), pyth_price_info, clock)?;
+    let market_price = match config.fixed_market_price() {
+        Some(fixed_price) => fixed_price,
+        None => get_price(
+            Some(switchboard_feed_info),
+            pyth_price_info,
+            &[],
+            clock,
+            &config,
+        )?,
+    };
 
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
@@ -359,9 +699,16 @@ fn process_init_reserve(
         }),
         config,
     });
+    reserve.use_price_confidence = lending_market.config.use_price_confidence;
 
-    let collateral_amount = reserve.deposit_liquidity(liquidity_amount)?;
-    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    let collateral_amount = if collateral_exchange_rate_wads == 0 {
+        reserve.deposit_liquidity(liquidity_amount)?
+    } else {
+        reserve.deposit_initial_liquidity(
+            liquidity_amount,
+            Rate::from_scaled_val(collateral_exchange_rate_wads),
+        )?
+    };
 
     spl_token_init_account(TokenInitializeAccountParams {
         account: reserve_liquidity_supply_info.clone(),
@@ -421,62 +768,703 @@ fn process_init_reserve(
         token_program: token_program_id.clone(),
     })?;
 
+    let reserve_collateral_mint = unpack_mint(&reserve_collateral_mint_info.data.borrow())?;
+    reserve
+        .collateral
+        .validate_mint_supply(reserve_collateral_mint.supply)?;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
     Ok(())
 }
 
 fn process_refresh_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter().peekable();
     let reserve_info = next_account_info(account_info_iter)?;
-    let pyth_price_info = next_account_info(account_info_iter)?;
-    // set switchboard to a placeholder account info
-    let mut switchboard_feed_info = None;
-    // if the next account info exists and is not the clock set it to be switchboard
-    let switchboard_peek = account_info_iter.peek().map(|a| a.key);
-    if switchboard_peek.is_some() && switchboard_peek != Some(&clock::ID) {
-        switchboard_feed_info = Some(next_account_info(account_info_iter)?);
-    }
+    let (pyth_price_info, switchboard_feed_info, extra_pyth_price_infos) =
+        next_price_sources(account_info_iter)?;
     let clock = &Clock::get()?;
-    if account_info_iter.peek().map(|a| a.key) == Some(&clock::ID) {
-        next_account_info(account_info_iter)?;
-    }
     _refresh_reserve(
         program_id,
         reserve_info,
         pyth_price_info,
         switchboard_feed_info,
+        &extra_pyth_price_infos,
         clock,
     )
 }
 
-fn _refresh_reserve<'a>(
-    program_id: &Pubkey,
-    reserve_info: &AccountInfo<'a>,
-    pyth_price_info: &AccountInfo<'a>,
-    switchboard_feed_info: Option<&AccountInfo<'a>>,
-    clock: &Clock,
-) -> ProgramResult {
-    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
-    if reserve_info.owner != program_id {
-        msg!("Reserve provided is not owned by the lending program");
-        return Err(LendingError::InvalidAccountOwner.into());
-    }
-    if &reserve.liquidity.pyth_oracle_pubkey != pyth_price_info.key {
-        msg!("Reserve liquidity pyth oracle does not match the reserve liquidity pyth oracle provided");
+fn process_refresh_reserves(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    if accounts.is_empty() {
+        msg!("At least one reserve must be provided");
         return Err(LendingError::InvalidAccountInput.into());
     }
-    // the first check is to allow for the only passing in pyth case
-    // TODO maybe change this to is_some_and later
-    if switchboard_feed_info.is_some()
-        && &reserve.liquidity.switchboard_oracle_pubkey != switchboard_feed_info.unwrap().key
-    {
-        msg!("Reserve liquidity switchboard oracle does not match the reserve liquidity switchboard oracle provided");
-        return Err(LendingError::InvalidOracleConfig.into());
-    }
-
-    reserve.liquidity.market_price = get_price(switchboard_feed_info, pyth_price_info, clock)?;
+    let account_info_iter = &mut accounts.iter();
+    let clock = &Clock::get()?;
+
+    let mut index = 0;
+    while let Some(reserve_info) = account_info_iter.next() {
+        // each reserve is followed by as many extra pyth oracle accounts as it has configured
+        let extra_pyth_oracle_count = Reserve::unpack(&reserve_info.data.borrow())?
+            .liquidity
+            .extra_pyth_oracles()
+            .len();
+        let pyth_price_info = next_account_info(account_info_iter)?;
+        let switchboard_feed_info = next_account_info(account_info_iter)?;
+        let extra_pyth_price_infos = account_info_iter
+            .by_ref()
+            .take(extra_pyth_oracle_count)
+            .collect::<Vec<_>>();
+        if extra_pyth_price_infos.len() != extra_pyth_oracle_count {
+            msg!(
+                "Reserve {} has {} extra pyth oracles, got {} accounts for them",
+                index,
+                extra_pyth_oracle_count,
+                extra_pyth_price_infos.len()
+            );
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        if let Err(err) = _refresh_reserve(
+            program_id,
+            reserve_info,
+            Some(pyth_price_info),
+            Some(switchboard_feed_info),
+            &extra_pyth_price_infos,
+            clock,
+        ) {
+            msg!("Reserve {} could not be refreshed", index);
+            return Err(err);
+        }
+        index += 1;
+    }
+
+    Ok(())
+}
+
+fn process_view_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter().peekable();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let (pyth_price_info, switchboard_feed_info, extra_pyth_price_infos) =
+        next_price_sources(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let reserve = _refreshed_reserve(
+        program_id,
+        reserve_info,
+        pyth_price_info,
+        switchboard_feed_info,
+        &extra_pyth_price_infos,
+        clock,
+    )?;
+
+    let mut data = [0; Reserve::LEN];
+    Reserve::pack(reserve, &mut data)?;
+    set_return_data(&data);
+
+    Ok(())
+}
+
+fn process_can_borrow(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if liquidity_amount == 0 || liquidity_amount == u64::MAX {
+        msg!("Liquidity amount provided must be a specific non-zero amount");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let borrow_reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+
+    let borrow_reserve = Reserve::unpack(&borrow_reserve_info.data.borrow())?;
+    if borrow_reserve_info.owner != program_id {
+        msg!("Borrow reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if borrow_reserve.lending_market != obligation.lending_market {
+        msg!("Borrow reserve lending market does not match the obligation lending market");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if borrow_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Borrow reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let error_code = match validate_borrow(
+        &lending_market,
+        borrow_reserve_info.key,
+        &borrow_reserve,
+        &obligation,
+        liquidity_amount,
+    ) {
+        Ok(_) => 0,
+        Err(ProgramError::Custom(error_code)) => error_code,
+        Err(err) => return Err(err),
+    };
+
+    // fees too large for the amount make the borrow itself fail
+    let borrow_fee = borrow_reserve
+        .config
+        .fees
+        .calculate_borrow_fees(liquidity_amount.into(), FeeCalculation::Exclusive)
+        .map(|(borrow_fee, _host_fee)| borrow_fee)
+        .unwrap_or_default();
+    let borrow_amount = Decimal::from(liquidity_amount).try_add(borrow_fee.into())?;
+    let borrowed_value = obligation.borrowed_value.try_add(
+        borrow_reserve
+            .liquidity
+            .market_value_at(borrow_amount, borrow_reserve.liquidity.market_price)?,
+    )?;
+    let loan_to_value = if obligation.deposited_value == Decimal::zero() {
+        Decimal::zero()
+    } else {
+        borrowed_value.try_div(obligation.deposited_value)?
+    };
+
+    let mut data = [0; BorrowCheck::LEN];
+    BorrowCheck {
+        can_borrow: error_code == 0,
+        loan_to_value,
+        error_code,
+    }
+    .pack_into_slice(&mut data);
+    set_return_data(&data);
+
+    Ok(())
+}
+
+fn process_batch_obligation_summary(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if accounts.is_empty() || accounts.len() > MAX_OBLIGATION_SUMMARIES {
+        msg!(
+            "Between 1 and {} obligations must be provided",
+            MAX_OBLIGATION_SUMMARIES
+        );
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    let clock = &Clock::get()?;
+
+    let mut data = vec![0; accounts.len() * ObligationSummary::LEN];
+    for (obligation_info, summary_data) in accounts
+        .iter()
+        .zip(data.chunks_exact_mut(ObligationSummary::LEN))
+    {
+        if obligation_info.owner != program_id {
+            msg!("Obligation provided is not owned by the lending program");
+            return Err(LendingError::InvalidAccountOwner.into());
+        }
+        let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+        if obligation.last_update.is_stale(clock.slot)? {
+            msg!("Obligation is stale and must be refreshed in the current slot");
+            return Err(LendingError::ObligationStale.into());
+        }
+        ObligationSummary::pack(
+            ObligationSummary::new(*obligation_info.key, &obligation),
+            summary_data,
+        )?;
+    }
+    set_return_data(&data);
+
+    Ok(())
+}
+
+fn process_get_refresh_accounts(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let reserve_pubkeys: Vec<Pubkey> = obligation
+        .deposits
+        .iter()
+        .map(|collateral| collateral.deposit_reserve)
+        .chain(
+            obligation
+                .borrows
+                .iter()
+                .map(|liquidity| liquidity.borrow_reserve),
+        )
+        .collect();
+    if account_info_iter.len() != reserve_pubkeys.len() {
+        msg!(
+            "Obligation has {} deposit and borrow reserves, got {} reserve accounts",
+            reserve_pubkeys.len(),
+            account_info_iter.len()
+        );
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let mut data = vec![];
+    for (index, (reserve_info, reserve_pubkey)) in
+        account_info_iter.zip(reserve_pubkeys.iter()).enumerate()
+    {
+        if reserve_info.owner != program_id {
+            msg!(
+                "Reserve account {} provided is not owned by the lending program",
+                index
+            );
+            return Err(LendingError::InvalidAccountOwner.into());
+        }
+        if reserve_info.key != reserve_pubkey {
+            msg!(
+                "Reserve account {} provided does not match the obligation's reserve",
+                index
+            );
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+        ReserveRefreshAccounts {
+            reserve: *reserve_pubkey,
+            pyth_oracle: reserve.liquidity.pyth_oracle_pubkey,
+            switchboard_oracle: reserve.liquidity.switchboard_oracle_pubkey,
+            extra_pyth_oracles: reserve.liquidity.extra_pyth_oracles(),
+        }
+        .pack_into_vec(&mut data);
+    }
+    if data.len() > MAX_RETURN_DATA {
+        msg!(
+            "Refresh accounts of the obligation's reserves take {} bytes, more than fit in return data",
+            data.len()
+        );
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    set_return_data(&data);
+
+    Ok(())
+}
+
+fn process_get_market_reserves(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if account_info_iter.len() == 0 || account_info_iter.len() > MAX_RESERVE_CONFIG_SUMMARIES {
+        msg!(
+            "Between 1 and {} reserves must be provided",
+            MAX_RESERVE_CONFIG_SUMMARIES
+        );
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let mut data = vec![0; account_info_iter.len() * ReserveConfigSummary::LEN];
+    for (index, (reserve_info, summary_data)) in account_info_iter
+        .zip(data.chunks_exact_mut(ReserveConfigSummary::LEN))
+        .enumerate()
+    {
+        if reserve_info.owner != program_id {
+            msg!(
+                "Reserve account {} provided is not owned by the lending program",
+                index
+            );
+            return Err(LendingError::InvalidAccountOwner.into());
+        }
+        let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+        if &reserve.lending_market != lending_market_info.key {
+            msg!(
+                "Reserve account {} lending market does not match the lending market provided",
+                index
+            );
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        ReserveConfigSummary::pack(
+            ReserveConfigSummary::new(*reserve_info.key, &reserve),
+            summary_data,
+        )?;
+    }
+    set_return_data(&data);
+
+    Ok(())
+}
+
+fn process_snapshot_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+
+    let mut data = vec![0; ObligationSnapshot::LEN];
+    ObligationSnapshot::pack(
+        ObligationSnapshot::new(*obligation_info.key, obligation, clock.slot),
+        &mut data,
+    )?;
+    sol_log_data(&[&data]);
+
+    Ok(())
+}
+
+fn process_migrate_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if reserve_info.data_len() >= Reserve::LEN {
+        msg!("Reserve account already has the current reserve layout");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if !payer_info.is_signer {
+        msg!("Payer provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if system_program_info.key != &system_program::id() {
+        msg!("System program provided is not the system program");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+
+    let minimum_balance = Rent::get()?.minimum_balance(Reserve::LEN);
+    let lamports = reserve_info.lamports();
+    if lamports < minimum_balance {
+        invoke(
+            &system_instruction::transfer(
+                payer_info.key,
+                reserve_info.key,
+                minimum_balance - lamports,
+            ),
+            &[
+                payer_info.clone(),
+                reserve_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+
+    reserve_info.realloc(Reserve::LEN, true)?;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_get_max_liquidation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let repay_reserve_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let repay_reserve = Reserve::unpack(&repay_reserve_info.data.borrow())?;
+    if repay_reserve_info.owner != program_id {
+        msg!("Repay reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &repay_reserve.lending_market != lending_market_info.key {
+        msg!("Repay reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if repay_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Repay reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
+    }
+
+    let withdraw_reserve = Reserve::unpack(&withdraw_reserve_info.data.borrow())?;
+    if withdraw_reserve_info.owner != program_id {
+        msg!("Withdraw reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &withdraw_reserve.lending_market != lending_market_info.key {
+        msg!("Withdraw reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if withdraw_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Withdraw reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
+    }
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+
+    // the obligation is only read, any bonus deferred along the way is never packed
+    let (
+        CalculateLiquidationResult {
+            repay_amount,
+            withdraw_amount,
+            ..
+        },
+        _,
+        _,
+    ) = calculate_obligation_liquidation(
+        u64::MAX,
+        &lending_market,
+        &repay_reserve,
+        repay_reserve_info.key,
+        &withdraw_reserve,
+        withdraw_reserve_info.key,
+        &mut obligation,
+        clock,
+    )?;
+
+    let mut data = [0; MaxLiquidation::LEN];
+    MaxLiquidation {
+        repay_amount,
+        withdraw_amount,
+    }
+    .pack_into_slice(&mut data);
+    set_return_data(&data);
+
+    Ok(())
+}
+
+/// Reads the pyth and optional switchboard accounts passed to a reserve refresh, skipping the
+/// optional clock sysvar.
+#[allow(clippy::type_complexity)]
+fn next_price_sources<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    account_info_iter: &mut Peekable<I>,
+) -> Result<
+    (
+        Option<&'a AccountInfo<'b>>,
+        Option<&'a AccountInfo<'b>>,
+        Vec<&'a AccountInfo<'b>>,
+    ),
+    ProgramError,
+> {
+    // reserves with a fixed price can be refreshed without any oracle accounts
+    let pyth_peek = account_info_iter.peek().map(|a| a.key);
+    if pyth_peek.is_none() || pyth_peek == Some(&clock::ID) {
+        account_info_iter.next();
+        return Ok((None, None, vec![]));
+    }
+    let pyth_price_info = next_account_info(account_info_iter)?;
+    // set switchboard to a placeholder account info
+    let mut switchboard_feed_info = None;
+    // if the next account info exists and is not the clock set it to be switchboard
+    let switchboard_peek = account_info_iter.peek().map(|a| a.key);
+    if switchboard_peek.is_some() && switchboard_peek != Some(&clock::ID) {
+        switchboard_feed_info = Some(next_account_info(account_info_iter)?);
+    }
+    if account_info_iter.peek().map(|a| a.key) == Some(&clock::ID) {
+        next_account_info(account_info_iter)?;
+    }
+    // anything left over is an extra pyth feed, bounded so that refreshes can't be made to read
+    // arbitrarily many
+    let extra_pyth_price_infos: Vec<_> =
+        account_info_iter.filter(|a| a.key != &clock::ID).collect();
+    let price_sources = 1 + switchboard_feed_info.iter().count() + extra_pyth_price_infos.len();
+    if price_sources > MAX_PRICE_SOURCES {
+        msg!(
+            "At most {} price sources can be supplied to refresh a reserve, got {}",
+            MAX_PRICE_SOURCES,
+            price_sources
+        );
+        return Err(LendingError::TooManyPriceSources.into());
+    }
+
+    Ok((
+        Some(pyth_price_info),
+        switchboard_feed_info,
+        extra_pyth_price_infos,
+    ))
+}
+
+fn _refresh_reserve<'a>(
+    program_id: &Pubkey,
+    reserve_info: &AccountInfo<'a>,
+    pyth_price_info: Option<&AccountInfo<'a>>,
+    switchboard_feed_info: Option<&AccountInfo<'a>>,
+    extra_pyth_price_infos: &[&AccountInfo<'a>],
+    clock: &Clock,
+) -> ProgramResult {
+    let reserve = _refreshed_reserve(
+        program_id,
+        reserve_info,
+        pyth_price_info,
+        switchboard_feed_info,
+        extra_pyth_price_infos,
+        clock,
+    )?;
     Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
 
-    _refresh_reserve_interest(program_id, reserve_info, clock)
+    Ok(())
+}
+
+/// Returns the reserve with its market price updated and interest accrued up to the current
+/// slot, without writing it back to the account.
+fn _refreshed_reserve<'a>(
+    program_id: &Pubkey,
+    reserve_info: &AccountInfo<'a>,
+    pyth_price_info: Option<&AccountInfo<'a>>,
+    switchboard_feed_info: Option<&AccountInfo<'a>>,
+    extra_pyth_price_infos: &[&AccountInfo<'a>],
+    clock: &Clock,
+) -> Result<Reserve, ProgramError> {
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if pyth_price_info.is_some()
+        && &reserve.liquidity.pyth_oracle_pubkey != pyth_price_info.unwrap().key
+    {
+        msg!("Reserve liquidity pyth oracle does not match the reserve liquidity pyth oracle provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    // the first check is to allow for the only passing in pyth case
+    // TODO maybe change this to is_some_and later
+    if switchboard_feed_info.is_some()
+        && &reserve.liquidity.switchboard_oracle_pubkey != switchboard_feed_info.unwrap().key
+    {
+        msg!("Reserve liquidity switchboard oracle does not match the reserve liquidity switchboard oracle provided");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    // leaving out an extra feed would let a single feed set the price again
+    if pyth_price_info.is_some()
+        && !extra_pyth_price_infos
+            .iter()
+            .map(|info| *info.key)
+            .eq(reserve.liquidity.extra_pyth_oracles())
+    {
+        msg!("Reserve liquidity extra pyth oracles do not match the extra pyth oracles provided");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    // already refreshed in this slot and untouched since, nothing to read or accrue
+    if !reserve.last_update.is_stale(clock.slot)? {
+        return Ok(reserve);
+    }
+
+    if let Some(fixed_price) = reserve.config.fixed_market_price() {
+        msg!(
+            "WARNING: reserve liquidity is valued at its fixed price of {}, not its oracles",
+            fixed_price
+        );
+        reserve.update_fixed_market_price(fixed_price, clock.slot);
+        reserve.accrue_interest(clock.slot)?;
+        reserve.last_update.update_slot(clock.slot);
+        return Ok(reserve);
+    }
+    let pyth_price_info = pyth_price_info.ok_or_else(|| {
+        msg!("Reserve liquidity oracle accounts must be provided unless it has a fixed price");
+        ProgramError::NotEnoughAccountKeys
+    })?;
+
+    let mut oracle_unavailable = false;
+    let mut price_unusable = false;
+    match get_price_and_confidence(
+        switchboard_feed_info,
+        pyth_price_info,
+        extra_pyth_price_infos,
+        clock,
+        &reserve.config,
+    ) {
+        Ok((market_price, confidence)) => {
+            if market_price == Decimal::zero() && !reserve.config.allow_zero_price {
+                msg!("Oracle price must be positive");
+                return Err(LendingError::InvalidOracleConfig.into());
+            }
+            reserve.update_market_price(market_price, clock.slot)?;
+            reserve.liquidity.market_price_confidence = confidence;
+        }
+        // through a brief outage the cached price is kept, and valued more conservatively the
+        // longer it goes unconfirmed
+        Err(err) => {
+            oracle_unavailable = true;
+            let stale_slots = clock
+                .slot
+                .saturating_sub(reserve.liquidity.oracle_price_slot);
+            if reserve.config.max_stale_price_slots != 0
+                && reserve.liquidity.oracle_price_slot != 0
+                && stale_slots <= reserve.config.max_stale_price_slots
+            {
+                msg!(
+                    "Oracle price unavailable, using the market price read {} slots ago",
+                    stale_slots
+                );
+            } else if reserve.config.pause_interest_on_oracle_error {
+                msg!("Oracle price unavailable, the reserve stays stale until its oracles recover");
+                price_unusable = true;
+            } else {
+                return Err(err);
+            }
+        }
+    }
+
+    // with the pause enabled, the slots since a refresh that found the oracles unavailable accrue
+    // no interest, whether the next one finds them still down or recovered
+    reserve.accrue_interest(clock.slot)?;
+    if reserve.config.pause_interest_on_oracle_error {
+        if oracle_unavailable && !reserve.liquidity.interest_paused {
+            msg!("Interest accrual is paused until the oracles recover");
+        }
+        reserve.liquidity.interest_paused = oracle_unavailable;
+    }
+    reserve.last_update.update_slot(clock.slot);
+    if price_unusable {
+        reserve.last_update.mark_stale();
+    }
+
+    Ok(reserve)
 }
 
 /// Lite version of refresh_reserve that should be used when the oracle price doesn't need to be updated
@@ -499,6 +1487,47 @@ fn _refresh_reserve_interest<'a>(
     Ok(())
 }
 
+/// Refreshes a stale reserve that enables auto refresh when its oracle accounts, followed by any
+/// extra pyth oracle accounts, are the next accounts supplied. Matching oracle accounts are
+/// consumed either way, so that the optional accounts following them can still be told apart.
+fn _auto_refresh_reserve<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    reserve_info: &AccountInfo<'b>,
+    account_info_iter: &mut Peekable<I>,
+    clock: &Clock,
+) -> ProgramResult {
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if account_info_iter.peek().map(|a| a.key) != Some(&reserve.liquidity.pyth_oracle_pubkey) {
+        return Ok(());
+    }
+    let pyth_price_info = next_account_info(account_info_iter)?;
+    let mut switchboard_feed_info = None;
+    if account_info_iter.peek().map(|a| a.key) == Some(&reserve.liquidity.switchboard_oracle_pubkey)
+    {
+        switchboard_feed_info = Some(next_account_info(account_info_iter)?);
+    }
+    let mut extra_pyth_price_infos = vec![];
+    for extra_pyth_oracle in reserve.liquidity.extra_pyth_oracles() {
+        if account_info_iter.peek().map(|a| a.key) != Some(&extra_pyth_oracle) {
+            break;
+        }
+        extra_pyth_price_infos.push(next_account_info(account_info_iter)?);
+    }
+
+    if reserve.config.auto_refresh && reserve.last_update.is_stale(clock.slot)? {
+        _refresh_reserve(
+            program_id,
+            reserve_info,
+            Some(pyth_price_info),
+            switchboard_feed_info,
+            &extra_pyth_price_infos,
+            clock,
+        )?;
+    }
+
+    Ok(())
+}
+
 fn process_deposit_reserve_liquidity(
     program_id: &Pubkey,
     liquidity_amount: u64,
@@ -567,6 +1596,10 @@ fn _deposit_reserve_liquidity<'a>(
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
     }
+    if lending_market.config.deleveraging {
+        msg!("Lending market is deleveraging and does not allow new deposits");
+        return Err(LendingError::MarketDeleveraging.into());
+    }
     let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
     if reserve_info.owner != program_id {
         msg!("Reserve provided is not owned by the lending program");
@@ -614,10 +1647,10 @@ fn _deposit_reserve_liquidity<'a>(
     if Decimal::from(liquidity_amount)
         .try_add(reserve.liquidity.total_supply()?)?
         .try_floor_u64()?
-        > reserve.config.deposit_limit
+        > reserve.deposit_limit()?
     {
         msg!("Cannot deposit liquidity above the reserve deposit limit");
-        return Err(LendingError::InvalidAmount.into());
+        return Err(LendingError::DepositLimitExceeded.into());
     }
 
     let collateral_amount = reserve.deposit_liquidity(liquidity_amount)?;
@@ -786,7 +1819,11 @@ fn _redeem_reserve_collateral<'a>(
 }
 
 #[inline(never)] // avoid stack frame limit
-fn process_init_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+fn process_init_obligation(
+    program_id: &Pubkey,
+    tranche: u8,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
     let account_info_iter = &mut accounts.iter().peekable();
     let obligation_info = next_account_info(account_info_iter)?;
     let lending_market_info = next_account_info(account_info_iter)?;
@@ -826,6 +1863,7 @@ fn process_init_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> Pro
         owner: *obligation_owner_info.key,
         deposits: vec![],
         borrows: vec![],
+        tranche,
     });
     Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
 
@@ -845,13 +1883,28 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
         msg!("Obligation provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+    // already refreshed in this slot and untouched since, the reserves can't have moved either
+    if !obligation.last_update.is_stale(clock.slot)? {
+        return Ok(());
+    }
 
-    let mut deposited_value = Decimal::zero();
-    let mut borrowed_value = Decimal::zero();
-    let mut allowed_borrow_value = Decimal::zero();
-    let mut unhealthy_borrow_value = Decimal::zero();
+    // the lending market trails the reserves, but its policy decides how they're valued
+    let lending_market = match account_info_iter
+        .clone()
+        .nth(obligation.deposits.len() + obligation.borrows.len())
+    {
+        Some(lending_market_info) if lending_market_info.key == &obligation.lending_market => {
+            if lending_market_info.owner != program_id {
+                msg!("Lending market provided is not owned by the lending program");
+                return Err(LendingError::InvalidAccountOwner.into());
+            }
+            Some(LendingMarket::unpack(&lending_market_info.data.borrow())?)
+        }
+        _ => None,
+    };
 
-    for (index, collateral) in obligation.deposits.iter_mut().enumerate() {
+    let mut deposit_reserves = Vec::with_capacity(obligation.deposits.len());
+    for (index, collateral) in obligation.deposits.iter().enumerate() {
         let deposit_reserve_info = next_account_info(account_info_iter)?;
         if deposit_reserve_info.owner != program_id {
             msg!(
@@ -868,6 +1921,7 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
             return Err(LendingError::InvalidAccountInput.into());
         }
 
+        assert_reserve_version(deposit_reserve_info)?;
         let deposit_reserve = Reserve::unpack(&deposit_reserve_info.data.borrow())?;
         if deposit_reserve.last_update.is_stale(clock.slot)? {
             msg!(
@@ -876,31 +1930,11 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
             );
             return Err(LendingError::ReserveStale.into());
         }
-
-        // @TODO: add lookup table https://git.io/JOCYq
-        let decimals = 10u64
-            .checked_pow(deposit_reserve.liquidity.mint_decimals as u32)
-            .ok_or(LendingError::MathOverflow)?;
-
-        let market_value = deposit_reserve
-            .collateral_exchange_rate()?
-            .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?
-            .try_mul(deposit_reserve.liquidity.market_price)?
-            .try_div(decimals)?;
-        collateral.market_value = market_value;
-
-        let loan_to_value_rate = Rate::from_percent(deposit_reserve.config.loan_to_value_ratio);
-        let liquidation_threshold_rate =
-            Rate::from_percent(deposit_reserve.config.liquidation_threshold);
-
-        deposited_value = deposited_value.try_add(market_value)?;
-        allowed_borrow_value =
-            allowed_borrow_value.try_add(market_value.try_mul(loan_to_value_rate)?)?;
-        unhealthy_borrow_value =
-            unhealthy_borrow_value.try_add(market_value.try_mul(liquidation_threshold_rate)?)?;
+        deposit_reserves.push(deposit_reserve);
     }
 
-    for (index, liquidity) in obligation.borrows.iter_mut().enumerate() {
+    let mut borrow_reserves = Vec::with_capacity(obligation.borrows.len());
+    for (index, liquidity) in obligation.borrows.iter().enumerate() {
         let borrow_reserve_info = next_account_info(account_info_iter)?;
         if borrow_reserve_info.owner != program_id {
             msg!(
@@ -917,6 +1951,7 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
             return Err(LendingError::InvalidAccountInput.into());
         }
 
+        assert_reserve_version(borrow_reserve_info)?;
         let borrow_reserve = Reserve::unpack(&borrow_reserve_info.data.borrow())?;
         if borrow_reserve.last_update.is_stale(clock.slot)? {
             msg!(
@@ -925,26 +1960,153 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
             );
             return Err(LendingError::ReserveStale.into());
         }
+        borrow_reserves.push(borrow_reserve);
+    }
 
-        liquidity.accrue_interest(borrow_reserve.liquidity.cumulative_borrow_rate_wads)?;
+    if lending_market.is_some() {
+        next_account_info(account_info_iter)?;
+    }
+    if account_info_iter.peek().is_some() {
+        msg!("Too many obligation deposit or borrow reserves provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
 
-        // @TODO: add lookup table https://git.io/JOCYq
-        let decimals = 10u64
-            .checked_pow(borrow_reserve.liquidity.mint_decimals as u32)
-            .ok_or(LendingError::MathOverflow)?;
+    _refresh_obligation_values(
+        &mut obligation,
+        &deposit_reserves,
+        &borrow_reserves,
+        lending_market.as_ref(),
+        clock,
+    )?;
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
 
-        let market_value = liquidity
-            .borrowed_amount_wads
-            .try_mul(borrow_reserve.liquidity.market_price)?
-            .try_div(decimals)?;
-        liquidity.market_value = market_value;
+    Ok(())
+}
 
-        borrowed_value = borrowed_value.try_add(market_value)?;
+/// Values an obligation's deposits and borrows against its refreshed deposit and borrow
+/// reserves, in obligation order, and marks it refreshed in the current slot.
+fn _refresh_obligation_values(
+    obligation: &mut Obligation,
+    deposit_reserves: &[Reserve],
+    borrow_reserves: &[Reserve],
+    lending_market: Option<&LendingMarket>,
+    clock: &Clock,
+) -> ProgramResult {
+    let use_price_confidence = matches!(
+        lending_market,
+        Some(lending_market) if lending_market.config.use_price_confidence
+    );
+
+    let mut deposited_value = Decimal::zero();
+    let mut borrowed_value = Decimal::zero();
+    let mut allowed_borrow_value = Decimal::zero();
+    let mut unhealthy_borrow_value = Decimal::zero();
+
+    for (index, (collateral, deposit_reserve)) in obligation
+        .deposits
+        .iter_mut()
+        .zip(deposit_reserves)
+        .enumerate()
+    {
+        // leaving the market out can't be used to dodge its confidence pricing
+        if lending_market.is_none() && deposit_reserve.use_price_confidence {
+            msg!(
+                "Lending market must be provided to value deposit reserve {} with confidence pricing",
+                index
+            );
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        let mut collateral_price = deposit_reserve.collateral_price()?;
+        let mut borrow_collateral_price = deposit_reserve.borrow_collateral_price()?;
+        if use_price_confidence {
+            collateral_price = deposit_reserve.price_less_confidence(collateral_price)?;
+            borrow_collateral_price =
+                deposit_reserve.price_less_confidence(borrow_collateral_price)?;
+        }
+
+        let liquidity_amount = deposit_reserve
+            .collateral_exchange_rate()?
+            .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?;
+        let market_value = deposit_reserve
+            .liquidity
+            .market_value_at(liquidity_amount, collateral_price)?;
+        collateral.market_value = market_value;
+
+        let borrow_market_value = deposit_reserve
+            .liquidity
+            .market_value_at(liquidity_amount, borrow_collateral_price)?;
+        // protected collateral only cushions against liquidation, it doesn't back borrows
+        let borrowable_value = if collateral.protected_amount == 0 {
+            borrow_market_value
+        } else {
+            borrow_market_value
+                .try_mul(collateral.unprotected_amount()?)?
+                .try_div(collateral.deposited_amount)?
+        };
+
+        let loan_to_value_rate = Rate::from_percent(deposit_reserve.config.loan_to_value_ratio);
+        let liquidation_threshold_rate = deposit_reserve.liquidation_threshold(clock.slot)?;
+
+        deposited_value = deposited_value.try_add(market_value)?;
+        allowed_borrow_value =
+            allowed_borrow_value.try_add(borrowable_value.try_mul(loan_to_value_rate)?)?;
+        unhealthy_borrow_value =
+            unhealthy_borrow_value.try_add(market_value.try_mul(liquidation_threshold_rate)?)?;
     }
 
-    if account_info_iter.peek().is_some() {
-        msg!("Too many obligation deposit or borrow reserves provided");
-        return Err(LendingError::InvalidAccountInput.into());
+    let mut debt_prices = Vec::with_capacity(borrow_reserves.len());
+    for (index, borrow_reserve) in borrow_reserves.iter().enumerate() {
+        if lending_market.is_none() && borrow_reserve.use_price_confidence {
+            msg!(
+                "Lending market must be provided to value borrow reserve {} with confidence pricing",
+                index
+            );
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        let mut debt_price = borrow_reserve.debt_price()?;
+        if use_price_confidence {
+            debt_price = borrow_reserve.price_plus_confidence(debt_price)?;
+        }
+        debt_prices.push(debt_price);
+    }
+
+    // the balance is judged before interest, so a borrow taken since the last refresh counts
+    let interest_free = match lending_market.and_then(|m| m.interest_free_borrow_value()) {
+        Some(interest_free_borrow_value) => {
+            let mut principal_value = Decimal::zero();
+            for (liquidity, borrow_reserve) in obligation.borrows.iter().zip(borrow_reserves) {
+                principal_value =
+                    principal_value.try_add(borrow_reserve.liquidity.market_value_at(
+                        liquidity.borrowed_amount_wads,
+                        borrow_reserve.liquidity.market_price,
+                    )?)?;
+            }
+            principal_value < interest_free_borrow_value
+        }
+        None => false,
+    };
+
+    for ((liquidity, borrow_reserve), debt_price) in obligation
+        .borrows
+        .iter_mut()
+        .zip(borrow_reserves)
+        .zip(debt_prices)
+    {
+        let borrow_liquidity = &borrow_reserve.liquidity;
+        // forgiven interest is written off the reserve when the borrow is next repaid
+        if interest_free {
+            liquidity.forgive_interest(borrow_liquidity.cumulative_borrow_rate_wads)?;
+        } else {
+            liquidity.accrue_interest(borrow_liquidity.cumulative_borrow_rate_wads)?;
+        }
+
+        let market_value =
+            borrow_liquidity.market_value_at(liquidity.borrowed_amount_wads, debt_price)?;
+        liquidity.market_value = market_value;
+
+        borrowed_value = borrowed_value.try_add(market_value)?;
     }
 
     obligation.deposited_value = deposited_value;
@@ -957,7 +2119,102 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
     obligation.unhealthy_borrow_value = min(unhealthy_borrow_value, global_unhealthy_borrow_value);
 
     obligation.last_update.update_slot(clock.slot);
-    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_view_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+
+    let reserve_pubkeys: Vec<Pubkey> = obligation
+        .deposits
+        .iter()
+        .map(|collateral| collateral.deposit_reserve)
+        .chain(
+            obligation
+                .borrows
+                .iter()
+                .map(|liquidity| liquidity.borrow_reserve),
+        )
+        .collect();
+    let mut reserves = Vec::with_capacity(reserve_pubkeys.len());
+    for (index, reserve_pubkey) in reserve_pubkeys.iter().enumerate() {
+        let reserve_info = next_account_info(account_info_iter)?;
+        if reserve_info.key != reserve_pubkey {
+            msg!(
+                "Reserve {} of the obligation does not match the reserve provided",
+                index
+            );
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        assert_reserve_version(reserve_info)?;
+        let extra_pyth_oracle_count = Reserve::unpack(&reserve_info.data.borrow())?
+            .liquidity
+            .extra_pyth_oracles()
+            .len();
+        let pyth_price_info = next_account_info(account_info_iter)?;
+        let switchboard_feed_info = next_account_info(account_info_iter)?;
+        let extra_pyth_price_infos = account_info_iter
+            .by_ref()
+            .take(extra_pyth_oracle_count)
+            .collect::<Vec<_>>();
+        if extra_pyth_price_infos.len() != extra_pyth_oracle_count {
+            msg!(
+                "Reserve {} has {} extra pyth oracles, got {} accounts for them",
+                index,
+                extra_pyth_oracle_count,
+                extra_pyth_price_infos.len()
+            );
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        reserves.push(_refreshed_reserve(
+            program_id,
+            reserve_info,
+            Some(pyth_price_info),
+            Some(switchboard_feed_info),
+            &extra_pyth_price_infos,
+            clock,
+        )?);
+    }
+    if account_info_iter.next().is_some() {
+        msg!("Too many obligation deposit or borrow reserves provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let (deposit_reserves, borrow_reserves) = reserves.split_at(obligation.deposits.len());
+    _refresh_obligation_values(
+        &mut obligation,
+        deposit_reserves,
+        borrow_reserves,
+        Some(&lending_market),
+        clock,
+    )?;
+
+    let mut data = [0; ObligationSummary::LEN];
+    ObligationSummary::pack(
+        ObligationSummary::new(*obligation_info.key, &obligation),
+        &mut data,
+    )?;
+    set_return_data(&data);
 
     Ok(())
 }
@@ -1029,6 +2286,10 @@ fn _deposit_obligation_collateral<'a>(
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
     }
+    if lending_market.config.deleveraging {
+        msg!("Lending market is deleveraging and does not allow new deposits");
+        return Err(LendingError::MarketDeleveraging.into());
+    }
 
     let deposit_reserve = Reserve::unpack(&deposit_reserve_info.data.borrow())?;
     if deposit_reserve_info.owner != program_id {
@@ -1053,6 +2314,10 @@ fn _deposit_obligation_collateral<'a>(
         msg!("Deposit reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
     }
+    if !deposit_reserve.config.usable_as_collateral {
+        msg!("Deposit reserve is supply-only and its collateral can't be used by obligations");
+        return Err(LendingError::CollateralNotAllowed.into());
+    }
 
     let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
     if obligation_info.owner != program_id {
@@ -1063,14 +2328,26 @@ fn _deposit_obligation_collateral<'a>(
         msg!("Obligation lending market does not match the lending market provided");
         return Err(LendingError::InvalidAccountInput.into());
     }
-    if &obligation.owner != obligation_owner_info.key {
-        msg!("Obligation owner does not match the obligation owner provided");
+    if &obligation.owner != obligation_owner_info.key
+        && !obligation.is_delegate(obligation_owner_info.key)
+    {
+        msg!("Obligation owner or delegate does not match the authority provided");
         return Err(LendingError::InvalidObligationOwner.into());
     }
-    if !obligation_owner_info.is_signer {
+    // sponsored deposits are funded and signed for by the transfer authority alone
+    if !obligation_owner_info.is_signer && !lending_market.config.allow_sponsored_deposits {
         msg!("Obligation owner provided must be a signer");
         return Err(LendingError::InvalidSigner.into());
     }
+    // a sponsor could otherwise fill the obligation's deposit slots with unwanted collateral
+    if !obligation_owner_info.is_signer && !obligation.has_deposit(*deposit_reserve_info.key) {
+        msg!("Sponsored deposits can only add to collateral the obligation already has");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if deposit_reserve.config.tranche != obligation.tranche {
+        msg!("Deposit reserve tranche does not match the obligation tranche");
+        return Err(LendingError::TrancheMismatch.into());
+    }
 
     obligation
         .find_or_add_collateral_to_deposits(*deposit_reserve_info.key)?
@@ -1088,6 +2365,74 @@ fn _deposit_obligation_collateral<'a>(
     Ok(())
 }
 
+#[inline(never)] // avoid stack frame limit
+fn process_deposit_collateral_and_assert_healthy(
+    program_id: &Pubkey,
+    collateral_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if collateral_amount == 0 {
+        msg!("Collateral amount provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let source_collateral_info = next_account_info(account_info_iter)?;
+    let destination_collateral_info = next_account_info(account_info_iter)?;
+    let deposit_reserve_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+
+    _deposit_obligation_collateral(
+        program_id,
+        collateral_amount,
+        source_collateral_info,
+        destination_collateral_info,
+        deposit_reserve_info,
+        obligation_info,
+        lending_market_info,
+        obligation_owner_info,
+        user_transfer_authority_info,
+        clock,
+        token_program_id,
+    )?;
+
+    // judge health as the next refresh would, with the deposit added to the refreshed values
+    let deposit_reserve = Reserve::unpack(&deposit_reserve_info.data.borrow())?;
+    let deposit_value = deposit_reserve.liquidity.market_value_at(
+        deposit_reserve
+            .collateral_exchange_rate()?
+            .decimal_collateral_to_liquidity(collateral_amount.into())?,
+        deposit_reserve.collateral_price()?,
+    )?;
+    let unhealthy_borrow_value = obligation
+        .unhealthy_borrow_value
+        .try_add(deposit_value.try_mul(deposit_reserve.liquidation_threshold(clock.slot)?)?)?;
+
+    if obligation.borrowed_value > Decimal::zero()
+        && obligation.borrowed_value >= unhealthy_borrow_value
+    {
+        msg!(
+            "Obligation borrowed value {} is still at or above its unhealthy borrow value {}",
+            obligation.borrowed_value,
+            unhealthy_borrow_value
+        );
+        return Err(LendingError::ObligationUnhealthy.into());
+    }
+
+    Ok(())
+}
+
 #[inline(never)] // avoid stack frame limit
 fn process_deposit_reserve_liquidity_and_obligation_collateral(
     program_id: &Pubkey,
@@ -1270,6 +2615,11 @@ fn _withdraw_obligation_collateral<'a>(
         msg!("Collateral deposited amount is zero");
         return Err(LendingError::ObligationCollateralEmpty.into());
     }
+    let unprotected_amount = collateral.unprotected_amount()?;
+    if unprotected_amount == 0 {
+        msg!("Collateral is entirely set aside as liquidation protection");
+        return Err(LendingError::WithdrawTooLarge.into());
+    }
 
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
@@ -1286,17 +2636,18 @@ fn _withdraw_obligation_collateral<'a>(
 
     let withdraw_amount = if obligation.borrows.is_empty() {
         if collateral_amount == u64::MAX {
-            collateral.deposited_amount
+            unprotected_amount
         } else {
-            collateral.deposited_amount.min(collateral_amount)
+            unprotected_amount.min(collateral_amount)
         }
     } else if obligation.deposited_value == Decimal::zero() {
         msg!("Obligation deposited value is zero");
         return Err(LendingError::ObligationDepositsZero.into());
     } else {
-        let max_withdraw_value = obligation.max_withdraw_value(Rate::from_percent(
-            withdraw_reserve.config.loan_to_value_ratio,
-        ))?;
+        let max_withdraw_value = obligation.max_withdraw_value(
+            Rate::from_percent(withdraw_reserve.config.loan_to_value_ratio),
+            lending_market.borrow_health_buffer(),
+        )?;
 
         if max_withdraw_value == Decimal::zero() {
             msg!("Maximum withdraw value is zero");
@@ -1309,9 +2660,9 @@ fn _withdraw_obligation_collateral<'a>(
             withdraw_pct
                 .try_mul(collateral.deposited_amount)?
                 .try_floor_u64()?
-                .min(collateral.deposited_amount)
+                .min(unprotected_amount)
         } else {
-            let withdraw_amount = collateral_amount.min(collateral.deposited_amount);
+            let withdraw_amount = collateral_amount.min(unprotected_amount);
             let withdraw_pct =
                 Decimal::from(withdraw_amount).try_div(collateral.deposited_amount)?;
             let withdraw_value = collateral.market_value.try_mul(withdraw_pct)?;
@@ -1369,6 +2720,7 @@ fn process_borrow_obligation_liquidity(
         next_account_info(account_info_iter)?;
     }
     let token_program_id = next_account_info(account_info_iter)?;
+    _auto_refresh_reserve(program_id, borrow_reserve_info, account_info_iter, clock)?;
 
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -1401,21 +2753,12 @@ fn process_borrow_obligation_liquidity(
     }
     if &borrow_reserve.config.fee_receiver != borrow_reserve_liquidity_fee_receiver_info.key {
         msg!("Borrow reserve liquidity fee receiver does not match the borrow reserve liquidity fee receiver provided");
-        return Err(LendingError::InvalidAccountInput.into());
+        return Err(LendingError::InvalidFeeReceiver.into());
     }
     if borrow_reserve.last_update.is_stale(clock.slot)? {
         msg!("Borrow reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
     }
-    if liquidity_amount != u64::MAX
-        && Decimal::from(liquidity_amount)
-            .try_add(borrow_reserve.liquidity.borrowed_amount_wads)?
-            .try_floor_u64()?
-            > borrow_reserve.config.borrow_limit
-    {
-        msg!("Cannot borrow above the borrow limit");
-        return Err(LendingError::InvalidAmount.into());
-    }
 
     let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
     if obligation_info.owner != program_id {
@@ -1432,19 +2775,11 @@ fn process_borrow_obligation_liquidity(
     }
     if !obligation_owner_info.is_signer {
         msg!("Obligation owner provided must be a signer");
-        return Err(LendingError::InvalidSigner.into());
-    }
-    if obligation.last_update.is_stale(clock.slot)? {
-        msg!("Obligation is stale and must be refreshed in the current slot");
-        return Err(LendingError::ObligationStale.into());
-    }
-    if obligation.deposits.is_empty() {
-        msg!("Obligation has no deposits to borrow against");
-        return Err(LendingError::ObligationDepositsEmpty.into());
+        return Err(LendingError::InvalidSigner.into());
     }
-    if obligation.deposited_value == Decimal::zero() {
-        msg!("Obligation deposits have zero value");
-        return Err(LendingError::ObligationDepositsZero.into());
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
     }
 
     let authority_signer_seeds = &[
@@ -1460,32 +2795,20 @@ fn process_borrow_obligation_liquidity(
         return Err(LendingError::InvalidMarketAuthority.into());
     }
 
-    let remaining_borrow_value = obligation.remaining_borrow_value()?;
-    if remaining_borrow_value == Decimal::zero() {
-        msg!("Remaining borrow value is zero");
-        return Err(LendingError::BorrowTooLarge.into());
-    }
-
-    let remaining_reserve_capacity = Decimal::from(borrow_reserve.config.borrow_limit)
-        .try_sub(borrow_reserve.liquidity.borrowed_amount_wads)
-        .unwrap_or_else(|_| Decimal::zero());
-
     let CalculateBorrowResult {
         borrow_amount,
         receive_amount,
         borrow_fee,
         host_fee,
-    } = borrow_reserve.calculate_borrow(
+    } = validate_borrow(
+        &lending_market,
+        borrow_reserve_info.key,
+        &borrow_reserve,
+        &obligation,
         liquidity_amount,
-        remaining_borrow_value,
-        remaining_reserve_capacity,
     )?;
 
-    if receive_amount == 0 {
-        msg!("Borrow amount is too small to receive liquidity after fees");
-        return Err(LendingError::BorrowTooSmall.into());
-    }
-
+    let isolated = borrow_reserve.config.reserve_type == ReserveType::Isolated;
     let cumulative_borrow_rate_wads = borrow_reserve.liquidity.cumulative_borrow_rate_wads;
 
     borrow_reserve.liquidity.borrow(borrow_amount)?;
@@ -1496,6 +2819,7 @@ fn process_borrow_obligation_liquidity(
         .find_or_add_liquidity_to_borrows(*borrow_reserve_info.key, cumulative_borrow_rate_wads)?;
 
     obligation_liquidity.borrow(borrow_amount)?;
+    obligation_liquidity.isolated = isolated;
     obligation.last_update.mark_stale();
     Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
 
@@ -1539,6 +2863,103 @@ fn process_borrow_obligation_liquidity(
     Ok(())
 }
 
+/// Checks that the market, borrow reserve and obligation allow borrowing `liquidity_amount` from
+/// the reserve, returning what the borrow would move. Borrow fails with the error and CanBorrow
+/// reports it, so the two can't disagree.
+fn validate_borrow(
+    lending_market: &LendingMarket,
+    borrow_reserve_pubkey: &Pubkey,
+    borrow_reserve: &Reserve,
+    obligation: &Obligation,
+    liquidity_amount: u64,
+) -> Result<CalculateBorrowResult, ProgramError> {
+    if lending_market.config.deleveraging {
+        msg!("Lending market is deleveraging and does not allow new borrows");
+        return Err(LendingError::MarketDeleveraging.into());
+    }
+    if liquidity_amount != u64::MAX
+        && Decimal::from(liquidity_amount)
+            .try_add(borrow_reserve.liquidity.borrowed_amount_wads)?
+            .try_floor_u64()?
+            > borrow_reserve.borrow_limit()?
+    {
+        msg!("Cannot borrow above the borrow limit");
+        return Err(LendingError::BorrowLimitExceeded.into());
+    }
+    if obligation.deposits.is_empty() {
+        msg!("Obligation has no deposits to borrow against");
+        return Err(LendingError::ObligationDepositsEmpty.into());
+    }
+    if obligation.deposited_value == Decimal::zero() {
+        msg!("Obligation deposits have zero value");
+        return Err(LendingError::ObligationDepositsZero.into());
+    }
+    if borrow_reserve.config.permissioned && !obligation.whitelisted {
+        msg!("Borrow reserve is permissioned and the obligation is not whitelisted");
+        return Err(LendingError::Unauthorized.into());
+    }
+    if borrow_reserve.config.tranche != obligation.tranche {
+        msg!("Borrow reserve tranche does not match the obligation tranche");
+        return Err(LendingError::TrancheMismatch.into());
+    }
+    if borrow_reserve.config.reject_self_borrow && obligation.has_deposit(*borrow_reserve_pubkey) {
+        msg!("Borrow reserve does not allow borrowing against its own collateral");
+        return Err(LendingError::SelfBorrow.into());
+    }
+    if obligation.violates_isolation(
+        *borrow_reserve_pubkey,
+        borrow_reserve.config.reserve_type == ReserveType::Isolated,
+    ) {
+        msg!("Isolated assets can't be borrowed alongside any other asset");
+        return Err(LendingError::IsolatedBorrowViolation.into());
+    }
+
+    let remaining_borrow_value =
+        obligation.remaining_borrow_value(lending_market.borrow_health_buffer())?;
+    if remaining_borrow_value == Decimal::zero() {
+        msg!("Remaining borrow value is zero");
+        return Err(LendingError::BorrowTooLarge.into());
+    }
+
+    let remaining_reserve_capacity = Decimal::from(borrow_reserve.borrow_limit()?)
+        .try_sub(borrow_reserve.liquidity.borrowed_amount_wads)
+        .unwrap_or_else(|_| Decimal::zero());
+
+    let result = borrow_reserve.calculate_borrow(
+        liquidity_amount,
+        remaining_borrow_value,
+        remaining_reserve_capacity,
+    )?;
+
+    if result.receive_amount == 0 {
+        msg!("Borrow amount is too small to receive liquidity after fees");
+        return Err(LendingError::BorrowTooSmall.into());
+    }
+
+    // interest can carry an obligation past the cap, it only stops new borrows
+    if let Some(max_obligation_borrow_value) = lending_market.max_obligation_borrow_value() {
+        let borrowed_value = obligation.borrowed_value.try_add(
+            borrow_reserve
+                .liquidity
+                .market_value_at(result.borrow_amount, borrow_reserve.debt_price()?)?,
+        )?;
+        if borrowed_value > max_obligation_borrow_value {
+            msg!(
+                "Borrow would take the obligation's borrowed value above the market's {} cap",
+                max_obligation_borrow_value
+            );
+            return Err(LendingError::ObligationValueLimit.into());
+        }
+    }
+
+    if result.borrow_amount.try_floor_u64()? > borrow_reserve.liquidity.available_amount {
+        msg!("Borrow amount cannot exceed available amount");
+        return Err(LendingError::InsufficientLiquidity.into());
+    }
+
+    Ok(result)
+}
+
 #[inline(never)] // avoid stack frame limit
 fn process_repay_obligation_liquidity(
     program_id: &Pubkey,
@@ -1561,7 +2982,33 @@ fn process_repay_obligation_liquidity(
         next_account_info(account_info_iter)?;
     }
     let token_program_id = next_account_info(account_info_iter)?;
+    _repay_obligation_liquidity(
+        program_id,
+        liquidity_amount,
+        source_liquidity_info,
+        destination_liquidity_info,
+        repay_reserve_info,
+        obligation_info,
+        lending_market_info,
+        user_transfer_authority_info,
+        clock,
+        token_program_id,
+    )
+}
 
+#[allow(clippy::too_many_arguments)]
+fn _repay_obligation_liquidity<'a>(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    source_liquidity_info: &AccountInfo<'a>,
+    destination_liquidity_info: &AccountInfo<'a>,
+    repay_reserve_info: &AccountInfo<'a>,
+    obligation_info: &AccountInfo<'a>,
+    lending_market_info: &AccountInfo<'a>,
+    user_transfer_authority_info: &AccountInfo<'a>,
+    clock: &Clock,
+    token_program_id: &AccountInfo<'a>,
+) -> ProgramResult {
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
         msg!("Lending market provided is not owned by the lending program");
@@ -1626,6 +3073,9 @@ fn process_repay_obligation_liquidity(
     }
 
     repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
+    repay_reserve
+        .liquidity
+        .write_off(obligation.borrows[liquidity_index].take_forgiven_interest())?;
     repay_reserve.last_update.mark_stale();
     Reserve::pack(repay_reserve, &mut repay_reserve_info.data.borrow_mut())?;
 
@@ -1645,6 +3095,217 @@ fn process_repay_obligation_liquidity(
     Ok(())
 }
 
+#[inline(never)] // avoid stack frame limit
+fn process_repay_and_withdraw(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    collateral_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if liquidity_amount == 0 || collateral_amount == 0 {
+        msg!("Liquidity and collateral amounts provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
+    }
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let repay_reserve_info = next_account_info(account_info_iter)?;
+    let source_collateral_info = next_account_info(account_info_iter)?;
+    let destination_collateral_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+    let (liquidity, _) = obligation.find_liquidity_in_borrows(*repay_reserve_info.key)?;
+    let borrowed_amount_wads = liquidity.borrowed_amount_wads;
+    let borrowed_market_value = liquidity.market_value;
+
+    _repay_obligation_liquidity(
+        program_id,
+        liquidity_amount,
+        source_liquidity_info,
+        destination_liquidity_info,
+        repay_reserve_info,
+        obligation_info,
+        lending_market_info,
+        user_transfer_authority_info,
+        clock,
+        token_program_id,
+    )?;
+
+    // the obligation was fresh before the repay, so instead of requiring another refresh only
+    // the repaid share of the borrow's market value is taken off before withdrawing
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    let remaining_liquidity = obligation
+        .borrows
+        .iter_mut()
+        .find(|liquidity| liquidity.borrow_reserve == *repay_reserve_info.key);
+    let remaining_amount_wads = remaining_liquidity
+        .as_ref()
+        .map_or(Decimal::zero(), |liquidity| liquidity.borrowed_amount_wads);
+    let repaid_value = borrowed_market_value
+        .try_mul(borrowed_amount_wads.try_sub(remaining_amount_wads)?)?
+        .try_div(borrowed_amount_wads)?;
+    if let Some(liquidity) = remaining_liquidity {
+        liquidity.market_value = borrowed_market_value.try_sub(repaid_value)?;
+    }
+    obligation.borrowed_value = obligation
+        .borrowed_value
+        .try_sub(repaid_value.min(obligation.borrowed_value))?;
+    obligation.last_update.update_slot(clock.slot);
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    // likewise the repay reserve had to be fresh for the repay, which updated it in place, so it
+    // can be withdrawn from too before being left stale as a repay leaves it
+    let same_reserve = repay_reserve_info.key == withdraw_reserve_info.key;
+    if same_reserve {
+        let mut reserve = Reserve::unpack(&repay_reserve_info.data.borrow())?;
+        reserve.last_update.update_slot(clock.slot);
+        Reserve::pack(reserve, &mut repay_reserve_info.data.borrow_mut())?;
+    }
+
+    _withdraw_obligation_collateral(
+        program_id,
+        collateral_amount,
+        source_collateral_info,
+        destination_collateral_info,
+        withdraw_reserve_info,
+        obligation_info,
+        lending_market_info,
+        lending_market_authority_info,
+        obligation_owner_info,
+        clock,
+        token_program_id,
+    )?;
+
+    if same_reserve {
+        let mut reserve = Reserve::unpack(&repay_reserve_info.data.borrow())?;
+        reserve.last_update.mark_stale();
+        Reserve::pack(reserve, &mut repay_reserve_info.data.borrow_mut())?;
+    }
+    Ok(())
+}
+
+/// Work out a liquidation of up to `liquidity_amount` against the obligation, deferring the
+/// liquidation bonus when the lending market claws it back. Liquidations and GetMaxLiquidation
+/// both go through here so that quoted amounts are exactly what a liquidation would move.
+#[allow(clippy::too_many_arguments)]
+fn calculate_obligation_liquidation(
+    liquidity_amount: u64,
+    lending_market: &LendingMarket,
+    repay_reserve: &Reserve,
+    repay_reserve_key: &Pubkey,
+    withdraw_reserve: &Reserve,
+    withdraw_reserve_key: &Pubkey,
+    obligation: &mut Obligation,
+    clock: &Clock,
+) -> Result<(CalculateLiquidationResult, usize, usize), ProgramError> {
+    if obligation.deposited_value == Decimal::zero() {
+        msg!("Obligation deposited value is zero");
+        return Err(LendingError::ObligationDepositsZero.into());
+    }
+    if obligation.borrowed_value == Decimal::zero() {
+        msg!("Obligation borrowed value is zero");
+        return Err(LendingError::ObligationBorrowsZero.into());
+    }
+    if obligation.borrowed_value < obligation.unhealthy_borrow_value {
+        msg!("Obligation is healthy and cannot be liquidated");
+        return Err(LendingError::ObligationHealthy.into());
+    }
+    if obligation
+        .in_liquidation_cooldown(clock.slot, lending_market.config.liquidation_cooldown_slots)
+    {
+        msg!(
+            "Obligation was liquidated at slot {} and can't be liquidated again for {} slots",
+            obligation.last_liquidation_slot,
+            lending_market.config.liquidation_cooldown_slots
+        );
+        return Err(LendingError::LiquidationCooldown.into());
+    }
+
+    let (liquidity, liquidity_index) = obligation.find_liquidity_in_borrows(*repay_reserve_key)?;
+    if liquidity.market_value == Decimal::zero() {
+        msg!("Obligation borrow value is zero");
+        return Err(LendingError::ObligationLiquidityEmpty.into());
+    }
+
+    let (collateral, collateral_index) =
+        obligation.find_collateral_in_deposits(*withdraw_reserve_key)?;
+    if collateral.market_value == Decimal::zero() {
+        msg!("Obligation deposit value is zero");
+        return Err(LendingError::ObligationCollateralEmpty.into());
+    }
+
+    // on top of the close factor, repay no more than brings the obligation back to the market's
+    // target. Borrows small enough to be closed out in full are left alone
+    let liquidity_amount = match lending_market.post_liquidation_target_ltv() {
+        Some(target_ltv) if liquidity.borrowed_amount_wads >= LIQUIDATION_CLOSE_AMOUNT.into() => {
+            let bonus_rate = withdraw_reserve
+                .liquidation_bonus(obligation)?
+                .try_add(Rate::one())?;
+            match obligation.target_liquidation_amount(liquidity, target_ltv, bonus_rate)? {
+                Some(target_amount) => liquidity_amount.min(target_amount.try_ceil_u64()?),
+                None => liquidity_amount,
+            }
+        }
+        _ => liquidity_amount,
+    };
+
+    let CalculateLiquidationResult {
+        settle_amount,
+        repay_amount,
+        withdraw_amount,
+    } = withdraw_reserve.calculate_liquidation(
+        liquidity_amount,
+        obligation,
+        liquidity,
+        collateral,
+        repay_reserve.config.liquidation_close_factor,
+        lending_market.max_liquidation_bonus_value(),
+    )?;
+    let withdraw_amount = match lending_market.liquidation_bonus_clawback_rate() {
+        Some(clawback_rate) if withdraw_amount > 0 => obligation.defer_liquidation_bonus(
+            settle_amount,
+            withdraw_amount,
+            liquidity_index,
+            collateral_index,
+            withdraw_reserve.liquidation_threshold(clock.slot)?,
+            clawback_rate,
+            clock.slot,
+        )?,
+        _ => withdraw_amount,
+    };
+
+    if repay_amount == 0 {
+        msg!("Liquidation is too small to transfer liquidity");
+        return Err(LendingError::LiquidationTooSmall.into());
+    }
+    if withdraw_amount == 0 {
+        msg!("Liquidation is too small to receive collateral");
+        return Err(LendingError::LiquidationTooSmall.into());
+    }
+
+    Ok((
+        CalculateLiquidationResult {
+            settle_amount,
+            repay_amount,
+            withdraw_amount,
+        },
+        liquidity_index,
+        collateral_index,
+    ))
+}
+
 #[allow(clippy::too_many_arguments)]
 fn _liquidate_obligation<'a>(
     program_id: &Pubkey,
@@ -1661,7 +3322,7 @@ fn _liquidate_obligation<'a>(
     user_transfer_authority_info: &AccountInfo<'a>,
     clock: &Clock,
     token_program_id: &AccountInfo<'a>,
-) -> Result<u64, ProgramError> {
+) -> Result<(u64, Rate), ProgramError> {
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
         msg!("Lending market provided is not owned by the lending program");
@@ -1699,6 +3360,10 @@ fn _liquidate_obligation<'a>(
         msg!("Repay reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
     }
+    if !repay_reserve.is_price_fresh_for_liquidation(clock.slot) {
+        msg!("Repay reserve price was read from its oracles too long ago to liquidate against");
+        return Err(LendingError::ReserveStale.into());
+    }
 
     let withdraw_reserve = Reserve::unpack(&withdraw_reserve_info.data.borrow())?;
     if withdraw_reserve_info.owner != program_id {
@@ -1725,6 +3390,10 @@ fn _liquidate_obligation<'a>(
         msg!("Withdraw reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
     }
+    if !withdraw_reserve.is_price_fresh_for_liquidation(clock.slot) {
+        msg!("Withdraw reserve price was read from its oracles too long ago to liquidate against");
+        return Err(LendingError::ReserveStale.into());
+    }
 
     let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
     if obligation_info.owner != program_id {
@@ -1739,32 +3408,6 @@ fn _liquidate_obligation<'a>(
         msg!("Obligation is stale and must be refreshed in the current slot");
         return Err(LendingError::ObligationStale.into());
     }
-    if obligation.deposited_value == Decimal::zero() {
-        msg!("Obligation deposited value is zero");
-        return Err(LendingError::ObligationDepositsZero.into());
-    }
-    if obligation.borrowed_value == Decimal::zero() {
-        msg!("Obligation borrowed value is zero");
-        return Err(LendingError::ObligationBorrowsZero.into());
-    }
-    if obligation.borrowed_value < obligation.unhealthy_borrow_value {
-        msg!("Obligation is healthy and cannot be liquidated");
-        return Err(LendingError::ObligationHealthy.into());
-    }
-
-    let (liquidity, liquidity_index) =
-        obligation.find_liquidity_in_borrows(*repay_reserve_info.key)?;
-    if liquidity.market_value == Decimal::zero() {
-        msg!("Obligation borrow value is zero");
-        return Err(LendingError::ObligationLiquidityEmpty.into());
-    }
-
-    let (collateral, collateral_index) =
-        obligation.find_collateral_in_deposits(*withdraw_reserve_info.key)?;
-    if collateral.market_value == Decimal::zero() {
-        msg!("Obligation deposit value is zero");
-        return Err(LendingError::ObligationCollateralEmpty.into());
-    }
 
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
@@ -1778,33 +3421,37 @@ fn _liquidate_obligation<'a>(
         );
         return Err(LendingError::InvalidMarketAuthority.into());
     }
-
-    let CalculateLiquidationResult {
-        settle_amount,
-        repay_amount,
-        withdraw_amount,
-    } = withdraw_reserve.calculate_liquidation(
+
+    let liquidation_bonus = withdraw_reserve.liquidation_bonus(&obligation)?;
+    let (
+        CalculateLiquidationResult {
+            settle_amount,
+            repay_amount,
+            withdraw_amount,
+        },
+        liquidity_index,
+        collateral_index,
+    ) = calculate_obligation_liquidation(
         liquidity_amount,
-        &obligation,
-        liquidity,
-        collateral,
+        &lending_market,
+        &repay_reserve,
+        repay_reserve_info.key,
+        &withdraw_reserve,
+        withdraw_reserve_info.key,
+        &mut obligation,
+        clock,
     )?;
 
-    if repay_amount == 0 {
-        msg!("Liquidation is too small to transfer liquidity");
-        return Err(LendingError::LiquidationTooSmall.into());
-    }
-    if withdraw_amount == 0 {
-        msg!("Liquidation is too small to receive collateral");
-        return Err(LendingError::LiquidationTooSmall.into());
-    }
-
     repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
+    repay_reserve
+        .liquidity
+        .write_off(obligation.borrows[liquidity_index].take_forgiven_interest())?;
     repay_reserve.last_update.mark_stale();
     Reserve::pack(repay_reserve, &mut repay_reserve_info.data.borrow_mut())?;
 
     obligation.repay(settle_amount, liquidity_index)?;
     obligation.withdraw(withdraw_amount, collateral_index)?;
+    obligation.last_liquidation_slot = clock.slot;
     obligation.last_update.mark_stale();
     Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
 
@@ -1826,7 +3473,78 @@ fn _liquidate_obligation<'a>(
         token_program: token_program_id.clone(),
     })?;
 
-    Ok(withdraw_amount)
+    log_liquidation(
+        obligation_info.key,
+        repay_reserve_info.key,
+        withdraw_reserve_info.key,
+        repay_amount,
+        withdraw_amount,
+        liquidation_bonus,
+    );
+
+    Ok((withdraw_amount, liquidation_bonus))
+}
+
+/// Longest base58 encoding of a pubkey
+const MAX_BASE58_PUBKEY_LEN: usize = 44;
+/// Longest liquidation log line, with every pubkey and amount at its maximum length
+const MAX_LIQUIDATION_LOG_LEN: usize = 320;
+
+/// Log line written into a fixed stack buffer, so logging doesn't allocate
+struct LogLine {
+    buf: [u8; MAX_LIQUIDATION_LOG_LEN],
+    len: usize,
+}
+
+impl Write for LogLine {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len.checked_add(s.len()).ok_or(fmt::Error)?;
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+fn encode_pubkey<'a>(pubkey: &Pubkey, buf: &'a mut [u8; MAX_BASE58_PUBKEY_LEN]) -> &'a str {
+    let len = bs58::encode(pubkey).into(&mut buf[..]).unwrap_or(0);
+    str::from_utf8(&buf[..len]).unwrap_or_default()
+}
+
+/// Logs a successful liquidation as a single `Liquidation: {...}` line for indexers, with the
+/// repay amount in liquidity, the withdraw amount in collateral and the liquidation bonus
+/// applied in basis points
+fn log_liquidation(
+    obligation: &Pubkey,
+    repay_reserve: &Pubkey,
+    withdraw_reserve: &Pubkey,
+    repay_amount: u64,
+    withdraw_amount: u64,
+    liquidation_bonus: Rate,
+) {
+    let mut obligation_buf = [0; MAX_BASE58_PUBKEY_LEN];
+    let mut repay_reserve_buf = [0; MAX_BASE58_PUBKEY_LEN];
+    let mut withdraw_reserve_buf = [0; MAX_BASE58_PUBKEY_LEN];
+    let mut line = LogLine {
+        buf: [0; MAX_LIQUIDATION_LOG_LEN],
+        len: 0,
+    };
+    if write!(
+        line,
+        "Liquidation: {{\"obligation\":\"{}\",\"repay_reserve\":\"{}\",\"withdraw_reserve\":\"{}\",\"repay_amount\":{},\"withdraw_amount\":{},\"liquidation_bonus_bps\":{}}}",
+        encode_pubkey(obligation, &mut obligation_buf),
+        encode_pubkey(repay_reserve, &mut repay_reserve_buf),
+        encode_pubkey(withdraw_reserve, &mut withdraw_reserve_buf),
+        repay_amount,
+        withdraw_amount,
+        liquidation_bonus.to_scaled_val() / (WAD / 10_000) as u128,
+    )
+    .is_ok()
+    {
+        sol_log(str::from_utf8(&line.buf[..line.len]).unwrap_or_default());
+    }
 }
 
 #[inline(never)] // avoid stack frame limit
@@ -1858,7 +3576,7 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
     let token_program_id = next_account_info(account_info_iter)?;
     let clock = &Clock::get()?;
 
-    let withdrawn_collateral_amount = _liquidate_obligation(
+    let (withdrawn_collateral_amount, liquidation_bonus) = _liquidate_obligation(
         program_id,
         liquidity_amount,
         source_liquidity_info,
@@ -1901,10 +3619,14 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
         if &withdraw_reserve.config.fee_receiver != withdraw_reserve_liquidity_fee_receiver_info.key
         {
             msg!("Withdraw reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
-            return Err(LendingError::InvalidAccountInput.into());
+            return Err(LendingError::InvalidFeeReceiver.into());
         }
-        let protocol_fee =
-            withdraw_reserve.calculate_protocol_liquidation_fee(withdraw_liquidity_amount)?;
+        let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+        let protocol_fee = withdraw_reserve.calculate_protocol_liquidation_fee(
+            withdraw_liquidity_amount,
+            liquidation_bonus,
+            lending_market.max_liquidation_bonus_value(),
+        )?;
 
         spl_token_transfer(TokenTransferParams {
             source: destination_liquidity_info.clone(),
@@ -1957,69 +3679,388 @@ fn process_withdraw_obligation_collateral_and_redeem_reserve_liquidity(
         token_program_id,
     )?;
 
-    _redeem_reserve_collateral(
-        program_id,
-        liquidity_amount,
-        user_collateral_info,
-        user_liquidity_info,
-        reserve_info,
-        reserve_collateral_mint_info,
-        reserve_liquidity_supply_info,
-        lending_market_info,
-        lending_market_authority_info,
-        user_transfer_authority_info,
+    _redeem_reserve_collateral(
+        program_id,
+        liquidity_amount,
+        user_collateral_info,
+        user_liquidity_info,
+        reserve_info,
+        reserve_collateral_mint_info,
+        reserve_liquidity_supply_info,
+        lending_market_info,
+        lending_market_authority_info,
+        user_transfer_authority_info,
+        clock,
+        token_program_id,
+    )?;
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_update_reserve_config(
+    program_id: &Pubkey,
+    config: ReserveConfig,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    config.validate()?;
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let pyth_product_info = next_account_info(account_info_iter)?;
+    let pyth_price_info = next_account_info(account_info_iter)?;
+    let switchboard_feed_info = next_account_info(account_info_iter)?;
+
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!(
+            "Reserve provided is not owned by the lending program {} != {}",
+            &reserve_info.owner.to_string(),
+            &program_id.to_string(),
+        );
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!(
+            "Lending market provided is not owned by the lending program  {} != {}",
+            &lending_market_info.owner.to_string(),
+            &program_id.to_string(),
+        );
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+    if config.loan_to_value_ratio != reserve.config.loan_to_value_ratio
+        || config.liquidation_threshold != reserve.config.liquidation_threshold
+        || config.protocol_take_rate != reserve.config.protocol_take_rate
+    {
+        msg!("Risk parameters must be changed with QueueReserveConfigUpdate and ApplyReserveConfigUpdate");
+        return Err(LendingError::RiskParameterTimelocked.into());
+    }
+    validate_collateral_ltv(&config, &lending_market)?;
+
+    let authority_signer_seeds = &[
+        lending_market_info.key.as_ref(),
+        &[lending_market.bump_seed],
+    ];
+    let lending_market_authority_pubkey =
+        Pubkey::create_program_address(authority_signer_seeds, program_id)?;
+    if &lending_market_authority_pubkey != lending_market_authority_info.key {
+        msg!(
+            "Derived lending market authority does not match the lending market authority provided"
+        );
+        return Err(LendingError::InvalidMarketAuthority.into());
+    }
+
+    if *pyth_price_info.key != reserve.liquidity.pyth_oracle_pubkey {
+        validate_pyth_keys(&lending_market, pyth_product_info, pyth_price_info)?;
+        reserve.liquidity.pyth_oracle_pubkey = *pyth_price_info.key;
+    }
+
+    if *switchboard_feed_info.key != reserve.liquidity.switchboard_oracle_pubkey {
+        validate_switchboard_keys(&lending_market, switchboard_feed_info)?;
+        reserve.liquidity.switchboard_oracle_pubkey = *switchboard_feed_info.key;
+    }
+    if reserve.liquidity.switchboard_oracle_pubkey == solend_program::NULL_PUBKEY
+        && (*pyth_price_info.key == solend_program::NULL_PUBKEY
+            || *pyth_product_info.key == solend_program::NULL_PUBKEY)
+    {
+        msg!("At least one price oracle must have a non-null pubkey");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    // switching between a fixed price and the oracles takes effect on the next refresh
+    if config.fixed_price != reserve.config.fixed_price {
+        reserve.last_update.mark_stale();
+    }
+    reserve.update_config(config, Clock::get()?.slot)?;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_queue_reserve_config_update(
+    program_id: &Pubkey,
+    loan_to_value_ratio: u8,
+    liquidation_threshold: u8,
+    protocol_take_rate: u8,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+
+    reserve.queue_config_update(
+        loan_to_value_ratio,
+        liquidation_threshold,
+        protocol_take_rate,
+        Clock::get()?.slot,
+    )?;
+    let pending_config = reserve.pending_config()?;
+    pending_config.validate()?;
+    validate_collateral_ltv(&pending_config, &lending_market)?;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_apply_reserve_config_update(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    // the rest of the config may have changed since the update was queued
+    reserve.pending_config()?.validate()?;
+    reserve.apply_config_update(Clock::get()?.slot)?;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_reserve_collateral_floor_price(
+    program_id: &Pubkey,
+    floor_price_wads: u128,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+
+    reserve.collateral_floor_price = Decimal::from_scaled_val(floor_price_wads);
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_reserve_oracles(
+    program_id: &Pubkey,
+    max_price_divergence_bps: u16,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if max_price_divergence_bps > MAX_ORACLE_PRICE_DIVERGENCE_BPS {
+        msg!(
+            "Max price divergence provided cannot be more than {} bps",
+            MAX_ORACLE_PRICE_DIVERGENCE_BPS
+        );
+        return Err(LendingError::InvalidConfig.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let pyth_product_info = next_account_info(account_info_iter)?;
+    let pyth_price_info = next_account_info(account_info_iter)?;
+    let switchboard_feed_info = next_account_info(account_info_iter)?;
+    let extra_pyth_infos = account_info_iter.as_slice();
+    let clock = &Clock::get()?;
+
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+
+    if *switchboard_feed_info.key == solend_program::NULL_PUBKEY
+        && (*pyth_price_info.key == solend_program::NULL_PUBKEY
+            || *pyth_product_info.key == solend_program::NULL_PUBKEY)
+    {
+        msg!("At least one price oracle must have a non-null pubkey");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    validate_pyth_keys(&lending_market, pyth_product_info, pyth_price_info)?;
+    validate_switchboard_keys(&lending_market, switchboard_feed_info)?;
+
+    let extra_pyth_pairs = extra_pyth_infos.chunks_exact(2);
+    if !extra_pyth_pairs.remainder().is_empty() {
+        msg!("Extra pyth oracles must be provided as product and price account pairs");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    let mut extra_pyth_price_infos = vec![];
+    for pair in extra_pyth_pairs {
+        let (extra_pyth_product_info, extra_pyth_price_info) = (&pair[0], &pair[1]);
+        if *pyth_price_info.key == solend_program::NULL_PUBKEY
+            || *extra_pyth_price_info.key == solend_program::NULL_PUBKEY
+        {
+            msg!("Extra pyth oracles can only be set alongside a pyth oracle and can't be null");
+            return Err(LendingError::InvalidOracleConfig.into());
+        }
+        // the same feed counted twice could outvote the others
+        if extra_pyth_price_info.key == pyth_price_info.key
+            || extra_pyth_price_infos
+                .iter()
+                .any(|info: &&AccountInfo| info.key == extra_pyth_price_info.key)
+        {
+            msg!("Each pyth oracle of a reserve must be a different price account");
+            return Err(LendingError::InvalidOracleConfig.into());
+        }
+        validate_pyth_keys(
+            &lending_market,
+            extra_pyth_product_info,
+            extra_pyth_price_info,
+        )?;
+        extra_pyth_price_infos.push(extra_pyth_price_info);
+    }
+    let extra_pyth_oracles: Vec<_> = extra_pyth_price_infos
+        .iter()
+        .map(|info| *info.key)
+        .collect();
+    reserve
+        .liquidity
+        .set_extra_pyth_oracles(&extra_pyth_oracles)?;
+
+    // a jump in price on the switch could make healthy obligations liquidatable
+    let market_price = reserve.liquidity.market_price;
+    if market_price == Decimal::zero() {
+        if !reserve.config.allow_zero_price {
+            msg!("Reserve has no market price to compare the new oracles against, it must be refreshed first");
+            return Err(LendingError::ReserveStale.into());
+        }
+        msg!("Reserve market price is zero, so no divergence from it can be tolerated");
+        return Err(LendingError::OraclePriceDivergence.into());
+    }
+    let new_market_price = get_price(
+        Some(switchboard_feed_info),
+        pyth_price_info,
+        &extra_pyth_price_infos,
         clock,
-        token_program_id,
+        &reserve.config,
     )?;
+    let price_divergence = if new_market_price > market_price {
+        new_market_price.try_sub(market_price)?
+    } else {
+        market_price.try_sub(new_market_price)?
+    };
+    if price_divergence > market_price.try_mul(Rate::from_bps(max_price_divergence_bps))? {
+        msg!(
+            "New oracle price {} diverges from the reserve market price {} by more than {} bps",
+            new_market_price,
+            market_price,
+            max_price_divergence_bps
+        );
+        return Err(LendingError::OraclePriceDivergence.into());
+    }
+
+    reserve.liquidity.pyth_oracle_pubkey = *pyth_price_info.key;
+    reserve.liquidity.switchboard_oracle_pubkey = *switchboard_feed_info.key;
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
     Ok(())
 }
 
 #[inline(never)] // avoid stack frame limit
-fn process_update_reserve_config(
+fn process_deploy_reserve_liquidity(
     program_id: &Pubkey,
-    config: ReserveConfig,
+    liquidity_amount: u64,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
-    validate_reserve_config(config)?;
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
     let account_info_iter = &mut accounts.iter();
     let reserve_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let strategy_vault_info = next_account_info(account_info_iter)?;
     let lending_market_info = next_account_info(account_info_iter)?;
     let lending_market_authority_info = next_account_info(account_info_iter)?;
     let lending_market_owner_info = next_account_info(account_info_iter)?;
-    let pyth_product_info = next_account_info(account_info_iter)?;
-    let pyth_price_info = next_account_info(account_info_iter)?;
-    let switchboard_feed_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.token_program_id != token_program_id.key {
+        msg!("Lending market token program does not match the token program provided");
+        return Err(LendingError::InvalidTokenProgram.into());
+    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
 
     let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
     if reserve_info.owner != program_id {
-        msg!(
-            "Reserve provided is not owned by the lending program {} != {}",
-            &reserve_info.owner.to_string(),
-            &program_id.to_string(),
-        );
+        msg!("Reserve provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
     if &reserve.lending_market != lending_market_info.key {
         msg!("Reserve lending market does not match the lending market provided");
         return Err(LendingError::InvalidAccountInput.into());
     }
-
-    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
-    if lending_market_info.owner != program_id {
-        msg!(
-            "Lending market provided is not owned by the lending program  {} != {}",
-            &lending_market_info.owner.to_string(),
-            &program_id.to_string(),
-        );
-        return Err(LendingError::InvalidAccountOwner.into());
+    if &reserve.liquidity.supply_pubkey != reserve_liquidity_supply_info.key {
+        msg!("Reserve liquidity supply does not match the reserve liquidity supply provided");
+        return Err(LendingError::InvalidAccountInput.into());
     }
-    if &lending_market.owner != lending_market_owner_info.key {
-        msg!("Lending market owner does not match the lending market owner provided");
-        return Err(LendingError::InvalidMarketOwner.into());
+    if reserve.config.strategy_vault() != Some(*strategy_vault_info.key) {
+        msg!("Reserve strategy vault does not match the strategy vault provided");
+        return Err(LendingError::InvalidAccountInput.into());
     }
-    if !lending_market_owner_info.is_signer {
-        msg!("Lending market owner provided must be a signer");
-        return Err(LendingError::InvalidSigner.into());
+    if reserve.last_update.is_stale(clock.slot)? {
+        msg!("Reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
     }
 
     let authority_signer_seeds = &[
@@ -2035,25 +4076,91 @@ fn process_update_reserve_config(
         return Err(LendingError::InvalidMarketAuthority.into());
     }
 
-    if *pyth_price_info.key != reserve.liquidity.pyth_oracle_pubkey {
-        validate_pyth_keys(&lending_market, pyth_product_info, pyth_price_info)?;
-        reserve.liquidity.pyth_oracle_pubkey = *pyth_price_info.key;
+    reserve
+        .liquidity
+        .deploy(liquidity_amount, reserve.config.max_deployed_pct)?;
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    spl_token_transfer(TokenTransferParams {
+        source: reserve_liquidity_supply_info.clone(),
+        destination: strategy_vault_info.clone(),
+        amount: liquidity_amount,
+        authority: lending_market_authority_info.clone(),
+        authority_signer_seeds,
+        token_program: token_program_id.clone(),
+    })?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_recall_reserve_liquidity(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
     }
 
-    if *switchboard_feed_info.key != reserve.liquidity.switchboard_oracle_pubkey {
-        validate_switchboard_keys(&lending_market, switchboard_feed_info)?;
-        reserve.liquidity.switchboard_oracle_pubkey = *switchboard_feed_info.key;
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let strategy_vault_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let strategy_vault_authority_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
     }
-    if reserve.liquidity.switchboard_oracle_pubkey == solend_program::NULL_PUBKEY
-        && (*pyth_price_info.key == solend_program::NULL_PUBKEY
-            || *pyth_product_info.key == solend_program::NULL_PUBKEY)
-    {
-        msg!("At least one price oracle must have a non-null pubkey");
-        return Err(LendingError::InvalidOracleConfig.into());
+    if &lending_market.token_program_id != token_program_id.key {
+        msg!("Lending market token program does not match the token program provided");
+        return Err(LendingError::InvalidTokenProgram.into());
+    }
+    assert_lending_market_owner(&lending_market, lending_market_owner_info)?;
+
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.liquidity.supply_pubkey != reserve_liquidity_supply_info.key {
+        msg!("Reserve liquidity supply does not match the reserve liquidity supply provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if reserve.config.strategy_vault() != Some(*strategy_vault_info.key) {
+        msg!("Reserve strategy vault does not match the strategy vault provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if reserve.last_update.is_stale(clock.slot)? {
+        msg!("Reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
     }
 
-    reserve.config = config;
+    reserve.liquidity.recall(liquidity_amount)?;
+    reserve.last_update.mark_stale();
     Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    spl_token_transfer(TokenTransferParams {
+        source: strategy_vault_info.clone(),
+        destination: reserve_liquidity_supply_info.clone(),
+        amount: liquidity_amount,
+        authority: strategy_vault_authority_info.clone(),
+        authority_signer_seeds: &[],
+        token_program: token_program_id.clone(),
+    })?;
+
     Ok(())
 }
 
@@ -2080,7 +4187,7 @@ fn process_redeem_fees(program_id: &Pubkey, accounts: &[AccountInfo]) -> Program
 
     if &reserve.config.fee_receiver != reserve_liquidity_fee_receiver_info.key {
         msg!("Reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
-        return Err(LendingError::InvalidAccountInput.into());
+        return Err(LendingError::InvalidFeeReceiver.into());
     }
     if &reserve.liquidity.supply_pubkey != reserve_supply_liquidity_info.key {
         msg!("Reserve liquidity supply must be used as the reserve supply liquidity provided");
@@ -2231,10 +4338,10 @@ fn _flash_borrow_reserve_liquidity<'a>(
     if Decimal::from(liquidity_amount)
         .try_add(reserve.liquidity.borrowed_amount_wads)?
         .try_floor_u64()?
-        > reserve.config.borrow_limit
+        > reserve.borrow_limit()?
     {
         msg!("Cannot borrow above the borrow limit");
-        return Err(LendingError::InvalidAmount.into());
+        return Err(LendingError::BorrowLimitExceeded.into());
     }
 
     // Make sure this isnt a cpi call
@@ -2402,16 +4509,25 @@ fn _flash_repay_reserve_liquidity<'a>(
     }
     if &reserve.config.fee_receiver != reserve_liquidity_fee_receiver_info.key {
         msg!("Reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
-        return Err(LendingError::InvalidAccountInput.into());
+        return Err(LendingError::InvalidFeeReceiver.into());
     }
 
     let flash_loan_amount = liquidity_amount;
 
     let flash_loan_amount_decimal = Decimal::from(flash_loan_amount);
-    let (origination_fee, host_fee) = reserve
+    let (flash_loan_fee, host_fee) = reserve
         .config
         .fees
         .calculate_flash_loan_fees(flash_loan_amount_decimal)?;
+    // without a host, the host's cut stays with the reserve
+    let host_fee = if host_fee_receiver_info.key == reserve_liquidity_fee_receiver_info.key {
+        0
+    } else {
+        host_fee
+    };
+    let origination_fee = flash_loan_fee
+        .checked_sub(host_fee)
+        .ok_or(LendingError::MathOverflow)?;
 
     // Make sure this isnt a cpi call
     let current_index = load_current_index_checked(sysvar_info)? as usize;
@@ -2526,6 +4642,53 @@ fn assert_uninitialized<T: Pack + IsInitialized>(
     }
 }
 
+/// Checks the lending market owner provided signed. A renounced market has no owner left to sign
+/// for it.
+fn assert_lending_market_owner(
+    lending_market: &LendingMarket,
+    lending_market_owner_info: &AccountInfo,
+) -> ProgramResult {
+    if lending_market.is_owner_renounced() {
+        msg!("Lending market ownership has been renounced");
+        return Err(LendingError::Unauthorized.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    Ok(())
+}
+
+/// Checks a reserve's version before unpacking it, so a reserve on a layout obligations can't be
+/// valued against fails clearly rather than being read at the wrong offsets
+fn assert_reserve_version(reserve_info: &AccountInfo) -> ProgramResult {
+    let version = reserve_info
+        .data
+        .borrow()
+        .first()
+        .copied()
+        .unwrap_or(UNINITIALIZED_VERSION);
+    // uninitialized reserves are left for unpacking to reject
+    if version != UNINITIALIZED_VERSION
+        && !(MIN_RESERVE_VERSION..=PROGRAM_VERSION).contains(&version)
+    {
+        msg!(
+            "Reserve {} is at version {}, it must be migrated to a version from {} to {} before refreshing obligations",
+            reserve_info.key,
+            version,
+            MIN_RESERVE_VERSION,
+            PROGRAM_VERSION
+        );
+        return Err(LendingError::ReserveVersionMismatch.into());
+    }
+    Ok(())
+}
+
 /// Unpacks a spl_token `Mint`.
 fn unpack_mint(data: &[u8]) -> Result<Mint, LendingError> {
     Mint::unpack(data).map_err(|_| LendingError::InvalidTokenMint)
@@ -2551,28 +4714,106 @@ fn get_pyth_product_quote_currency(
         })
 }
 
-fn get_price(
+fn get_price<'a>(
     switchboard_feed_info: Option<&AccountInfo>,
-    pyth_price_account_info: &AccountInfo,
+    pyth_price_account_info: &AccountInfo<'a>,
+    extra_pyth_price_infos: &[&AccountInfo<'a>],
     clock: &Clock,
+    config: &ReserveConfig,
 ) -> Result<Decimal, ProgramError> {
-    let pyth_price = get_pyth_price(pyth_price_account_info, clock).unwrap_or_default();
+    get_price_and_confidence(
+        switchboard_feed_info,
+        pyth_price_account_info,
+        extra_pyth_price_infos,
+        clock,
+        config,
+    )
+    .map(|(market_price, _)| market_price)
+}
+
+/// The oracle price along with its confidence interval: pyth's confidence, or the standard
+/// deviation of the latest switchboard v2 round. Switchboard v1 reports none.
+fn get_price_and_confidence<'a>(
+    switchboard_feed_info: Option<&AccountInfo>,
+    pyth_price_account_info: &AccountInfo<'a>,
+    extra_pyth_price_infos: &[&AccountInfo<'a>],
+    clock: &Clock,
+    config: &ReserveConfig,
+) -> Result<(Decimal, Decimal), ProgramError> {
+    let (pyth_price, pyth_confidence) = get_median_pyth_price_and_confidence(
+        pyth_price_account_info,
+        extra_pyth_price_infos,
+        clock,
+        config,
+    )
+    .unwrap_or_default();
     if pyth_price != Decimal::zero() {
-        return Ok(pyth_price);
+        return Ok((pyth_price, pyth_confidence));
     }
 
     // if switchboard was not passed in don't try to grab the price
     if let Some(switchboard_feed_info_unwrapped) = switchboard_feed_info {
-        return get_switchboard_price(switchboard_feed_info_unwrapped, clock);
+        return get_switchboard_price(
+            switchboard_feed_info_unwrapped,
+            clock,
+            config.max_price_confidence_bps,
+        );
     }
 
     Err(LendingError::InvalidOracleConfig.into())
 }
 
+/// The median price of the pyth feeds that can be read, leaving out stale ones, along with its
+/// confidence. With an even number of them the middle two are averaged.
+fn get_median_pyth_price_and_confidence<'a>(
+    pyth_price_account_info: &AccountInfo<'a>,
+    extra_pyth_price_infos: &[&AccountInfo<'a>],
+    clock: &Clock,
+    config: &ReserveConfig,
+) -> Result<(Decimal, Decimal), ProgramError> {
+    let pyth_price_infos =
+        std::iter::once(pyth_price_account_info).chain(extra_pyth_price_infos.iter().copied());
+    let mut prices: Vec<(Decimal, Decimal)> = pyth_price_infos
+        .filter_map(|pyth_price_info| {
+            get_pyth_price_and_confidence(
+                pyth_price_info,
+                clock,
+                config.use_ema_price,
+                config.effective_max_price_age_slots(),
+            )
+            .ok()
+        })
+        .filter(|(price, _)| *price != Decimal::zero())
+        .collect();
+    if !extra_pyth_price_infos.is_empty() && prices.len() <= extra_pyth_price_infos.len() {
+        msg!(
+            "Only {} of {} pyth oracles have a usable price",
+            prices.len(),
+            extra_pyth_price_infos.len() + 1
+        );
+    }
+
+    prices.sort_unstable();
+    let middle = prices.len() / 2;
+    match prices.len() {
+        0 => Err(LendingError::InvalidOracleConfig.into()),
+        len if len % 2 == 1 => Ok(prices[middle]),
+        _ => {
+            let (low_price, low_confidence) = prices[middle - 1];
+            let (high_price, high_confidence) = prices[middle];
+            Ok((
+                low_price.try_add(high_price)?.try_div(2u64)?,
+                low_confidence.try_add(high_confidence)?.try_div(2u64)?,
+            ))
+        }
+    }
+}
+
 fn get_switchboard_price(
     switchboard_feed_info: &AccountInfo,
     clock: &Clock,
-) -> Result<Decimal, ProgramError> {
+    max_price_confidence_bps: u16,
+) -> Result<(Decimal, Decimal), ProgramError> {
     const STALE_AFTER_SLOTS_ELAPSED: u64 = 240;
 
     if *switchboard_feed_info.key == solend_program::NULL_PUBKEY {
@@ -2581,7 +4822,7 @@ fn get_switchboard_price(
     if switchboard_feed_info.owner == &switchboard_v2_mainnet::id()
         || switchboard_feed_info.owner == &switchboard_v2_devnet::id()
     {
-        return get_switchboard_price_v2(switchboard_feed_info, clock);
+        return get_switchboard_price_v2(switchboard_feed_info, clock, max_price_confidence_bps);
     }
 
     let account_buf = switchboard_feed_info.try_borrow_data()?;
@@ -2608,27 +4849,42 @@ fn get_switchboard_price(
     }
 
     let price_float = round_result.result.unwrap_or(0.0);
+    if price_float < 0.0 {
+        msg!("Switchboard oracle price is negative which is not allowed");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
 
     // we just do this so we can parse coins with low usd value
     // it might be better to just extract the mantissa and exponent from the float directly
     let price_quotient = 10u64.pow(9);
     let price = ((price_quotient as f64) * price_float) as u128;
 
-    Decimal::from(price).try_div(price_quotient)
+    Ok((
+        Decimal::from(price).try_div(price_quotient)?,
+        Decimal::zero(),
+    ))
 }
 
 fn get_switchboard_price_v2(
     switchboard_feed_info: &AccountInfo,
     clock: &Clock,
-) -> Result<Decimal, ProgramError> {
+    max_price_confidence_bps: u16,
+) -> Result<(Decimal, Decimal), ProgramError> {
     const STALE_AFTER_SLOTS_ELAPSED: u64 = 240;
 
     let feed = AggregatorAccountData::new(switchboard_feed_info)?;
+    // feeds publishing on a heartbeat must have updated within it instead of the default window
+    let stale_after_slots_elapsed = match u64::try_from(feed.force_report_period) {
+        Ok(heartbeat_seconds) if heartbeat_seconds > 0 => heartbeat_seconds
+            .checked_mul(SLOTS_PER_SECOND)
+            .ok_or(LendingError::MathOverflow)?,
+        _ => STALE_AFTER_SLOTS_ELAPSED,
+    };
     let slots_elapsed = clock
         .slot
         .checked_sub(feed.latest_confirmed_round.round_open_slot)
         .ok_or(LendingError::MathOverflow)?;
-    if slots_elapsed >= STALE_AFTER_SLOTS_ELAPSED {
+    if slots_elapsed >= stale_after_slots_elapsed {
         msg!("Switchboard oracle price is stale");
         return Err(LendingError::InvalidOracleConfig.into());
     }
@@ -2640,7 +4896,27 @@ fn get_switchboard_price_v2(
     }
     let price = Decimal::from(price_switchboard_desc.mantissa as u128);
     let exp = Decimal::from((10u128).checked_pow(price_switchboard_desc.scale).unwrap());
-    price.try_div(exp)
+    let price = price.try_div(exp)?;
+
+    let std_deviation_desc = feed.latest_confirmed_round.std_deviation;
+    let std_deviation =
+        Decimal::from(std_deviation_desc.mantissa.max(0) as u128).try_div(Decimal::from(
+            10u128
+                .checked_pow(std_deviation_desc.scale)
+                .ok_or(LendingError::MathOverflow)?,
+        ))?;
+    if max_price_confidence_bps > 0
+        && std_deviation.try_mul(10_000u64)? > price.try_mul(max_price_confidence_bps as u64)?
+    {
+        msg!(
+            "Switchboard oracle std deviation is too wide. price: {}, std deviation: {}",
+            price,
+            std_deviation
+        );
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    Ok((price, std_deviation))
 }
 
 /// Issue a spl_token `InitializeAccount` instruction.
@@ -2776,50 +5052,20 @@ fn spl_token_burn(params: TokenBurnParams<'_, '_>) -> ProgramResult {
     result.map_err(|_| LendingError::TokenBurnFailed.into())
 }
 
-/// validates reserve configs
-#[inline(always)]
-fn validate_reserve_config(config: ReserveConfig) -> ProgramResult {
-    if config.optimal_utilization_rate > 100 {
-        msg!("Optimal utilization rate must be in range [0, 100]");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.loan_to_value_ratio >= 100 {
-        msg!("Loan to value ratio must be in range [0, 100)");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.liquidation_bonus > 100 {
-        msg!("Liquidation bonus must be in range [0, 100]");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.liquidation_threshold < config.loan_to_value_ratio
-        || config.liquidation_threshold > 100
+/// validates a collateral reserve's loan to value ratio against the market's minimum
+fn validate_collateral_ltv(
+    config: &ReserveConfig,
+    lending_market: &LendingMarket,
+) -> ProgramResult {
+    if config.usable_as_collateral
+        && config.loan_to_value_ratio < lending_market.config.min_collateral_ltv_pct
     {
-        msg!("Liquidation threshold must be in range [LTV, 100]");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.optimal_borrow_rate < config.min_borrow_rate {
-        msg!("Optimal borrow rate must be >= min borrow rate");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.optimal_borrow_rate > config.max_borrow_rate {
-        msg!("Optimal borrow rate must be <= max borrow rate");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.fees.borrow_fee_wad >= WAD {
-        msg!("Borrow fee must be in range [0, 1_000_000_000_000_000_000)");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.fees.host_fee_percentage > 100 {
-        msg!("Host fee percentage must be in range [0, 100]");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.protocol_liquidation_fee > 100 {
-        msg!("Protocol liquidation fee must be in range [0, 100]");
-        return Err(LendingError::InvalidConfig.into());
-    }
-    if config.protocol_take_rate > 100 {
-        msg!("Protocol take rate must be in range [0, 100]");
-        return Err(LendingError::InvalidConfig.into());
+        msg!(
+            "Loan to value ratio of {}% is below the lending market's minimum of {}% for reserves usable as collateral, mark the reserve supply-only instead",
+            config.loan_to_value_ratio,
+            lending_market.config.min_collateral_ltv_pct
+        );
+        return Err(LendingError::CollateralLtvTooLow.into());
     }
     Ok(())
 }
@@ -1,4 +1,5 @@
 use super::*;
+use crate::math::{Decimal, Rate};
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
     msg,
@@ -25,6 +26,8 @@ pub struct LendingMarket {
     pub oracle_program_id: Pubkey,
     /// Oracle (Switchboard) program id
     pub switchboard_oracle_program_id: Pubkey,
+    /// Market-wide settings the owner sets with UpdateLendingMarket
+    pub config: LendingMarketConfig,
 }
 
 impl LendingMarket {
@@ -44,6 +47,315 @@ impl LendingMarket {
         self.token_program_id = params.token_program_id;
         self.oracle_program_id = params.oracle_program_id;
         self.switchboard_oracle_program_id = params.switchboard_oracle_program_id;
+        self.config = LendingMarketConfig::default();
+    }
+
+    /// Whether the owner has been given up for good, leaving no one to change the market
+    pub fn is_owner_renounced(&self) -> bool {
+        self.owner == crate::NULL_PUBKEY
+    }
+
+    /// Maximum liquidation bonus value per liquidation, if the market caps it
+    pub fn max_liquidation_bonus_value(&self) -> Option<Decimal> {
+        if self.config.max_liquidation_bonus_usd == 0 {
+            None
+        } else {
+            Some(Decimal::from(self.config.max_liquidation_bonus_usd))
+        }
+    }
+
+    /// Borrowed value below which obligations don't accrue interest, if the market grants one
+    pub fn interest_free_borrow_value(&self) -> Option<Decimal> {
+        if self.config.interest_free_borrow_value_usd == 0 {
+            None
+        } else {
+            Some(Decimal::from(self.config.interest_free_borrow_value_usd))
+        }
+    }
+
+    /// Share of the bonus withheld from liquidations that don't restore health, if the market
+    /// claws it back
+    pub fn liquidation_bonus_clawback_rate(&self) -> Option<Rate> {
+        if self.config.liquidation_bonus_clawback_pct == 0 {
+            None
+        } else {
+            Some(Rate::from_percent(
+                self.config.liquidation_bonus_clawback_pct,
+            ))
+        }
+    }
+
+    /// Borrowed value new borrows can't take an obligation past, if the market caps it
+    pub fn max_obligation_borrow_value(&self) -> Option<Decimal> {
+        if self.config.max_obligation_borrow_usd == 0 {
+            None
+        } else {
+            Some(Decimal::from(self.config.max_obligation_borrow_usd))
+        }
+    }
+
+    /// Ratio of borrowed to deposited value liquidations stop at, if the market sets one
+    pub fn post_liquidation_target_ltv(&self) -> Option<Rate> {
+        if self.config.post_liquidation_target_ltv == 0 {
+            None
+        } else {
+            Some(Rate::from_percent(self.config.post_liquidation_target_ltv))
+        }
+    }
+
+    /// Share of an obligation's allowed borrow value kept free by borrows and withdrawals
+    pub fn borrow_health_buffer(&self) -> Rate {
+        Rate::from_bps(self.config.borrow_health_buffer_bps)
+    }
+}
+
+/// Market-wide settings of a lending market
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LendingMarketConfig {
+    /// Maximum liquidation bonus paid out per liquidation instruction, in whole units of the
+    /// quote currency. 0 means the bonus is uncapped
+    pub max_liquidation_bonus_usd: u64,
+    /// Borrowed value, in whole units of the quote currency, below which an obligation's borrows
+    /// don't accrue interest when it is refreshed. 0 means every borrow accrues interest
+    pub interest_free_borrow_value_usd: u64,
+    /// Percent of the bonus withheld from liquidations that leave the obligation unhealthy, paid
+    /// out instead by the liquidation that restores its health. 0 pays the full bonus every time
+    pub liquidation_bonus_clawback_pct: u8,
+    /// Whether the market is deleveraging, blocking new borrows and deposits in every reserve
+    /// while repayments, withdrawals and liquidations proceed
+    pub deleveraging: bool,
+    /// Basis points of an obligation's allowed borrow value that borrows and withdrawals must
+    /// leave unused as a health buffer. 0 lets them use the full allowed borrow value
+    pub borrow_health_buffer_bps: u16,
+    /// Number of slots after a liquidation during which the same obligation can't be liquidated
+    /// again. 0 allows back to back liquidations
+    pub liquidation_cooldown_slots: u64,
+    /// Whether anyone can deposit collateral into an obligation without its owner signing, with
+    /// the depositor funding it as the transfer authority. Only collateral the obligation already
+    /// has can be added to this way, and withdrawals still need the owner
+    pub allow_sponsored_deposits: bool,
+    /// Total borrowed value, in whole units of the quote currency, past which an obligation
+    /// can't take new borrows from any reserve. 0 means obligations are uncapped
+    pub max_obligation_borrow_usd: u64,
+    /// Whether obligations are valued at the conservative end of each oracle's confidence
+    /// interval: collateral at the price less its confidence, debt at the price plus it.
+    /// Reserves keep a copy, and refreshing an obligation without the market account fails for
+    /// reserves that have it set
+    pub use_price_confidence: bool,
+    /// Lowest loan to value ratio, in percent, a reserve usable as collateral can be configured
+    /// with. Supply-only reserves are exempt. 0 allows any ratio
+    pub min_collateral_ltv_pct: u8,
+    /// Ratio of borrowed to deposited value, in percent, that a liquidation brings an obligation
+    /// back down to and no further, repaying less than the close factor allows when that is
+    /// enough. 0 only limits liquidations by the close factor
+    pub post_liquidation_target_ltv: u8,
+}
+
+impl LendingMarketConfig {
+    /// Overwrite the settings the update sets, keeping the rest
+    pub fn apply(&mut self, update: &LendingMarketConfigUpdate) {
+        if let Some(value) = update.max_liquidation_bonus_usd {
+            self.max_liquidation_bonus_usd = value;
+        }
+        if let Some(value) = update.interest_free_borrow_value_usd {
+            self.interest_free_borrow_value_usd = value;
+        }
+        if let Some(value) = update.liquidation_bonus_clawback_pct {
+            self.liquidation_bonus_clawback_pct = value;
+        }
+        if let Some(value) = update.deleveraging {
+            self.deleveraging = value;
+        }
+        if let Some(value) = update.borrow_health_buffer_bps {
+            self.borrow_health_buffer_bps = value;
+        }
+        if let Some(value) = update.liquidation_cooldown_slots {
+            self.liquidation_cooldown_slots = value;
+        }
+        if let Some(value) = update.allow_sponsored_deposits {
+            self.allow_sponsored_deposits = value;
+        }
+        if let Some(value) = update.max_obligation_borrow_usd {
+            self.max_obligation_borrow_usd = value;
+        }
+        if let Some(value) = update.use_price_confidence {
+            self.use_price_confidence = value;
+        }
+        if let Some(value) = update.min_collateral_ltv_pct {
+            self.min_collateral_ltv_pct = value;
+        }
+        if let Some(value) = update.post_liquidation_target_ltv {
+            self.post_liquidation_target_ltv = value;
+        }
+    }
+}
+
+impl Sealed for LendingMarketConfig {}
+
+// reserves room for new settings without changing the instruction layout
+const LENDING_MARKET_CONFIG_LEN: usize = 128; // 8 + 8 + 1 + 1 + 2 + 8 + 1 + 8 + 1 + 1 + 1 + 88
+impl Pack for LendingMarketConfig {
+    const LEN: usize = LENDING_MARKET_CONFIG_LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, LENDING_MARKET_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            max_liquidation_bonus_usd,
+            interest_free_borrow_value_usd,
+            liquidation_bonus_clawback_pct,
+            deleveraging,
+            borrow_health_buffer_bps,
+            liquidation_cooldown_slots,
+            allow_sponsored_deposits,
+            max_obligation_borrow_usd,
+            use_price_confidence,
+            min_collateral_ltv_pct,
+            post_liquidation_target_ltv,
+            _padding,
+        ) = mut_array_refs![output, 8, 8, 1, 1, 2, 8, 1, 8, 1, 1, 1, 88];
+
+        *max_liquidation_bonus_usd = self.max_liquidation_bonus_usd.to_le_bytes();
+        *interest_free_borrow_value_usd = self.interest_free_borrow_value_usd.to_le_bytes();
+        *liquidation_bonus_clawback_pct = self.liquidation_bonus_clawback_pct.to_le_bytes();
+        pack_bool(self.deleveraging, deleveraging);
+        *borrow_health_buffer_bps = self.borrow_health_buffer_bps.to_le_bytes();
+        *liquidation_cooldown_slots = self.liquidation_cooldown_slots.to_le_bytes();
+        pack_bool(self.allow_sponsored_deposits, allow_sponsored_deposits);
+        *max_obligation_borrow_usd = self.max_obligation_borrow_usd.to_le_bytes();
+        pack_bool(self.use_price_confidence, use_price_confidence);
+        *min_collateral_ltv_pct = self.min_collateral_ltv_pct.to_le_bytes();
+        *post_liquidation_target_ltv = self.post_liquidation_target_ltv.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, LENDING_MARKET_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            max_liquidation_bonus_usd,
+            interest_free_borrow_value_usd,
+            liquidation_bonus_clawback_pct,
+            deleveraging,
+            borrow_health_buffer_bps,
+            liquidation_cooldown_slots,
+            allow_sponsored_deposits,
+            max_obligation_borrow_usd,
+            use_price_confidence,
+            min_collateral_ltv_pct,
+            post_liquidation_target_ltv,
+            _padding,
+        ) = array_refs![input, 8, 8, 1, 1, 2, 8, 1, 8, 1, 1, 1, 88];
+
+        Ok(Self {
+            max_liquidation_bonus_usd: u64::from_le_bytes(*max_liquidation_bonus_usd),
+            interest_free_borrow_value_usd: u64::from_le_bytes(*interest_free_borrow_value_usd),
+            liquidation_bonus_clawback_pct: u8::from_le_bytes(*liquidation_bonus_clawback_pct),
+            deleveraging: unpack_bool(deleveraging)?,
+            borrow_health_buffer_bps: u16::from_le_bytes(*borrow_health_buffer_bps),
+            liquidation_cooldown_slots: u64::from_le_bytes(*liquidation_cooldown_slots),
+            allow_sponsored_deposits: unpack_bool(allow_sponsored_deposits)?,
+            max_obligation_borrow_usd: u64::from_le_bytes(*max_obligation_borrow_usd),
+            use_price_confidence: unpack_bool(use_price_confidence)?,
+            min_collateral_ltv_pct: u8::from_le_bytes(*min_collateral_ltv_pct),
+            post_liquidation_target_ltv: u8::from_le_bytes(*post_liquidation_target_ltv),
+        })
+    }
+}
+
+/// Changes to a lending market's settings, see `LendingMarketConfig`. Settings left as `None`
+/// keep their current value
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LendingMarketConfigUpdate {
+    /// New maximum liquidation bonus per liquidation
+    pub max_liquidation_bonus_usd: Option<u64>,
+    /// New interest free borrow value
+    pub interest_free_borrow_value_usd: Option<u64>,
+    /// New liquidation bonus clawback percent
+    pub liquidation_bonus_clawback_pct: Option<u8>,
+    /// Start or stop deleveraging the market
+    pub deleveraging: Option<bool>,
+    /// New borrow health buffer
+    pub borrow_health_buffer_bps: Option<u16>,
+    /// New liquidation cooldown
+    pub liquidation_cooldown_slots: Option<u64>,
+    /// Allow or disallow sponsored deposits
+    pub allow_sponsored_deposits: Option<bool>,
+    /// New obligation borrow cap
+    pub max_obligation_borrow_usd: Option<u64>,
+    /// Turn confidence pricing on or off
+    pub use_price_confidence: Option<bool>,
+    /// New minimum collateral loan to value ratio
+    pub min_collateral_ltv_pct: Option<u8>,
+    /// New post liquidation target loan to value ratio
+    pub post_liquidation_target_ltv: Option<u8>,
+}
+
+impl LendingMarketConfigUpdate {
+    // one bit per setting, in `LendingMarketConfig` order
+    fn field_mask(&self) -> u16 {
+        [
+            self.max_liquidation_bonus_usd.is_some(),
+            self.interest_free_borrow_value_usd.is_some(),
+            self.liquidation_bonus_clawback_pct.is_some(),
+            self.deleveraging.is_some(),
+            self.borrow_health_buffer_bps.is_some(),
+            self.liquidation_cooldown_slots.is_some(),
+            self.allow_sponsored_deposits.is_some(),
+            self.max_obligation_borrow_usd.is_some(),
+            self.use_price_confidence.is_some(),
+            self.min_collateral_ltv_pct.is_some(),
+            self.post_liquidation_target_ltv.is_some(),
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |mask, (bit, is_set)| mask | (u16::from(*is_set) << bit))
+    }
+}
+
+impl Sealed for LendingMarketConfigUpdate {}
+
+const LENDING_MARKET_CONFIG_UPDATE_FIELDS: u32 = 11;
+const LENDING_MARKET_CONFIG_UPDATE_LEN: usize = 130; // 2 + 128
+impl Pack for LendingMarketConfigUpdate {
+    const LEN: usize = LENDING_MARKET_CONFIG_UPDATE_LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, LENDING_MARKET_CONFIG_UPDATE_LEN];
+        let (field_mask, values) = mut_array_refs![output, 2, LENDING_MARKET_CONFIG_LEN];
+
+        let mut config = LendingMarketConfig::default();
+        config.apply(self);
+        *field_mask = self.field_mask().to_le_bytes();
+        config.pack_into_slice(values);
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, LENDING_MARKET_CONFIG_UPDATE_LEN];
+        let (field_mask, values) = array_refs![input, 2, LENDING_MARKET_CONFIG_LEN];
+
+        let field_mask = u16::from_le_bytes(*field_mask);
+        if field_mask >> LENDING_MARKET_CONFIG_UPDATE_FIELDS != 0 {
+            msg!("Lending market config update sets an unknown setting");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let config = LendingMarketConfig::unpack_from_slice(values)?;
+        let is_set = |bit: u32| field_mask & (1 << bit) != 0;
+
+        Ok(Self {
+            max_liquidation_bonus_usd: is_set(0).then_some(config.max_liquidation_bonus_usd),
+            interest_free_borrow_value_usd: is_set(1)
+                .then_some(config.interest_free_borrow_value_usd),
+            liquidation_bonus_clawback_pct: is_set(2)
+                .then_some(config.liquidation_bonus_clawback_pct),
+            deleveraging: is_set(3).then_some(config.deleveraging),
+            borrow_health_buffer_bps: is_set(4).then_some(config.borrow_health_buffer_bps),
+            liquidation_cooldown_slots: is_set(5).then_some(config.liquidation_cooldown_slots),
+            allow_sponsored_deposits: is_set(6).then_some(config.allow_sponsored_deposits),
+            max_obligation_borrow_usd: is_set(7).then_some(config.max_obligation_borrow_usd),
+            use_price_confidence: is_set(8).then_some(config.use_price_confidence),
+            min_collateral_ltv_pct: is_set(9).then_some(config.min_collateral_ltv_pct),
+            post_liquidation_target_ltv: is_set(10).then_some(config.post_liquidation_target_ltv),
+        })
     }
 }
 
@@ -86,7 +398,7 @@ impl Pack for LendingMarket {
             token_program_id,
             oracle_program_id,
             switchboard_oracle_program_id,
-            _padding,
+            config,
         ) = mut_array_refs![
             output,
             1,
@@ -96,7 +408,7 @@ impl Pack for LendingMarket {
             PUBKEY_BYTES,
             PUBKEY_BYTES,
             PUBKEY_BYTES,
-            128
+            LENDING_MARKET_CONFIG_LEN
         ];
 
         *version = self.version.to_le_bytes();
@@ -106,6 +418,7 @@ impl Pack for LendingMarket {
         token_program_id.copy_from_slice(self.token_program_id.as_ref());
         oracle_program_id.copy_from_slice(self.oracle_program_id.as_ref());
         switchboard_oracle_program_id.copy_from_slice(self.switchboard_oracle_program_id.as_ref());
+        self.config.pack_into_slice(config);
     }
 
     /// Unpacks a byte buffer into a [LendingMarketInfo](struct.LendingMarketInfo.html)
@@ -120,7 +433,7 @@ impl Pack for LendingMarket {
             token_program_id,
             oracle_program_id,
             switchboard_oracle_program_id,
-            _padding,
+            config,
         ) = array_refs![
             input,
             1,
@@ -130,7 +443,7 @@ impl Pack for LendingMarket {
             PUBKEY_BYTES,
             PUBKEY_BYTES,
             PUBKEY_BYTES,
-            128
+            LENDING_MARKET_CONFIG_LEN
         ];
 
         let version = u8::from_le_bytes(*version);
@@ -147,6 +460,7 @@ impl Pack for LendingMarket {
             token_program_id: Pubkey::new_from_array(*token_program_id),
             oracle_program_id: Pubkey::new_from_array(*oracle_program_id),
             switchboard_oracle_program_id: Pubkey::new_from_array(*switchboard_oracle_program_id),
+            config: LendingMarketConfig::unpack_from_slice(config)?,
         })
     }
 }
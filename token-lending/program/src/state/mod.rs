@@ -17,14 +17,25 @@ use solana_program::{msg, program_error::ProgramError};
 // @FIXME: restore to 5
 pub const INITIAL_COLLATERAL_RATIO: u64 = 1;
 const INITIAL_COLLATERAL_RATE: u64 = INITIAL_COLLATERAL_RATIO * WAD;
+/// Lowest collateral exchange rate a reserve can be initialized at, 1:100 (collateral:liquidity)
+pub const MIN_INITIAL_COLLATERAL_RATE: u64 = WAD / 100;
+/// Highest collateral exchange rate a reserve can be initialized at, 10:1 (collateral:liquidity)
+pub const MAX_INITIAL_COLLATERAL_RATE: u64 = 10 * WAD;
 
 /// Current version of the program and all new accounts created
 pub const PROGRAM_VERSION: u8 = 1;
 
+/// Oldest reserve version obligations can be refreshed against, raised whenever a reserve layout
+/// change means older reserves must be migrated before they can be valued
+pub const MIN_RESERVE_VERSION: u8 = 1;
+
 /// Accounts are created with data zeroed out, so uninitialized state instances
 /// will have the version set to 0.
 pub const UNINITIALIZED_VERSION: u8 = 0;
 
+/// Number of slots per second
+pub const SLOTS_PER_SECOND: u64 = 2;
+
 /// Number of slots per year
 // 2 (slots per second) * 60 * 60 * 24 * 365 = 63072000
 pub const SLOTS_PER_YEAR: u64 = 63072000;
@@ -8,6 +8,7 @@ use solana_program::{
     clock::Slot,
     entrypoint::ProgramResult,
     msg,
+    program::MAX_RETURN_DATA,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::{Pubkey, PUBKEY_BYTES},
@@ -20,6 +21,16 @@ use std::{
 /// Max number of collateral and liquidity reserve accounts combined for an obligation
 pub const MAX_OBLIGATION_RESERVES: usize = 10;
 
+/// Number of slots a withheld liquidation bonus waits for the liquidation that restores health
+pub const LIQUIDATION_BONUS_DEFERRAL_SLOTS: u64 = 60 * SLOTS_PER_SECOND; // roughly 1 min
+
+/// Max number of obligations summarized by a single BatchObligationSummary, so that the
+/// summaries fit in the return data
+pub const MAX_OBLIGATION_SUMMARIES: usize = MAX_RETURN_DATA / OBLIGATION_SUMMARY_LEN;
+
+/// Current layout version of ObligationSnapshot
+pub const OBLIGATION_SNAPSHOT_VERSION: u8 = 1;
+
 /// Lending market obligation state
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Obligation {
@@ -43,6 +54,21 @@ pub struct Obligation {
     pub allowed_borrow_value: Decimal,
     /// The dangerous borrow value at the weighted average liquidation threshold
     pub unhealthy_borrow_value: Decimal,
+    /// Whether the lending market owner allows this obligation to borrow from permissioned
+    /// reserves
+    pub whitelisted: bool,
+    /// Market value of the liquidation bonus withheld from liquidations that left the obligation
+    /// unhealthy
+    pub deferred_liquidation_bonus_value: Decimal,
+    /// Slot of the last liquidation a bonus was withheld from
+    pub deferred_liquidation_bonus_slot: Slot,
+    /// Delegate authority which can deposit collateral on the owner's behalf
+    pub delegate: Pubkey,
+    /// Slot of the last liquidation, 0 if never liquidated
+    pub last_liquidation_slot: Slot,
+    /// Tranche the obligation belongs to, it can only deposit into and borrow from reserves
+    /// of the same tranche
+    pub tranche: u8,
 }
 
 impl Obligation {
@@ -61,13 +87,231 @@ impl Obligation {
         self.owner = params.owner;
         self.deposits = params.deposits;
         self.borrows = params.borrows;
+        self.tranche = params.tranche;
+    }
+
+    /// Check whether a liquidation at `slot` falls within `cooldown_slots` of the last one
+    pub fn in_liquidation_cooldown(&self, slot: Slot, cooldown_slots: u64) -> bool {
+        self.last_liquidation_slot != 0
+            && slot.saturating_sub(self.last_liquidation_slot) < cooldown_slots
+    }
+
+    /// Check whether `authority` is the obligation's delegate
+    pub fn is_delegate(&self, authority: &Pubkey) -> bool {
+        self.delegate != Pubkey::default() && &self.delegate == authority
     }
 
     /// Calculate the current ratio of borrowed value to deposited value
     pub fn loan_to_value(&self) -> Result<Decimal, ProgramError> {
+        // an obligation without borrows has nothing at risk, even without deposits
+        if self.borrowed_value == Decimal::zero() {
+            return Ok(Decimal::zero());
+        }
         self.borrowed_value.try_div(self.deposited_value)
     }
 
+    /// Calculate the deposited value minus the borrowed value, returned as its magnitude and
+    /// whether it is negative (the obligation is underwater)
+    pub fn net_value(&self) -> Result<(Decimal, bool), ProgramError> {
+        if self.borrowed_value > self.deposited_value {
+            Ok((self.borrowed_value.try_sub(self.deposited_value)?, true))
+        } else {
+            Ok((self.deposited_value.try_sub(self.borrowed_value)?, false))
+        }
+    }
+
+    /// Recompute the values a refresh would give the obligation if the reserves in
+    /// `price_overrides` traded at the given market prices. `reserves` are the deposit reserves
+    /// followed by the borrow reserves, in the order RefreshObligation takes them. Meant for
+    /// off-chain stress testing, the obligation itself is left untouched.
+    pub fn health_at_prices(
+        &self,
+        price_overrides: &[(Pubkey, Decimal)],
+        reserves: &[Reserve],
+    ) -> Result<ObligationValues, ProgramError> {
+        if reserves.len() != self.deposits.len() + self.borrows.len() {
+            msg!("Obligation deposit and borrow reserves must all be provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        let (deposit_reserves, borrow_reserves) = reserves.split_at(self.deposits.len());
+        let market_price = |reserve_pubkey: &Pubkey, reserve: &Reserve| {
+            price_overrides
+                .iter()
+                .find(|(pubkey, _)| pubkey == reserve_pubkey)
+                .map_or(reserve.liquidity.market_price, |(_, price)| *price)
+        };
+
+        let mut values = ObligationValues::default();
+        for (collateral, deposit_reserve) in self.deposits.iter().zip(deposit_reserves) {
+            let collateral_price = deposit_reserve
+                .collateral_price_at(market_price(&collateral.deposit_reserve, deposit_reserve));
+
+            let market_value = deposit_reserve.liquidity.market_value_at(
+                deposit_reserve
+                    .collateral_exchange_rate()?
+                    .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?,
+                collateral_price,
+            )?;
+            let borrowable_value = if collateral.protected_amount == 0 {
+                market_value
+            } else {
+                market_value
+                    .try_mul(collateral.unprotected_amount()?)?
+                    .try_div(collateral.deposited_amount)?
+            };
+
+            let loan_to_value_rate = Rate::from_percent(deposit_reserve.config.loan_to_value_ratio);
+            let liquidation_threshold_rate =
+                deposit_reserve.liquidation_threshold(self.last_update.slot)?;
+
+            values.deposited_value = values.deposited_value.try_add(market_value)?;
+            values.allowed_borrow_value = values
+                .allowed_borrow_value
+                .try_add(borrowable_value.try_mul(loan_to_value_rate)?)?;
+            values.unhealthy_borrow_value = values
+                .unhealthy_borrow_value
+                .try_add(market_value.try_mul(liquidation_threshold_rate)?)?;
+        }
+
+        for (liquidity, borrow_reserve) in self.borrows.iter().zip(borrow_reserves) {
+            let mut liquidity = liquidity.clone();
+            liquidity.accrue_interest(borrow_reserve.liquidity.cumulative_borrow_rate_wads)?;
+            let market_value = borrow_reserve.liquidity.market_value_at(
+                liquidity.borrowed_amount_wads,
+                market_price(&liquidity.borrow_reserve, borrow_reserve),
+            )?;
+
+            values.borrowed_value = values.borrowed_value.try_add(market_value)?;
+        }
+
+        Ok(values)
+    }
+
+    /// Deposit reserves whose market price falling by `price_drop_bps`, with every other price
+    /// as last refreshed, would leave the obligation open to liquidation. `reserves` are as for
+    /// `health_at_prices`. An obligation that can already be liquidated lists all of them.
+    pub fn positions_at_risk(
+        &self,
+        price_drop_bps: u16,
+        reserves: &[Reserve],
+    ) -> Result<Vec<Pubkey>, ProgramError> {
+        if price_drop_bps > 10_000 {
+            msg!("Price drop must be at most 10000 basis points");
+            return Err(LendingError::InvalidAmount.into());
+        }
+        if reserves.len() != self.deposits.len() + self.borrows.len() {
+            msg!("Obligation deposit and borrow reserves must all be provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        let remaining_price_rate = Rate::one().try_sub(Rate::from_bps(price_drop_bps))?;
+
+        let mut at_risk = vec![];
+        for (collateral, deposit_reserve) in self.deposits.iter().zip(reserves) {
+            let dropped_price = deposit_reserve
+                .liquidity
+                .market_price
+                .try_mul(remaining_price_rate)?;
+            let values =
+                self.health_at_prices(&[(collateral.deposit_reserve, dropped_price)], reserves)?;
+            if values.borrowed_value > Decimal::zero()
+                && values.borrowed_value >= values.unhealthy_borrow_value
+            {
+                at_risk.push(collateral.deposit_reserve);
+            }
+        }
+        Ok(at_risk)
+    }
+
+    /// Calculate the average of the borrow reserves' current borrow rates, weighted by the
+    /// market value of each borrow as of the last refresh. `reserves` are the borrow reserves,
+    /// in the order of the obligation's borrows.
+    pub fn weighted_borrow_rate(&self, reserves: &[Reserve]) -> Result<Rate, ProgramError> {
+        if reserves.len() != self.borrows.len() {
+            msg!("Obligation borrow reserves must all be provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+
+        let mut borrowed_value = Decimal::zero();
+        let mut weighted_rate_sum = Decimal::zero();
+        for (liquidity, borrow_reserve) in self.borrows.iter().zip(reserves) {
+            borrowed_value = borrowed_value.try_add(liquidity.market_value)?;
+            weighted_rate_sum = weighted_rate_sum.try_add(
+                liquidity
+                    .market_value
+                    .try_mul(borrow_reserve.current_borrow_rate()?)?,
+            )?;
+        }
+
+        if borrowed_value == Decimal::zero() {
+            return Ok(Rate::zero());
+        }
+        Rate::try_from(weighted_rate_sum.try_div(borrowed_value)?)
+    }
+
+    /// Adjust the collateral withdrawn by a liquidation, returning the new withdraw amount.
+    /// A liquidation that leaves the obligation unhealthy has `clawback_rate` of its bonus
+    /// withheld, and the one that restores health is also paid the bonus withheld within the
+    /// last LIQUIDATION_BONUS_DEFERRAL_SLOTS. Health is judged as if the full bonus were paid.
+    #[allow(clippy::too_many_arguments)]
+    pub fn defer_liquidation_bonus(
+        &mut self,
+        settle_amount: Decimal,
+        withdraw_amount: u64,
+        liquidity_index: usize,
+        collateral_index: usize,
+        liquidation_threshold: Rate,
+        clawback_rate: Rate,
+        slot: Slot,
+    ) -> Result<u64, ProgramError> {
+        let liquidity = &self.borrows[liquidity_index];
+        let collateral = &self.deposits[collateral_index];
+        let deposited_amount = collateral.deposited_amount;
+        let repaid_value = liquidity
+            .market_value
+            .try_mul(settle_amount)?
+            .try_div(liquidity.borrowed_amount_wads)?;
+        let withdrawn_value = collateral
+            .market_value
+            .try_mul(withdraw_amount)?
+            .try_div(deposited_amount)?;
+
+        let remaining_borrowed_value = self
+            .borrowed_value
+            .try_sub(repaid_value.min(self.borrowed_value))?;
+        let withdrawn_unhealthy_value = withdrawn_value.try_mul(liquidation_threshold)?;
+        let remaining_unhealthy_borrow_value = self
+            .unhealthy_borrow_value
+            .try_sub(withdrawn_unhealthy_value.min(self.unhealthy_borrow_value))?;
+
+        if slot.saturating_sub(self.deferred_liquidation_bonus_slot)
+            > LIQUIDATION_BONUS_DEFERRAL_SLOTS
+        {
+            self.deferred_liquidation_bonus_value = Decimal::zero();
+        }
+
+        if remaining_borrowed_value >= remaining_unhealthy_borrow_value {
+            let bonus_value = withdrawn_value.try_sub(repaid_value.min(withdrawn_value))?;
+            let clawback_value = bonus_value.try_mul(clawback_rate)?;
+            self.deferred_liquidation_bonus_value = self
+                .deferred_liquidation_bonus_value
+                .try_add(clawback_value)?;
+            self.deferred_liquidation_bonus_slot = slot;
+
+            Decimal::from(withdraw_amount)
+                .try_mul(withdrawn_value.try_sub(clawback_value)?)?
+                .try_div(withdrawn_value)?
+                .try_floor_u64()
+        } else {
+            let deferred_amount = Decimal::from(withdraw_amount)
+                .try_mul(std::mem::take(&mut self.deferred_liquidation_bonus_value))?
+                .try_div(withdrawn_value)?
+                .try_floor_u64()?;
+            Ok(withdraw_amount
+                .saturating_add(deferred_amount)
+                .min(deposited_amount))
+        }
+    }
+
     /// Repay liquidity and remove it from borrows if zeroed out
     pub fn repay(&mut self, settle_amount: Decimal, liquidity_index: usize) -> ProgramResult {
         let liquidity = &mut self.borrows[liquidity_index];
@@ -90,35 +334,111 @@ impl Obligation {
         Ok(())
     }
 
+    /// Calculate the allowed borrow value left to borrows and withdrawals once `health_buffer`
+    /// of it is kept free
+    fn buffered_allowed_borrow_value(&self, health_buffer: Rate) -> Result<Decimal, ProgramError> {
+        self.allowed_borrow_value
+            .try_mul(Rate::one().try_sub(health_buffer)?)
+    }
+
     /// Calculate the maximum collateral value that can be withdrawn
     pub fn max_withdraw_value(
         &self,
         withdraw_collateral_ltv: Rate,
+        health_buffer: Rate,
     ) -> Result<Decimal, ProgramError> {
-        if self.allowed_borrow_value <= self.borrowed_value {
+        let allowed_borrow_value = self.buffered_allowed_borrow_value(health_buffer)?;
+        if allowed_borrow_value <= self.borrowed_value {
             return Ok(Decimal::zero());
         }
         if withdraw_collateral_ltv == Rate::zero() {
             return Ok(self.deposited_value);
         }
-        self.allowed_borrow_value
+        allowed_borrow_value
             .try_sub(self.borrowed_value)?
             .try_div(withdraw_collateral_ltv)
     }
 
+    /// Calculate the most of `collateral` that can be withdrawn while the obligation stays
+    /// healthy, which brings its allowed borrow value down to exactly its borrowed value. This is
+    /// the amount withdrawing `u64::MAX` takes when the market keeps no health buffer. Collateral
+    /// set aside as liquidation protection can't be withdrawn.
+    pub fn max_withdraw_amount(
+        &self,
+        collateral: &ObligationCollateral,
+        withdraw_reserve: &Reserve,
+    ) -> Result<u64, ProgramError> {
+        let unprotected_amount = collateral.unprotected_amount()?;
+        if self.borrows.is_empty() {
+            return Ok(unprotected_amount);
+        }
+
+        let max_withdraw_value = self.max_withdraw_value(
+            Rate::from_percent(withdraw_reserve.config.loan_to_value_ratio),
+            Rate::zero(),
+        )?;
+        if max_withdraw_value == Decimal::zero() || collateral.market_value == Decimal::zero() {
+            return Ok(0);
+        }
+
+        let withdraw_value = max_withdraw_value.min(collateral.market_value);
+        let withdraw_pct = withdraw_value.try_div(collateral.market_value)?;
+        Ok(withdraw_pct
+            .try_mul(collateral.deposited_amount)?
+            .try_floor_u64()?
+            .min(unprotected_amount))
+    }
+
     /// Calculate the maximum liquidity value that can be borrowed
-    pub fn remaining_borrow_value(&self) -> Result<Decimal, ProgramError> {
-        self.allowed_borrow_value.try_sub(self.borrowed_value)
+    pub fn remaining_borrow_value(&self, health_buffer: Rate) -> Result<Decimal, ProgramError> {
+        let allowed_borrow_value = self.buffered_allowed_borrow_value(health_buffer)?;
+        if allowed_borrow_value <= self.borrowed_value {
+            return Ok(Decimal::zero());
+        }
+        allowed_borrow_value.try_sub(self.borrowed_value)
+    }
+
+    /// Calculate the amount of `collateral_reserve` collateral tokens that must be deposited
+    /// before another `borrow_value` can be borrowed, on top of any borrowing power the
+    /// obligation has to spare
+    pub fn collateral_needed_for_borrow(
+        &self,
+        borrow_value: Decimal,
+        collateral_reserve: &Reserve,
+    ) -> Result<u64, ProgramError> {
+        let required_borrow_value = self.borrowed_value.try_add(borrow_value)?;
+        if required_borrow_value <= self.allowed_borrow_value {
+            return Ok(0);
+        }
+
+        let loan_to_value_rate = Rate::from_percent(collateral_reserve.config.loan_to_value_ratio);
+        if loan_to_value_rate == Rate::zero() {
+            msg!("Collateral reserve does not allow borrowing against its collateral");
+            return Err(LendingError::ReserveCollateralDisabled.into());
+        }
+
+        let liquidity_amount = collateral_reserve.usd_to_liquidity_amount(
+            required_borrow_value
+                .try_sub(self.allowed_borrow_value)?
+                .try_div(loan_to_value_rate)?,
+        )?;
+
+        collateral_reserve
+            .collateral_exchange_rate()?
+            .decimal_liquidity_to_collateral(liquidity_amount)?
+            .try_ceil_u64()
     }
 
-    /// Calculate the maximum liquidation amount for a given liquidity
+    /// Calculate the maximum liquidation amount for a given liquidity, repaying at most
+    /// `close_factor` percent of the borrowed value
     pub fn max_liquidation_amount(
         &self,
         liquidity: &ObligationLiquidity,
+        close_factor: u8,
     ) -> Result<Decimal, ProgramError> {
         let max_liquidation_value = self
             .borrowed_value
-            .try_mul(Rate::from_percent(LIQUIDATION_CLOSE_FACTOR))?
+            .try_mul(Rate::from_percent(close_factor))?
             .min(liquidity.market_value)
             .min(Decimal::from(MAX_LIQUIDATABLE_VALUE_AT_ONCE));
 
@@ -126,6 +446,80 @@ impl Obligation {
         liquidity.borrowed_amount_wads.try_mul(max_liquidation_pct)
     }
 
+    /// Calculate how much of a given liquidity to repay for the obligation's borrowed value to fall
+    /// back to `target_ltv` of its deposited value, with collateral worth the value repaid times
+    /// `bonus_rate` seized along the way. None if the obligation is already at the target, or if
+    /// seizing collateral outpaces repaying so that no liquidation can get it there
+    pub fn target_liquidation_amount(
+        &self,
+        liquidity: &ObligationLiquidity,
+        target_ltv: Rate,
+        bonus_rate: Rate,
+    ) -> Result<Option<Decimal>, ProgramError> {
+        let target_borrowed_value = self.deposited_value.try_mul(target_ltv)?;
+        let seized_target_rate = target_ltv.try_mul(bonus_rate)?;
+        if self.borrowed_value <= target_borrowed_value || seized_target_rate >= Rate::one() {
+            return Ok(None);
+        }
+
+        // repaying a value of x leaves borrowed - x against deposited - x * bonus_rate, which is
+        // back at the target once x = (borrowed - target * deposited) / (1 - target * bonus_rate)
+        let target_repay_value = self
+            .borrowed_value
+            .try_sub(target_borrowed_value)?
+            .try_div(Rate::one().try_sub(seized_target_rate)?)?;
+        let target_repay_pct = target_repay_value.try_div(liquidity.market_value)?;
+        Ok(Some(
+            liquidity
+                .borrowed_amount_wads
+                .try_mul(target_repay_pct)?
+                .min(liquidity.borrowed_amount_wads),
+        ))
+    }
+
+    /// Calculate how much `repay_reserve` liquidity a liquidator needs on hand to make the largest
+    /// liquidation of the obligation's borrow from it that is allowed right now, zero if the
+    /// obligation is healthy. Collateral worth less than the repayment plus bonus only lowers the
+    /// amount actually repaid, and the market's liquidation cooldown is not taken into account.
+    pub fn repay_capital_needed(
+        &self,
+        repay_reserve_pubkey: Pubkey,
+        repay_reserve: &Reserve,
+    ) -> Result<u64, ProgramError> {
+        if self.deposited_value == Decimal::zero()
+            || self.borrowed_value == Decimal::zero()
+            || self.borrowed_value < self.unhealthy_borrow_value
+        {
+            return Ok(0);
+        }
+
+        let (liquidity, _) = self.find_liquidity_in_borrows(repay_reserve_pubkey)?;
+        if liquidity.market_value == Decimal::zero() {
+            return Ok(0);
+        }
+
+        // obligations too small to liquidate normally are closed out in full
+        if liquidity.borrowed_amount_wads < LIQUIDATION_CLOSE_AMOUNT.into() {
+            return liquidity.borrowed_amount_wads.try_ceil_u64();
+        }
+        self.max_liquidation_amount(liquidity, repay_reserve.config.liquidation_close_factor)?
+            .try_ceil_u64()
+    }
+
+    /// Check whether borrowing from `borrow_reserve` would put an isolated borrow alongside any
+    /// other borrow, `isolated` being whether that reserve is isolated
+    pub fn violates_isolation(&self, borrow_reserve: Pubkey, isolated: bool) -> bool {
+        self.borrows.iter().any(|liquidity| {
+            liquidity.borrow_reserve != borrow_reserve && (isolated || liquidity.isolated)
+        })
+    }
+
+    /// Check whether the obligation has collateral deposited from `deposit_reserve`
+    pub fn has_deposit(&self, deposit_reserve: Pubkey) -> bool {
+        self._find_collateral_index_in_deposits(deposit_reserve)
+            .is_some()
+    }
+
     /// Find collateral by deposit reserve
     pub fn find_collateral_in_deposits(
         &self,
@@ -237,6 +631,8 @@ pub struct InitObligationParams {
     pub deposits: Vec<ObligationCollateral>,
     /// Borrowed liquidity for the obligation, unique by borrow reserve address
     pub borrows: Vec<ObligationLiquidity>,
+    /// Tranche the obligation belongs to
+    pub tranche: u8,
 }
 
 impl Sealed for Obligation {}
@@ -255,6 +651,9 @@ pub struct ObligationCollateral {
     pub deposited_amount: u64,
     /// Collateral market value in quote currency
     pub market_value: Decimal,
+    /// Amount of the deposited collateral set aside as liquidation protection. It counts toward
+    /// the unhealthy borrow value but not the allowed borrow value, and can't be withdrawn
+    pub protected_amount: u64,
 }
 
 impl ObligationCollateral {
@@ -264,6 +663,7 @@ impl ObligationCollateral {
             deposit_reserve,
             deposited_amount: 0,
             market_value: Decimal::zero(),
+            protected_amount: 0,
         }
     }
 
@@ -276,14 +676,32 @@ impl ObligationCollateral {
         Ok(())
     }
 
-    /// Decrease deposited collateral
+    /// Decrease deposited collateral, eating into the protected amount once the rest is gone
     pub fn withdraw(&mut self, collateral_amount: u64) -> ProgramResult {
         self.deposited_amount = self
             .deposited_amount
             .checked_sub(collateral_amount)
             .ok_or(LendingError::MathOverflow)?;
+        self.protected_amount = self.protected_amount.min(self.deposited_amount);
+        Ok(())
+    }
+
+    /// Set aside part of the deposited collateral as liquidation protection
+    pub fn protect(&mut self, protected_amount: u64) -> ProgramResult {
+        if protected_amount > self.deposited_amount {
+            msg!("Protected amount cannot exceed the deposited amount");
+            return Err(LendingError::InvalidAmount.into());
+        }
+        self.protected_amount = protected_amount;
         Ok(())
     }
+
+    /// Amount of collateral which isn't set aside as liquidation protection
+    pub fn unprotected_amount(&self) -> Result<u64, ProgramError> {
+        self.deposited_amount
+            .checked_sub(self.protected_amount)
+            .ok_or_else(|| LendingError::MathOverflow.into())
+    }
 }
 
 /// Obligation liquidity state
@@ -297,6 +715,12 @@ pub struct ObligationLiquidity {
     pub borrowed_amount_wads: Decimal,
     /// Liquidity market value in quote currency
     pub market_value: Decimal,
+    /// Interest the borrow reserve has accrued on this borrow that was waived under the lending
+    /// market's interest-free borrow value, and still needs to be written off the reserve
+    pub forgiven_interest_wads: Decimal,
+    /// Whether the liquidity is borrowed from an isolated reserve, barring the obligation from
+    /// borrowing anything else
+    pub isolated: bool,
 }
 
 impl ObligationLiquidity {
@@ -307,6 +731,8 @@ impl ObligationLiquidity {
             cumulative_borrow_rate_wads,
             borrowed_amount_wads: Decimal::zero(),
             market_value: Decimal::zero(),
+            forgiven_interest_wads: Decimal::zero(),
+            isolated: false,
         }
     }
 
@@ -322,7 +748,9 @@ impl ObligationLiquidity {
         Ok(())
     }
 
-    /// Accrue interest
+    /// Accrue interest by capitalizing it into the borrowed amount. The interest compounds in
+    /// the reserve's cumulative borrow rate, so the borrowed amount ends up the same however
+    /// often this runs
     pub fn accrue_interest(&mut self, cumulative_borrow_rate_wads: Decimal) -> ProgramResult {
         match cumulative_borrow_rate_wads.cmp(&self.cumulative_borrow_rate_wads) {
             Ordering::Less => {
@@ -344,11 +772,32 @@ impl ObligationLiquidity {
 
         Ok(())
     }
+
+    /// Catch up with the borrow rate without accruing interest. The interest the reserve
+    /// accrued on this borrow in the meantime is added to the forgiven interest instead.
+    pub fn forgive_interest(&mut self, cumulative_borrow_rate_wads: Decimal) -> ProgramResult {
+        let borrowed_amount_wads = self.borrowed_amount_wads;
+        self.accrue_interest(cumulative_borrow_rate_wads)?;
+        self.forgiven_interest_wads = self
+            .forgiven_interest_wads
+            .try_add(self.borrowed_amount_wads.try_sub(borrowed_amount_wads)?)?;
+        self.borrowed_amount_wads = borrowed_amount_wads;
+        Ok(())
+    }
+
+    /// Take the forgiven interest to write it off the borrow reserve
+    pub fn take_forgiven_interest(&mut self) -> Decimal {
+        std::mem::take(&mut self.forgiven_interest_wads)
+    }
 }
 
-const OBLIGATION_COLLATERAL_LEN: usize = 88; // 32 + 8 + 16 + 32
-const OBLIGATION_LIQUIDITY_LEN: usize = 112; // 32 + 16 + 16 + 16 + 32
-const OBLIGATION_LEN: usize = 1300; // 1 + 8 + 1 + 32 + 32 + 16 + 16 + 16 + 16 + 64 + 1 + 1 + (88 * 1) + (112 * 9)
+const OBLIGATION_COLLATERAL_LEN: usize = 88; // 32 + 8 + 16 + 8 + 24
+const OBLIGATION_LIQUIDITY_LEN: usize = 112; // 32 + 16 + 16 + 16 + 16 + 1 + 15
+
+// the last liquidation slot only has the 6 bytes of padding that were left after the tranche,
+// which still hold slots for far longer than the chain will run
+const LAST_LIQUIDATION_SLOT_LEN: usize = 6;
+const OBLIGATION_LEN: usize = 1300; // 1 + 8 + 1 + 32 + 32 + 16 + 16 + 16 + 16 + 1 + 16 + 8 + 32 + 6 + 1 + 1 + 1 + (88 * 1) + (112 * 9)
                                     // @TODO: break this up by obligation / collateral / liquidity https://git.io/JOCca
 impl Pack for Obligation {
     const LEN: usize = OBLIGATION_LEN;
@@ -366,7 +815,12 @@ impl Pack for Obligation {
             borrowed_value,
             allowed_borrow_value,
             unhealthy_borrow_value,
-            _padding,
+            whitelisted,
+            deferred_liquidation_bonus_value,
+            deferred_liquidation_bonus_slot,
+            delegate,
+            last_liquidation_slot,
+            tranche,
             deposits_len,
             borrows_len,
             data_flat,
@@ -381,7 +835,12 @@ impl Pack for Obligation {
             16,
             16,
             16,
-            64,
+            1,
+            16,
+            8,
+            PUBKEY_BYTES,
+            LAST_LIQUIDATION_SLOT_LEN,
+            1,
             1,
             1,
             OBLIGATION_COLLATERAL_LEN + (OBLIGATION_LIQUIDITY_LEN * (MAX_OBLIGATION_RESERVES - 1))
@@ -397,6 +856,17 @@ impl Pack for Obligation {
         pack_decimal(self.borrowed_value, borrowed_value);
         pack_decimal(self.allowed_borrow_value, allowed_borrow_value);
         pack_decimal(self.unhealthy_borrow_value, unhealthy_borrow_value);
+        pack_bool(self.whitelisted, whitelisted);
+        pack_decimal(
+            self.deferred_liquidation_bonus_value,
+            deferred_liquidation_bonus_value,
+        );
+        *deferred_liquidation_bonus_slot = self.deferred_liquidation_bonus_slot.to_le_bytes();
+        delegate.copy_from_slice(self.delegate.as_ref());
+        last_liquidation_slot.copy_from_slice(
+            &self.last_liquidation_slot.to_le_bytes()[..LAST_LIQUIDATION_SLOT_LEN],
+        );
+        *tranche = self.tranche.to_le_bytes();
         *deposits_len = u8::try_from(self.deposits.len()).unwrap().to_le_bytes();
         *borrows_len = u8::try_from(self.borrows.len()).unwrap().to_le_bytes();
 
@@ -406,11 +876,17 @@ impl Pack for Obligation {
         for collateral in &self.deposits {
             let deposits_flat = array_mut_ref![data_flat, offset, OBLIGATION_COLLATERAL_LEN];
             #[allow(clippy::ptr_offset_with_cast)]
-            let (deposit_reserve, deposited_amount, market_value, _padding_deposit) =
-                mut_array_refs![deposits_flat, PUBKEY_BYTES, 8, 16, 32];
+            let (
+                deposit_reserve,
+                deposited_amount,
+                market_value,
+                protected_amount,
+                _padding_deposit,
+            ) = mut_array_refs![deposits_flat, PUBKEY_BYTES, 8, 16, 8, 24];
             deposit_reserve.copy_from_slice(collateral.deposit_reserve.as_ref());
             *deposited_amount = collateral.deposited_amount.to_le_bytes();
             pack_decimal(collateral.market_value, market_value);
+            *protected_amount = collateral.protected_amount.to_le_bytes();
             offset += OBLIGATION_COLLATERAL_LEN;
         }
 
@@ -423,8 +899,10 @@ impl Pack for Obligation {
                 cumulative_borrow_rate_wads,
                 borrowed_amount_wads,
                 market_value,
+                forgiven_interest_wads,
+                isolated,
                 _padding_borrow,
-            ) = mut_array_refs![borrows_flat, PUBKEY_BYTES, 16, 16, 16, 32];
+            ) = mut_array_refs![borrows_flat, PUBKEY_BYTES, 16, 16, 16, 16, 1, 15];
             borrow_reserve.copy_from_slice(liquidity.borrow_reserve.as_ref());
             pack_decimal(
                 liquidity.cumulative_borrow_rate_wads,
@@ -432,6 +910,8 @@ impl Pack for Obligation {
             );
             pack_decimal(liquidity.borrowed_amount_wads, borrowed_amount_wads);
             pack_decimal(liquidity.market_value, market_value);
+            pack_decimal(liquidity.forgiven_interest_wads, forgiven_interest_wads);
+            pack_bool(liquidity.isolated, isolated);
             offset += OBLIGATION_LIQUIDITY_LEN;
         }
     }
@@ -450,7 +930,12 @@ impl Pack for Obligation {
             borrowed_value,
             allowed_borrow_value,
             unhealthy_borrow_value,
-            _padding,
+            whitelisted,
+            deferred_liquidation_bonus_value,
+            deferred_liquidation_bonus_slot,
+            delegate,
+            last_liquidation_slot,
+            tranche,
             deposits_len,
             borrows_len,
             data_flat,
@@ -465,7 +950,12 @@ impl Pack for Obligation {
             16,
             16,
             16,
-            64,
+            1,
+            16,
+            8,
+            PUBKEY_BYTES,
+            LAST_LIQUIDATION_SLOT_LEN,
+            1,
             1,
             1,
             OBLIGATION_COLLATERAL_LEN + (OBLIGATION_LIQUIDITY_LEN * (MAX_OBLIGATION_RESERVES - 1))
@@ -486,12 +976,18 @@ impl Pack for Obligation {
         for _ in 0..deposits_len {
             let deposits_flat = array_ref![data_flat, offset, OBLIGATION_COLLATERAL_LEN];
             #[allow(clippy::ptr_offset_with_cast)]
-            let (deposit_reserve, deposited_amount, market_value, _padding_deposit) =
-                array_refs![deposits_flat, PUBKEY_BYTES, 8, 16, 32];
+            let (
+                deposit_reserve,
+                deposited_amount,
+                market_value,
+                protected_amount,
+                _padding_deposit,
+            ) = array_refs![deposits_flat, PUBKEY_BYTES, 8, 16, 8, 24];
             deposits.push(ObligationCollateral {
                 deposit_reserve: Pubkey::new(deposit_reserve),
                 deposited_amount: u64::from_le_bytes(*deposited_amount),
                 market_value: unpack_decimal(market_value),
+                protected_amount: u64::from_le_bytes(*protected_amount),
             });
             offset += OBLIGATION_COLLATERAL_LEN;
         }
@@ -503,17 +999,25 @@ impl Pack for Obligation {
                 cumulative_borrow_rate_wads,
                 borrowed_amount_wads,
                 market_value,
+                forgiven_interest_wads,
+                isolated,
                 _padding_borrow,
-            ) = array_refs![borrows_flat, PUBKEY_BYTES, 16, 16, 16, 32];
+            ) = array_refs![borrows_flat, PUBKEY_BYTES, 16, 16, 16, 16, 1, 15];
             borrows.push(ObligationLiquidity {
                 borrow_reserve: Pubkey::new(borrow_reserve),
                 cumulative_borrow_rate_wads: unpack_decimal(cumulative_borrow_rate_wads),
                 borrowed_amount_wads: unpack_decimal(borrowed_amount_wads),
                 market_value: unpack_decimal(market_value),
+                forgiven_interest_wads: unpack_decimal(forgiven_interest_wads),
+                isolated: unpack_bool(isolated)?,
             });
             offset += OBLIGATION_LIQUIDITY_LEN;
         }
 
+        let mut last_liquidation_slot_bytes = [0u8; 8];
+        last_liquidation_slot_bytes[..LAST_LIQUIDATION_SLOT_LEN]
+            .copy_from_slice(last_liquidation_slot);
+
         Ok(Self {
             version,
             last_update: LastUpdate {
@@ -528,103 +1032,510 @@ impl Pack for Obligation {
             borrowed_value: unpack_decimal(borrowed_value),
             allowed_borrow_value: unpack_decimal(allowed_borrow_value),
             unhealthy_borrow_value: unpack_decimal(unhealthy_borrow_value),
+            whitelisted: unpack_bool(whitelisted)?,
+            deferred_liquidation_bonus_value: unpack_decimal(deferred_liquidation_bonus_value),
+            deferred_liquidation_bonus_slot: u64::from_le_bytes(*deferred_liquidation_bonus_slot),
+            delegate: Pubkey::new_from_array(*delegate),
+            last_liquidation_slot: u64::from_le_bytes(last_liquidation_slot_bytes),
+            tranche: u8::from_le_bytes(*tranche),
         })
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::math::TryAdd;
-    use proptest::prelude::*;
+/// Values of an obligation recomputed at hypothetical prices, see `Obligation::health_at_prices`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObligationValues {
+    /// Market value of deposits
+    pub deposited_value: Decimal,
+    /// Market value of borrows
+    pub borrowed_value: Decimal,
+    /// The maximum borrow value at the weighted average loan to value ratio
+    pub allowed_borrow_value: Decimal,
+    /// The dangerous borrow value at the weighted average liquidation threshold
+    pub unhealthy_borrow_value: Decimal,
+}
 
-    const MAX_COMPOUNDED_INTEREST: u64 = 100; // 10,000%
+/// Health of a refreshed obligation, as returned by BatchObligationSummary
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObligationSummary {
+    /// Obligation address
+    pub obligation: Pubkey,
+    /// Slot the obligation was last refreshed at
+    pub last_update_slot: Slot,
+    /// Market value of deposits
+    pub deposited_value: Decimal,
+    /// Market value of borrows
+    pub borrowed_value: Decimal,
+    /// The maximum borrow value at the weighted average loan to value ratio
+    pub allowed_borrow_value: Decimal,
+    /// The dangerous borrow value at the weighted average liquidation threshold
+    pub unhealthy_borrow_value: Decimal,
+}
 
-    #[test]
-    fn obligation_accrue_interest_failure() {
-        assert_eq!(
-            ObligationLiquidity {
-                cumulative_borrow_rate_wads: Decimal::zero(),
-                ..ObligationLiquidity::default()
-            }
-            .accrue_interest(Decimal::one()),
-            Err(LendingError::MathOverflow.into())
-        );
+impl ObligationSummary {
+    /// Summarize an obligation stored at `obligation_pubkey`
+    pub fn new(obligation_pubkey: Pubkey, obligation: &Obligation) -> Self {
+        Self {
+            obligation: obligation_pubkey,
+            last_update_slot: obligation.last_update.slot,
+            deposited_value: obligation.deposited_value,
+            borrowed_value: obligation.borrowed_value,
+            allowed_borrow_value: obligation.allowed_borrow_value,
+            unhealthy_borrow_value: obligation.unhealthy_borrow_value,
+        }
+    }
+}
 
-        assert_eq!(
-            ObligationLiquidity {
-                cumulative_borrow_rate_wads: Decimal::from(2u64),
-                ..ObligationLiquidity::default()
-            }
-            .accrue_interest(Decimal::one()),
-            Err(LendingError::NegativeInterestRate.into())
-        );
+impl Sealed for ObligationSummary {}
 
-        assert_eq!(
-            ObligationLiquidity {
-                cumulative_borrow_rate_wads: Decimal::one(),
-                borrowed_amount_wads: Decimal::from(u64::MAX),
-                ..ObligationLiquidity::default()
-            }
-            .accrue_interest(Decimal::from(10 * MAX_COMPOUNDED_INTEREST)),
-            Err(LendingError::MathOverflow.into())
-        );
-    }
+const OBLIGATION_SUMMARY_LEN: usize = 104; // 32 + 8 + 16 + 16 + 16 + 16
+impl Pack for ObligationSummary {
+    const LEN: usize = OBLIGATION_SUMMARY_LEN;
 
-    // Creates rates (r1, r2) where 0 < r1 <= r2 <= 100*r1
-    prop_compose! {
-        fn cumulative_rates()(rate in 1..=u128::MAX)(
-            current_rate in Just(rate),
-            max_new_rate in rate..=rate.saturating_mul(MAX_COMPOUNDED_INTEREST as u128),
-        ) -> (u128, u128) {
-            (current_rate, max_new_rate)
-        }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, OBLIGATION_SUMMARY_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            obligation,
+            last_update_slot,
+            deposited_value,
+            borrowed_value,
+            allowed_borrow_value,
+            unhealthy_borrow_value,
+        ) = mut_array_refs![output, PUBKEY_BYTES, 8, 16, 16, 16, 16];
+
+        obligation.copy_from_slice(self.obligation.as_ref());
+        *last_update_slot = self.last_update_slot.to_le_bytes();
+        pack_decimal(self.deposited_value, deposited_value);
+        pack_decimal(self.borrowed_value, borrowed_value);
+        pack_decimal(self.allowed_borrow_value, allowed_borrow_value);
+        pack_decimal(self.unhealthy_borrow_value, unhealthy_borrow_value);
     }
 
-    const MAX_BORROWED: u128 = u64::MAX as u128 * WAD as u128;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, OBLIGATION_SUMMARY_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            obligation,
+            last_update_slot,
+            deposited_value,
+            borrowed_value,
+            allowed_borrow_value,
+            unhealthy_borrow_value,
+        ) = array_refs![input, PUBKEY_BYTES, 8, 16, 16, 16, 16];
 
-    // Creates liquidity amounts (repay, borrow) where repay < borrow
-    prop_compose! {
-        fn repay_partial_amounts()(amount in 1..=u64::MAX)(
-            repay_amount in Just(WAD as u128 * amount as u128),
-            borrowed_amount in (WAD as u128 * amount as u128 + 1)..=MAX_BORROWED,
-        ) -> (u128, u128) {
-            (repay_amount, borrowed_amount)
-        }
+        Ok(Self {
+            obligation: Pubkey::new_from_array(*obligation),
+            last_update_slot: u64::from_le_bytes(*last_update_slot),
+            deposited_value: unpack_decimal(deposited_value),
+            borrowed_value: unpack_decimal(borrowed_value),
+            allowed_borrow_value: unpack_decimal(allowed_borrow_value),
+            unhealthy_borrow_value: unpack_decimal(unhealthy_borrow_value),
+        })
     }
+}
 
-    // Creates liquidity amounts (repay, borrow) where repay >= borrow
-    prop_compose! {
-        fn repay_full_amounts()(amount in 1..=u64::MAX)(
-            repay_amount in Just(WAD as u128 * amount as u128),
-        ) -> (u128, u128) {
-            (repay_amount, repay_amount)
+/// Full state of a refreshed obligation at a slot, as logged by SnapshotObligation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObligationSnapshot {
+    /// Layout version of the snapshot
+    pub version: u8,
+    /// Obligation address
+    pub obligation_pubkey: Pubkey,
+    /// Slot the snapshot was taken at
+    pub slot: Slot,
+    /// Obligation state, including its deposits, borrows, market values and health
+    pub obligation: Obligation,
+}
+
+impl ObligationSnapshot {
+    /// Snapshot an obligation stored at `obligation_pubkey`
+    pub fn new(obligation_pubkey: Pubkey, obligation: Obligation, slot: Slot) -> Self {
+        Self {
+            version: OBLIGATION_SNAPSHOT_VERSION,
+            obligation_pubkey,
+            slot,
+            obligation,
         }
     }
+}
 
-    proptest! {
-        #[test]
-        fn repay_partial(
-            (repay_amount, borrowed_amount) in repay_partial_amounts(),
-        ) {
-            let borrowed_amount_wads = Decimal::from_scaled_val(borrowed_amount);
-            let repay_amount_wads = Decimal::from_scaled_val(repay_amount);
-            let mut obligation = Obligation {
-                borrows: vec![ObligationLiquidity {
-                    borrowed_amount_wads,
-                    ..ObligationLiquidity::default()
-                }],
-                ..Obligation::default()
-            };
+impl Sealed for ObligationSnapshot {}
 
-            obligation.repay(repay_amount_wads, 0)?;
-            assert!(obligation.borrows[0].borrowed_amount_wads < borrowed_amount_wads);
-            assert!(obligation.borrows[0].borrowed_amount_wads > Decimal::zero());
-        }
+const OBLIGATION_SNAPSHOT_LEN: usize = 1341; // 1 + 32 + 8 + 1300
+impl Pack for ObligationSnapshot {
+    const LEN: usize = OBLIGATION_SNAPSHOT_LEN;
 
-        #[test]
-        fn repay_full(
-            (repay_amount, borrowed_amount) in repay_full_amounts(),
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, OBLIGATION_SNAPSHOT_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, obligation_pubkey, slot, obligation) =
+            mut_array_refs![output, 1, PUBKEY_BYTES, 8, OBLIGATION_LEN];
+
+        *version = self.version.to_le_bytes();
+        obligation_pubkey.copy_from_slice(self.obligation_pubkey.as_ref());
+        *slot = self.slot.to_le_bytes();
+        self.obligation.pack_into_slice(obligation);
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, OBLIGATION_SNAPSHOT_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, obligation_pubkey, slot, obligation) =
+            array_refs![input, 1, PUBKEY_BYTES, 8, OBLIGATION_LEN];
+
+        let version = u8::from_le_bytes(*version);
+        if version > OBLIGATION_SNAPSHOT_VERSION {
+            msg!("Obligation snapshot version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            obligation_pubkey: Pubkey::new_from_array(*obligation_pubkey),
+            slot: u64::from_le_bytes(*slot),
+            obligation: Obligation::unpack_from_slice(obligation)?,
+        })
+    }
+}
+
+/// Outcome of a prospective borrow, as returned by CanBorrow
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BorrowCheck {
+    /// Whether the borrow would succeed and keep the obligation healthy
+    pub can_borrow: bool,
+    /// Ratio of borrowed to deposited value after the borrow, zero without deposit value
+    pub loan_to_value: Decimal,
+    /// Code of the LendingError the borrow would fail with, 0 when it can borrow
+    pub error_code: u32,
+}
+
+impl Sealed for BorrowCheck {}
+
+const BORROW_CHECK_LEN: usize = 21; // 1 + 16 + 4
+impl Pack for BorrowCheck {
+    const LEN: usize = BORROW_CHECK_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, BORROW_CHECK_LEN];
+        let (can_borrow, loan_to_value, error_code) = mut_array_refs![output, 1, 16, 4];
+
+        pack_bool(self.can_borrow, can_borrow);
+        pack_decimal(self.loan_to_value, loan_to_value);
+        *error_code = self.error_code.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, BORROW_CHECK_LEN];
+        let (can_borrow, loan_to_value, error_code) = array_refs![input, 1, 16, 4];
+
+        Ok(Self {
+            can_borrow: unpack_bool(can_borrow)?,
+            loan_to_value: unpack_decimal(loan_to_value),
+            error_code: u32::from_le_bytes(*error_code),
+        })
+    }
+}
+
+/// Largest liquidation currently allowed for a borrow and deposit pair, as returned by
+/// GetMaxLiquidation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaxLiquidation {
+    /// Amount of liquidity the liquidation repays
+    pub repay_amount: u64,
+    /// Amount of collateral tokens the liquidation seizes
+    pub withdraw_amount: u64,
+}
+
+impl Sealed for MaxLiquidation {}
+
+const MAX_LIQUIDATION_LEN: usize = 16; // 8 + 8
+impl Pack for MaxLiquidation {
+    const LEN: usize = MAX_LIQUIDATION_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, MAX_LIQUIDATION_LEN];
+        let (repay_amount, withdraw_amount) = mut_array_refs![output, 8, 8];
+
+        *repay_amount = self.repay_amount.to_le_bytes();
+        *withdraw_amount = self.withdraw_amount.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, MAX_LIQUIDATION_LEN];
+        let (repay_amount, withdraw_amount) = array_refs![input, 8, 8];
+
+        Ok(Self {
+            repay_amount: u64::from_le_bytes(*repay_amount),
+            withdraw_amount: u64::from_le_bytes(*withdraw_amount),
+        })
+    }
+}
+
+/// Accounts needed to refresh one of an obligation's reserves, as returned by
+/// GetRefreshAccounts. Entries are packed back to back, each only as long as its reserve's extra
+/// pyth oracles need, so that obligations with as many reserves as allowed still fit in the
+/// return data when few of them have extra pyth oracles.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReserveRefreshAccounts {
+    /// Reserve address
+    pub reserve: Pubkey,
+    /// Reserve liquidity pyth oracle account
+    pub pyth_oracle: Pubkey,
+    /// Reserve liquidity switchboard oracle account
+    pub switchboard_oracle: Pubkey,
+    /// Reserve liquidity extra pyth oracle accounts, supplied after the switchboard oracle
+    pub extra_pyth_oracles: Vec<Pubkey>,
+}
+
+const RESERVE_REFRESH_ACCOUNTS_HEADER_LEN: usize = 97; // 32 + 32 + 32 + 1
+impl ReserveRefreshAccounts {
+    /// Packed length of the entry
+    pub fn packed_len(&self) -> usize {
+        RESERVE_REFRESH_ACCOUNTS_HEADER_LEN + PUBKEY_BYTES * self.extra_pyth_oracles.len()
+    }
+
+    /// Appends the packed entry to `dst`
+    pub fn pack_into_vec(&self, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(self.reserve.as_ref());
+        dst.extend_from_slice(self.pyth_oracle.as_ref());
+        dst.extend_from_slice(self.switchboard_oracle.as_ref());
+        dst.push(self.extra_pyth_oracles.len() as u8);
+        for pubkey in &self.extra_pyth_oracles {
+            dst.extend_from_slice(pubkey.as_ref());
+        }
+    }
+
+    /// Unpacks the back to back entries making up all of `src`
+    pub fn unpack_all(mut src: &[u8]) -> Result<Vec<Self>, ProgramError> {
+        let mut entries = vec![];
+        while !src.is_empty() {
+            if src.len() < RESERVE_REFRESH_ACCOUNTS_HEADER_LEN {
+                msg!("Reserve refresh accounts entry is truncated");
+                return Err(ProgramError::InvalidAccountData);
+            }
+            let (header, rest) = src.split_at(RESERVE_REFRESH_ACCOUNTS_HEADER_LEN);
+            let header = array_ref![header, 0, RESERVE_REFRESH_ACCOUNTS_HEADER_LEN];
+            let (reserve, pyth_oracle, switchboard_oracle, extra_pyth_oracle_count) =
+                array_refs![header, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 1];
+
+            let extra_pyth_oracles_len = PUBKEY_BYTES * extra_pyth_oracle_count[0] as usize;
+            if rest.len() < extra_pyth_oracles_len {
+                msg!("Reserve refresh accounts entry is truncated");
+                return Err(ProgramError::InvalidAccountData);
+            }
+            let (extra_pyth_oracles, rest) = rest.split_at(extra_pyth_oracles_len);
+
+            entries.push(Self {
+                reserve: Pubkey::new_from_array(*reserve),
+                pyth_oracle: Pubkey::new_from_array(*pyth_oracle),
+                switchboard_oracle: Pubkey::new_from_array(*switchboard_oracle),
+                extra_pyth_oracles: extra_pyth_oracles
+                    .chunks_exact(PUBKEY_BYTES)
+                    .map(Pubkey::new)
+                    .collect(),
+            });
+            src = rest;
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::TryAdd;
+    use proptest::prelude::*;
+
+    const MAX_COMPOUNDED_INTEREST: u64 = 100; // 10,000%
+
+    #[test]
+    fn obligation_violates_isolation() {
+        let regular_reserve = Pubkey::new_unique();
+        let isolated_reserve = Pubkey::new_unique();
+        let other_reserve = Pubkey::new_unique();
+
+        let mut obligation = Obligation::default();
+        assert!(!obligation.violates_isolation(isolated_reserve, true));
+
+        obligation.borrows = vec![ObligationLiquidity::new(regular_reserve, Decimal::one())];
+        assert!(!obligation.violates_isolation(regular_reserve, false));
+        assert!(!obligation.violates_isolation(other_reserve, false));
+        assert!(obligation.violates_isolation(isolated_reserve, true));
+
+        obligation.borrows = vec![ObligationLiquidity {
+            isolated: true,
+            ..ObligationLiquidity::new(isolated_reserve, Decimal::one())
+        }];
+        assert!(!obligation.violates_isolation(isolated_reserve, true));
+        assert!(obligation.violates_isolation(regular_reserve, false));
+        assert!(obligation.violates_isolation(other_reserve, true));
+    }
+
+    #[test]
+    fn obligation_collateral_protection() {
+        let mut collateral = ObligationCollateral {
+            deposited_amount: 100,
+            ..ObligationCollateral::default()
+        };
+        assert_eq!(
+            collateral.protect(101),
+            Err(LendingError::InvalidAmount.into())
+        );
+
+        collateral.protect(60).unwrap();
+        assert_eq!(collateral.unprotected_amount(), Ok(40));
+
+        // liquidation takes the unprotected collateral first, then the cushion itself
+        collateral.withdraw(70).unwrap();
+        assert_eq!(collateral.protected_amount, 30);
+        assert_eq!(collateral.unprotected_amount(), Ok(0));
+    }
+
+    #[test]
+    fn obligation_accrue_interest_failure() {
+        assert_eq!(
+            ObligationLiquidity {
+                cumulative_borrow_rate_wads: Decimal::zero(),
+                ..ObligationLiquidity::default()
+            }
+            .accrue_interest(Decimal::one()),
+            Err(LendingError::MathOverflow.into())
+        );
+
+        assert_eq!(
+            ObligationLiquidity {
+                cumulative_borrow_rate_wads: Decimal::from(2u64),
+                ..ObligationLiquidity::default()
+            }
+            .accrue_interest(Decimal::one()),
+            Err(LendingError::NegativeInterestRate.into())
+        );
+
+        assert_eq!(
+            ObligationLiquidity {
+                cumulative_borrow_rate_wads: Decimal::one(),
+                borrowed_amount_wads: Decimal::from(u64::MAX),
+                ..ObligationLiquidity::default()
+            }
+            .accrue_interest(Decimal::from(10 * MAX_COMPOUNDED_INTEREST)),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn obligation_accrue_interest_cadence() {
+        // 10% per period, compounded by the reserve
+        let cumulative_borrow_rates: Vec<Decimal> = (1..=4u32)
+            .map(|period| {
+                Decimal::from_scaled_val(11u128.pow(period) * WAD as u128 / 10u128.pow(period))
+            })
+            .collect();
+
+        let mut every_period = ObligationLiquidity::new(Pubkey::new_unique(), Decimal::one());
+        every_period.borrow(Decimal::from(100u64)).unwrap();
+        let mut every_other_period = every_period.clone();
+        let mut once = every_period.clone();
+
+        let mut progression = vec![];
+        for (period, cumulative_borrow_rate_wads) in cumulative_borrow_rates.iter().enumerate() {
+            every_period
+                .accrue_interest(*cumulative_borrow_rate_wads)
+                .unwrap();
+            progression.push(every_period.borrowed_amount_wads);
+            if period % 2 == 1 {
+                every_other_period
+                    .accrue_interest(*cumulative_borrow_rate_wads)
+                    .unwrap();
+                assert_eq!(every_other_period, every_period);
+            }
+        }
+        assert_eq!(
+            progression,
+            vec![
+                Decimal::from(110u64),
+                Decimal::from(121u64),
+                Decimal::from_scaled_val(1331 * WAD as u128 / 10),
+                Decimal::from_scaled_val(14641 * WAD as u128 / 100),
+            ]
+        );
+
+        once.accrue_interest(cumulative_borrow_rates[3]).unwrap();
+        assert_eq!(once, every_period);
+    }
+
+    #[test]
+    fn obligation_forgive_interest() {
+        let mut liquidity = ObligationLiquidity {
+            cumulative_borrow_rate_wads: Decimal::one(),
+            borrowed_amount_wads: Decimal::from(100u64),
+            ..ObligationLiquidity::default()
+        };
+
+        liquidity.forgive_interest(Decimal::from(2u64)).unwrap();
+        liquidity.forgive_interest(Decimal::from(3u64)).unwrap();
+        assert_eq!(liquidity.cumulative_borrow_rate_wads, Decimal::from(3u64));
+        assert_eq!(liquidity.borrowed_amount_wads, Decimal::from(100u64));
+        assert_eq!(liquidity.forgiven_interest_wads, Decimal::from(150u64));
+
+        assert_eq!(liquidity.take_forgiven_interest(), Decimal::from(150u64));
+        assert_eq!(liquidity.forgiven_interest_wads, Decimal::zero());
+    }
+
+    // Creates rates (r1, r2) where 0 < r1 <= r2 <= 100*r1
+    prop_compose! {
+        fn cumulative_rates()(rate in 1..=u128::MAX)(
+            current_rate in Just(rate),
+            max_new_rate in rate..=rate.saturating_mul(MAX_COMPOUNDED_INTEREST as u128),
+        ) -> (u128, u128) {
+            (current_rate, max_new_rate)
+        }
+    }
+
+    const MAX_BORROWED: u128 = u64::MAX as u128 * WAD as u128;
+
+    // Creates liquidity amounts (repay, borrow) where repay < borrow
+    prop_compose! {
+        fn repay_partial_amounts()(amount in 1..=u64::MAX)(
+            repay_amount in Just(WAD as u128 * amount as u128),
+            borrowed_amount in (WAD as u128 * amount as u128 + 1)..=MAX_BORROWED,
+        ) -> (u128, u128) {
+            (repay_amount, borrowed_amount)
+        }
+    }
+
+    // Creates liquidity amounts (repay, borrow) where repay >= borrow
+    prop_compose! {
+        fn repay_full_amounts()(amount in 1..=u64::MAX)(
+            repay_amount in Just(WAD as u128 * amount as u128),
+        ) -> (u128, u128) {
+            (repay_amount, repay_amount)
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn repay_partial(
+            (repay_amount, borrowed_amount) in repay_partial_amounts(),
+        ) {
+            let borrowed_amount_wads = Decimal::from_scaled_val(borrowed_amount);
+            let repay_amount_wads = Decimal::from_scaled_val(repay_amount);
+            let mut obligation = Obligation {
+                borrows: vec![ObligationLiquidity {
+                    borrowed_amount_wads,
+                    ..ObligationLiquidity::default()
+                }],
+                ..Obligation::default()
+            };
+
+            obligation.repay(repay_amount_wads, 0)?;
+            assert!(obligation.borrows[0].borrowed_amount_wads < borrowed_amount_wads);
+            assert!(obligation.borrows[0].borrowed_amount_wads > Decimal::zero());
+        }
+
+        #[test]
+        fn repay_full(
+            (repay_amount, borrowed_amount) in repay_full_amounts(),
         ) {
             let borrowed_amount_wads = Decimal::from_scaled_val(borrowed_amount);
             let repay_amount_wads = Decimal::from_scaled_val(repay_amount);
@@ -664,6 +1575,411 @@ mod test {
         }
     }
 
+    #[test]
+    fn loan_to_value() {
+        let obligation = Obligation {
+            deposited_value: Decimal::from(100u64),
+            borrowed_value: Decimal::from(50u64),
+            ..Obligation::default()
+        };
+        assert_eq!(
+            obligation.loan_to_value().unwrap(),
+            Decimal::from_percent(50)
+        );
+
+        let obligation = Obligation {
+            deposited_value: Decimal::from(100u64),
+            ..Obligation::default()
+        };
+        assert_eq!(obligation.loan_to_value().unwrap(), Decimal::zero());
+
+        // empty obligations don't divide by zero
+        assert_eq!(
+            Obligation::default().loan_to_value().unwrap(),
+            Decimal::zero()
+        );
+
+        let obligation = Obligation {
+            deposited_value: Decimal::from(100u64),
+            borrowed_value: Decimal::from(100u64),
+            ..Obligation::default()
+        };
+        assert_eq!(obligation.loan_to_value().unwrap(), Decimal::one());
+
+        // debt without collateral has no finite ratio rather than reading as risk free
+        let obligation = Obligation {
+            borrowed_value: Decimal::from(100u64),
+            ..Obligation::default()
+        };
+        assert!(obligation.loan_to_value().is_err());
+    }
+
+    #[test]
+    fn net_value() {
+        let obligation = Obligation {
+            deposited_value: Decimal::from(100u64),
+            borrowed_value: Decimal::from(40u64),
+            ..Obligation::default()
+        };
+        assert_eq!(
+            obligation.net_value().unwrap(),
+            (Decimal::from(60u64), false)
+        );
+
+        let obligation = Obligation {
+            deposited_value: Decimal::from(100u64),
+            borrowed_value: Decimal::from(100u64),
+            ..Obligation::default()
+        };
+        assert_eq!(obligation.net_value().unwrap(), (Decimal::zero(), false));
+
+        // underwater obligations have a negative net value
+        let obligation = Obligation {
+            deposited_value: Decimal::from(100u64),
+            borrowed_value: Decimal::from(130u64),
+            ..Obligation::default()
+        };
+        assert_eq!(
+            obligation.net_value().unwrap(),
+            (Decimal::from(30u64), true)
+        );
+    }
+
+    #[test]
+    fn liquidation_cooldown() {
+        let mut obligation = Obligation {
+            version: PROGRAM_VERSION,
+            ..Obligation::default()
+        };
+        // never liquidated
+        assert!(!obligation.in_liquidation_cooldown(5, 10));
+
+        obligation.last_liquidation_slot = 100;
+        assert!(obligation.in_liquidation_cooldown(100, 10));
+        assert!(obligation.in_liquidation_cooldown(109, 10));
+        assert!(!obligation.in_liquidation_cooldown(110, 10));
+        // disabled
+        assert!(!obligation.in_liquidation_cooldown(100, 0));
+
+        // the slot survives the trip through the 6 bytes it's packed into
+        obligation.last_liquidation_slot = (1 << 48) - 1;
+        obligation.tranche = u8::MAX;
+        let mut data = [0u8; OBLIGATION_LEN];
+        Obligation::pack(obligation.clone(), &mut data).unwrap();
+        assert_eq!(Obligation::unpack(&data).unwrap(), obligation);
+    }
+
+    #[test]
+    fn health_at_prices() {
+        let sol_reserve_pubkey = Pubkey::new_unique();
+        let usdc_reserve_pubkey = Pubkey::new_unique();
+        let sol_reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 9,
+                available_amount: 200_000_000_000,
+                market_price: Decimal::from(20u64),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 200_000_000_000,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                loan_to_value_ratio: 50,
+                liquidation_threshold: 75,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        let usdc_reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 6,
+                market_price: Decimal::one(),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+        let reserves = [sol_reserve, usdc_reserve];
+
+        // 100 SOL deposited against 1000 USDC borrowed
+        let obligation = Obligation {
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 100_000_000_000,
+                ..ObligationCollateral::new(sol_reserve_pubkey)
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(1_000_000_000u64),
+                ..ObligationLiquidity::new(usdc_reserve_pubkey, Decimal::one())
+            }],
+            ..Obligation::default()
+        };
+
+        assert_eq!(
+            obligation.health_at_prices(&[], &reserves).unwrap(),
+            ObligationValues {
+                deposited_value: Decimal::from(2_000u64),
+                borrowed_value: Decimal::from(1_000u64),
+                allowed_borrow_value: Decimal::from(1_000u64),
+                unhealthy_borrow_value: Decimal::from(1_500u64),
+            }
+        );
+
+        // SOL drops 30% to $14: 100 SOL * $14 = $1400 deposited, $700 allowed at 50% and
+        // $1050 unhealthy at 75%, which the $1000 borrowed no longer clears
+        let values = obligation
+            .health_at_prices(&[(sol_reserve_pubkey, Decimal::from(14u64))], &reserves)
+            .unwrap();
+        assert_eq!(
+            values,
+            ObligationValues {
+                deposited_value: Decimal::from(1_400u64),
+                borrowed_value: Decimal::from(1_000u64),
+                allowed_borrow_value: Decimal::from(700u64),
+                unhealthy_borrow_value: Decimal::from(1_050u64),
+            }
+        );
+        assert!(values.borrowed_value > values.allowed_borrow_value);
+        assert!(values.borrowed_value < values.unhealthy_borrow_value);
+
+        // the obligation itself is untouched
+        assert_eq!(obligation.deposited_value, Decimal::zero());
+
+        assert_eq!(
+            obligation.health_at_prices(&[], &reserves[..1]),
+            Err(LendingError::InvalidAccountInput.into())
+        );
+    }
+
+    #[test]
+    fn positions_at_risk() {
+        let collateral_reserve = |decimals: u8, price: u64| Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: decimals,
+                available_amount: 1_000 * 10u64.pow(decimals as u32),
+                market_price: Decimal::from(price),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000 * 10u64.pow(decimals as u32),
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                loan_to_value_ratio: 50,
+                liquidation_threshold: 75,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        let sol_reserve_pubkey = Pubkey::new_unique();
+        let eth_reserve_pubkey = Pubkey::new_unique();
+        let usdc_reserve_pubkey = Pubkey::new_unique();
+        let usdc_reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 6,
+                market_price: Decimal::one(),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+        let reserves = [
+            collateral_reserve(9, 20),
+            collateral_reserve(8, 1_000),
+            usdc_reserve,
+        ];
+
+        // 100 SOL ($2000) and 1 ETH ($1000) deposited, unhealthy at $2250, against 1900 USDC
+        let obligation = Obligation {
+            deposits: vec![
+                ObligationCollateral {
+                    deposited_amount: 100_000_000_000,
+                    ..ObligationCollateral::new(sol_reserve_pubkey)
+                },
+                ObligationCollateral {
+                    deposited_amount: 100_000_000,
+                    ..ObligationCollateral::new(eth_reserve_pubkey)
+                },
+            ],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(1_900_000_000u64),
+                ..ObligationLiquidity::new(usdc_reserve_pubkey, Decimal::one())
+            }],
+            ..Obligation::default()
+        };
+
+        // a 10% drop in either leaves it unhealthy at $2100 or more
+        assert_eq!(obligation.positions_at_risk(0, &reserves), Ok(vec![]));
+        assert_eq!(obligation.positions_at_risk(1_000, &reserves), Ok(vec![]));
+        // a 30% drop in SOL takes it to $1800, the same drop in ETH only to $2025
+        assert_eq!(
+            obligation.positions_at_risk(3_000, &reserves),
+            Ok(vec![sol_reserve_pubkey])
+        );
+        // halving ETH takes it to $1875
+        assert_eq!(
+            obligation.positions_at_risk(5_000, &reserves),
+            Ok(vec![sol_reserve_pubkey, eth_reserve_pubkey])
+        );
+
+        // already liquidatable, every drop keeps it that way
+        let mut underwater_obligation = obligation.clone();
+        underwater_obligation.borrows[0].borrowed_amount_wads = Decimal::from(2_250_000_000u64);
+        assert_eq!(
+            underwater_obligation.positions_at_risk(0, &reserves),
+            Ok(vec![sol_reserve_pubkey, eth_reserve_pubkey])
+        );
+
+        assert_eq!(
+            obligation.positions_at_risk(10_001, &reserves),
+            Err(LendingError::InvalidAmount.into())
+        );
+        assert_eq!(
+            obligation.positions_at_risk(1_000, &reserves[..2]),
+            Err(LendingError::InvalidAccountInput.into())
+        );
+    }
+
+    #[test]
+    fn weighted_borrow_rate() {
+        let borrow_reserve = |min_borrow_rate: u8| Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 1_000_000,
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                optimal_utilization_rate: 80,
+                min_borrow_rate,
+                optimal_borrow_rate: 20,
+                max_borrow_rate: 50,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        // no utilization, so each reserve charges its min borrow rate
+        let reserves = [borrow_reserve(3), borrow_reserve(12)];
+        assert_eq!(reserves[0].current_borrow_rate(), Ok(Rate::from_percent(3)));
+        assert_eq!(
+            reserves[1].current_borrow_rate(),
+            Ok(Rate::from_percent(12))
+        );
+
+        let obligation = Obligation {
+            borrows: vec![
+                ObligationLiquidity {
+                    market_value: Decimal::from(1_000u64),
+                    ..ObligationLiquidity::default()
+                },
+                ObligationLiquidity {
+                    market_value: Decimal::from(500u64),
+                    ..ObligationLiquidity::default()
+                },
+            ],
+            ..Obligation::default()
+        };
+
+        // ($1000 * 3% + $500 * 12%) / $1500 = $90 / $1500 = 6%
+        assert_eq!(
+            obligation.weighted_borrow_rate(&reserves),
+            Ok(Rate::from_percent(6))
+        );
+
+        // equal values give the plain average
+        let mut obligation = obligation;
+        obligation.borrows[1].market_value = Decimal::from(1_000u64);
+        assert_eq!(
+            obligation.weighted_borrow_rate(&reserves),
+            Ok(Rate::from_bps(750))
+        );
+
+        // nothing borrowed has no rate to weigh
+        obligation.borrows[0].market_value = Decimal::zero();
+        obligation.borrows[1].market_value = Decimal::zero();
+        assert_eq!(obligation.weighted_borrow_rate(&reserves), Ok(Rate::zero()));
+        assert_eq!(
+            Obligation::default().weighted_borrow_rate(&[]),
+            Ok(Rate::zero())
+        );
+
+        assert_eq!(
+            obligation.weighted_borrow_rate(&reserves[..1]),
+            Err(LendingError::InvalidAccountInput.into())
+        );
+    }
+
+    #[test]
+    fn defer_liquidation_bonus() {
+        let unhealthy_obligation = |deposited_amount: u64, borrowed_amount: u64| Obligation {
+            deposits: vec![ObligationCollateral {
+                deposited_amount,
+                // $2 per collateral token
+                market_value: Decimal::from(2 * deposited_amount),
+                ..ObligationCollateral::default()
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(borrowed_amount),
+                market_value: Decimal::from(borrowed_amount),
+                ..ObligationLiquidity::default()
+            }],
+            deposited_value: Decimal::from(2 * deposited_amount),
+            borrowed_value: Decimal::from(borrowed_amount),
+            unhealthy_borrow_value: Decimal::from(2 * deposited_amount)
+                .try_mul(Rate::from_percent(75))
+                .unwrap(),
+            ..Obligation::default()
+        };
+        let defer = |obligation: &mut Obligation, settle: u64, withdraw: u64, slot: Slot| {
+            obligation.defer_liquidation_bonus(
+                Decimal::from(settle),
+                withdraw,
+                0,
+                0,
+                Rate::from_percent(75),
+                Rate::from_percent(50),
+                slot,
+            )
+        };
+
+        // repaying $320 for $352 of collateral leaves $1280 borrowed against $1236, so half of
+        // the $32 bonus is withheld
+        let mut obligation = unhealthy_obligation(1_000, 1_600);
+        assert_eq!(defer(&mut obligation, 320, 176, 10), Ok(168));
+        assert_eq!(
+            obligation.deferred_liquidation_bonus_value,
+            Decimal::from(16u64)
+        );
+        assert_eq!(obligation.deferred_liquidation_bonus_slot, 10);
+
+        // repaying $256 for $280 of collateral leaves $1024 borrowed against $1038, which also
+        // pays out the $16 withheld
+        let mut restored_obligation = Obligation {
+            deferred_liquidation_bonus_value: obligation.deferred_liquidation_bonus_value,
+            deferred_liquidation_bonus_slot: obligation.deferred_liquidation_bonus_slot,
+            ..unhealthy_obligation(832, 1_280)
+        };
+        let mut expired_obligation = restored_obligation.clone();
+        assert_eq!(defer(&mut restored_obligation, 256, 140, 20), Ok(148));
+        assert_eq!(
+            restored_obligation.deferred_liquidation_bonus_value,
+            Decimal::zero()
+        );
+
+        // the withheld bonus isn't paid out after the deferral window
+        assert_eq!(
+            defer(
+                &mut expired_obligation,
+                256,
+                140,
+                11 + LIQUIDATION_BONUS_DEFERRAL_SLOTS
+            ),
+            Ok(140)
+        );
+        assert_eq!(
+            expired_obligation.deferred_liquidation_bonus_value,
+            Decimal::zero()
+        );
+    }
+
     #[test]
     fn max_liquidation_amount_normal() {
         let obligation_liquidity = ObligationLiquidity {
@@ -687,7 +2003,7 @@ mod test {
 
         assert_eq!(
             obligation
-                .max_liquidation_amount(&obligation_liquidity)
+                .max_liquidation_amount(&obligation_liquidity, LIQUIDATION_CLOSE_FACTOR)
                 .unwrap(),
             expected_collateral
         );
@@ -710,7 +2026,7 @@ mod test {
 
         assert_eq!(
             obligation
-                .max_liquidation_amount(&obligation_liquidity)
+                .max_liquidation_amount(&obligation_liquidity, LIQUIDATION_CLOSE_FACTOR)
                 .unwrap(),
             Decimal::from(100u64)
         );
@@ -733,9 +2049,351 @@ mod test {
 
         assert_eq!(
             obligation
-                .max_liquidation_amount(&obligation_liquidity)
+                .max_liquidation_amount(&obligation_liquidity, LIQUIDATION_CLOSE_FACTOR)
                 .unwrap(),
             Decimal::from(MAX_LIQUIDATABLE_VALUE_AT_ONCE)
         );
     }
+
+    #[test]
+    fn target_liquidation_amount() {
+        // 2 units of liquidity per unit of value
+        let obligation_liquidity = ObligationLiquidity {
+            borrowed_amount_wads: Decimal::from(3_400u64),
+            market_value: Decimal::from(1_700u64),
+            ..ObligationLiquidity::default()
+        };
+        let obligation = Obligation {
+            deposited_value: Decimal::from(2_000u64),
+            borrowed_value: Decimal::from(1_700u64),
+            borrows: vec![obligation_liquidity.clone()],
+            ..Obligation::default()
+        };
+        let target_amount = |target_pct: u8, bonus_pct: u8| {
+            obligation
+                .target_liquidation_amount(
+                    &obligation_liquidity,
+                    Rate::from_percent(target_pct),
+                    Rate::from_percent(100 + bonus_pct),
+                )
+                .unwrap()
+                .map(|amount| amount.try_round_u64().unwrap())
+        };
+
+        // repaying 800 of value leaves 900 borrowed against 1200 deposited
+        assert_eq!(target_amount(75, 0), Some(1_600));
+        // repaying 625 of value with a 5% bonus leaves 1075 borrowed against 1343.75 deposited
+        assert_eq!(target_amount(80, 5), Some(1_250));
+        // when repaying this borrow alone can't reach the target, the amount is capped at the
+        // whole borrow
+        assert_eq!(target_amount(50, 20), Some(3_400));
+
+        // already at or below the target
+        assert_eq!(target_amount(85, 0), None);
+        // seizing collateral as fast as debt is repaid never gets there
+        assert_eq!(target_amount(80, 25), None);
+    }
+
+    #[test]
+    fn repay_capital_needed() {
+        let repay_reserve_pubkey = Pubkey::new_unique();
+        let collateral = ObligationCollateral {
+            deposited_amount: u64::MAX / 2,
+            market_value: Decimal::from(u64::MAX / 2),
+            ..ObligationCollateral::default()
+        };
+        let withdraw_reserve = Reserve::default();
+        let mut repay_reserve = Reserve::default();
+
+        // borrows worth $2 a token, from a single token up to whale sized
+        for borrowed_amount in [1u64, 3, 1_000, 123_456_789, 1_000_000_000_000] {
+            let liquidity = ObligationLiquidity {
+                borrow_reserve: repay_reserve_pubkey,
+                borrowed_amount_wads: Decimal::from(borrowed_amount),
+                market_value: Decimal::from(2 * borrowed_amount),
+                ..ObligationLiquidity::default()
+            };
+            let obligation = Obligation {
+                deposits: vec![collateral.clone()],
+                borrows: vec![liquidity.clone()],
+                deposited_value: collateral.market_value,
+                borrowed_value: liquidity.market_value,
+                unhealthy_borrow_value: liquidity.market_value,
+                ..Obligation::default()
+            };
+
+            for close_factor in [1, 20, 50, 100] {
+                repay_reserve.config.liquidation_close_factor = close_factor;
+                let capital = obligation
+                    .repay_capital_needed(repay_reserve_pubkey, &repay_reserve)
+                    .unwrap();
+
+                // exactly what liquidating as much as possible repays
+                let max_liquidation = withdraw_reserve
+                    .calculate_liquidation(
+                        u64::MAX,
+                        &obligation,
+                        &liquidity,
+                        &collateral,
+                        close_factor,
+                        None,
+                    )
+                    .unwrap();
+                assert_eq!(capital, max_liquidation.repay_amount);
+                assert_eq!(
+                    withdraw_reserve
+                        .calculate_liquidation(
+                            capital,
+                            &obligation,
+                            &liquidity,
+                            &collateral,
+                            close_factor,
+                            None,
+                        )
+                        .unwrap()
+                        .repay_amount,
+                    capital
+                );
+            }
+        }
+
+        // a $500,000 liquidation at most, so 250,000 tokens
+        repay_reserve.config.liquidation_close_factor = 100;
+        let whale_liquidity = ObligationLiquidity {
+            borrow_reserve: repay_reserve_pubkey,
+            borrowed_amount_wads: Decimal::from(1_000_000_000u64),
+            market_value: Decimal::from(2_000_000_000u64),
+            ..ObligationLiquidity::default()
+        };
+        let mut obligation = Obligation {
+            deposits: vec![collateral.clone()],
+            borrows: vec![whale_liquidity],
+            deposited_value: collateral.market_value,
+            borrowed_value: Decimal::from(2_000_000_000u64),
+            unhealthy_borrow_value: Decimal::from(2_000_000_000u64),
+            ..Obligation::default()
+        };
+        assert_eq!(
+            obligation.repay_capital_needed(repay_reserve_pubkey, &repay_reserve),
+            Ok(250_000)
+        );
+
+        // healthy obligations can't be liquidated at all
+        obligation.unhealthy_borrow_value = Decimal::from(2_000_000_001u64);
+        assert_eq!(
+            obligation.repay_capital_needed(repay_reserve_pubkey, &repay_reserve),
+            Ok(0)
+        );
+
+        // nor can borrows the obligation doesn't have
+        obligation.unhealthy_borrow_value = Decimal::zero();
+        assert_eq!(
+            obligation.repay_capital_needed(Pubkey::new_unique(), &repay_reserve),
+            Err(LendingError::InvalidObligationLiquidity.into())
+        );
+    }
+
+    #[test]
+    fn max_withdraw_amount_no_borrows() {
+        let collateral = ObligationCollateral {
+            deposited_amount: 1_000,
+            market_value: Decimal::from(100u64),
+            ..ObligationCollateral::default()
+        };
+        let obligation = Obligation {
+            deposits: vec![collateral.clone()],
+            deposited_value: Decimal::from(100u64),
+            allowed_borrow_value: Decimal::from(50u64),
+            ..Obligation::default()
+        };
+        let mut reserve = Reserve::default();
+        reserve.config.loan_to_value_ratio = 50;
+
+        assert_eq!(
+            obligation.max_withdraw_amount(&collateral, &reserve),
+            Ok(1_000)
+        );
+    }
+
+    #[test]
+    fn max_withdraw_amount_partially_borrowed() {
+        // 1,000 collateral tokens worth $100 at a 50% loan to value ratio
+        let collateral = ObligationCollateral {
+            deposited_amount: 1_000,
+            market_value: Decimal::from(100u64),
+            ..ObligationCollateral::default()
+        };
+        let mut obligation = Obligation {
+            deposits: vec![collateral.clone()],
+            borrows: vec![ObligationLiquidity::new(
+                Pubkey::new_unique(),
+                Decimal::one(),
+            )],
+            deposited_value: Decimal::from(100u64),
+            allowed_borrow_value: Decimal::from(50u64),
+            borrowed_value: Decimal::from(30u64),
+            ..Obligation::default()
+        };
+        let mut reserve = Reserve::default();
+        reserve.config.loan_to_value_ratio = 50;
+
+        // $20 of spare borrowing power frees $40 of collateral, leaving $30 allowed against the
+        // $30 borrowed
+        let max_withdraw_amount = obligation
+            .max_withdraw_amount(&collateral, &reserve)
+            .unwrap();
+        assert_eq!(max_withdraw_amount, 400);
+        let remaining_allowed_borrow_value = collateral
+            .market_value
+            .try_mul(collateral.deposited_amount - max_withdraw_amount)
+            .unwrap()
+            .try_div(collateral.deposited_amount)
+            .unwrap()
+            .try_mul(Rate::from_percent(50))
+            .unwrap();
+        assert_eq!(remaining_allowed_borrow_value, obligation.borrowed_value);
+
+        // protected collateral stays put
+        let mut protected_collateral = collateral.clone();
+        protected_collateral.protect(700).unwrap();
+        assert_eq!(
+            obligation.max_withdraw_amount(&protected_collateral, &reserve),
+            Ok(300)
+        );
+
+        // nothing can be withdrawn once the borrows use up the allowed borrow value
+        obligation.borrowed_value = Decimal::from(50u64);
+        assert_eq!(obligation.max_withdraw_amount(&collateral, &reserve), Ok(0));
+    }
+
+    #[test]
+    fn collateral_needed_for_borrow() {
+        // $2.50 per token, 2 collateral tokens per liquidity token
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 6,
+                available_amount: 1_000_000_000,
+                market_price: Decimal::from(5u64).try_div(2).unwrap(),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 2_000_000_000,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        };
+        let deposit_value = |reserve: &Reserve, collateral_amount: u64| {
+            reserve
+                .collateral_exchange_rate()
+                .unwrap()
+                .decimal_collateral_to_liquidity(collateral_amount.into())
+                .unwrap()
+                .try_mul(reserve.liquidity.market_price)
+                .unwrap()
+                .try_div(1_000_000)
+                .unwrap()
+        };
+
+        let obligation = Obligation {
+            deposited_value: Decimal::from(100u64),
+            allowed_borrow_value: Decimal::from(50u64),
+            borrowed_value: Decimal::from(30u64),
+            ..Obligation::default()
+        };
+
+        for loan_to_value_ratio in [10, 33, 50, 75, 99] {
+            reserve.config.loan_to_value_ratio = loan_to_value_ratio;
+            let loan_to_value_rate = Rate::from_percent(loan_to_value_ratio);
+
+            for borrow_value in [
+                Decimal::from(21u64),
+                Decimal::from(12_345u64).try_div(100).unwrap(),
+                Decimal::from(1_000_000u64),
+            ] {
+                let collateral_amount = obligation
+                    .collateral_needed_for_borrow(borrow_value, &reserve)
+                    .unwrap();
+                let required_borrow_value =
+                    obligation.borrowed_value.try_add(borrow_value).unwrap();
+                let allowed_borrow_value = |collateral_amount| {
+                    obligation
+                        .allowed_borrow_value
+                        .try_add(
+                            deposit_value(&reserve, collateral_amount)
+                                .try_mul(loan_to_value_rate)
+                                .unwrap(),
+                        )
+                        .unwrap()
+                };
+
+                // exactly enough: one collateral token less falls short
+                assert!(allowed_borrow_value(collateral_amount) >= required_borrow_value);
+                assert!(allowed_borrow_value(collateral_amount - 1) < required_borrow_value);
+            }
+        }
+
+        // spare borrowing power covers the borrow
+        reserve.config.loan_to_value_ratio = 50;
+        assert_eq!(
+            obligation
+                .collateral_needed_for_borrow(Decimal::from(20u64), &reserve)
+                .unwrap(),
+            0
+        );
+        // $10 more at 50% is $20 of collateral, 8 tokens or 16 collateral tokens
+        assert_eq!(
+            obligation
+                .collateral_needed_for_borrow(Decimal::from(30u64), &reserve)
+                .unwrap(),
+            16_000_000
+        );
+
+        reserve.config.loan_to_value_ratio = 0;
+        assert_eq!(
+            obligation.collateral_needed_for_borrow(Decimal::from(30u64), &reserve),
+            Err(LendingError::ReserveCollateralDisabled.into())
+        );
+    }
+
+    #[test]
+    fn reserve_refresh_accounts_round_trip() {
+        let entries = vec![
+            ReserveRefreshAccounts {
+                reserve: Pubkey::new_unique(),
+                pyth_oracle: Pubkey::new_unique(),
+                switchboard_oracle: Pubkey::new_unique(),
+                extra_pyth_oracles: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            },
+            ReserveRefreshAccounts {
+                reserve: Pubkey::new_unique(),
+                pyth_oracle: Pubkey::new_unique(),
+                switchboard_oracle: Pubkey::new_unique(),
+                extra_pyth_oracles: vec![],
+            },
+        ];
+        let mut data = vec![];
+        for entry in &entries {
+            entry.pack_into_vec(&mut data);
+        }
+        assert_eq!(
+            data.len(),
+            entries
+                .iter()
+                .map(|entry| entry.packed_len())
+                .sum::<usize>()
+        );
+        assert_eq!(ReserveRefreshAccounts::unpack_all(&data), Ok(entries));
+
+        // entries can't be cut short
+        assert_eq!(
+            ReserveRefreshAccounts::unpack_all(&data[..data.len() - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            ReserveRefreshAccounts::unpack_all(
+                &data[..RESERVE_REFRESH_ACCOUNTS_HEADER_LEN + PUBKEY_BYTES]
+            ),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
 }
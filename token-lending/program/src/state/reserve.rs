@@ -1,13 +1,14 @@
 use super::*;
 use crate::{
     error::LendingError,
-    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
+    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub, PERCENT_SCALER},
 };
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
     clock::Slot,
     entrypoint::ProgramResult,
     msg,
+    program::MAX_RETURN_DATA,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::{Pubkey, PUBKEY_BYTES},
@@ -17,7 +18,8 @@ use std::{
     convert::{TryFrom, TryInto},
 };
 
-/// Percentage of an obligation that can be repaid during each liquidation call
+/// Default percentage of an obligation that can be repaid during each liquidation call, for
+/// reserves that don't configure their own close factor
 pub const LIQUIDATION_CLOSE_FACTOR: u8 = 20;
 
 /// Obligation borrow amount that is small enough to close out
@@ -26,6 +28,28 @@ pub const LIQUIDATION_CLOSE_AMOUNT: u64 = 2;
 /// Maximum quote currency value that can be liquidated in 1 liquidate_obligation call
 pub const MAX_LIQUIDATABLE_VALUE_AT_ONCE: u64 = 500_000;
 
+/// Max number of reserves summarized by a single GetMarketReserves, so that the summaries fit in
+/// the return data
+pub const MAX_RESERVE_CONFIG_SUMMARIES: usize = MAX_RETURN_DATA / RESERVE_CONFIG_SUMMARY_LEN;
+
+/// Number of slots a queued reserve config update waits before it can be applied, ~1 day
+pub const RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS: u64 = 172_800;
+
+/// Maximum combined borrow and flash loan fee a reserve can be configured with, 10%
+pub const MAX_TOTAL_FEE_WAD: u64 = WAD / 10;
+
+/// Default number of slots after which a Pyth price is too old to use, roughly 2 minutes, for
+/// reserves that don't configure their own max price age
+pub const DEFAULT_MAX_PRICE_AGE_SLOTS: u64 = 240;
+
+/// Max number of Pyth price feeds a reserve reads besides its primary one, taking the median of
+/// them all as its market price
+pub const MAX_EXTRA_PYTH_ORACLES: usize = 2;
+
+/// Largest price divergence, in basis points, SetReserveOracles can be told to tolerate when
+/// switching a reserve's oracles, 10%
+pub const MAX_ORACLE_PRICE_DIVERGENCE_BPS: u16 = 1_000;
+
 /// Lending market reserve state
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Reserve {
@@ -41,6 +65,15 @@ pub struct Reserve {
     pub collateral: ReserveCollateral,
     /// Reserve configuration values
     pub config: ReserveConfig,
+    /// Liquidation threshold decrease that is still being phased in
+    pub liquidation_threshold_ramp: LiquidationThresholdRamp,
+    /// Risk parameter change waiting for its timelock to elapse
+    pub pending_config_update: PendingReserveConfigUpdate,
+    /// Manual price that deposited collateral is valued at when spot is higher, zero if unset
+    pub collateral_floor_price: Decimal,
+    /// Copy of the lending market's use_price_confidence, so RefreshObligation can tell it needs
+    /// the lending market account to value this reserve
+    pub use_price_confidence: bool,
 }
 
 impl Reserve {
@@ -57,10 +90,275 @@ impl Reserve {
         self.last_update = LastUpdate::new(params.current_slot);
         self.lending_market = params.lending_market;
         self.liquidity = params.liquidity;
+        self.liquidity.market_price_last_changed_slot = params.current_slot;
+        self.liquidity.oracle_price_slot = params.current_slot;
         self.collateral = params.collateral;
         self.config = params.config;
     }
 
+    /// Set the market price read from the oracle at `slot`. Fails if the price has stayed the
+    /// same for longer than the configured window, which points at a stuck feed.
+    pub fn update_market_price(&mut self, market_price: Decimal, slot: Slot) -> ProgramResult {
+        self.liquidity.previous_market_price = self.liquidity.market_price;
+        self.liquidity.previous_market_price_slot = self.last_update.slot;
+        self.liquidity.oracle_price_slot = slot;
+
+        // reserves written before the slot was tracked start counting from their first refresh
+        if market_price != self.liquidity.market_price
+            || self.liquidity.market_price_last_changed_slot == 0
+        {
+            self.liquidity.market_price = market_price;
+            self.liquidity.market_price_last_changed_slot = slot;
+            return Ok(());
+        }
+
+        let max_unchanged_slots = self.config.max_unchanged_price_slots;
+        let unchanged_slots = slot.saturating_sub(self.liquidity.market_price_last_changed_slot);
+        if max_unchanged_slots > 0 && unchanged_slots > max_unchanged_slots {
+            msg!(
+                "Oracle price has not changed in {} slots, more than the {} allowed",
+                unchanged_slots,
+                max_unchanged_slots
+            );
+            return Err(LendingError::OraclePriceStuck.into());
+        }
+
+        Ok(())
+    }
+
+    /// Set the market price to the configured fixed price in place of an oracle reading. The
+    /// slot an oracle price was last read at is left alone, so that the reserve doesn't keep
+    /// refreshing on a cached fixed price once switched back to its oracles.
+    pub fn update_fixed_market_price(&mut self, market_price: Decimal, slot: Slot) {
+        self.liquidity.previous_market_price = self.liquidity.market_price;
+        self.liquidity.previous_market_price_slot = self.last_update.slot;
+        self.liquidity.market_price_confidence = Decimal::zero();
+        if market_price != self.liquidity.market_price {
+            self.liquidity.market_price = market_price;
+            self.liquidity.market_price_last_changed_slot = slot;
+        }
+    }
+
+    /// Price deposited collateral is valued at: the market price, lowered to the collateral
+    /// floor price when one is set and discounted while the price is cached through an outage.
+    pub fn collateral_price(&self) -> Result<Decimal, ProgramError> {
+        self.collateral_price_at(self.liquidity.market_price)
+            .try_mul(Rate::one().try_sub(self.stale_price_decay()?)?)
+    }
+
+    /// Price borrowed liquidity is valued at: the market price, marked up while the price is
+    /// cached through an outage.
+    pub fn debt_price(&self) -> Result<Decimal, ProgramError> {
+        self.liquidity
+            .market_price
+            .try_mul(Rate::one().try_add(self.stale_price_decay()?)?)
+    }
+
+    /// `price` lowered to the bottom of the market price's confidence interval, for valuing
+    /// collateral as conservatively as the oracle allows. Floored at zero.
+    pub fn price_less_confidence(&self, price: Decimal) -> Result<Decimal, ProgramError> {
+        let confidence = self.liquidity.market_price_confidence;
+        if confidence >= price {
+            return Ok(Decimal::zero());
+        }
+        price.try_sub(confidence)
+    }
+
+    /// `price` raised to the top of the market price's confidence interval, for valuing debt as
+    /// conservatively as the oracle allows.
+    pub fn price_plus_confidence(&self, price: Decimal) -> Result<Decimal, ProgramError> {
+        price.try_add(self.liquidity.market_price_confidence)
+    }
+
+    /// Fraction collateral is discounted and debt marked up by when the reserve was last
+    /// refreshed on a market price cached through an oracle outage. Grows by the configured
+    /// decay for every slot since the price was last read from an oracle, up to 100%.
+    pub fn stale_price_decay(&self) -> Result<Rate, ProgramError> {
+        // reserves written before the read slot was tracked have no cached price to decay, and
+        // fixed prices aren't read from an oracle at all
+        if self.config.max_stale_price_slots == 0
+            || self.liquidity.oracle_price_slot == 0
+            || self.config.fixed_market_price().is_some()
+        {
+            return Ok(Rate::zero());
+        }
+
+        let stale_slots = self
+            .last_update
+            .slot
+            .saturating_sub(self.liquidity.oracle_price_slot);
+        let decay_bps = stale_slots
+            .saturating_mul(self.config.stale_price_decay_bps as u64)
+            .min(10_000);
+        Ok(Rate::from_bps(decay_bps as u16))
+    }
+
+    /// Maximum total supply of liquidity in native units
+    pub fn deposit_limit(&self) -> Result<u64, ProgramError> {
+        self.effective_limit(self.config.deposit_limit)
+    }
+
+    /// Maximum amount of liquidity borrowed from the reserve in native units
+    pub fn borrow_limit(&self) -> Result<u64, ProgramError> {
+        self.effective_limit(self.config.borrow_limit)
+    }
+
+    /// Configured limit in native units, scaled to the current total supply for fractional limits
+    fn effective_limit(&self, limit: u64) -> Result<u64, ProgramError> {
+        if !self.config.fractional_limits {
+            return Ok(limit);
+        }
+
+        // a limit past u64::MAX native units can't be reached, so it saturates
+        Ok(self
+            .liquidity
+            .total_supply()?
+            .try_mul(limit)?
+            .try_div(10_000u64)?
+            .try_floor_u64()
+            .unwrap_or(u64::MAX))
+    }
+
+    /// Whether the market price was read from the oracles recently enough to liquidate against.
+    /// A cached price can keep refreshing the reserve through an outage for longer than it can be
+    /// used to seize collateral.
+    pub fn is_price_fresh_for_liquidation(&self, slot: Slot) -> bool {
+        // fixed prices aren't read from an oracle, so they never age
+        self.config.max_liquidation_price_age_slots == 0
+            || self.config.fixed_market_price().is_some()
+            || slot.saturating_sub(self.liquidity.oracle_price_slot)
+                <= self.config.max_liquidation_price_age_slots
+    }
+
+    /// Price deposited collateral is valued at when backing borrows. A price that jumped since a
+    /// refresh in the same or the previous slot is capped at the configured increase over the
+    /// previous price, so a momentary oracle spike can't be borrowed against.
+    pub fn borrow_collateral_price(&self) -> Result<Decimal, ProgramError> {
+        let collateral_price = self.collateral_price()?;
+        let max_increase_pct = self.config.max_borrow_price_increase_pct;
+        let previous_market_price = self.liquidity.previous_market_price;
+        let slots_since_previous = self
+            .last_update
+            .slot
+            .saturating_sub(self.liquidity.previous_market_price_slot);
+        if max_increase_pct == 0
+            || previous_market_price == Decimal::zero()
+            || slots_since_previous > 1
+        {
+            return Ok(collateral_price);
+        }
+
+        let max_price = previous_market_price
+            .try_mul(Rate::one().try_add(Rate::from_percent(max_increase_pct))?)?;
+        Ok(collateral_price.min(max_price))
+    }
+
+    /// Price deposited collateral would be valued at if the liquidity traded at `market_price`
+    pub fn collateral_price_at(&self, market_price: Decimal) -> Decimal {
+        if self.collateral_floor_price == Decimal::zero() {
+            market_price
+        } else {
+            market_price.min(self.collateral_floor_price)
+        }
+    }
+
+    /// Liquidation threshold in effect at `slot`. A decrease of the configured threshold is
+    /// interpolated linearly from the threshold it replaced over the configured ramp.
+    pub fn liquidation_threshold(&self, slot: Slot) -> Result<Rate, ProgramError> {
+        let target_threshold = Rate::from_percent(self.config.liquidation_threshold);
+        let ramp_slots = self.config.liquidation_threshold_ramp_slots;
+        let slots_elapsed = slot.saturating_sub(self.liquidation_threshold_ramp.start_slot);
+        if self.liquidation_threshold_ramp.start_threshold <= self.config.liquidation_threshold
+            || slots_elapsed >= ramp_slots
+        {
+            return Ok(target_threshold);
+        }
+
+        let start_threshold = Rate::from_percent(self.liquidation_threshold_ramp.start_threshold);
+        start_threshold
+            .try_sub(target_threshold)?
+            .try_mul(ramp_slots - slots_elapsed)?
+            .try_div(ramp_slots)?
+            .try_add(target_threshold)
+    }
+
+    /// Replace the reserve config. Increases of the liquidation threshold apply immediately,
+    /// decreases ramp in from the threshold currently in effect.
+    pub fn update_config(&mut self, config: ReserveConfig, slot: Slot) -> ProgramResult {
+        let current_threshold = self.liquidation_threshold(slot)?;
+        self.liquidation_threshold_ramp = if config.liquidation_threshold_ramp_slots > 0
+            && Rate::from_percent(config.liquidation_threshold) < current_threshold
+        {
+            // round up so the ramp never starts below the threshold users were held to
+            let start_threshold = current_threshold
+                .to_scaled_val()
+                .checked_add(PERCENT_SCALER as u128 - 1)
+                .ok_or(LendingError::MathOverflow)?
+                / PERCENT_SCALER as u128;
+            LiquidationThresholdRamp {
+                start_threshold: u8::try_from(start_threshold)
+                    .map_err(|_| LendingError::MathOverflow)?,
+                start_slot: slot,
+            }
+        } else {
+            LiquidationThresholdRamp::default()
+        };
+        self.config = config;
+
+        Ok(())
+    }
+
+    /// Queue a change of the risk parameters that can be applied once the timelock has elapsed,
+    /// replacing any change that is already queued.
+    pub fn queue_config_update(
+        &mut self,
+        loan_to_value_ratio: u8,
+        liquidation_threshold: u8,
+        protocol_take_rate: u8,
+        slot: Slot,
+    ) -> ProgramResult {
+        self.pending_config_update = PendingReserveConfigUpdate {
+            loan_to_value_ratio,
+            liquidation_threshold,
+            protocol_take_rate,
+            applicable_slot: slot
+                .checked_add(RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS)
+                .ok_or(LendingError::MathOverflow)?,
+        };
+        Ok(())
+    }
+
+    /// Config that results from applying the queued risk parameter change
+    pub fn pending_config(&self) -> Result<ReserveConfig, ProgramError> {
+        let pending = &self.pending_config_update;
+        if !pending.is_queued() {
+            msg!("Reserve has no queued config update");
+            return Err(LendingError::NoPendingConfigUpdate.into());
+        }
+        Ok(ReserveConfig {
+            loan_to_value_ratio: pending.loan_to_value_ratio,
+            liquidation_threshold: pending.liquidation_threshold,
+            protocol_take_rate: pending.protocol_take_rate,
+            ..self.config
+        })
+    }
+
+    /// Apply the queued risk parameter change. Fails if the timelock has not elapsed yet.
+    pub fn apply_config_update(&mut self, slot: Slot) -> ProgramResult {
+        let config = self.pending_config()?;
+        if slot < self.pending_config_update.applicable_slot {
+            msg!(
+                "Reserve config update can't be applied until slot {}",
+                self.pending_config_update.applicable_slot
+            );
+            return Err(LendingError::ConfigUpdateTimelocked.into());
+        }
+        self.update_config(config, slot)?;
+        self.pending_config_update = PendingReserveConfigUpdate::default();
+
+        Ok(())
+    }
+
     /// Record deposited liquidity and return amount of collateral tokens to mint
     pub fn deposit_liquidity(&mut self, liquidity_amount: u64) -> Result<u64, ProgramError> {
         let collateral_amount = self
@@ -73,6 +371,27 @@ impl Reserve {
         Ok(collateral_amount)
     }
 
+    /// Record the liquidity a new reserve is seeded with, minting collateral at
+    /// `collateral_exchange_rate` rather than the default initial rate, and return amount of
+    /// collateral tokens to mint
+    pub fn deposit_initial_liquidity(
+        &mut self,
+        liquidity_amount: u64,
+        collateral_exchange_rate: Rate,
+    ) -> Result<u64, ProgramError> {
+        let collateral_amount = CollateralExchangeRate(collateral_exchange_rate)
+            .liquidity_to_collateral(liquidity_amount)?;
+        if collateral_amount == 0 {
+            msg!("Initial deposit is too small to mint collateral");
+            return Err(LendingError::InvalidAmount.into());
+        }
+
+        self.liquidity.deposit(liquidity_amount)?;
+        self.collateral.mint(collateral_amount)?;
+
+        Ok(collateral_amount)
+    }
+
     /// Record redeemed collateral and return amount of liquidity to withdraw
     pub fn redeem_collateral(&mut self, collateral_amount: u64) -> Result<u64, ProgramError> {
         let collateral_exchange_rate = self.collateral_exchange_rate()?;
@@ -89,6 +408,8 @@ impl Reserve {
     pub fn current_borrow_rate(&self) -> Result<Rate, ProgramError> {
         let utilization_rate = self.liquidity.utilization_rate()?;
         let optimal_utilization_rate = Rate::from_percent(self.config.optimal_utilization_rate);
+        let extreme_utilization_rate = Rate::from_percent(self.config.extreme_utilization_rate);
+        let has_extreme_segment = self.config.has_extreme_segment();
         let low_utilization = utilization_rate < optimal_utilization_rate;
         if low_utilization || self.config.optimal_utilization_rate == 100 {
             let normalized_rate = utilization_rate.try_div(optimal_utilization_rate)?;
@@ -101,26 +422,19 @@ impl Reserve {
             );
 
             Ok(normalized_rate.try_mul(rate_range)?.try_add(min_rate)?)
-        } else {
+        } else if !has_extreme_segment || utilization_rate <= extreme_utilization_rate {
             if self.config.optimal_borrow_rate == self.config.max_borrow_rate {
-                let rate = Rate::from_percent(50u8);
-                return Ok(match self.config.max_borrow_rate {
-                    251u8 => rate.try_mul(6)?,  //300%
-                    252u8 => rate.try_mul(7)?,  //350%
-                    253u8 => rate.try_mul(8)?,  //400%
-                    254u8 => rate.try_mul(10)?, //500%
-                    255u8 => rate.try_mul(12)?, //600%
-                    250u8 => rate.try_mul(20)?, //1000%
-                    249u8 => rate.try_mul(30)?, //1500%
-                    248u8 => rate.try_mul(40)?, //2000%
-                    247u8 => rate.try_mul(50)?, //2500%
-                    _ => Rate::from_percent(self.config.max_borrow_rate),
-                });
+                return self.config.effective_max_borrow_rate();
             }
+            let upper_utilization_rate = if has_extreme_segment {
+                self.config.extreme_utilization_rate
+            } else {
+                100u8
+            };
             let normalized_rate = utilization_rate
                 .try_sub(optimal_utilization_rate)?
                 .try_div(Rate::from_percent(
-                    100u8
+                    upper_utilization_rate
                         .checked_sub(self.config.optimal_utilization_rate)
                         .ok_or(LendingError::MathOverflow)?,
                 ))?;
@@ -132,6 +446,19 @@ impl Reserve {
                     .ok_or(LendingError::MathOverflow)?,
             );
 
+            Ok(normalized_rate.try_mul(rate_range)?.try_add(min_rate)?)
+        } else {
+            let normalized_rate = utilization_rate
+                .try_sub(extreme_utilization_rate)?
+                .try_div(Rate::from_percent(
+                    100u8
+                        .checked_sub(self.config.extreme_utilization_rate)
+                        .ok_or(LendingError::MathOverflow)?,
+                ))?;
+            let min_rate = self.config.effective_max_borrow_rate()?;
+            let rate_range =
+                Rate::from_percent_u16(self.config.extreme_borrow_rate).try_sub(min_rate)?;
+
             Ok(normalized_rate.try_mul(rate_range)?.try_add(min_rate)?)
         }
     }
@@ -142,18 +469,107 @@ impl Reserve {
         self.collateral.exchange_rate(total_liquidity)
     }
 
+    /// Annualized yield implied by the cumulative borrow rate growing from `prev_index` at
+    /// `prev_slot` to its current value at `now_slot`
+    pub fn realized_apy(
+        &self,
+        prev_index: Decimal,
+        prev_slot: Slot,
+        now_slot: Slot,
+    ) -> Result<Rate, ProgramError> {
+        let slots_elapsed = now_slot
+            .checked_sub(prev_slot)
+            .ok_or(LendingError::MathOverflow)?;
+        if slots_elapsed == 0 {
+            return Ok(Rate::zero());
+        }
+
+        let growth = Rate::try_from(
+            self.liquidity
+                .cumulative_borrow_rate_wads
+                .try_div(prev_index)?,
+        )?;
+        let windows_per_year = SLOTS_PER_YEAR / slots_elapsed;
+        let remaining_slots = SLOTS_PER_YEAR % slots_elapsed;
+
+        // growth^(remaining_slots / slots_elapsed) from its binomial series. Terms alternate in
+        // sign and shrink as long as the index less than doubled over the window.
+        let excess = growth.try_sub(Rate::one())?;
+        let mut remaining_growth = Rate::one();
+        let mut term = Rate::one();
+        for k in 1..64u64 {
+            let numerator = if k == 1 {
+                remaining_slots
+            } else {
+                (k - 1) * slots_elapsed - remaining_slots
+            };
+            term = term
+                .try_mul(excess)?
+                .try_mul(numerator)?
+                .try_div(k * slots_elapsed)?;
+            if term == Rate::zero() {
+                break;
+            }
+            remaining_growth = if k % 2 == 1 {
+                remaining_growth.try_add(term)?
+            } else {
+                remaining_growth.try_sub(term)?
+            };
+        }
+
+        growth
+            .try_pow(windows_per_year)?
+            .try_mul(remaining_growth)?
+            .try_sub(Rate::one())
+    }
+
     /// Update borrow rate and accrue interest
     pub fn accrue_interest(&mut self, current_slot: Slot) -> ProgramResult {
         let slots_elapsed = self.last_update.slots_elapsed(current_slot)?;
-        if slots_elapsed > 0 {
+        if slots_elapsed > 0 && !self.interest_paused() {
             let current_borrow_rate = self.current_borrow_rate()?;
             let take_rate = Rate::from_percent(self.config.protocol_take_rate);
-            self.liquidity
-                .compound_interest(current_borrow_rate, slots_elapsed, take_rate)?;
+            let previous_protocol_fees = self.liquidity.accumulated_protocol_fees_wads;
+            self.liquidity.compound_interest(
+                current_borrow_rate,
+                slots_elapsed,
+                take_rate,
+                self.config.round_supplier_interest_up,
+            )?;
+
+            if self.config.protocol_fees_cap > 0 {
+                // clamp rather than fail, this is a guard against runaway fees and must never
+                // block interest accrual. Fees already above a lowered cap are left alone.
+                let max_protocol_fees = Decimal::from(self.liquidity.available_amount)
+                    .try_add(self.liquidity.borrowed_amount_wads)?
+                    .try_mul(Rate::from_percent(self.config.protocol_fees_cap))?
+                    .max(previous_protocol_fees);
+                self.liquidity.accumulated_protocol_fees_wads = self
+                    .liquidity
+                    .accumulated_protocol_fees_wads
+                    .min(max_protocol_fees);
+            }
         }
         Ok(())
     }
 
+    /// Whether interest accrual is held through an oracle outage
+    pub fn interest_paused(&self) -> bool {
+        self.config.pause_interest_on_oracle_error && self.liquidity.interest_paused
+    }
+
+    /// Amount of liquidity in the mint's smallest units worth `value` in the quote currency at
+    /// the reserve's market price, the inverse of `ReserveLiquidity::market_value_at`
+    pub fn usd_to_liquidity_amount(&self, value: Decimal) -> Result<Decimal, ProgramError> {
+        // @TODO: add lookup table https://git.io/JOCYq
+        let decimals = 10u64
+            .checked_pow(self.liquidity.mint_decimals as u32)
+            .ok_or(LendingError::MathOverflow)?;
+        value
+            .try_mul(decimals)?
+            .try_div(self.liquidity.market_price)
+    }
+
     /// Borrow liquidity up to a maximum market value
     pub fn calculate_borrow(
         &self,
@@ -161,14 +577,9 @@ impl Reserve {
         max_borrow_value: Decimal,
         remaining_reserve_borrow: Decimal,
     ) -> Result<CalculateBorrowResult, ProgramError> {
-        // @TODO: add lookup table https://git.io/JOCYq
-        let decimals = 10u64
-            .checked_pow(self.liquidity.mint_decimals as u32)
-            .ok_or(LendingError::MathOverflow)?;
         if amount_to_borrow == u64::MAX {
-            let borrow_amount = max_borrow_value
-                .try_mul(decimals)?
-                .try_div(self.liquidity.market_price)?
+            let borrow_amount = self
+                .usd_to_liquidity_amount(max_borrow_value)?
                 .min(remaining_reserve_borrow)
                 .min(self.liquidity.available_amount.into());
             let (borrow_fee, host_fee) = self
@@ -195,9 +606,9 @@ impl Reserve {
                 .calculate_borrow_fees(borrow_amount, FeeCalculation::Exclusive)?;
 
             let borrow_amount = borrow_amount.try_add(borrow_fee.into())?;
-            let borrow_value = borrow_amount
-                .try_mul(self.liquidity.market_price)?
-                .try_div(decimals)?;
+            let borrow_value = self
+                .liquidity
+                .market_value_at(borrow_amount, self.liquidity.market_price)?;
             if borrow_value > max_borrow_value {
                 msg!("Borrow value cannot exceed maximum borrow value");
                 return Err(LendingError::BorrowTooLarge.into());
@@ -231,15 +642,26 @@ impl Reserve {
         })
     }
 
-    /// Liquidate some or all of an unhealthy obligation
+    /// Liquidate some or all of an unhealthy obligation, repaying at most `close_factor` percent of
+    /// its borrowed value and paying at most `max_bonus_value` on top of the value repaid when set
+    #[allow(clippy::too_many_arguments)]
     pub fn calculate_liquidation(
         &self,
         amount_to_liquidate: u64,
         obligation: &Obligation,
         liquidity: &ObligationLiquidity,
         collateral: &ObligationCollateral,
+        close_factor: u8,
+        max_bonus_value: Option<Decimal>,
     ) -> Result<CalculateLiquidationResult, ProgramError> {
-        let bonus_rate = Rate::from_percent(self.config.liquidation_bonus).try_add(Rate::one())?;
+        let bonus_rate = self.liquidation_bonus(obligation)?.try_add(Rate::one())?;
+        let with_bonus = |value: Decimal| -> Result<Decimal, ProgramError> {
+            let value_with_bonus = value.try_mul(bonus_rate)?;
+            match max_bonus_value {
+                Some(max_bonus_value) => Ok(value_with_bonus.min(value.try_add(max_bonus_value)?)),
+                None => Ok(value_with_bonus),
+            }
+        };
 
         let max_amount = if amount_to_liquidate == u64::MAX {
             liquidity.borrowed_amount_wads
@@ -256,7 +678,7 @@ impl Reserve {
             // settle_amount is fixed, calculate withdraw_amount and repay_amount
             settle_amount = liquidity.borrowed_amount_wads;
 
-            let liquidation_value = liquidity.market_value.try_mul(bonus_rate)?;
+            let liquidation_value = with_bonus(liquidity.market_value)?;
             match liquidation_value.cmp(&collateral.market_value) {
                 Ordering::Greater => {
                     let repay_pct = collateral.market_value.try_div(liquidation_value)?;
@@ -278,13 +700,10 @@ impl Reserve {
         } else {
             // calculate settle_amount and withdraw_amount, repay_amount is settle_amount rounded
             let liquidation_amount = obligation
-                .max_liquidation_amount(liquidity)?
+                .max_liquidation_amount(liquidity, close_factor)?
                 .min(max_amount);
             let liquidation_pct = liquidation_amount.try_div(liquidity.borrowed_amount_wads)?;
-            let liquidation_value = liquidity
-                .market_value
-                .try_mul(liquidation_pct)?
-                .try_mul(bonus_rate)?;
+            let liquidation_value = with_bonus(liquidity.market_value.try_mul(liquidation_pct)?)?;
 
             match liquidation_value.cmp(&collateral.market_value) {
                 Ordering::Greater => {
@@ -316,15 +735,51 @@ impl Reserve {
         })
     }
 
+    /// Liquidation bonus for liquidating the obligation. Ramps up linearly from the liquidation
+    /// bonus while borrows are worth the unhealthy borrow value to the max liquidation bonus once
+    /// they are worth as much as the deposits, so that the deeper an obligation is underwater
+    /// the faster it gets liquidated.
+    pub fn liquidation_bonus(&self, obligation: &Obligation) -> Result<Rate, ProgramError> {
+        let min_bonus = Rate::from_percent(self.config.liquidation_bonus);
+        if self.config.max_liquidation_bonus <= self.config.liquidation_bonus
+            || obligation.borrowed_value <= obligation.unhealthy_borrow_value
+        {
+            return Ok(min_bonus);
+        }
+
+        let max_bonus = Rate::from_percent(self.config.max_liquidation_bonus);
+        if obligation.borrowed_value >= obligation.deposited_value {
+            return Ok(max_bonus);
+        }
+
+        let ramp_pct = Rate::try_from(
+            obligation
+                .borrowed_value
+                .try_sub(obligation.unhealthy_borrow_value)?
+                .try_div(
+                    obligation
+                        .deposited_value
+                        .try_sub(obligation.unhealthy_borrow_value)?,
+                )?,
+        )?;
+        min_bonus.try_add(max_bonus.try_sub(min_bonus)?.try_mul(ramp_pct)?)
+    }
+
     /// Calculate protocol cut of liquidation bonus always at least 1 lamport
     pub fn calculate_protocol_liquidation_fee(
         &self,
         amount_liquidated: u64,
+        liquidation_bonus: Rate,
+        max_bonus_value: Option<Decimal>,
     ) -> Result<u64, ProgramError> {
-        let bonus_rate = Rate::from_percent(self.config.liquidation_bonus).try_add(Rate::one())?;
+        let bonus_rate = liquidation_bonus.try_add(Rate::one())?;
         let amount_liquidated_wads = Decimal::from(amount_liquidated);
 
-        let bonus = amount_liquidated_wads.try_sub(amount_liquidated_wads.try_div(bonus_rate)?)?;
+        let mut bonus =
+            amount_liquidated_wads.try_sub(amount_liquidated_wads.try_div(bonus_rate)?)?;
+        if let Some(max_bonus_value) = max_bonus_value {
+            bonus = bonus.min(self.usd_to_liquidity_amount(max_bonus_value)?);
+        }
 
         // After deploying must update all reserves to set liquidation fee then redeploy with this line instead of hardcode
         let protocol_fee = std::cmp::max(
@@ -418,6 +873,22 @@ pub struct ReserveLiquidity {
     pub accumulated_protocol_fees_wads: Decimal,
     /// Reserve liquidity market price in quote currency
     pub market_price: Decimal,
+    /// Slot at which the market price last changed value
+    pub market_price_last_changed_slot: Slot,
+    /// Market price before the most recent refresh
+    pub previous_market_price: Decimal,
+    /// Slot of the refresh that read the previous market price
+    pub previous_market_price_slot: Slot,
+    /// Slot the market price was last read from an oracle
+    pub oracle_price_slot: Slot,
+    /// Interest accrual is held since the last refresh found the oracles unavailable
+    pub interest_paused: bool,
+    /// Reserve liquidity deployed to the strategy vault, still owed to suppliers
+    pub deployed_amount: u64,
+    /// Confidence interval of the market price as reported by the oracle it was read from
+    pub market_price_confidence: Decimal,
+    /// Further Pyth price accounts read alongside the pyth oracle, default pubkeys when unused
+    pub extra_pyth_oracle_pubkeys: [Pubkey; MAX_EXTRA_PYTH_ORACLES],
 }
 
 impl ReserveLiquidity {
@@ -434,12 +905,60 @@ impl ReserveLiquidity {
             cumulative_borrow_rate_wads: Decimal::one(),
             accumulated_protocol_fees_wads: Decimal::zero(),
             market_price: params.market_price,
+            market_price_last_changed_slot: 0,
+            previous_market_price: Decimal::zero(),
+            previous_market_price_slot: 0,
+            oracle_price_slot: 0,
+            interest_paused: false,
+            deployed_amount: 0,
+            market_price_confidence: Decimal::zero(),
+            extra_pyth_oracle_pubkeys: [Pubkey::default(); MAX_EXTRA_PYTH_ORACLES],
+        }
+    }
+
+    /// Pyth price accounts the market price is the median of along with the pyth oracle
+    pub fn extra_pyth_oracles(&self) -> Vec<Pubkey> {
+        self.extra_pyth_oracle_pubkeys
+            .iter()
+            .filter(|pubkey| **pubkey != Pubkey::default())
+            .copied()
+            .collect()
+    }
+
+    /// Replace the extra Pyth price accounts, at most `MAX_EXTRA_PYTH_ORACLES` of them
+    pub fn set_extra_pyth_oracles(&mut self, pubkeys: &[Pubkey]) -> ProgramResult {
+        if pubkeys.len() > MAX_EXTRA_PYTH_ORACLES {
+            msg!(
+                "At most {} extra pyth oracles can be set, got {}",
+                MAX_EXTRA_PYTH_ORACLES,
+                pubkeys.len()
+            );
+            return Err(LendingError::InvalidOracleConfig.into());
         }
+        self.extra_pyth_oracle_pubkeys = [Pubkey::default(); MAX_EXTRA_PYTH_ORACLES];
+        self.extra_pyth_oracle_pubkeys[..pubkeys.len()].copy_from_slice(pubkeys);
+        Ok(())
+    }
+
+    /// Value in the quote currency of an amount of liquidity in the mint's smallest units, at
+    /// `price` per whole token. Dividing out the mint decimals puts every reserve's values on the
+    /// same scale, so values of mints with different decimals can be summed.
+    pub fn market_value_at(
+        &self,
+        liquidity_amount: Decimal,
+        price: Decimal,
+    ) -> Result<Decimal, ProgramError> {
+        // @TODO: add lookup table https://git.io/JOCYq
+        let decimals = 10u64
+            .checked_pow(self.mint_decimals as u32)
+            .ok_or(LendingError::MathOverflow)?;
+        liquidity_amount.try_mul(price)?.try_div(decimals)
     }
 
-    /// Calculate the total reserve supply including active loans
+    /// Calculate the total reserve supply including active loans and deployed liquidity
     pub fn total_supply(&self) -> Result<Decimal, ProgramError> {
         Decimal::from(self.available_amount)
+            .try_add(self.deployed_amount.into())?
             .try_add(self.borrowed_amount_wads)?
             .try_sub(self.accumulated_protocol_fees_wads)
     }
@@ -466,6 +985,49 @@ impl ReserveLiquidity {
         Ok(())
     }
 
+    /// Move liquidity from available to deployed, keeping at most `max_deployed_pct` of the
+    /// liquidity that isn't lent out deployed at once
+    pub fn deploy(&mut self, liquidity_amount: u64, max_deployed_pct: u8) -> ProgramResult {
+        if liquidity_amount > self.available_amount {
+            msg!("Deploy amount cannot exceed available amount");
+            return Err(LendingError::InsufficientLiquidity.into());
+        }
+        let deployed_amount = self
+            .deployed_amount
+            .checked_add(liquidity_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        let max_deployed_amount = Decimal::from(self.available_amount)
+            .try_add(self.deployed_amount.into())?
+            .try_mul(Rate::from_percent(max_deployed_pct))?
+            .try_floor_u64()?;
+        if deployed_amount > max_deployed_amount {
+            msg!(
+                "Deployed amount {} would exceed the max deployed amount {}",
+                deployed_amount,
+                max_deployed_amount
+            );
+            return Err(LendingError::DeployLimitExceeded.into());
+        }
+
+        self.available_amount = self
+            .available_amount
+            .checked_sub(liquidity_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        self.deployed_amount = deployed_amount;
+        Ok(())
+    }
+
+    /// Move liquidity returned from the strategy vault back to available. Anything returned
+    /// beyond the deployed amount is yield and accrues to suppliers.
+    pub fn recall(&mut self, liquidity_amount: u64) -> ProgramResult {
+        self.available_amount = self
+            .available_amount
+            .checked_add(liquidity_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        self.deployed_amount = self.deployed_amount.saturating_sub(liquidity_amount);
+        Ok(())
+    }
+
     /// Subtract borrow amount from available liquidity and add to borrows
     pub fn borrow(&mut self, borrow_decimal: Decimal) -> ProgramResult {
         let borrow_amount = borrow_decimal.try_floor_u64()?;
@@ -495,6 +1057,16 @@ impl ReserveLiquidity {
         Ok(())
     }
 
+    /// Remove interest an obligation was forgiven from the borrowed amount, so the reserve no
+    /// longer counts on it being repaid
+    pub fn write_off(&mut self, forgiven_interest_wads: Decimal) -> ProgramResult {
+        self.borrowed_amount_wads = self
+            .borrowed_amount_wads
+            .try_sub(forgiven_interest_wads.min(self.borrowed_amount_wads))?;
+
+        Ok(())
+    }
+
     /// Subtract settle amount from accumulated_protocol_fees_wads and withdraw_amount from available liquidity
     pub fn redeem_fees(&mut self, withdraw_amount: u64) -> ProgramResult {
         self.available_amount = self
@@ -517,12 +1089,15 @@ impl ReserveLiquidity {
         self.borrowed_amount_wads.try_div(total_supply)?.try_into()
     }
 
-    /// Compound current borrow rate over elapsed slots
+    /// Compound current borrow rate over elapsed slots. When `round_supplier_interest_up` is set,
+    /// suppliers are credited the ceiling of their share of the new interest in whole liquidity
+    /// units, paid for out of the protocol's share as far as it goes.
     fn compound_interest(
         &mut self,
         current_borrow_rate: Rate,
         slots_elapsed: u64,
         take_rate: Rate,
+        round_supplier_interest_up: bool,
     ) -> ProgramResult {
         let slot_interest_rate = current_borrow_rate.try_div(SLOTS_PER_YEAR)?;
         let compounded_interest_rate = Rate::one()
@@ -537,9 +1112,15 @@ impl ReserveLiquidity {
             .try_mul(compounded_interest_rate)?
             .try_sub(self.borrowed_amount_wads)?;
 
-        self.accumulated_protocol_fees_wads = net_new_debt
-            .try_mul(take_rate)?
-            .try_add(self.accumulated_protocol_fees_wads)?;
+        let mut new_protocol_fees = net_new_debt.try_mul(take_rate)?;
+        if round_supplier_interest_up {
+            let supplier_interest = net_new_debt.try_sub(new_protocol_fees)?.try_ceil_u64()?;
+            new_protocol_fees =
+                net_new_debt.try_sub(Decimal::from(supplier_interest).min(net_new_debt))?;
+        }
+
+        self.accumulated_protocol_fees_wads =
+            new_protocol_fees.try_add(self.accumulated_protocol_fees_wads)?;
 
         self.borrowed_amount_wads = self.borrowed_amount_wads.try_add(net_new_debt)?;
         Ok(())
@@ -601,6 +1182,19 @@ impl ReserveCollateral {
         Ok(())
     }
 
+    /// Check the recorded total supply against the collateral mint's actual supply
+    pub fn validate_mint_supply(&self, mint_supply: u64) -> ProgramResult {
+        if self.mint_total_supply != mint_supply {
+            msg!(
+                "Reserve collateral supply {} does not match the collateral mint supply {}",
+                self.mint_total_supply,
+                mint_supply
+            );
+            return Err(LendingError::ReserveAccountingInconsistent.into());
+        }
+        Ok(())
+    }
+
     /// Return the current collateral exchange rate.
     fn exchange_rate(
         &self,
@@ -665,6 +1259,36 @@ impl From<CollateralExchangeRate> for Rate {
     }
 }
 
+/// Decrease of a reserve's liquidation threshold that is being phased in. The target is the
+/// threshold in the reserve config.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LiquidationThresholdRamp {
+    /// Liquidation threshold when the ramp started, as a percentage
+    pub start_threshold: u8,
+    /// Slot at which the ramp started
+    pub start_slot: Slot,
+}
+
+/// Change of a reserve's risk parameters waiting for its timelock to elapse
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PendingReserveConfigUpdate {
+    /// Queued loan to value ratio, as a percentage
+    pub loan_to_value_ratio: u8,
+    /// Queued liquidation threshold, as a percentage
+    pub liquidation_threshold: u8,
+    /// Queued protocol take rate, as a percentage
+    pub protocol_take_rate: u8,
+    /// First slot at which the update can be applied, 0 if nothing is queued
+    pub applicable_slot: Slot,
+}
+
+impl PendingReserveConfigUpdate {
+    /// Whether an update is waiting to be applied
+    pub fn is_queued(&self) -> bool {
+        self.applicable_slot != 0
+    }
+}
+
 /// Reserve configuration values
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct ReserveConfig {
@@ -685,9 +1309,11 @@ pub struct ReserveConfig {
     pub max_borrow_rate: u8,
     /// Program owner fees assessed, separate from gains due to interest accrual
     pub fees: ReserveFees,
-    /// Maximum deposit limit of liquidity in native units, u64::MAX for inf
+    /// Maximum deposit limit of liquidity in native units, u64::MAX for inf. In basis points of
+    /// the reserve's total supply when limits are fractional
     pub deposit_limit: u64,
-    /// Borrows disabled
+    /// Maximum amount of liquidity borrowed from the reserve in native units, u64::MAX for inf.
+    /// In basis points of the reserve's total supply when limits are fractional
     pub borrow_limit: u64,
     /// Reserve liquidity fee receiver address
     pub fee_receiver: Pubkey,
@@ -695,6 +1321,235 @@ pub struct ReserveConfig {
     pub protocol_liquidation_fee: u8,
     /// Protocol take rate is the amount borrowed interest protocol recieves, as a percentage  
     pub protocol_take_rate: u8,
+    /// Number of slots over which a decrease of the liquidation threshold is phased in,
+    /// 0 to apply it immediately
+    pub liquidation_threshold_ramp_slots: u64,
+    /// Maximum accumulated protocol fees as a percentage of the reserve liquidity, 0 for no cap
+    pub protocol_fees_cap: u8,
+    /// Only obligations whitelisted by the lending market owner can borrow from this reserve
+    pub permissioned: bool,
+    /// Accept a zero oracle price instead of rejecting the refresh
+    pub allow_zero_price: bool,
+    /// Refreshes fail once the oracle price has not changed for more than this many slots, 0 to
+    /// disable the check
+    pub max_unchanged_price_slots: u64,
+    /// Borrows supplied the reserve's oracle accounts refresh the reserve inline when it is stale
+    /// instead of failing
+    pub auto_refresh: bool,
+    /// Whether the reserve's collateral tokens can be deposited into obligations. Supply-only
+    /// reserves still take deposits and redemptions, earning depositors yield
+    pub usable_as_collateral: bool,
+    /// Maximum percent the price collateral is valued at for new borrows may rise above the
+    /// previous refresh's price when refreshed again in the same or the next slot, 0 for no cap
+    pub max_borrow_price_increase_pct: u8,
+    /// Tranche the reserve belongs to, only obligations of the same tranche can deposit into or
+    /// borrow from it. 0 is the default tranche
+    pub tranche: u8,
+    /// Utilization rate above which the borrow rate climbs from the max borrow rate to the extreme
+    /// borrow rate, as a percentage. 100 keeps the two slope curve
+    pub extreme_utilization_rate: u8,
+    /// Borrow APY at full utilization when the extreme utilization rate is below 100, as a
+    /// percentage
+    pub extreme_borrow_rate: u16,
+    /// Price the reserve from Pyth's exponential moving average instead of its aggregate price,
+    /// falling back to the aggregate when the average is unavailable
+    pub use_ema_price: bool,
+    /// Percentage of an obligation's borrowed value that can be repaid in a single liquidation of
+    /// this reserve's liquidity
+    pub liquidation_close_factor: u8,
+    /// Round the suppliers' share of accrued interest up to whole liquidity units, with the
+    /// protocol absorbing the difference
+    pub round_supplier_interest_up: bool,
+    /// Maximum standard deviation of a Switchboard v2 round, in basis points of its price, before
+    /// the price is rejected. 0 disables the check
+    pub max_price_confidence_bps: u16,
+    /// Obligations with collateral deposited in this reserve can't borrow its liquidity
+    pub reject_self_borrow: bool,
+    /// Maximum number of slots the reserve keeps refreshing on its cached market price while its
+    /// oracles are unavailable. 0 fails refreshes as soon as the oracles do
+    pub max_stale_price_slots: u64,
+    /// Discount on collateral and markup on debt valued at a cached market price, in basis points
+    /// per slot since the price was last read from an oracle
+    pub stale_price_decay_bps: u16,
+    /// Price the liquidity is valued at in place of its oracles, scaled by WAD. Exposes the
+    /// reserve to the liquidity trading away from it, so it is meant for blue-chip stablecoins
+    /// only. 0 prices the liquidity from its oracles
+    pub fixed_price: u64,
+    /// Bonus a liquidator gets once an obligation's borrows are worth as much as its deposits, as
+    /// a percentage. The bonus ramps up to it linearly from the liquidation bonus at the
+    /// liquidation threshold. 0 keeps the bonus flat
+    pub max_liquidation_bonus: u8,
+    /// Hold interest accrual while the oracles are unavailable, so debt doesn't compound on a
+    /// reserve that can't be priced. Refreshes succeed through the outage, leaving the reserve
+    /// stale once the cached price can't be used, and accrual resumes when the oracles recover
+    pub pause_interest_on_oracle_error: bool,
+    /// Whether the liquidity can be borrowed alongside other assets
+    pub reserve_type: ReserveType,
+    /// Token account of the strategy the lending market owner may deploy idle liquidity to, the
+    /// default pubkey if the reserve has none
+    pub strategy_vault: Pubkey,
+    /// Max percentage of the liquidity that isn't lent out which can be deployed to the strategy
+    /// vault, the rest stays available for withdrawals and borrows
+    pub max_deployed_pct: u8,
+    /// Number of slots after which a Pyth price is too old to use. 0 uses
+    /// DEFAULT_MAX_PRICE_AGE_SLOTS
+    pub max_price_age_slots: u64,
+    /// Number of slots after its price was last read from the oracles that the reserve can no
+    /// longer be liquidated against, even on a cached price still good for refreshing. 0 adds
+    /// no bound beyond refreshing
+    pub max_liquidation_price_age_slots: u64,
+    /// Express the deposit and borrow limits in basis points of the reserve's current total
+    /// supply instead of in native units, so the caps shrink and grow with its liquidity
+    pub fractional_limits: bool,
+}
+
+impl ReserveConfig {
+    /// Price the liquidity is valued at in place of its oracles, if it has a fixed price
+    pub fn fixed_market_price(&self) -> Option<Decimal> {
+        match self.fixed_price {
+            0 => None,
+            fixed_price => Some(Decimal::from_scaled_val(fixed_price as u128)),
+        }
+    }
+
+    /// Number of slots after which a Pyth price is too old to use
+    pub fn effective_max_price_age_slots(&self) -> u64 {
+        match self.max_price_age_slots {
+            0 => DEFAULT_MAX_PRICE_AGE_SLOTS,
+            max_price_age_slots => max_price_age_slots,
+        }
+    }
+
+    /// Strategy vault idle liquidity can be deployed to, if the reserve has one
+    pub fn strategy_vault(&self) -> Option<Pubkey> {
+        Some(self.strategy_vault).filter(|vault| *vault != Pubkey::default())
+    }
+
+    /// Whether utilization above the extreme utilization rate follows a separate, steeper segment
+    pub fn has_extreme_segment(&self) -> bool {
+        self.extreme_utilization_rate < 100
+            && self.extreme_utilization_rate > self.optimal_utilization_rate
+    }
+
+    /// Borrow rate the optimal to max segment of the curve ends at. A max borrow rate of 247 or
+    /// above that equals the optimal borrow rate stands for a rate above 255%
+    pub fn effective_max_borrow_rate(&self) -> Result<Rate, ProgramError> {
+        if self.optimal_borrow_rate != self.max_borrow_rate {
+            return Ok(Rate::from_percent(self.max_borrow_rate));
+        }
+        let rate = Rate::from_percent(50u8);
+        Ok(match self.max_borrow_rate {
+            251u8 => rate.try_mul(6)?,  //300%
+            252u8 => rate.try_mul(7)?,  //350%
+            253u8 => rate.try_mul(8)?,  //400%
+            254u8 => rate.try_mul(10)?, //500%
+            255u8 => rate.try_mul(12)?, //600%
+            250u8 => rate.try_mul(20)?, //1000%
+            249u8 => rate.try_mul(30)?, //1500%
+            248u8 => rate.try_mul(40)?, //2000%
+            247u8 => rate.try_mul(50)?, //2500%
+            _ => Rate::from_percent(self.max_borrow_rate),
+        })
+    }
+
+    /// Rejects configs a reserve can't safely operate under
+    pub fn validate(&self) -> ProgramResult {
+        if self.optimal_utilization_rate > 100 {
+            msg!("Optimal utilization rate must be in range [0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.max_price_confidence_bps > 10_000 {
+            msg!("Max price confidence must be in range [0, 10_000] bps");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.stale_price_decay_bps > 10_000 {
+            msg!("Stale price decay must be in range [0, 10_000] bps");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.liquidation_close_factor == 0 || self.liquidation_close_factor > 100 {
+            msg!("Liquidation close factor must be in range (0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.liquidation_bonus > 100 {
+            msg!("Liquidation bonus must be in range [0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.max_liquidation_bonus > 100
+            || (self.max_liquidation_bonus != 0
+                && self.max_liquidation_bonus < self.liquidation_bonus)
+        {
+            msg!("Max liquidation bonus must be 0 or in range [liquidation bonus, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        // a borrow at the loan to value ratio must not be liquidatable straight away
+        if self.loan_to_value_ratio >= self.liquidation_threshold
+            || self.liquidation_threshold > 100
+        {
+            msg!("Liquidation threshold must be in range (LTV, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.optimal_borrow_rate < self.min_borrow_rate {
+            msg!("Optimal borrow rate must be >= min borrow rate");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.optimal_borrow_rate > self.max_borrow_rate {
+            msg!("Optimal borrow rate must be <= max borrow rate");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.extreme_utilization_rate > 100
+            || (self.extreme_utilization_rate < 100
+                && self.extreme_utilization_rate <= self.optimal_utilization_rate)
+        {
+            msg!(
+                "Extreme utilization rate must be 100 or in range (optimal utilization rate, 100)"
+            );
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.has_extreme_segment()
+            && Rate::from_percent_u16(self.extreme_borrow_rate)
+                < self.effective_max_borrow_rate()?
+        {
+            msg!("Extreme borrow rate must be >= max borrow rate");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.fees.borrow_fee_wad >= WAD {
+            msg!("Borrow fee must be in range [0, 1_000_000_000_000_000_000)");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.fees.total_fee_wad() > MAX_TOTAL_FEE_WAD {
+            msg!(
+                "Combined borrow and flash loan fee must be in range [0, 100_000_000_000_000_000]"
+            );
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.fees.host_fee_percentage > 100 {
+            msg!("Host fee percentage must be in range [0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.protocol_liquidation_fee > 100 {
+            msg!("Protocol liquidation fee must be in range [0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.protocol_take_rate > 100 {
+            msg!("Protocol take rate must be in range [0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.protocol_fees_cap > 100 {
+            msg!("Protocol fees cap must be in range [0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.max_deployed_pct > 100 {
+            msg!("Max deployed percentage must be in range [0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if let Some(fixed_price) = self.fixed_market_price() {
+            msg!(
+                "WARNING: reserve liquidity will be valued at a fixed price of {} instead of its oracles",
+                fixed_price
+            );
+        }
+        Ok(())
+    }
 }
 
 /// Additional fee information on a reserve
@@ -719,6 +1574,18 @@ pub struct ReserveFees {
 }
 
 impl ReserveFees {
+    /// Sum of the fees charged on top of borrowed and flash loaned liquidity, expressed as a Wad.
+    /// A disabled flash loan fee counts as zero and the host fee is a share of these, not an
+    /// additional charge
+    pub fn total_fee_wad(&self) -> u64 {
+        let flash_loan_fee_wad = if self.flash_loan_fee_wad == u64::MAX {
+            0
+        } else {
+            self.flash_loan_fee_wad
+        };
+        self.borrow_fee_wad.saturating_add(flash_loan_fee_wad)
+    }
+
     /// Calculate the owner and host fees on borrow
     pub fn calculate_borrow_fees(
         &self,
@@ -728,27 +1595,52 @@ impl ReserveFees {
         self.calculate_fees(borrow_amount, self.borrow_fee_wad, fee_calculation)
     }
 
-    /// Calculate the owner and host fees on flash loan
+    /// Calculate the total and host fees on flash loan. The total fee rounds up in the protocol's
+    /// favor and the host's cut of it rounds down. Any non-zero rate still charges at least one
+    /// native unit to the owner and one to any host, so small loans can't round their fee down to
+    /// nothing.
     pub fn calculate_flash_loan_fees(
         &self,
         flash_loan_amount: Decimal,
     ) -> Result<(u64, u64), ProgramError> {
-        let (total_fees, host_fee) = self.calculate_fees(
-            flash_loan_amount,
-            self.flash_loan_fee_wad,
-            FeeCalculation::Exclusive,
-        )?;
+        let flash_loan_fee_rate = Rate::from_scaled_val(self.flash_loan_fee_wad);
+        if flash_loan_fee_rate == Rate::zero() || flash_loan_amount == Decimal::zero() {
+            return Ok((0, 0));
+        }
 
-        let origination_fee = total_fees
-            .checked_sub(host_fee)
-            .ok_or(LendingError::MathOverflow)?;
-        Ok((origination_fee, host_fee))
-    }
+        let host_fee_rate = Rate::from_percent(self.host_fee_percentage);
+        let need_to_assess_host_fee = host_fee_rate > Rate::zero();
+        let minimum_fee = if need_to_assess_host_fee {
+            2u64 // 1 token to owner, 1 to host
+        } else {
+            1u64 // 1 token to owner, nothing else
+        };
 
-    fn calculate_fees(
-        &self,
-        amount: Decimal,
-        fee_wad: u64,
+        let total_fee_decimal = flash_loan_amount
+            .try_mul(flash_loan_fee_rate)?
+            .max(minimum_fee.into());
+        if total_fee_decimal >= flash_loan_amount {
+            msg!("Flash loan amount is too small to cover its fees");
+            return Err(LendingError::BorrowTooSmall.into());
+        }
+
+        let total_fee = total_fee_decimal.try_ceil_u64()?;
+        let host_fee = if need_to_assess_host_fee {
+            Decimal::from(total_fee)
+                .try_mul(host_fee_rate)?
+                .try_floor_u64()?
+                .max(1u64)
+        } else {
+            0
+        };
+
+        Ok((total_fee, host_fee))
+    }
+
+    fn calculate_fees(
+        &self,
+        amount: Decimal,
+        fee_wad: u64,
         fee_calculation: FeeCalculation,
     ) -> Result<(u64, u64), ProgramError> {
         let borrow_fee_rate = Rate::from_scaled_val(fee_wad);
@@ -795,6 +1687,31 @@ impl ReserveFees {
     }
 }
 
+/// Whether a reserve's liquidity can be borrowed alongside other assets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReserveType {
+    /// Borrowed alongside the obligation's other regular borrows
+    #[default]
+    Regular = 0,
+    /// Borrowed on its own, an obligation borrowing it can't borrow any other asset
+    Isolated = 1,
+}
+
+impl TryFrom<u8> for ReserveType {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Regular),
+            1 => Ok(Self::Isolated),
+            _ => {
+                msg!("Reserve type {} is not valid", value);
+                Err(LendingError::InvalidConfig.into())
+            }
+        }
+    }
+}
+
 /// Calculate fees exlusive or inclusive of an amount
 pub enum FeeCalculation {
     /// Fee added to amount: fee = rate * amount
@@ -810,10 +1727,51 @@ impl IsInitialized for Reserve {
     }
 }
 
-const RESERVE_LEN: usize = 619; // 1 + 8 + 1 + 32 + 32 + 1 + 32 + 32 + 32 + 8 + 16 + 16 + 16 + 32 + 8 + 32 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 8 + 8 + 1 + 8 + 8 + 32 + 1 + 1 + 16 + 230
+const RESERVE_LEN: usize = 683; // 1 + 8 + 1 + 32 + 32 + 1 + 32 + 32 + 32 + 8 + 16 + 16 + 16 + 32 + 8 + 32 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 8 + 8 + 1 + 8 + 8 + 32 + 1 + 1 + 16 + 8 + 1 + 8 + 1 + 1 + 1 + 8 + 8 + 1 + 1 + 1 + 8 + 16 + 1 + 1 + 16 + 8 + 1 + 1 + 1 + 2 + 1 + 1 + 1 + 2 + 1 + 8 + 2 + 8 + 8 + 1 + 1 + 1 + 1 + 8 + 32 + 1 + 8 + 8 + 16 + 1 + 1 + 25 + 64
+
+// reserve accounts created before the extra pyth oracles were added to the layout end where they
+// start, so the older layout is a prefix of the current one
+const UNMIGRATED_RESERVE_LEN: usize = RESERVE_LEN - PUBKEY_BYTES * MAX_EXTRA_PYTH_ORACLES;
+
 impl Pack for Reserve {
     const LEN: usize = RESERVE_LEN;
 
+    /// Unpacks a reserve account with either the current layout or the layout before extra pyth
+    /// oracles, reading them as unset for the latter
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        match input.len() {
+            RESERVE_LEN => Self::unpack_from_slice(input),
+            UNMIGRATED_RESERVE_LEN => {
+                let mut data = [0u8; RESERVE_LEN];
+                data[..UNMIGRATED_RESERVE_LEN].copy_from_slice(input);
+                Self::unpack_from_slice(&data)
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Packs a reserve into an account with either layout, failing for the layout before extra
+    /// pyth oracles if any are set
+    fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        match dst.len() {
+            RESERVE_LEN => {
+                src.pack_into_slice(dst);
+                Ok(())
+            }
+            UNMIGRATED_RESERVE_LEN => {
+                if !src.liquidity.extra_pyth_oracles().is_empty() {
+                    msg!("Reserve account must be migrated with MigrateReserve to hold extra pyth oracles");
+                    return Err(ProgramError::AccountDataTooSmall);
+                }
+                let mut data = [0u8; RESERVE_LEN];
+                src.pack_into_slice(&mut data);
+                dst.copy_from_slice(&data[..UNMIGRATED_RESERVE_LEN]);
+                Ok(())
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
     // @TODO: break this up by reserve / liquidity / collateral / config https://git.io/JOCca
     fn pack_into_slice(&self, output: &mut [u8]) {
         let output = array_mut_ref![output, 0, RESERVE_LEN];
@@ -851,7 +1809,50 @@ impl Pack for Reserve {
             config_protocol_liquidation_fee,
             config_protocol_take_rate,
             liquidity_accumulated_protocol_fees_wads,
+            config_liquidation_threshold_ramp_slots,
+            liquidation_threshold_ramp_start_threshold,
+            liquidation_threshold_ramp_start_slot,
+            config_protocol_fees_cap,
+            config_permissioned,
+            config_allow_zero_price,
+            config_max_unchanged_price_slots,
+            liquidity_market_price_last_changed_slot,
+            pending_config_update_loan_to_value_ratio,
+            pending_config_update_liquidation_threshold,
+            pending_config_update_protocol_take_rate,
+            pending_config_update_applicable_slot,
+            collateral_floor_price,
+            config_auto_refresh,
+            config_supply_only,
+            liquidity_previous_market_price,
+            liquidity_previous_market_price_slot,
+            config_max_borrow_price_increase_pct,
+            config_tranche,
+            config_extreme_utilization_headroom,
+            config_extreme_borrow_rate,
+            config_use_ema_price,
+            config_liquidation_close_factor,
+            config_round_supplier_interest_up,
+            config_max_price_confidence_bps,
+            config_reject_self_borrow,
+            config_max_stale_price_slots,
+            config_stale_price_decay_bps,
+            liquidity_oracle_price_slot,
+            config_fixed_price,
+            config_max_liquidation_bonus,
+            config_pause_interest_on_oracle_error,
+            liquidity_interest_paused,
+            config_reserve_type,
+            liquidity_deployed_amount,
+            config_strategy_vault,
+            config_max_deployed_pct,
+            config_max_price_age_slots,
+            config_max_liquidation_price_age_slots,
+            liquidity_market_price_confidence,
+            config_fractional_limits,
+            use_price_confidence,
             _padding,
+            liquidity_extra_pyth_oracle_pubkeys,
         ) = mut_array_refs![
             output,
             1,
@@ -886,7 +1887,50 @@ impl Pack for Reserve {
             1,
             1,
             16,
-            230
+            8,
+            1,
+            8,
+            1,
+            1,
+            1,
+            8,
+            8,
+            1,
+            1,
+            1,
+            8,
+            16,
+            1,
+            1,
+            16,
+            8,
+            1,
+            1,
+            1,
+            2,
+            1,
+            1,
+            1,
+            2,
+            1,
+            8,
+            2,
+            8,
+            8,
+            1,
+            1,
+            1,
+            1,
+            8,
+            PUBKEY_BYTES,
+            1,
+            8,
+            8,
+            16,
+            1,
+            1,
+            25,
+            PUBKEY_BYTES * MAX_EXTRA_PYTH_ORACLES
         ];
 
         // reserve
@@ -938,6 +1982,88 @@ impl Pack for Reserve {
         config_fee_receiver.copy_from_slice(self.config.fee_receiver.as_ref());
         *config_protocol_liquidation_fee = self.config.protocol_liquidation_fee.to_le_bytes();
         *config_protocol_take_rate = self.config.protocol_take_rate.to_le_bytes();
+        *config_liquidation_threshold_ramp_slots =
+            self.config.liquidation_threshold_ramp_slots.to_le_bytes();
+        *liquidation_threshold_ramp_start_threshold = self
+            .liquidation_threshold_ramp
+            .start_threshold
+            .to_le_bytes();
+        *liquidation_threshold_ramp_start_slot =
+            self.liquidation_threshold_ramp.start_slot.to_le_bytes();
+        *config_protocol_fees_cap = self.config.protocol_fees_cap.to_le_bytes();
+        pack_bool(self.config.permissioned, config_permissioned);
+        pack_bool(self.config.allow_zero_price, config_allow_zero_price);
+        *config_max_unchanged_price_slots = self.config.max_unchanged_price_slots.to_le_bytes();
+        *liquidity_market_price_last_changed_slot =
+            self.liquidity.market_price_last_changed_slot.to_le_bytes();
+        *pending_config_update_loan_to_value_ratio =
+            self.pending_config_update.loan_to_value_ratio.to_le_bytes();
+        *pending_config_update_liquidation_threshold = self
+            .pending_config_update
+            .liquidation_threshold
+            .to_le_bytes();
+        *pending_config_update_protocol_take_rate =
+            self.pending_config_update.protocol_take_rate.to_le_bytes();
+        *pending_config_update_applicable_slot =
+            self.pending_config_update.applicable_slot.to_le_bytes();
+        pack_decimal(self.collateral_floor_price, collateral_floor_price);
+        pack_bool(self.config.auto_refresh, config_auto_refresh);
+        // stored inverted so that reserves written before the flag existed stay usable
+        pack_bool(!self.config.usable_as_collateral, config_supply_only);
+        pack_decimal(
+            self.liquidity.previous_market_price,
+            liquidity_previous_market_price,
+        );
+        *liquidity_previous_market_price_slot =
+            self.liquidity.previous_market_price_slot.to_le_bytes();
+        *config_max_borrow_price_increase_pct =
+            self.config.max_borrow_price_increase_pct.to_le_bytes();
+        *config_tranche = self.config.tranche.to_le_bytes();
+        // stored as the distance to 100 so that reserves written before the jump segment existed
+        // keep the two slope curve
+        *config_extreme_utilization_headroom = 100u8
+            .saturating_sub(self.config.extreme_utilization_rate)
+            .to_le_bytes();
+        *config_extreme_borrow_rate = self.config.extreme_borrow_rate.to_le_bytes();
+        pack_bool(self.config.use_ema_price, config_use_ema_price);
+        *config_liquidation_close_factor = self.config.liquidation_close_factor.to_le_bytes();
+        pack_bool(
+            self.config.round_supplier_interest_up,
+            config_round_supplier_interest_up,
+        );
+        *config_max_price_confidence_bps = self.config.max_price_confidence_bps.to_le_bytes();
+        pack_bool(self.config.reject_self_borrow, config_reject_self_borrow);
+        *config_max_stale_price_slots = self.config.max_stale_price_slots.to_le_bytes();
+        *config_stale_price_decay_bps = self.config.stale_price_decay_bps.to_le_bytes();
+        *liquidity_oracle_price_slot = self.liquidity.oracle_price_slot.to_le_bytes();
+        *config_fixed_price = self.config.fixed_price.to_le_bytes();
+        *config_max_liquidation_bonus = self.config.max_liquidation_bonus.to_le_bytes();
+        pack_bool(
+            self.config.pause_interest_on_oracle_error,
+            config_pause_interest_on_oracle_error,
+        );
+        pack_bool(self.liquidity.interest_paused, liquidity_interest_paused);
+        *config_reserve_type = (self.config.reserve_type as u8).to_le_bytes();
+        *liquidity_deployed_amount = self.liquidity.deployed_amount.to_le_bytes();
+        config_strategy_vault.copy_from_slice(self.config.strategy_vault.as_ref());
+        *config_max_deployed_pct = self.config.max_deployed_pct.to_le_bytes();
+        *config_max_price_age_slots = self.config.max_price_age_slots.to_le_bytes();
+        *config_max_liquidation_price_age_slots =
+            self.config.max_liquidation_price_age_slots.to_le_bytes();
+        pack_decimal(
+            self.liquidity.market_price_confidence,
+            liquidity_market_price_confidence,
+        );
+        for (pubkey, dst) in self
+            .liquidity
+            .extra_pyth_oracle_pubkeys
+            .iter()
+            .zip(liquidity_extra_pyth_oracle_pubkeys.chunks_exact_mut(PUBKEY_BYTES))
+        {
+            dst.copy_from_slice(pubkey.as_ref());
+        }
+        pack_bool(self.config.fractional_limits, config_fractional_limits);
+        pack_bool(self.use_price_confidence, use_price_confidence);
     }
 
     /// Unpacks a byte buffer into a [ReserveInfo](struct.ReserveInfo.html).
@@ -977,7 +2103,50 @@ impl Pack for Reserve {
             config_protocol_liquidation_fee,
             config_protocol_take_rate,
             liquidity_accumulated_protocol_fees_wads,
+            config_liquidation_threshold_ramp_slots,
+            liquidation_threshold_ramp_start_threshold,
+            liquidation_threshold_ramp_start_slot,
+            config_protocol_fees_cap,
+            config_permissioned,
+            config_allow_zero_price,
+            config_max_unchanged_price_slots,
+            liquidity_market_price_last_changed_slot,
+            pending_config_update_loan_to_value_ratio,
+            pending_config_update_liquidation_threshold,
+            pending_config_update_protocol_take_rate,
+            pending_config_update_applicable_slot,
+            collateral_floor_price,
+            config_auto_refresh,
+            config_supply_only,
+            liquidity_previous_market_price,
+            liquidity_previous_market_price_slot,
+            config_max_borrow_price_increase_pct,
+            config_tranche,
+            config_extreme_utilization_headroom,
+            config_extreme_borrow_rate,
+            config_use_ema_price,
+            config_liquidation_close_factor,
+            config_round_supplier_interest_up,
+            config_max_price_confidence_bps,
+            config_reject_self_borrow,
+            config_max_stale_price_slots,
+            config_stale_price_decay_bps,
+            liquidity_oracle_price_slot,
+            config_fixed_price,
+            config_max_liquidation_bonus,
+            config_pause_interest_on_oracle_error,
+            liquidity_interest_paused,
+            config_reserve_type,
+            liquidity_deployed_amount,
+            config_strategy_vault,
+            config_max_deployed_pct,
+            config_max_price_age_slots,
+            config_max_liquidation_price_age_slots,
+            liquidity_market_price_confidence,
+            config_fractional_limits,
+            use_price_confidence,
             _padding,
+            liquidity_extra_pyth_oracle_pubkeys,
         ) = array_refs![
             input,
             1,
@@ -1012,7 +2181,50 @@ impl Pack for Reserve {
             1,
             1,
             16,
-            230
+            8,
+            1,
+            8,
+            1,
+            1,
+            1,
+            8,
+            8,
+            1,
+            1,
+            1,
+            8,
+            16,
+            1,
+            1,
+            16,
+            8,
+            1,
+            1,
+            1,
+            2,
+            1,
+            1,
+            1,
+            2,
+            1,
+            8,
+            2,
+            8,
+            8,
+            1,
+            1,
+            1,
+            1,
+            8,
+            PUBKEY_BYTES,
+            1,
+            8,
+            8,
+            16,
+            1,
+            1,
+            25,
+            PUBKEY_BYTES * MAX_EXTRA_PYTH_ORACLES
         ];
 
         let version = u8::from_le_bytes(*version);
@@ -1021,6 +2233,14 @@ impl Pack for Reserve {
             return Err(ProgramError::InvalidAccountData);
         }
 
+        let mut extra_pyth_oracle_pubkeys = [Pubkey::default(); MAX_EXTRA_PYTH_ORACLES];
+        for (pubkey, src) in extra_pyth_oracle_pubkeys
+            .iter_mut()
+            .zip(liquidity_extra_pyth_oracle_pubkeys.chunks_exact(PUBKEY_BYTES))
+        {
+            *pubkey = Pubkey::new(src);
+        }
+
         Ok(Self {
             version,
             last_update: LastUpdate {
@@ -1043,6 +2263,18 @@ impl Pack for Reserve {
                     liquidity_accumulated_protocol_fees_wads,
                 ),
                 market_price: unpack_decimal(liquidity_market_price),
+                market_price_last_changed_slot: u64::from_le_bytes(
+                    *liquidity_market_price_last_changed_slot,
+                ),
+                previous_market_price: unpack_decimal(liquidity_previous_market_price),
+                previous_market_price_slot: u64::from_le_bytes(
+                    *liquidity_previous_market_price_slot,
+                ),
+                oracle_price_slot: u64::from_le_bytes(*liquidity_oracle_price_slot),
+                interest_paused: unpack_bool(liquidity_interest_paused)?,
+                deployed_amount: u64::from_le_bytes(*liquidity_deployed_amount),
+                market_price_confidence: unpack_decimal(liquidity_market_price_confidence),
+                extra_pyth_oracle_pubkeys,
             },
             collateral: ReserveCollateral {
                 mint_pubkey: Pubkey::new_from_array(*collateral_mint_pubkey),
@@ -1067,7 +2299,219 @@ impl Pack for Reserve {
                 fee_receiver: Pubkey::new_from_array(*config_fee_receiver),
                 protocol_liquidation_fee: u8::from_le_bytes(*config_protocol_liquidation_fee),
                 protocol_take_rate: u8::from_le_bytes(*config_protocol_take_rate),
+                liquidation_threshold_ramp_slots: u64::from_le_bytes(
+                    *config_liquidation_threshold_ramp_slots,
+                ),
+                protocol_fees_cap: u8::from_le_bytes(*config_protocol_fees_cap),
+                permissioned: unpack_bool(config_permissioned)?,
+                allow_zero_price: unpack_bool(config_allow_zero_price)?,
+                max_unchanged_price_slots: u64::from_le_bytes(*config_max_unchanged_price_slots),
+                auto_refresh: unpack_bool(config_auto_refresh)?,
+                usable_as_collateral: !unpack_bool(config_supply_only)?,
+                max_borrow_price_increase_pct: u8::from_le_bytes(
+                    *config_max_borrow_price_increase_pct,
+                ),
+                tranche: u8::from_le_bytes(*config_tranche),
+                extreme_utilization_rate: 100u8
+                    .saturating_sub(u8::from_le_bytes(*config_extreme_utilization_headroom)),
+                extreme_borrow_rate: u16::from_le_bytes(*config_extreme_borrow_rate),
+                use_ema_price: unpack_bool(config_use_ema_price)?,
+                // reserves written before the close factor was configurable keep the default
+                liquidation_close_factor: match u8::from_le_bytes(*config_liquidation_close_factor)
+                {
+                    0 => LIQUIDATION_CLOSE_FACTOR,
+                    close_factor => close_factor,
+                },
+                round_supplier_interest_up: unpack_bool(config_round_supplier_interest_up)?,
+                max_price_confidence_bps: u16::from_le_bytes(*config_max_price_confidence_bps),
+                reject_self_borrow: unpack_bool(config_reject_self_borrow)?,
+                max_stale_price_slots: u64::from_le_bytes(*config_max_stale_price_slots),
+                stale_price_decay_bps: u16::from_le_bytes(*config_stale_price_decay_bps),
+                fixed_price: u64::from_le_bytes(*config_fixed_price),
+                max_liquidation_bonus: u8::from_le_bytes(*config_max_liquidation_bonus),
+                pause_interest_on_oracle_error: unpack_bool(config_pause_interest_on_oracle_error)?,
+                reserve_type: ReserveType::try_from(u8::from_le_bytes(*config_reserve_type))?,
+                strategy_vault: Pubkey::new_from_array(*config_strategy_vault),
+                max_deployed_pct: u8::from_le_bytes(*config_max_deployed_pct),
+                max_price_age_slots: u64::from_le_bytes(*config_max_price_age_slots),
+                max_liquidation_price_age_slots: u64::from_le_bytes(
+                    *config_max_liquidation_price_age_slots,
+                ),
+                fractional_limits: unpack_bool(config_fractional_limits)?,
+            },
+            liquidation_threshold_ramp: LiquidationThresholdRamp {
+                start_threshold: u8::from_le_bytes(*liquidation_threshold_ramp_start_threshold),
+                start_slot: u64::from_le_bytes(*liquidation_threshold_ramp_start_slot),
+            },
+            pending_config_update: PendingReserveConfigUpdate {
+                loan_to_value_ratio: u8::from_le_bytes(*pending_config_update_loan_to_value_ratio),
+                liquidation_threshold: u8::from_le_bytes(
+                    *pending_config_update_liquidation_threshold,
+                ),
+                protocol_take_rate: u8::from_le_bytes(*pending_config_update_protocol_take_rate),
+                applicable_slot: u64::from_le_bytes(*pending_config_update_applicable_slot),
             },
+            collateral_floor_price: unpack_decimal(collateral_floor_price),
+            use_price_confidence: unpack_bool(use_price_confidence)?,
+        })
+    }
+}
+
+/// Key risk parameters of a reserve, as returned by GetMarketReserves
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReserveConfigSummary {
+    /// Reserve address
+    pub reserve: Pubkey,
+    /// Reserve liquidity mint address
+    pub liquidity_mint: Pubkey,
+    /// Target ratio of the value of borrows to deposits, as a percentage
+    pub loan_to_value_ratio: u8,
+    /// Loan to value ratio at which an obligation can be liquidated, as a percentage
+    pub liquidation_threshold: u8,
+    /// Bonus a liquidator gets when repaying part of an unhealthy obligation, as a percentage
+    pub liquidation_bonus: u8,
+    /// Percentage of an obligation's borrowed value repayable in a single liquidation
+    pub liquidation_close_factor: u8,
+    /// Maximum deposit limit of liquidity in native units
+    pub deposit_limit: u64,
+    /// Maximum borrow limit of liquidity in native units
+    pub borrow_limit: u64,
+    /// Borrow fee, expressed as a Wad
+    pub borrow_fee_wad: u64,
+    /// Flash loan fee, expressed as a Wad
+    pub flash_loan_fee_wad: u64,
+    /// Amount of fee going to host account, as a percentage
+    pub host_fee_percentage: u8,
+    /// Cut of the liquidation bonus that the protocol receives, as a percentage
+    pub protocol_liquidation_fee: u8,
+    /// Amount of borrowed interest the protocol receives, as a percentage
+    pub protocol_take_rate: u8,
+}
+
+impl ReserveConfigSummary {
+    /// Summarize the config of a reserve stored at `reserve_pubkey`
+    pub fn new(reserve_pubkey: Pubkey, reserve: &Reserve) -> Self {
+        Self {
+            reserve: reserve_pubkey,
+            liquidity_mint: reserve.liquidity.mint_pubkey,
+            loan_to_value_ratio: reserve.config.loan_to_value_ratio,
+            liquidation_threshold: reserve.config.liquidation_threshold,
+            liquidation_bonus: reserve.config.liquidation_bonus,
+            liquidation_close_factor: reserve.config.liquidation_close_factor,
+            deposit_limit: reserve.config.deposit_limit,
+            borrow_limit: reserve.config.borrow_limit,
+            borrow_fee_wad: reserve.config.fees.borrow_fee_wad,
+            flash_loan_fee_wad: reserve.config.fees.flash_loan_fee_wad,
+            host_fee_percentage: reserve.config.fees.host_fee_percentage,
+            protocol_liquidation_fee: reserve.config.protocol_liquidation_fee,
+            protocol_take_rate: reserve.config.protocol_take_rate,
+        }
+    }
+}
+
+impl Sealed for ReserveConfigSummary {}
+
+const RESERVE_CONFIG_SUMMARY_LEN: usize = 103; // 32 + 32 + 1 + 1 + 1 + 1 + 8 + 8 + 8 + 8 + 1 + 1 + 1
+impl Pack for ReserveConfigSummary {
+    const LEN: usize = RESERVE_CONFIG_SUMMARY_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, RESERVE_CONFIG_SUMMARY_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            reserve,
+            liquidity_mint,
+            loan_to_value_ratio,
+            liquidation_threshold,
+            liquidation_bonus,
+            liquidation_close_factor,
+            deposit_limit,
+            borrow_limit,
+            borrow_fee_wad,
+            flash_loan_fee_wad,
+            host_fee_percentage,
+            protocol_liquidation_fee,
+            protocol_take_rate,
+        ) = mut_array_refs![
+            output,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            1,
+            1,
+            1,
+            8,
+            8,
+            8,
+            8,
+            1,
+            1,
+            1
+        ];
+
+        reserve.copy_from_slice(self.reserve.as_ref());
+        liquidity_mint.copy_from_slice(self.liquidity_mint.as_ref());
+        *loan_to_value_ratio = self.loan_to_value_ratio.to_le_bytes();
+        *liquidation_threshold = self.liquidation_threshold.to_le_bytes();
+        *liquidation_bonus = self.liquidation_bonus.to_le_bytes();
+        *liquidation_close_factor = self.liquidation_close_factor.to_le_bytes();
+        *deposit_limit = self.deposit_limit.to_le_bytes();
+        *borrow_limit = self.borrow_limit.to_le_bytes();
+        *borrow_fee_wad = self.borrow_fee_wad.to_le_bytes();
+        *flash_loan_fee_wad = self.flash_loan_fee_wad.to_le_bytes();
+        *host_fee_percentage = self.host_fee_percentage.to_le_bytes();
+        *protocol_liquidation_fee = self.protocol_liquidation_fee.to_le_bytes();
+        *protocol_take_rate = self.protocol_take_rate.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, RESERVE_CONFIG_SUMMARY_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            reserve,
+            liquidity_mint,
+            loan_to_value_ratio,
+            liquidation_threshold,
+            liquidation_bonus,
+            liquidation_close_factor,
+            deposit_limit,
+            borrow_limit,
+            borrow_fee_wad,
+            flash_loan_fee_wad,
+            host_fee_percentage,
+            protocol_liquidation_fee,
+            protocol_take_rate,
+        ) = array_refs![
+            input,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            1,
+            1,
+            1,
+            8,
+            8,
+            8,
+            8,
+            1,
+            1,
+            1
+        ];
+
+        Ok(Self {
+            reserve: Pubkey::new_from_array(*reserve),
+            liquidity_mint: Pubkey::new_from_array(*liquidity_mint),
+            loan_to_value_ratio: u8::from_le_bytes(*loan_to_value_ratio),
+            liquidation_threshold: u8::from_le_bytes(*liquidation_threshold),
+            liquidation_bonus: u8::from_le_bytes(*liquidation_bonus),
+            liquidation_close_factor: u8::from_le_bytes(*liquidation_close_factor),
+            deposit_limit: u64::from_le_bytes(*deposit_limit),
+            borrow_limit: u64::from_le_bytes(*borrow_limit),
+            borrow_fee_wad: u64::from_le_bytes(*borrow_fee_wad),
+            flash_loan_fee_wad: u64::from_le_bytes(*flash_loan_fee_wad),
+            host_fee_percentage: u8::from_le_bytes(*host_fee_percentage),
+            protocol_liquidation_fee: u8::from_le_bytes(*protocol_liquidation_fee),
+            protocol_take_rate: u8::from_le_bytes(*protocol_take_rate),
         })
     }
 }
@@ -1244,7 +2688,7 @@ mod test {
             // Simulate running for max 1000 years, assuming that interest is
             // compounded at least once a year
             for _ in 0..1000 {
-                reserve.liquidity.compound_interest(borrow_rate, slots_elapsed, take_rate)?;
+                reserve.liquidity.compound_interest(borrow_rate, slots_elapsed, take_rate, false)?;
                 reserve.liquidity.cumulative_borrow_rate_wads.to_scaled_val()?;
                 reserve.liquidity.accumulated_protocol_fees_wads.to_scaled_val()?;
             }
@@ -1335,26 +2779,27 @@ mod test {
                 flash_loan_fee_wad,
                 host_fee_percentage,
             };
-            let (origination_fee, host_fee) = fees.calculate_flash_loan_fees(Decimal::from(borrow_amount))?;
+            let (total_fee, host_fee) = fees.calculate_flash_loan_fees(Decimal::from(borrow_amount))?;
 
             // The total fee can't be greater than the amount borrowed, as long
             // as amount borrowed is greater than 2.
             // At a borrow amount of 2, we can get a total fee of 2 if a host
             // fee is also specified.
-            assert!(origination_fee + host_fee <= borrow_amount);
+            assert!(total_fee <= borrow_amount);
+            assert!(host_fee <= total_fee);
 
             // for all fee rates greater than 0, we must have some fee
-            if borrow_fee_wad > 0 {
-                assert!(origination_fee + host_fee > 0);
+            if flash_loan_fee_wad > 0 {
+                assert!(total_fee > 0);
             }
 
             if host_fee_percentage == 100 {
                 // if the host fee percentage is maxed at 100%, it should get all the fee
-                assert_eq!(origination_fee, 0);
+                assert_eq!(host_fee, total_fee);
             }
 
             // if there's a host fee and some borrow fee, host fee must be greater than 0
-            if host_fee_percentage > 0 && borrow_fee_wad > 0 {
+            if host_fee_percentage > 0 && flash_loan_fee_wad > 0 {
                 assert!(host_fee > 0);
             } else {
                 assert_eq!(host_fee, 0);
@@ -1363,53 +2808,301 @@ mod test {
     }
 
     #[test]
-    fn borrow_fee_calculation_min_host() {
-        let fees = ReserveFees {
-            borrow_fee_wad: 10_000_000_000_000_000, // 1%
-            flash_loan_fee_wad: 0,
-            host_fee_percentage: 20,
+    fn protocol_fees_cap() {
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(0),
+            liquidity: ReserveLiquidity {
+                available_amount: 1_000_000,
+                borrowed_amount_wads: Decimal::from(1_000_000u64),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                optimal_utilization_rate: 100,
+                min_borrow_rate: 100,
+                optimal_borrow_rate: 100,
+                max_borrow_rate: 100,
+                protocol_take_rate: 100,
+                protocol_fees_cap: 5,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
         };
 
-        // only 2 tokens borrowed, get error
-        let err = fees
-            .calculate_borrow_fees(Decimal::from(2u64), FeeCalculation::Exclusive)
-            .unwrap_err();
-        assert_eq!(err, LendingError::BorrowTooSmall.into()); // minimum of 3 tokens
-
-        // only 1 token borrowed, get error
-        let err = fees
-            .calculate_borrow_fees(Decimal::one(), FeeCalculation::Exclusive)
-            .unwrap_err();
-        assert_eq!(err, LendingError::BorrowTooSmall.into());
+        // a few slots at 100% APY stay well under the cap
+        reserve.accrue_interest(10).unwrap();
+        reserve.last_update.update_slot(10);
+        let uncapped_fees = reserve.liquidity.accumulated_protocol_fees_wads;
+        assert!(uncapped_fees > Decimal::zero());
+        assert!(
+            uncapped_fees
+                < Decimal::from(reserve.liquidity.available_amount)
+                    .try_add(reserve.liquidity.borrowed_amount_wads)
+                    .unwrap()
+                    .try_mul(Rate::from_percent(5))
+                    .unwrap()
+        );
 
-        // 0 amount borrowed, 0 fee
-        let (total_fee, host_fee) = fees
-            .calculate_borrow_fees(Decimal::zero(), FeeCalculation::Exclusive)
+        // a year of 100% APY would take fees to roughly half the reserve, they're clamped instead
+        reserve.accrue_interest(10 + SLOTS_PER_YEAR).unwrap();
+        let max_protocol_fees = Decimal::from(reserve.liquidity.available_amount)
+            .try_add(reserve.liquidity.borrowed_amount_wads)
+            .unwrap()
+            .try_mul(Rate::from_percent(5))
             .unwrap();
-        assert_eq!(total_fee, 0);
-        assert_eq!(host_fee, 0);
+        assert_eq!(
+            reserve.liquidity.accumulated_protocol_fees_wads,
+            max_protocol_fees
+        );
+
+        // lowering the cap stops further accrual without clawing back existing fees
+        reserve.last_update.update_slot(10 + SLOTS_PER_YEAR);
+        reserve.config.protocol_fees_cap = 1;
+        reserve.accrue_interest(10 + 2 * SLOTS_PER_YEAR).unwrap();
+        assert_eq!(
+            reserve.liquidity.accumulated_protocol_fees_wads,
+            max_protocol_fees
+        );
     }
 
     #[test]
-    fn borrow_fee_calculation_min_no_host() {
-        let fees = ReserveFees {
-            borrow_fee_wad: 10_000_000_000_000_000, // 1%
-            flash_loan_fee_wad: 0,
-            host_fee_percentage: 0,
-        };
+    fn round_supplier_interest_up() {
+        let accrue =
+            |borrowed_amount: u64, slots_elapsed: u64, round_supplier_interest_up: bool| {
+                let mut reserve = Reserve {
+                    last_update: LastUpdate::new(0),
+                    liquidity: ReserveLiquidity {
+                        borrowed_amount_wads: Decimal::from(borrowed_amount),
+                        cumulative_borrow_rate_wads: Decimal::one(),
+                        ..ReserveLiquidity::default()
+                    },
+                    config: ReserveConfig {
+                        optimal_utilization_rate: 100,
+                        min_borrow_rate: 10,
+                        optimal_borrow_rate: 10,
+                        max_borrow_rate: 10,
+                        protocol_take_rate: 20,
+                        round_supplier_interest_up,
+                        ..ReserveConfig::default()
+                    },
+                    ..Reserve::default()
+                };
+                reserve.accrue_interest(slots_elapsed).unwrap();
+                reserve.liquidity
+            };
 
-        // only 2 tokens borrowed, ok
-        let (total_fee, host_fee) = fees
-            .calculate_borrow_fees(Decimal::from(2u64), FeeCalculation::Exclusive)
-            .unwrap();
-        assert_eq!(total_fee, 1);
-        assert_eq!(host_fee, 0);
+        for (borrowed_amount, slots_elapsed) in [
+            (1_000_000_000_000, 1),
+            (1_234_567_891, 7),
+            (999_999_999, 1_000),
+            (10_000, 1),
+        ] {
+            let truncated = accrue(borrowed_amount, slots_elapsed, false);
+            let rounded = accrue(borrowed_amount, slots_elapsed, true);
+
+            // borrowers owe the same either way, only the split of the interest changes
+            assert_eq!(rounded.borrowed_amount_wads, truncated.borrowed_amount_wads);
+            assert_eq!(
+                rounded.cumulative_borrow_rate_wads,
+                truncated.cumulative_borrow_rate_wads
+            );
 
-        // only 1 token borrowed, get error
-        let err = fees
-            .calculate_borrow_fees(Decimal::one(), FeeCalculation::Exclusive)
-            .unwrap_err();
-        assert_eq!(err, LendingError::BorrowTooSmall.into()); // minimum of 2 tokens
+            let net_new_debt = rounded
+                .borrowed_amount_wads
+                .try_sub(Decimal::from(borrowed_amount))
+                .unwrap();
+            assert!(net_new_debt.try_floor_u64().unwrap() < net_new_debt.try_ceil_u64().unwrap());
+
+            let supplier_interest = rounded
+                .total_supply()
+                .unwrap()
+                .try_sub(Decimal::from(borrowed_amount))
+                .unwrap();
+            assert_eq!(
+                supplier_interest
+                    .try_add(rounded.accumulated_protocol_fees_wads)
+                    .unwrap(),
+                net_new_debt
+            );
+
+            let supplier_interest_ceil = Decimal::from(
+                truncated
+                    .total_supply()
+                    .unwrap()
+                    .try_sub(Decimal::from(borrowed_amount))
+                    .unwrap()
+                    .try_ceil_u64()
+                    .unwrap(),
+            );
+            if supplier_interest_ceil <= net_new_debt {
+                assert_eq!(supplier_interest, supplier_interest_ceil);
+            } else {
+                // the protocol's share can't cover the rounding, suppliers get all of the interest
+                assert_eq!(supplier_interest, net_new_debt);
+                assert_eq!(rounded.accumulated_protocol_fees_wads, Decimal::zero());
+            }
+        }
+    }
+
+    #[test]
+    fn realized_apy() {
+        let mut reserve = Reserve::default();
+        let assert_apy = |reserve: &Reserve, prev_slot, now_slot, expected: f64| {
+            let apy = reserve
+                .realized_apy(Decimal::one(), prev_slot, now_slot)
+                .unwrap();
+            let actual = apy.to_scaled_val() as f64 / WAD as f64;
+            assert!(
+                (actual - expected).abs() < 1e-9,
+                "expected {} got {}",
+                expected,
+                actual
+            );
+        };
+
+        // no growth, or an empty window
+        reserve.liquidity.cumulative_borrow_rate_wads = Decimal::one();
+        assert_apy(&reserve, 0, 1000, 0.0);
+        reserve.liquidity.cumulative_borrow_rate_wads = Decimal::from_percent(110);
+        assert_apy(&reserve, 1000, 1000, 0.0);
+
+        // a quarter of a year at 2.5% compounds to ~10.38%
+        reserve.liquidity.cumulative_borrow_rate_wads =
+            Decimal::from_scaled_val(1_025 * WAD as u128 / 1_000);
+        assert_apy(&reserve, 0, SLOTS_PER_YEAR / 4, 1.025f64.powi(4) - 1.0);
+
+        // index compounded at 10% APR for 1000 slots realizes the same yield as compounding
+        // every slot for a year
+        let slot_rate = Rate::from_percent(10).try_div(SLOTS_PER_YEAR).unwrap();
+        reserve.liquidity.cumulative_borrow_rate_wads = Rate::one()
+            .try_add(slot_rate)
+            .unwrap()
+            .try_pow(1000)
+            .unwrap()
+            .into();
+        let expected = (SLOTS_PER_YEAR as f64 * (0.1 / SLOTS_PER_YEAR as f64).ln_1p()).exp_m1();
+        assert_apy(&reserve, 5000, 6000, expected);
+
+        // a window that does not divide the year
+        reserve.liquidity.cumulative_borrow_rate_wads = Rate::one()
+            .try_add(slot_rate)
+            .unwrap()
+            .try_pow(7_777_777)
+            .unwrap()
+            .into();
+        assert_apy(&reserve, 0, 7_777_777, expected);
+
+        // a window longer than a year
+        reserve.liquidity.cumulative_borrow_rate_wads = Rate::one()
+            .try_add(slot_rate)
+            .unwrap()
+            .try_pow(2 * SLOTS_PER_YEAR + 12_345)
+            .unwrap()
+            .into();
+        assert_apy(&reserve, 0, 2 * SLOTS_PER_YEAR + 12_345, expected);
+
+        // the index can't decrease
+        reserve.liquidity.cumulative_borrow_rate_wads = Decimal::from_percent(90);
+        assert!(reserve.realized_apy(Decimal::one(), 0, 1000).is_err());
+        assert!(reserve.realized_apy(Decimal::one(), 1000, 0).is_err());
+    }
+
+    #[test]
+    fn liquidation_threshold_ramp() {
+        let mut reserve = Reserve {
+            config: ReserveConfig {
+                liquidation_threshold: 55,
+                liquidation_threshold_ramp_slots: 100,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+
+        // decrease ramps in from the old threshold
+        let mut config = reserve.config;
+        config.liquidation_threshold = 35;
+        reserve.update_config(config, 1000).unwrap();
+        assert_eq!(
+            reserve.liquidation_threshold_ramp,
+            LiquidationThresholdRamp {
+                start_threshold: 55,
+                start_slot: 1000,
+            }
+        );
+        assert_eq!(
+            reserve.liquidation_threshold(1000).unwrap(),
+            Rate::from_percent(55)
+        );
+        assert_eq!(
+            reserve.liquidation_threshold(1025).unwrap(),
+            Rate::from_percent(50)
+        );
+        assert_eq!(
+            reserve.liquidation_threshold(1050).unwrap(),
+            Rate::from_percent(45)
+        );
+        assert_eq!(
+            reserve.liquidation_threshold(1100).unwrap(),
+            Rate::from_percent(35)
+        );
+        assert_eq!(
+            reserve.liquidation_threshold(5000).unwrap(),
+            Rate::from_percent(35)
+        );
+
+        // a further decrease mid-ramp starts from the threshold in effect, rounded up
+        let mut config = reserve.config;
+        config.liquidation_threshold = 20;
+        reserve.update_config(config, 1051).unwrap();
+        assert_eq!(reserve.liquidation_threshold_ramp.start_threshold, 45);
+        assert_eq!(
+            reserve.liquidation_threshold(1051).unwrap(),
+            Rate::from_percent(45)
+        );
+
+        // increases apply immediately
+        let mut config = reserve.config;
+        config.liquidation_threshold = 60;
+        reserve.update_config(config, 1052).unwrap();
+        assert_eq!(
+            reserve.liquidation_threshold_ramp,
+            LiquidationThresholdRamp::default()
+        );
+        assert_eq!(
+            reserve.liquidation_threshold(1052).unwrap(),
+            Rate::from_percent(60)
+        );
+
+        // no ramp configured
+        let mut config = reserve.config;
+        config.liquidation_threshold = 40;
+        config.liquidation_threshold_ramp_slots = 0;
+        reserve.update_config(config, 1053).unwrap();
+        assert_eq!(
+            reserve.liquidation_threshold(1053).unwrap(),
+            Rate::from_percent(40)
+        );
+    }
+
+    #[test]
+    fn borrow_fee_calculation_min_host() {
+        let fees = ReserveFees {
+            borrow_fee_wad: 10_000_000_000_000_000, // 1%
+            flash_loan_fee_wad: 0,
+            host_fee_percentage: 20,
+        };
+
+        // only 2 tokens borrowed, get error
+        let err = fees
+            .calculate_borrow_fees(Decimal::from(2u64), FeeCalculation::Exclusive)
+            .unwrap_err();
+        assert_eq!(err, LendingError::BorrowTooSmall.into()); // minimum of 3 tokens
+
+        // only 1 token borrowed, get error
+        let err = fees
+            .calculate_borrow_fees(Decimal::one(), FeeCalculation::Exclusive)
+            .unwrap_err();
+        assert_eq!(err, LendingError::BorrowTooSmall.into());
 
         // 0 amount borrowed, 0 fee
         let (total_fee, host_fee) = fees
@@ -1419,6 +3112,113 @@ mod test {
         assert_eq!(host_fee, 0);
     }
 
+    #[test]
+    fn borrow_fee_calculation_min_no_host() {
+        let fees = ReserveFees {
+            borrow_fee_wad: 10_000_000_000_000_000, // 1%
+            flash_loan_fee_wad: 0,
+            host_fee_percentage: 0,
+        };
+
+        // only 2 tokens borrowed, ok
+        let (total_fee, host_fee) = fees
+            .calculate_borrow_fees(Decimal::from(2u64), FeeCalculation::Exclusive)
+            .unwrap();
+        assert_eq!(total_fee, 1);
+        assert_eq!(host_fee, 0);
+
+        // only 1 token borrowed, get error
+        let err = fees
+            .calculate_borrow_fees(Decimal::one(), FeeCalculation::Exclusive)
+            .unwrap_err();
+        assert_eq!(err, LendingError::BorrowTooSmall.into()); // minimum of 2 tokens
+
+        // 0 amount borrowed, 0 fee
+        let (total_fee, host_fee) = fees
+            .calculate_borrow_fees(Decimal::zero(), FeeCalculation::Exclusive)
+            .unwrap();
+        assert_eq!(total_fee, 0);
+        assert_eq!(host_fee, 0);
+    }
+
+    #[test]
+    fn flash_loan_fee_calculation_min() {
+        let mut fees = ReserveFees {
+            borrow_fee_wad: 0,
+            flash_loan_fee_wad: 3_000_000_000_000_000, // 0.3%
+            host_fee_percentage: 0,
+        };
+
+        // 0.3% of anything below 334 tokens is less than 1 token, charge 1 token instead
+        for amount in &[2u64, 10, 166, 333] {
+            let (total_fee, host_fee) = fees
+                .calculate_flash_loan_fees(Decimal::from(*amount))
+                .unwrap();
+            assert_eq!(total_fee, 1);
+            assert_eq!(host_fee, 0);
+        }
+
+        // with a host fee, both the owner and the host get at least 1 token
+        fees.host_fee_percentage = 20;
+        for amount in &[3u64, 10, 166, 333] {
+            let (total_fee, host_fee) = fees
+                .calculate_flash_loan_fees(Decimal::from(*amount))
+                .unwrap();
+            assert_eq!(total_fee, 2);
+            assert_eq!(host_fee, 1);
+        }
+
+        // too small to charge the minimum fee at all, rejected rather than free
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::from(2u64)),
+            Err(LendingError::BorrowTooSmall.into())
+        );
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::one()),
+            Err(LendingError::BorrowTooSmall.into())
+        );
+
+        // a zero rate charges nothing, however small the loan
+        fees.flash_loan_fee_wad = 0;
+        for amount in &[0u64, 1, 2, 333] {
+            assert_eq!(
+                fees.calculate_flash_loan_fees(Decimal::from(*amount)),
+                Ok((0, 0))
+            );
+        }
+    }
+
+    #[test]
+    fn flash_loan_fee_calculation_rounding() {
+        let mut fees = ReserveFees {
+            borrow_fee_wad: 0,
+            flash_loan_fee_wad: 3_000_000_000_000_000, // 0.3%
+            host_fee_percentage: 0,
+        };
+
+        // 0.3% of 1000 is exactly 3
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::from(1_000u64)),
+            Ok((3, 0))
+        );
+
+        // 0.3% of 1100 is 3.3, rounded up for the protocol
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::from(1_100u64)),
+            Ok((4, 0))
+        );
+
+        // 40% of the 4 token fee is 1.6, rounded down for the host
+        fees.host_fee_percentage = 40;
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::from(1_100u64)),
+            Ok((4, 1))
+        );
+
+        // nothing is charged on nothing borrowed
+        assert_eq!(fees.calculate_flash_loan_fees(Decimal::zero()), Ok((0, 0)));
+    }
+
     #[test]
     fn borrow_fee_calculation_host() {
         let fees = ReserveFees {
@@ -1581,12 +3381,14 @@ mod test {
                     deposit_reserve: Pubkey::new_unique(),
                     deposited_amount: test_case.deposit_amount,
                     market_value: Decimal::from(test_case.deposit_market_value),
+                    ..ObligationCollateral::default()
                 }],
                 borrows: vec![ObligationLiquidity {
                     borrow_reserve: Pubkey::new_unique(),
                     cumulative_borrow_rate_wads: Decimal::one(),
                     borrowed_amount_wads: Decimal::from(test_case.borrow_amount),
                     market_value: Decimal::from(test_case.borrow_market_value),
+                    ..ObligationLiquidity::default()
                 }],
                 borrowed_value: Decimal::from(test_case.borrow_market_value),
                 ..Obligation::default()
@@ -1594,8 +3396,785 @@ mod test {
 
             assert_eq!(
                 reserve.calculate_liquidation(
-                    u64::MAX, &obligation, &obligation.borrows[0], &obligation.deposits[0]).unwrap(),
+                    u64::MAX, &obligation, &obligation.borrows[0], &obligation.deposits[0], LIQUIDATION_CLOSE_FACTOR, None).unwrap(),
                 test_case.liquidation_result);
         }
     }
+
+    #[test]
+    fn calculate_liquidation_bonus_cap() {
+        let reserve = Reserve {
+            config: ReserveConfig {
+                liquidation_bonus: 5,
+                protocol_liquidation_fee: 100,
+                ..ReserveConfig::default()
+            },
+            liquidity: ReserveLiquidity {
+                mint_decimals: 0,
+                market_price: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+
+        let obligation = Obligation {
+            deposits: vec![ObligationCollateral {
+                deposit_reserve: Pubkey::new_unique(),
+                deposited_amount: 100_000,
+                market_value: Decimal::from(100_000u64),
+                ..ObligationCollateral::default()
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrow_reserve: Pubkey::new_unique(),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                borrowed_amount_wads: Decimal::from(8_000u64),
+                market_value: Decimal::from(8_000u64),
+                ..ObligationLiquidity::default()
+            }],
+            borrowed_value: Decimal::from(8_000u64),
+            ..Obligation::default()
+        };
+        let liquidate = |max_bonus_value| {
+            reserve
+                .calculate_liquidation(
+                    u64::MAX,
+                    &obligation,
+                    &obligation.borrows[0],
+                    &obligation.deposits[0],
+                    LIQUIDATION_CLOSE_FACTOR,
+                    max_bonus_value,
+                )
+                .unwrap()
+        };
+
+        // 20% of the borrow is liquidated, a 5% bonus on $1,600 is $80
+        assert_eq!(liquidate(None).withdraw_amount, 1_680);
+        assert_eq!(
+            liquidate(Some(Decimal::from(100u64))).withdraw_amount,
+            1_680
+        );
+
+        let capped = liquidate(Some(Decimal::from(50u64)));
+        assert_eq!(capped.settle_amount, Decimal::from(1_600u64));
+        assert_eq!(capped.repay_amount, 1_600);
+        assert_eq!(capped.withdraw_amount, 1_650);
+
+        assert_eq!(
+            reserve
+                .calculate_protocol_liquidation_fee(1_680, Rate::from_percent(5), None)
+                .unwrap(),
+            80
+        );
+        assert_eq!(
+            reserve
+                .calculate_protocol_liquidation_fee(
+                    1_650,
+                    Rate::from_percent(5),
+                    Some(Decimal::from(50u64))
+                )
+                .unwrap(),
+            50
+        );
+    }
+
+    #[test]
+    fn liquidation_bonus_ramp() {
+        let mut reserve = Reserve {
+            config: ReserveConfig {
+                liquidation_bonus: 5,
+                max_liquidation_bonus: 15,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        let obligation = |borrowed_value: u64| Obligation {
+            deposited_value: Decimal::from(100u64),
+            unhealthy_borrow_value: Decimal::from(80u64),
+            borrowed_value: Decimal::from(borrowed_value),
+            ..Obligation::default()
+        };
+
+        // the liquidation bonus at the threshold, ramping up to the max once insolvent
+        assert_eq!(
+            reserve.liquidation_bonus(&obligation(80)),
+            Ok(Rate::from_percent(5))
+        );
+        assert_eq!(
+            reserve.liquidation_bonus(&obligation(85)),
+            Ok(Rate::from_scaled_val(75_000_000_000_000_000))
+        );
+        assert_eq!(
+            reserve.liquidation_bonus(&obligation(90)),
+            Ok(Rate::from_percent(10))
+        );
+        assert_eq!(
+            reserve.liquidation_bonus(&obligation(100)),
+            Ok(Rate::from_percent(15))
+        );
+        assert_eq!(
+            reserve.liquidation_bonus(&obligation(120)),
+            Ok(Rate::from_percent(15))
+        );
+
+        // no ramp without a higher max bonus
+        reserve.config.max_liquidation_bonus = 0;
+        assert_eq!(
+            reserve.liquidation_bonus(&obligation(90)),
+            Ok(Rate::from_percent(5))
+        );
+        reserve.config.max_liquidation_bonus = 5;
+        assert_eq!(
+            reserve.liquidation_bonus(&obligation(100)),
+            Ok(Rate::from_percent(5))
+        );
+    }
+
+    #[test]
+    fn update_market_price_stuck_feed() {
+        let mut reserve = Reserve {
+            config: ReserveConfig {
+                max_unchanged_price_slots: 10,
+                ..ReserveConfig::default()
+            },
+            liquidity: ReserveLiquidity {
+                market_price: Decimal::from(20u64),
+                market_price_last_changed_slot: 100,
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+
+        // the same price is accepted for the whole window
+        for slot in 101..=110 {
+            reserve
+                .update_market_price(Decimal::from(20u64), slot)
+                .unwrap();
+            assert_eq!(reserve.liquidity.market_price_last_changed_slot, 100);
+        }
+        assert_eq!(
+            reserve.update_market_price(Decimal::from(20u64), 111),
+            Err(LendingError::OraclePriceStuck.into())
+        );
+
+        // a new price restarts the window
+        reserve
+            .update_market_price(Decimal::from(21u64), 111)
+            .unwrap();
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(21u64));
+        assert_eq!(reserve.liquidity.market_price_last_changed_slot, 111);
+        reserve
+            .update_market_price(Decimal::from(21u64), 121)
+            .unwrap();
+
+        // disabled
+        reserve.config.max_unchanged_price_slots = 0;
+        reserve
+            .update_market_price(Decimal::from(21u64), 1_000)
+            .unwrap();
+        assert_eq!(reserve.liquidity.market_price_last_changed_slot, 111);
+    }
+
+    #[test]
+    fn collateral_price_floor() {
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                market_price: Decimal::from(20u64),
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+        assert_eq!(reserve.collateral_price(), Ok(Decimal::from(20u64)));
+
+        reserve.collateral_floor_price = Decimal::from(15u64);
+        assert_eq!(reserve.collateral_price(), Ok(Decimal::from(15u64)));
+
+        // never values collateral above spot
+        reserve.collateral_floor_price = Decimal::from(25u64);
+        assert_eq!(reserve.collateral_price(), Ok(Decimal::from(20u64)));
+    }
+
+    #[test]
+    fn price_confidence_bounds() {
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                market_price: Decimal::from(20u64),
+                market_price_confidence: Decimal::from(2u64),
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+        let price = reserve.liquidity.market_price;
+        assert_eq!(
+            reserve.price_less_confidence(price),
+            Ok(Decimal::from(18u64))
+        );
+        assert_eq!(
+            reserve.price_plus_confidence(price),
+            Ok(Decimal::from(22u64))
+        );
+
+        // never values collateral below zero
+        reserve.liquidity.market_price_confidence = Decimal::from(25u64);
+        assert_eq!(reserve.price_less_confidence(price), Ok(Decimal::zero()));
+
+        // a fixed price has no confidence interval
+        reserve.update_fixed_market_price(Decimal::from(20u64), 100);
+        assert_eq!(reserve.price_less_confidence(price), Ok(price));
+    }
+
+    #[test]
+    fn pack_unmigrated() {
+        let mut reserve = Reserve {
+            version: PROGRAM_VERSION,
+            liquidity: ReserveLiquidity {
+                pyth_oracle_pubkey: Pubkey::new_unique(),
+                market_price: Decimal::from(20u64),
+                market_price_confidence: Decimal::from(2u64),
+                ..ReserveLiquidity::default()
+            },
+            use_price_confidence: true,
+            ..Reserve::default()
+        };
+        let mut data = [0u8; RESERVE_LEN];
+        Reserve::pack(reserve.clone(), &mut data).unwrap();
+        let unpacked = Reserve::unpack(&data).unwrap();
+
+        // accounts with the older layout end where the extra pyth oracles start
+        let mut unmigrated = [0u8; UNMIGRATED_RESERVE_LEN];
+        Reserve::pack(reserve.clone(), &mut unmigrated).unwrap();
+        assert_eq!(unmigrated[..], data[..UNMIGRATED_RESERVE_LEN]);
+        let migrated = Reserve::unpack(&unmigrated).unwrap();
+        assert_eq!(migrated, unpacked);
+        assert!(migrated.liquidity.extra_pyth_oracles().is_empty());
+        assert_eq!(
+            Reserve::unpack(&data[..UNMIGRATED_RESERVE_LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // extra pyth oracles only fit once migrated
+        let extra_pyth_oracles = [Pubkey::new_unique(), Pubkey::new_unique()];
+        reserve
+            .liquidity
+            .set_extra_pyth_oracles(&extra_pyth_oracles)
+            .unwrap();
+        assert_eq!(
+            Reserve::pack(reserve.clone(), &mut unmigrated),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        Reserve::pack(reserve, &mut data).unwrap();
+        assert_eq!(
+            Reserve::unpack(&data)
+                .unwrap()
+                .liquidity
+                .extra_pyth_oracles(),
+            extra_pyth_oracles.to_vec()
+        );
+
+        // and only so many can be set
+        let mut liquidity = ReserveLiquidity::default();
+        assert_eq!(
+            liquidity.set_extra_pyth_oracles(&[Pubkey::new_unique(); MAX_EXTRA_PYTH_ORACLES + 1]),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+        liquidity
+            .set_extra_pyth_oracles(&extra_pyth_oracles[..1])
+            .unwrap();
+        assert_eq!(
+            liquidity.extra_pyth_oracles(),
+            extra_pyth_oracles[..1].to_vec()
+        );
+    }
+
+    #[test]
+    fn stale_price_decay() {
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(100),
+            liquidity: ReserveLiquidity {
+                market_price: Decimal::from(20u64),
+                oracle_price_slot: 100,
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                max_stale_price_slots: 50,
+                stale_price_decay_bps: 10,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        assert_eq!(reserve.stale_price_decay(), Ok(Rate::zero()));
+        assert_eq!(reserve.collateral_price(), Ok(Decimal::from(20u64)));
+        assert_eq!(reserve.debt_price(), Ok(Decimal::from(20u64)));
+
+        // 0.1% more conservative for every slot the cached price goes unconfirmed
+        reserve.last_update.update_slot(120);
+        assert_eq!(reserve.stale_price_decay(), Ok(Rate::from_percent(2)));
+        assert_eq!(
+            reserve.collateral_price(),
+            Ok(Decimal::from_scaled_val(19_600_000_000_000_000_000))
+        );
+        assert_eq!(
+            reserve.debt_price(),
+            Ok(Decimal::from_scaled_val(20_400_000_000_000_000_000))
+        );
+
+        // the floor price is discounted as well
+        reserve.collateral_floor_price = Decimal::from(10u64);
+        assert_eq!(
+            reserve.collateral_price(),
+            Ok(Decimal::from_scaled_val(9_800_000_000_000_000_000))
+        );
+        reserve.collateral_floor_price = Decimal::zero();
+
+        // the decay is capped at the whole price
+        reserve.last_update.update_slot(10_000);
+        assert_eq!(reserve.stale_price_decay(), Ok(Rate::one()));
+        assert_eq!(reserve.collateral_price(), Ok(Decimal::zero()));
+        assert_eq!(reserve.debt_price(), Ok(Decimal::from(40u64)));
+
+        // nothing decays without a fallback window or a tracked oracle read
+        reserve.config.max_stale_price_slots = 0;
+        assert_eq!(reserve.stale_price_decay(), Ok(Rate::zero()));
+        reserve.config.max_stale_price_slots = 50;
+        reserve.liquidity.oracle_price_slot = 0;
+        assert_eq!(reserve.stale_price_decay(), Ok(Rate::zero()));
+    }
+
+    #[test]
+    fn price_fresh_for_liquidation() {
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(120),
+            liquidity: ReserveLiquidity {
+                market_price: Decimal::from(20u64),
+                oracle_price_slot: 100,
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                max_stale_price_slots: 50,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        assert!(reserve.is_price_fresh_for_liquidation(120));
+
+        reserve.config.max_liquidation_price_age_slots = 20;
+        assert!(reserve.is_price_fresh_for_liquidation(120));
+        assert!(!reserve.is_price_fresh_for_liquidation(121));
+
+        reserve.config.fixed_price = WAD;
+        assert!(reserve.is_price_fresh_for_liquidation(121));
+    }
+
+    #[test]
+    fn validate_config() {
+        let config = ReserveConfig {
+            optimal_utilization_rate: 80,
+            loan_to_value_ratio: 50,
+            liquidation_bonus: 5,
+            liquidation_threshold: 55,
+            min_borrow_rate: 0,
+            optimal_borrow_rate: 4,
+            max_borrow_rate: 30,
+            fees: ReserveFees {
+                borrow_fee_wad: 100_000_000_000,
+                flash_loan_fee_wad: 3_000_000_000_000_000,
+                host_fee_percentage: 20,
+            },
+            protocol_liquidation_fee: 30,
+            protocol_take_rate: 10,
+            extreme_utilization_rate: 100,
+            liquidation_close_factor: 20,
+            ..ReserveConfig::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+
+        let invalid_configs: [fn(&mut ReserveConfig); 12] = [
+            |config| config.optimal_utilization_rate = 101,
+            |config| config.min_borrow_rate = config.optimal_borrow_rate + 1,
+            |config| config.max_borrow_rate = config.optimal_borrow_rate - 1,
+            |config| config.loan_to_value_ratio = config.liquidation_threshold,
+            |config| config.loan_to_value_ratio = config.liquidation_threshold + 1,
+            |config| {
+                config.loan_to_value_ratio = 100;
+                config.liquidation_threshold = 101;
+            },
+            |config| config.liquidation_threshold = 101,
+            |config| config.fees.host_fee_percentage = 101,
+            |config| config.protocol_liquidation_fee = 101,
+            |config| config.protocol_take_rate = 101,
+            |config| config.protocol_fees_cap = 101,
+            |config| config.max_deployed_pct = 101,
+        ];
+        for invalidate in invalid_configs {
+            let mut invalid_config = config;
+            invalidate(&mut invalid_config);
+            assert_eq!(
+                invalid_config.validate(),
+                Err(LendingError::InvalidConfig.into())
+            );
+        }
+    }
+
+    #[test]
+    fn effective_limits() {
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 600,
+                borrowed_amount_wads: Decimal::from(400u64),
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                deposit_limit: 15_000,
+                borrow_limit: 5_000,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        assert_eq!(reserve.deposit_limit(), Ok(15_000));
+        assert_eq!(reserve.borrow_limit(), Ok(5_000));
+
+        reserve.config.fractional_limits = true;
+        assert_eq!(reserve.deposit_limit(), Ok(1_500));
+        assert_eq!(reserve.borrow_limit(), Ok(500));
+
+        // fractional limits follow the supply
+        reserve.liquidity.available_amount = 9_600;
+        assert_eq!(reserve.deposit_limit(), Ok(15_000));
+        assert_eq!(reserve.borrow_limit(), Ok(5_000));
+
+        reserve.config.deposit_limit = u64::MAX;
+        assert_eq!(reserve.deposit_limit(), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn fixed_market_price() {
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(100),
+            liquidity: ReserveLiquidity {
+                market_price: Decimal::from_scaled_val(999_000_000_000_000_000),
+                oracle_price_slot: 100,
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                max_stale_price_slots: 50,
+                stale_price_decay_bps: 10,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        assert_eq!(reserve.config.fixed_market_price(), None);
+
+        reserve.config.fixed_price = WAD;
+        assert_eq!(reserve.config.fixed_market_price(), Some(Decimal::one()));
+        reserve.update_fixed_market_price(Decimal::one(), 120);
+        reserve.last_update.update_slot(120);
+        assert_eq!(reserve.liquidity.market_price, Decimal::one());
+        assert_eq!(
+            reserve.liquidity.previous_market_price,
+            Decimal::from_scaled_val(999_000_000_000_000_000)
+        );
+        assert_eq!(reserve.liquidity.market_price_last_changed_slot, 120);
+
+        // a fixed price isn't an oracle reading, so it neither decays nor counts as one
+        assert_eq!(reserve.liquidity.oracle_price_slot, 100);
+        assert_eq!(reserve.stale_price_decay(), Ok(Rate::zero()));
+        assert_eq!(reserve.collateral_price(), Ok(Decimal::one()));
+        assert_eq!(reserve.debt_price(), Ok(Decimal::one()));
+
+        // back on its oracles, the last oracle reading is as stale as it was
+        reserve.config.fixed_price = 0;
+        assert_eq!(reserve.stale_price_decay(), Ok(Rate::from_percent(2)));
+    }
+
+    fn reserve_at_utilization(config: ReserveConfig, utilization_percent: u64) -> Reserve {
+        Reserve {
+            liquidity: ReserveLiquidity {
+                borrowed_amount_wads: Decimal::from(utilization_percent),
+                available_amount: 100 - utilization_percent,
+                ..ReserveLiquidity::default()
+            },
+            config,
+            ..Reserve::default()
+        }
+    }
+
+    #[test]
+    fn current_borrow_rate_extreme_segment() {
+        let config = ReserveConfig {
+            optimal_utilization_rate: 80,
+            extreme_utilization_rate: 90,
+            min_borrow_rate: 0,
+            optimal_borrow_rate: 10,
+            max_borrow_rate: 50,
+            extreme_borrow_rate: 300,
+            ..ReserveConfig::default()
+        };
+        let borrow_rate = |utilization_percent| {
+            reserve_at_utilization(config, utilization_percent)
+                .current_borrow_rate()
+                .unwrap()
+        };
+
+        assert_eq!(borrow_rate(40), Rate::from_percent(5));
+        assert_eq!(borrow_rate(80), Rate::from_percent(10));
+        assert_eq!(borrow_rate(85), Rate::from_percent(30));
+        assert_eq!(borrow_rate(90), Rate::from_percent(50));
+        assert_eq!(borrow_rate(95), Rate::from_percent_u16(175));
+        assert_eq!(borrow_rate(100), Rate::from_percent_u16(300));
+
+        // the jump segment starts from an encoded max borrow rate above 255%
+        let encoded_max_config = ReserveConfig {
+            optimal_borrow_rate: 255,
+            max_borrow_rate: 255,
+            extreme_borrow_rate: 1_000,
+            ..config
+        };
+        let reserve = reserve_at_utilization(encoded_max_config, 90);
+        assert_eq!(
+            reserve.current_borrow_rate().unwrap(),
+            Rate::from_percent_u16(600)
+        );
+        let reserve = reserve_at_utilization(encoded_max_config, 95);
+        assert_eq!(
+            reserve.current_borrow_rate().unwrap(),
+            Rate::from_percent_u16(800)
+        );
+    }
+
+    #[test]
+    fn current_borrow_rate_without_extreme_segment() {
+        let config = ReserveConfig {
+            optimal_utilization_rate: 80,
+            extreme_utilization_rate: 100,
+            min_borrow_rate: 0,
+            optimal_borrow_rate: 10,
+            max_borrow_rate: 50,
+            extreme_borrow_rate: 300,
+            ..ReserveConfig::default()
+        };
+        let borrow_rate = |utilization_percent| {
+            reserve_at_utilization(config, utilization_percent)
+                .current_borrow_rate()
+                .unwrap()
+        };
+
+        assert_eq!(borrow_rate(80), Rate::from_percent(10));
+        assert_eq!(borrow_rate(90), Rate::from_percent(30));
+        assert_eq!(borrow_rate(100), Rate::from_percent(50));
+
+        // reserves packed before the jump segment existed keep the two slope curve
+        let reserve = Reserve::unpack_from_slice(&[0u8; Reserve::LEN]).unwrap();
+        assert_eq!(reserve.config.extreme_utilization_rate, 100);
+        assert!(!reserve.config.has_extreme_segment());
+    }
+
+    #[test]
+    fn market_value_normalizes_mint_decimals() {
+        let usdc_liquidity = ReserveLiquidity {
+            mint_decimals: 6,
+            ..ReserveLiquidity::default()
+        };
+        let sol_liquidity = ReserveLiquidity {
+            mint_decimals: 9,
+            ..ReserveLiquidity::default()
+        };
+
+        // 1,000 USDC at $1 and 50 SOL at $20 are both worth $1,000
+        assert_eq!(
+            usdc_liquidity
+                .market_value_at(Decimal::from(1_000_000_000u64), Decimal::one())
+                .unwrap(),
+            Decimal::from(1_000u64)
+        );
+        assert_eq!(
+            sol_liquidity
+                .market_value_at(Decimal::from(50_000_000_000u64), Decimal::from(20u64))
+                .unwrap(),
+            Decimal::from(1_000u64)
+        );
+        // a single smallest unit keeps its value rather than rounding away
+        assert_eq!(
+            sol_liquidity
+                .market_value_at(Decimal::one(), Decimal::from(20u64))
+                .unwrap(),
+            Decimal::from_scaled_val(20_000_000_000)
+        );
+
+        let overflowing_liquidity = ReserveLiquidity {
+            mint_decimals: 20,
+            ..ReserveLiquidity::default()
+        };
+        assert_eq!(
+            overflowing_liquidity.market_value_at(Decimal::one(), Decimal::one()),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn usd_to_liquidity_amount_round_trip() {
+        let reserve_at = |mint_decimals: u8, market_price: Decimal| Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals,
+                market_price,
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+        let usdc_reserve = reserve_at(6, Decimal::one());
+        let sol_reserve = reserve_at(9, Decimal::from(20u64));
+        // $23.45
+        let fractional_sol_reserve =
+            reserve_at(9, Decimal::from_scaled_val(23_450_000_000_000_000_000));
+
+        for (reserve, liquidity_amount) in [
+            (&usdc_reserve, 1_000_000_000u64),
+            (&usdc_reserve, 1),
+            (&sol_reserve, 50_000_000_000),
+            (&sol_reserve, 1),
+            (&fractional_sol_reserve, 50_000_000_000),
+            (&fractional_sol_reserve, 1),
+        ] {
+            let value = reserve
+                .liquidity
+                .market_value_at(
+                    Decimal::from(liquidity_amount),
+                    reserve.liquidity.market_price,
+                )
+                .unwrap();
+            assert_eq!(
+                reserve.usd_to_liquidity_amount(value).unwrap(),
+                Decimal::from(liquidity_amount)
+            );
+        }
+
+        // $1,000 is 1,000 USDC or 50 SOL
+        assert_eq!(
+            usdc_reserve
+                .usd_to_liquidity_amount(Decimal::from(1_000u64))
+                .unwrap(),
+            Decimal::from(1_000_000_000u64)
+        );
+        assert_eq!(
+            sol_reserve
+                .usd_to_liquidity_amount(Decimal::from(1_000u64))
+                .unwrap(),
+            Decimal::from(50_000_000_000u64)
+        );
+
+        assert_eq!(
+            reserve_at(9, Decimal::zero()).usd_to_liquidity_amount(Decimal::one()),
+            Err(LendingError::MathOverflow.into())
+        );
+        assert_eq!(
+            reserve_at(20, Decimal::one()).usd_to_liquidity_amount(Decimal::one()),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn borrow_collateral_price_cap() {
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(100),
+            liquidity: ReserveLiquidity {
+                market_price: Decimal::from(20u64),
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                max_borrow_price_increase_pct: 10,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+
+        // the price doubles on a refresh in the same slot as the previous one
+        reserve
+            .update_market_price(Decimal::from(40u64), 100)
+            .unwrap();
+        assert_eq!(
+            reserve.liquidity.previous_market_price,
+            Decimal::from(20u64)
+        );
+        assert_eq!(reserve.liquidity.previous_market_price_slot, 100);
+        assert_eq!(reserve.collateral_price(), Ok(Decimal::from(40u64)));
+        assert_eq!(reserve.borrow_collateral_price(), Ok(Decimal::from(22u64)));
+
+        // and in the next slot
+        reserve.last_update.update_slot(101);
+        assert_eq!(reserve.borrow_collateral_price(), Ok(Decimal::from(22u64)));
+
+        // a price that moved over a longer stretch is taken as is
+        reserve.last_update.update_slot(102);
+        assert_eq!(reserve.borrow_collateral_price(), Ok(Decimal::from(40u64)));
+
+        reserve.last_update.update_slot(100);
+        reserve.config.max_borrow_price_increase_pct = 0;
+        assert_eq!(reserve.borrow_collateral_price(), Ok(Decimal::from(40u64)));
+
+        // falls are never capped and the floor price still applies
+        reserve.config.max_borrow_price_increase_pct = 10;
+        reserve.liquidity.market_price = Decimal::from(15u64);
+        assert_eq!(reserve.borrow_collateral_price(), Ok(Decimal::from(15u64)));
+        reserve.collateral_floor_price = Decimal::from(12u64);
+        assert_eq!(reserve.borrow_collateral_price(), Ok(Decimal::from(12u64)));
+    }
+
+    #[test]
+    fn validate_collateral_mint_supply() {
+        let mut collateral = ReserveCollateral::default();
+        collateral.mint(1_000).unwrap();
+        assert_eq!(collateral.validate_mint_supply(1_000), Ok(()));
+
+        assert_eq!(
+            collateral.validate_mint_supply(1_001),
+            Err(LendingError::ReserveAccountingInconsistent.into())
+        );
+        assert_eq!(
+            collateral.validate_mint_supply(999),
+            Err(LendingError::ReserveAccountingInconsistent.into())
+        );
+    }
+
+    #[test]
+    fn deploy_and_recall_liquidity() {
+        let mut liquidity = ReserveLiquidity {
+            available_amount: 1_000,
+            borrowed_amount_wads: Decimal::from(500u64),
+            ..ReserveLiquidity::default()
+        };
+        let total_supply = liquidity.total_supply().unwrap();
+
+        // at most 60% of the 1,000 that isn't lent out can be deployed
+        assert_eq!(
+            liquidity.deploy(601, 60),
+            Err(LendingError::DeployLimitExceeded.into())
+        );
+        liquidity.deploy(400, 60).unwrap();
+        liquidity.deploy(200, 60).unwrap();
+        assert_eq!(
+            liquidity.deploy(1, 60),
+            Err(LendingError::DeployLimitExceeded.into())
+        );
+        assert_eq!(liquidity.available_amount, 400);
+        assert_eq!(liquidity.deployed_amount, 600);
+        assert_eq!(liquidity.total_supply().unwrap(), total_supply);
+
+        liquidity.recall(100).unwrap();
+        assert_eq!(liquidity.available_amount, 500);
+        assert_eq!(liquidity.deployed_amount, 500);
+        assert_eq!(liquidity.total_supply().unwrap(), total_supply);
+
+        // returning more than was deployed grows the supply by the difference
+        liquidity.recall(530).unwrap();
+        assert_eq!(liquidity.available_amount, 1_030);
+        assert_eq!(liquidity.deployed_amount, 0);
+        assert_eq!(
+            liquidity.total_supply().unwrap(),
+            total_supply.try_add(Decimal::from(30u64)).unwrap()
+        );
+
+        // deploying never takes more than is available
+        assert_eq!(
+            liquidity.deploy(1_031, 100),
+            Err(LendingError::InsufficientLiquidity.into())
+        );
+    }
 }
@@ -0,0 +1,213 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{AccountMeta, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{borrow_obligation_liquidity, refresh_obligation, refresh_reserve},
+    math::Decimal,
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+// pyth prices can only be read once the slot is past their max price age of 240 slots
+const REFRESH_SLOT: u64 = 300;
+
+/// Borrow from a stale USDC reserve, optionally supplying its oracle accounts to the borrow. With
+/// `extra_pyth_oracles` the reserve is priced at the median of three pyth feeds instead.
+async fn borrow_from_stale_reserve(
+    auto_refresh: bool,
+    supply_oracles: bool,
+    extra_pyth_oracles: bool,
+) -> (Result<(), TransportError>, BanksClient, TestReserve) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_pyth_oracle(&mut test, 20, REFRESH_SLOT);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut usdc_reserve_config = test_reserve_config();
+    usdc_reserve_config.fees.borrow_fee_wad = 0;
+    usdc_reserve_config.auto_refresh = auto_refresh;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_pyth_oracle(&mut test, 1, REFRESH_SLOT);
+    let usdc_extra_oracles = if extra_pyth_oracles {
+        vec![
+            add_pyth_oracle(&mut test, 1, REFRESH_SLOT),
+            add_pyth_oracle(&mut test, 100, REFRESH_SLOT),
+        ]
+    } else {
+        vec![]
+    };
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: usdc_reserve_config,
+            extra_pyth_oracles: usdc_extra_oracles
+                .iter()
+                .map(|oracle| oracle.pyth_price_pubkey)
+                .collect(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(REFRESH_SLOT).unwrap();
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let mut borrow_instruction = borrow_obligation_liquidity(
+        solend_program::id(),
+        USDC_BORROW_AMOUNT_FRACTIONAL,
+        usdc_test_reserve.liquidity_supply_pubkey,
+        usdc_test_reserve.user_liquidity_pubkey,
+        usdc_test_reserve.pubkey,
+        usdc_test_reserve.config.fee_receiver,
+        test_obligation.pubkey,
+        lending_market.pubkey,
+        user_accounts_owner.pubkey(),
+        None,
+    );
+    if supply_oracles {
+        borrow_instruction.accounts.extend([
+            AccountMeta::new_readonly(usdc_oracle.pyth_price_pubkey, false),
+            AccountMeta::new_readonly(usdc_oracle.switchboard_feed_pubkey, false),
+        ]);
+        borrow_instruction.accounts.extend(
+            usdc_extra_oracles
+                .iter()
+                .map(|oracle| AccountMeta::new_readonly(oracle.pyth_price_pubkey, false)),
+        );
+    }
+
+    // the USDC reserve is left stale
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            borrow_instruction,
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    let result = banks_client.process_transaction(transaction).await;
+
+    (result, banks_client, usdc_test_reserve)
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (result, mut banks_client, usdc_test_reserve) =
+        borrow_from_stale_reserve(true, true, false).await;
+    assert!(result.is_ok());
+
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(
+        usdc_reserve.liquidity.borrowed_amount_wads,
+        USDC_BORROW_AMOUNT_FRACTIONAL.into()
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await,
+        USDC_BORROW_AMOUNT_FRACTIONAL
+    );
+}
+
+#[tokio::test]
+async fn test_fail_auto_refresh_disabled() {
+    let (result, _, _) = borrow_from_stale_reserve(false, true, false).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            2,
+            InstructionError::Custom(LendingError::ReserveStale as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_oracles_not_supplied() {
+    let (result, _, _) = borrow_from_stale_reserve(true, false, false).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            2,
+            InstructionError::Custom(LendingError::ReserveStale as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_success_extra_pyth_oracles() {
+    let (result, mut banks_client, usdc_test_reserve) =
+        borrow_from_stale_reserve(true, true, true).await;
+    assert!(result.is_ok());
+
+    // refreshed at the median of its feeds, ignoring the outlier
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(usdc_reserve.liquidity.market_price, Decimal::one());
+    assert_eq!(
+        usdc_reserve.liquidity.borrowed_amount_wads,
+        USDC_BORROW_AMOUNT_FRACTIONAL.into()
+    );
+}
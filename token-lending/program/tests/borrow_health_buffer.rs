@@ -0,0 +1,277 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        borrow_obligation_liquidity, refresh_obligation, refresh_reserve, update_lending_market,
+        withdraw_obligation_collateral,
+    },
+    processor::process_instruction,
+    state::{LendingMarketConfigUpdate, INITIAL_COLLATERAL_RATIO},
+};
+
+// 100 SOL collateral at $20 and a 50% loan to value ratio -> $1000 allowed borrow value
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2_000 * FRACTIONAL_TO_USDC;
+// 5% buffer -> $950 usable borrow value
+const BORROW_HEALTH_BUFFER_BPS: u16 = 500;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup(borrow_amount: u64) -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut usdc_reserve_config = test_reserve_config();
+    usdc_reserve_config.fees.borrow_fee_wad = 0;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: usdc_reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let borrows = if borrow_amount > 0 {
+        vec![(&usdc_test_reserve, borrow_amount)]
+    } else {
+        vec![]
+    };
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &borrows,
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[update_lending_market(
+            solend_program::id(),
+            LendingMarketConfigUpdate {
+                borrow_health_buffer_bps: Some(BORROW_HEALTH_BUFFER_BPS),
+                ..LendingMarketConfigUpdate::default()
+            },
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+            vec![],
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(
+        market.config.borrow_health_buffer_bps,
+        BORROW_HEALTH_BUFFER_BPS
+    );
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+/// Refresh both reserves and the obligation, then run `instruction` signed by the user
+async fn process_refreshed(
+    setup: &mut TestSetup,
+    reserve_pubkeys: Vec<Pubkey>,
+    instruction: Instruction,
+) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.sol_test_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                reserve_pubkeys,
+                setup.lending_market.pubkey,
+            ),
+            instruction,
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    setup.banks_client.process_transaction(transaction).await
+}
+
+fn borrow_instruction(setup: &TestSetup, liquidity_amount: u64) -> Instruction {
+    borrow_obligation_liquidity(
+        solend_program::id(),
+        liquidity_amount,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        setup.usdc_test_reserve.config.fee_receiver,
+        setup.test_obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+        None,
+    )
+}
+
+fn withdraw_instruction(setup: &TestSetup, collateral_amount: u64) -> Instruction {
+    withdraw_obligation_collateral(
+        solend_program::id(),
+        collateral_amount,
+        setup.sol_test_reserve.collateral_supply_pubkey,
+        setup.sol_test_reserve.user_collateral_pubkey,
+        setup.sol_test_reserve.pubkey,
+        setup.test_obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn test_borrow() {
+    let mut setup = setup(0).await;
+    let reserve_pubkeys = vec![setup.sol_test_reserve.pubkey];
+
+    // the full $1000 allowed borrow value eats into the buffer
+    let instruction = borrow_instruction(&setup, 1_000 * FRACTIONAL_TO_USDC);
+    assert_eq!(
+        process_refreshed(&mut setup, reserve_pubkeys.clone(), instruction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::BorrowTooLarge as u32)
+        )
+    );
+
+    let instruction = borrow_instruction(&setup, 950 * FRACTIONAL_TO_USDC);
+    assert!(process_refreshed(&mut setup, reserve_pubkeys, instruction)
+        .await
+        .is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        obligation.borrows[0].borrowed_amount_wads,
+        (950 * FRACTIONAL_TO_USDC).into()
+    );
+}
+
+#[tokio::test]
+async fn test_withdraw() {
+    // $900 borrowed leaves $50 of the $950 usable borrow value -> $100 of SOL withdrawable
+    let mut setup = setup(900 * FRACTIONAL_TO_USDC).await;
+    let reserve_pubkeys = vec![
+        setup.sol_test_reserve.pubkey,
+        setup.usdc_test_reserve.pubkey,
+    ];
+
+    let instruction = withdraw_instruction(&setup, 6 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO);
+    assert_eq!(
+        process_refreshed(&mut setup, reserve_pubkeys.clone(), instruction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
+        )
+    );
+
+    let instruction = withdraw_instruction(&setup, 5 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO);
+    assert!(process_refreshed(&mut setup, reserve_pubkeys, instruction)
+        .await
+        .is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        95 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO
+    );
+}
@@ -3,6 +3,7 @@
 mod helpers;
 
 use helpers::*;
+use solana_program::pubkey::Pubkey;
 use solana_program_test::*;
 use solana_sdk::{
     instruction::InstructionError,
@@ -11,7 +12,10 @@ use solana_sdk::{
 };
 use solend_program::{
     error::LendingError,
-    instruction::{borrow_obligation_liquidity, refresh_obligation, refresh_reserve},
+    instruction::{
+        borrow_obligation_liquidity, refresh_obligation, refresh_reserve,
+        set_obligation_whitelisted,
+    },
     math::Decimal,
     processor::process_instruction,
     state::{FeeCalculation, INITIAL_COLLATERAL_RATIO},
@@ -98,6 +102,7 @@ async fn test_borrow_usdc_fixed_amount() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             borrow_obligation_liquidity(
                 solend_program::id(),
@@ -242,6 +247,7 @@ async fn test_borrow_sol_max_amount() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             borrow_obligation_liquidity(
                 solend_program::id(),
@@ -370,6 +376,7 @@ async fn test_borrow_too_large() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             borrow_obligation_liquidity(
                 solend_program::id(),
@@ -403,6 +410,117 @@ async fn test_borrow_too_large() {
     );
 }
 
+#[tokio::test]
+async fn test_fail_wrong_fee_receiver() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.fees.borrow_fee_wad = 1_000_000_000_000_000;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // the borrower's own account stands in for the reserve's fee receiver
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            borrow_obligation_liquidity(
+                solend_program::id(),
+                USDC_BORROW_AMOUNT_FRACTIONAL,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.user_liquidity_pubkey,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                test_obligation.owner,
+                None,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidFeeReceiver as u32)
+        )
+    );
+
+    let user_liquidity_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+    assert_eq!(user_liquidity_balance, 0);
+}
+
 #[tokio::test]
 async fn test_borrow_limit() {
     let mut test = ProgramTest::new(
@@ -493,6 +611,7 @@ async fn test_borrow_limit() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             borrow_obligation_liquidity(
                 solend_program::id(),
@@ -518,7 +637,7 @@ async fn test_borrow_limit() {
             .unwrap(),
         TransactionError::InstructionError(
             3,
-            InstructionError::Custom(LendingError::InvalidAmount as u32)
+            InstructionError::Custom(LendingError::BorrowLimitExceeded as u32)
         )
     );
 
@@ -544,6 +663,7 @@ async fn test_borrow_limit() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             borrow_obligation_liquidity(
                 solend_program::id(),
@@ -588,6 +708,7 @@ async fn test_borrow_limit() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             borrow_obligation_liquidity(
                 solend_program::id(),
@@ -618,3 +739,452 @@ async fn test_borrow_limit() {
         )
     );
 }
+
+#[tokio::test]
+async fn test_borrow_limit_boundary() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const USDC_BORROW_LIMIT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.borrow_limit = USDC_BORROW_LIMIT_FRACTIONAL;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let borrow = |liquidity_amount: u64, obligation_reserves: Vec<Pubkey>| {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                refresh_reserve(
+                    solend_program::id(),
+                    sol_test_reserve.pubkey,
+                    sol_oracle.pyth_price_pubkey,
+                    sol_oracle.switchboard_feed_pubkey,
+                ),
+                refresh_reserve(
+                    solend_program::id(),
+                    usdc_test_reserve.pubkey,
+                    usdc_oracle.pyth_price_pubkey,
+                    usdc_oracle.switchboard_feed_pubkey,
+                ),
+                refresh_obligation(
+                    solend_program::id(),
+                    test_obligation.pubkey,
+                    obligation_reserves,
+                    lending_market.pubkey,
+                ),
+                borrow_obligation_liquidity(
+                    solend_program::id(),
+                    liquidity_amount,
+                    usdc_test_reserve.liquidity_supply_pubkey,
+                    usdc_test_reserve.user_liquidity_pubkey,
+                    usdc_test_reserve.pubkey,
+                    usdc_test_reserve.config.fee_receiver,
+                    test_obligation.pubkey,
+                    lending_market.pubkey,
+                    test_obligation.owner,
+                    None,
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+        transaction
+    };
+
+    // one unit above the limit fails
+    assert_eq!(
+        banks_client
+            .process_transaction(borrow(
+                USDC_BORROW_LIMIT_FRACTIONAL + 1,
+                vec![sol_test_reserve.pubkey],
+            ))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::BorrowLimitExceeded as u32)
+        )
+    );
+
+    // borrowing up to the limit succeeds
+    assert!(banks_client
+        .process_transaction(borrow(
+            USDC_BORROW_LIMIT_FRACTIONAL,
+            vec![sol_test_reserve.pubkey],
+        ))
+        .await
+        .is_ok());
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert!(
+        usdc_reserve.liquidity.borrowed_amount_wads >= Decimal::from(USDC_BORROW_LIMIT_FRACTIONAL)
+    );
+
+    // and nothing more can be borrowed after
+    assert_eq!(
+        banks_client
+            .process_transaction(borrow(
+                1,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+            ))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::BorrowLimitExceeded as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_borrow_permissioned_reserve() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 10 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    reserve_config.permissioned = true;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let borrow = |amount| {
+        borrow_obligation_liquidity(
+            solend_program::id(),
+            amount,
+            usdc_test_reserve.liquidity_supply_pubkey,
+            usdc_test_reserve.user_liquidity_pubkey,
+            usdc_test_reserve.pubkey,
+            usdc_test_reserve.config.fee_receiver,
+            test_obligation.pubkey,
+            lending_market.pubkey,
+            test_obligation.owner,
+            Some(usdc_test_reserve.liquidity_host_pubkey),
+        )
+    };
+
+    // obligation is not whitelisted yet
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            borrow(USDC_BORROW_AMOUNT_FRACTIONAL),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::Unauthorized as u32)
+        )
+    );
+
+    // only the lending market owner can whitelist
+    let mut transaction = Transaction::new_with_payer(
+        &[set_obligation_whitelisted(
+            solend_program::id(),
+            true,
+            test_obligation.pubkey,
+            lending_market.pubkey,
+            user_accounts_owner.pubkey(),
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            set_obligation_whitelisted(
+                solend_program::id(),
+                true,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            borrow(USDC_BORROW_AMOUNT_FRACTIONAL),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(
+        &[&payer, &user_accounts_owner, &lending_market.owner],
+        recent_blockhash,
+    );
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert!(obligation.whitelisted);
+    assert_eq!(obligation.borrows.len(), 1);
+}
+
+#[tokio::test]
+async fn test_borrow_obligation_refreshed_in_previous_slot() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 10 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    // refreshed one slot before the borrow
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            mark_fresh: true,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let refresh_reserves = [
+        refresh_reserve(
+            solend_program::id(),
+            sol_test_reserve.pubkey,
+            sol_oracle.pyth_price_pubkey,
+            sol_oracle.switchboard_feed_pubkey,
+        ),
+        refresh_reserve(
+            solend_program::id(),
+            usdc_test_reserve.pubkey,
+            usdc_oracle.pyth_price_pubkey,
+            usdc_oracle.switchboard_feed_pubkey,
+        ),
+    ];
+    let borrow = borrow_obligation_liquidity(
+        solend_program::id(),
+        USDC_BORROW_AMOUNT_FRACTIONAL,
+        usdc_test_reserve.liquidity_supply_pubkey,
+        usdc_test_reserve.user_liquidity_pubkey,
+        usdc_test_reserve.pubkey,
+        usdc_test_reserve.config.fee_receiver,
+        test_obligation.pubkey,
+        lending_market.pubkey,
+        test_obligation.owner,
+        Some(usdc_test_reserve.liquidity_host_pubkey),
+    );
+
+    // fresh reserves don't make up for an obligation refreshed a slot ago
+    let mut transaction = Transaction::new_with_payer(
+        &[&refresh_reserves[..], &[borrow.clone()]].concat(),
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            2,
+            InstructionError::Custom(LendingError::ObligationStale as u32)
+        )
+    );
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            &refresh_reserves[..],
+            &[
+                refresh_obligation(
+                    solend_program::id(),
+                    test_obligation.pubkey,
+                    vec![sol_test_reserve.pubkey],
+                    lending_market.pubkey,
+                ),
+                borrow,
+            ],
+        ]
+        .concat(),
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+}
@@ -0,0 +1,208 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{borrow_obligation_liquidity, refresh_obligation, refresh_reserve},
+    math::Decimal,
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2_000 * FRACTIONAL_TO_USDC;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    // the reserve was last refreshed at $20 and the feed has since spiked to $40
+    let sol_oracle = add_switchboardv2_oracle(&mut test, 40, 0);
+    let mut sol_reserve_config = test_reserve_config();
+    sol_reserve_config.loan_to_value_ratio = 50;
+    sol_reserve_config.max_borrow_price_increase_pct = 10;
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &TestOracle {
+            price: Decimal::from(20u64),
+            ..sol_oracle
+        },
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: sol_reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut usdc_reserve_config = test_reserve_config();
+    usdc_reserve_config.fees.borrow_fee_wad = 0;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: usdc_reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+async fn refresh_and_borrow(
+    setup: &mut TestSetup,
+    liquidity_amount: u64,
+) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.sol_test_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![setup.sol_test_reserve.pubkey],
+                setup.lending_market.pubkey,
+            ),
+            borrow_obligation_liquidity(
+                solend_program::id(),
+                liquidity_amount,
+                setup.usdc_test_reserve.liquidity_supply_pubkey,
+                setup.usdc_test_reserve.user_liquidity_pubkey,
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_test_reserve.config.fee_receiver,
+                setup.test_obligation.pubkey,
+                setup.lending_market.pubkey,
+                setup.user_accounts_owner.pubkey(),
+                None,
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    setup.banks_client.process_transaction(transaction).await
+}
+
+#[tokio::test]
+async fn test_borrow_capped_at_pre_spike_price() {
+    let mut setup = setup().await;
+
+    // 100 SOL at the capped $22 and a 50% loan to value ratio -> $1100 allowed borrow value,
+    // well short of the $2000 the spiked price would allow
+    assert_eq!(
+        refresh_and_borrow(&mut setup, 1_200 * FRACTIONAL_TO_USDC)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::BorrowTooLarge as u32)
+        )
+    );
+
+    assert!(refresh_and_borrow(&mut setup, 1_100 * FRACTIONAL_TO_USDC)
+        .await
+        .is_ok());
+
+    let sol_reserve = setup
+        .sol_test_reserve
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(sol_reserve.liquidity.market_price, Decimal::from(40u64));
+    assert_eq!(
+        sol_reserve.liquidity.previous_market_price,
+        Decimal::from(20u64)
+    );
+
+    // the spiked price still counts towards deposited value, only borrowing power is capped
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(obligation.deposited_value, Decimal::from(4_000u64));
+    assert_eq!(obligation.allowed_borrow_value, Decimal::from(1_100u64));
+    assert_eq!(
+        get_token_balance(
+            &mut setup.banks_client,
+            setup.usdc_test_reserve.user_liquidity_pubkey
+        )
+        .await,
+        1_100 * FRACTIONAL_TO_USDC
+    );
+}
@@ -0,0 +1,567 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        borrow_obligation_liquidity, can_borrow, refresh_obligation, update_lending_market,
+    },
+    math::{Decimal, TryAdd, TryDiv},
+    processor::process_instruction,
+    state::{BorrowCheck, FeeCalculation, LendingMarketConfigUpdate, INITIAL_COLLATERAL_RATIO},
+};
+
+// $2,000 of SOL at a 50% loan to value ratio allows borrowing $1,000 of USDC
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const BORROW_AMOUNT_FRACTIONAL: u64 = 900 * FRACTIONAL_TO_USDC;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    reader_program_id: Pubkey,
+    return_data_pubkey: Pubkey,
+    lending_market: TestLendingMarket,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup(obligation_tranche: u8) -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reader_program_id = Pubkey::new_unique();
+    test.prefer_bpf(false);
+    test.add_program(
+        "return_data_reader",
+        reader_program_id,
+        processor!(helpers::return_data_reader::process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 2 * BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            tranche: obligation_tranche,
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let return_data_pubkey = Pubkey::new_unique();
+    test.add_account(
+        return_data_pubkey,
+        Account::new(u32::MAX as u64, BorrowCheck::LEN, &reader_program_id),
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        reader_program_id,
+        return_data_pubkey,
+        lending_market,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+fn update_market(
+    setup: &TestSetup,
+    deleveraging: bool,
+    borrow_health_buffer_bps: u16,
+    max_obligation_borrow_usd: u64,
+) -> Instruction {
+    update_lending_market(
+        solend_program::id(),
+        LendingMarketConfigUpdate {
+            deleveraging: Some(deleveraging),
+            borrow_health_buffer_bps: Some(borrow_health_buffer_bps),
+            max_obligation_borrow_usd: Some(max_obligation_borrow_usd),
+            ..LendingMarketConfigUpdate::default()
+        },
+        setup.lending_market.pubkey,
+        setup.lending_market.owner.pubkey(),
+        vec![],
+    )
+}
+
+/// Update the lending market with `market_instruction`, then check borrowing
+/// `liquidity_amount` of USDC against the refreshed obligation
+async fn check_borrow(
+    setup: &mut TestSetup,
+    market_instruction: Instruction,
+    liquidity_amount: u64,
+) -> BorrowCheck {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            market_instruction,
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![setup.sol_test_reserve.pubkey],
+                setup.lending_market.pubkey,
+            ),
+            helpers::return_data_reader::read_return_data(
+                setup.reader_program_id,
+                setup.return_data_pubkey,
+                can_borrow(
+                    solend_program::id(),
+                    liquidity_amount,
+                    setup.test_obligation.pubkey,
+                    setup.usdc_test_reserve.pubkey,
+                    setup.lending_market.pubkey,
+                ),
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.lending_market.owner],
+        setup.recent_blockhash,
+    );
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let return_data = setup
+        .banks_client
+        .get_account(setup.return_data_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    BorrowCheck::unpack_from_slice(&return_data.data).unwrap()
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reader_program_id = Pubkey::new_unique();
+    test.prefer_bpf(false);
+    test.add_program(
+        "return_data_reader",
+        reader_program_id,
+        processor!(helpers::return_data_reader::process_instruction),
+    );
+
+    // $2,000 of SOL at a 50% loan to value ratio allows borrowing $1,000 of USDC
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const HEALTHY_BORROW_AMOUNT_FRACTIONAL: u64 = 500 * FRACTIONAL_TO_USDC;
+    const UNHEALTHY_BORROW_AMOUNT_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * UNHEALTHY_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let healthy_return_data_pubkey = Pubkey::new_unique();
+    let unhealthy_return_data_pubkey = Pubkey::new_unique();
+    for return_data_pubkey in [healthy_return_data_pubkey, unhealthy_return_data_pubkey] {
+        test.add_account(
+            return_data_pubkey,
+            Account::new(u32::MAX as u64, BorrowCheck::LEN, &reader_program_id),
+        );
+    }
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            helpers::return_data_reader::read_return_data(
+                reader_program_id,
+                healthy_return_data_pubkey,
+                can_borrow(
+                    solend_program::id(),
+                    HEALTHY_BORROW_AMOUNT_FRACTIONAL,
+                    test_obligation.pubkey,
+                    usdc_test_reserve.pubkey,
+                    lending_market.pubkey,
+                ),
+            ),
+            helpers::return_data_reader::read_return_data(
+                reader_program_id,
+                unhealthy_return_data_pubkey,
+                can_borrow(
+                    solend_program::id(),
+                    UNHEALTHY_BORROW_AMOUNT_FRACTIONAL,
+                    test_obligation.pubkey,
+                    usdc_test_reserve.pubkey,
+                    lending_market.pubkey,
+                ),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.borrowed_value, Decimal::zero());
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(
+        usdc_reserve.liquidity.available_amount,
+        USDC_RESERVE_LIQUIDITY_FRACTIONAL
+    );
+
+    let expected_loan_to_value = |liquidity_amount: u64| {
+        let (borrow_fee, _) = usdc_reserve
+            .config
+            .fees
+            .calculate_borrow_fees(liquidity_amount.into(), FeeCalculation::Exclusive)
+            .unwrap();
+        Decimal::from(liquidity_amount)
+            .try_add(borrow_fee.into())
+            .unwrap()
+            .try_div(FRACTIONAL_TO_USDC)
+            .unwrap()
+            .try_div(obligation.deposited_value)
+            .unwrap()
+    };
+
+    let healthy_data = banks_client
+        .get_account(healthy_return_data_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        BorrowCheck::unpack_from_slice(&healthy_data.data).unwrap(),
+        BorrowCheck {
+            can_borrow: true,
+            loan_to_value: expected_loan_to_value(HEALTHY_BORROW_AMOUNT_FRACTIONAL),
+            error_code: 0,
+        }
+    );
+
+    // the borrow fee pushes the borrow past the allowed borrow value
+    let unhealthy_data = banks_client
+        .get_account(unhealthy_return_data_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        BorrowCheck::unpack_from_slice(&unhealthy_data.data).unwrap(),
+        BorrowCheck {
+            can_borrow: false,
+            loan_to_value: expected_loan_to_value(UNHEALTHY_BORROW_AMOUNT_FRACTIONAL),
+            error_code: LendingError::BorrowTooLarge as u32,
+        }
+    );
+
+    // borrowing agrees with both checks
+    let borrow_transaction = |liquidity_amount: u64| {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                refresh_obligation(
+                    solend_program::id(),
+                    test_obligation.pubkey,
+                    vec![sol_test_reserve.pubkey],
+                    lending_market.pubkey,
+                ),
+                borrow_obligation_liquidity(
+                    solend_program::id(),
+                    liquidity_amount,
+                    usdc_test_reserve.liquidity_supply_pubkey,
+                    usdc_test_reserve.user_liquidity_pubkey,
+                    usdc_test_reserve.pubkey,
+                    usdc_test_reserve.config.fee_receiver,
+                    test_obligation.pubkey,
+                    lending_market.pubkey,
+                    test_obligation.owner,
+                    Some(usdc_test_reserve.liquidity_host_pubkey),
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+        transaction
+    };
+
+    assert_eq!(
+        banks_client
+            .process_transaction(borrow_transaction(UNHEALTHY_BORROW_AMOUNT_FRACTIONAL))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::BorrowTooLarge as u32)
+        )
+    );
+    assert!(banks_client
+        .process_transaction(borrow_transaction(HEALTHY_BORROW_AMOUNT_FRACTIONAL))
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn test_fail_stale_obligation() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * FRACTIONAL_TO_USDC,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, 100 * LAMPORTS_TO_SOL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[can_borrow(
+            solend_program::id(),
+            FRACTIONAL_TO_USDC,
+            test_obligation.pubkey,
+            usdc_test_reserve.pubkey,
+            lending_market.pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ObligationStale as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_health_buffer() {
+    let mut setup = setup(0).await;
+
+    let instruction = update_market(&setup, false, 0, 0);
+    assert!(
+        check_borrow(&mut setup, instruction, BORROW_AMOUNT_FRACTIONAL)
+            .await
+            .can_borrow
+    );
+
+    // a 20% health buffer holds back $200 of the $1,000 allowed borrow value
+    let instruction = update_market(&setup, false, 2_000, 0);
+    assert_eq!(
+        check_borrow(&mut setup, instruction, BORROW_AMOUNT_FRACTIONAL)
+            .await
+            .error_code,
+        LendingError::BorrowTooLarge as u32
+    );
+}
+
+#[tokio::test]
+async fn test_deleveraging() {
+    let mut setup = setup(0).await;
+
+    // borrowing is blocked while the market deleverages
+    let instruction = update_market(&setup, true, 0, 0);
+    assert_eq!(
+        check_borrow(&mut setup, instruction, BORROW_AMOUNT_FRACTIONAL)
+            .await
+            .error_code,
+        LendingError::MarketDeleveraging as u32
+    );
+
+    let instruction = update_market(&setup, false, 0, 0);
+    assert!(
+        check_borrow(&mut setup, instruction, BORROW_AMOUNT_FRACTIONAL)
+            .await
+            .can_borrow
+    );
+}
+
+#[tokio::test]
+async fn test_tranche_mismatch() {
+    // both reserves are in tranche 0
+    let mut setup = setup(1).await;
+
+    let instruction = update_market(&setup, false, 0, 0);
+    assert_eq!(
+        check_borrow(&mut setup, instruction, BORROW_AMOUNT_FRACTIONAL)
+            .await
+            .error_code,
+        LendingError::TrancheMismatch as u32
+    );
+}
+
+#[tokio::test]
+async fn test_max_obligation_borrow_value() {
+    let mut setup = setup(0).await;
+
+    // the borrow fee takes a $900 borrow just past a $900 cap
+    let instruction = update_market(&setup, false, 0, 900);
+    assert_eq!(
+        check_borrow(&mut setup, instruction, BORROW_AMOUNT_FRACTIONAL)
+            .await
+            .error_code,
+        LendingError::ObligationValueLimit as u32
+    );
+
+    let instruction = update_market(&setup, false, 0, 1_000);
+    assert!(
+        check_borrow(&mut setup, instruction, BORROW_AMOUNT_FRACTIONAL)
+            .await
+            .can_borrow
+    );
+}
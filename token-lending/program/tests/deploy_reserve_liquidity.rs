@@ -0,0 +1,307 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        deploy_reserve_liquidity, recall_reserve_liquidity, redeem_reserve_collateral,
+        refresh_reserve,
+    },
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+use spl_token::state::{Account as Token, AccountState};
+
+const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+const USDC_RESERVE_COLLATERAL_AMOUNT: u64 =
+    USDC_RESERVE_LIQUIDITY_FRACTIONAL * INITIAL_COLLATERAL_RATIO;
+// what the strategy earned on the deployed liquidity by the time it is recalled
+const STRATEGY_YIELD_FRACTIONAL: u64 = 50 * FRACTIONAL_TO_USDC;
+const MAX_DEPLOYED_PCT: u8 = 80;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    usdc_test_reserve: TestReserve,
+    usdc_oracle: TestOracle,
+    strategy_vault_pubkey: Pubkey,
+    strategy_vault_authority: Keypair,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let strategy_vault_authority = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+    let usdc_mint = add_usdc_mint(&mut test);
+
+    let strategy_vault_pubkey = Pubkey::new_unique();
+    test.add_packable_account(
+        strategy_vault_pubkey,
+        u32::MAX as u64,
+        &Token {
+            mint: usdc_mint.pubkey,
+            owner: strategy_vault_authority.pubkey(),
+            amount: STRATEGY_YIELD_FRACTIONAL,
+            state: AccountState::Initialized,
+            ..Token::default()
+        },
+        &spl_token::id(),
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.strategy_vault = strategy_vault_pubkey;
+    reserve_config.max_deployed_pct = MAX_DEPLOYED_PCT;
+
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            collateral_amount: USDC_RESERVE_COLLATERAL_AMOUNT,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        usdc_test_reserve,
+        usdc_oracle,
+        strategy_vault_pubkey,
+        strategy_vault_authority,
+    }
+}
+
+/// Refreshes the reserve before `instruction`, so the instruction is at index 1
+fn after_refresh(setup: &TestSetup, instruction: Instruction, signers: &[&Keypair]) -> Transaction {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            instruction,
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    let mut all_signers = vec![&setup.payer];
+    all_signers.extend_from_slice(signers);
+    transaction.sign(&all_signers, setup.recent_blockhash);
+    transaction
+}
+
+async fn process(setup: &mut TestSetup, transaction: Transaction) -> Result<(), TransportError> {
+    setup.banks_client.process_transaction(transaction).await
+}
+
+fn deploy(setup: &TestSetup, liquidity_amount: u64, signer: Pubkey) -> Instruction {
+    deploy_reserve_liquidity(
+        solend_program::id(),
+        liquidity_amount,
+        setup.usdc_test_reserve.pubkey,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.strategy_vault_pubkey,
+        setup.lending_market.pubkey,
+        signer,
+    )
+}
+
+fn redeem(setup: &TestSetup, collateral_amount: u64) -> Instruction {
+    redeem_reserve_collateral(
+        solend_program::id(),
+        collateral_amount,
+        setup.usdc_test_reserve.user_collateral_pubkey,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        setup.usdc_test_reserve.collateral_mint_pubkey,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn test_deploy_and_recall_before_withdrawal() {
+    let mut setup = setup().await;
+
+    let deploy_amount = 800 * FRACTIONAL_TO_USDC;
+    let transaction = after_refresh(
+        &setup,
+        deploy(&setup, deploy_amount, setup.lending_market.owner.pubkey()),
+        &[&setup.lending_market.owner],
+    );
+    assert!(process(&mut setup, transaction).await.is_ok());
+
+    // deployed liquidity still belongs to suppliers
+    let reserve = setup
+        .usdc_test_reserve
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        reserve.liquidity.available_amount,
+        USDC_RESERVE_LIQUIDITY_FRACTIONAL - deploy_amount
+    );
+    assert_eq!(reserve.liquidity.deployed_amount, deploy_amount);
+    assert_eq!(
+        reserve
+            .collateral_exchange_rate()
+            .unwrap()
+            .collateral_to_liquidity(USDC_RESERVE_COLLATERAL_AMOUNT)
+            .unwrap(),
+        USDC_RESERVE_LIQUIDITY_FRACTIONAL
+    );
+    assert_eq!(
+        get_token_balance(
+            &mut setup.banks_client,
+            setup.usdc_test_reserve.liquidity_supply_pubkey
+        )
+        .await,
+        USDC_RESERVE_LIQUIDITY_FRACTIONAL - deploy_amount
+    );
+    assert_eq!(
+        get_token_balance(&mut setup.banks_client, setup.strategy_vault_pubkey).await,
+        deploy_amount + STRATEGY_YIELD_FRACTIONAL
+    );
+
+    // but can't be withdrawn while it's deployed
+    let transaction = after_refresh(
+        &setup,
+        redeem(&setup, USDC_RESERVE_COLLATERAL_AMOUNT / 2),
+        &[&setup.user_accounts_owner],
+    );
+    assert_eq!(
+        process(&mut setup, transaction).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InsufficientLiquidity as u32)
+        )
+    );
+
+    // the strategy returns the deployed liquidity along with its yield
+    let transaction = after_refresh(
+        &setup,
+        recall_reserve_liquidity(
+            solend_program::id(),
+            deploy_amount + STRATEGY_YIELD_FRACTIONAL,
+            setup.usdc_test_reserve.pubkey,
+            setup.strategy_vault_pubkey,
+            setup.usdc_test_reserve.liquidity_supply_pubkey,
+            setup.lending_market.pubkey,
+            setup.lending_market.owner.pubkey(),
+            setup.strategy_vault_authority.pubkey(),
+        ),
+        &[&setup.lending_market.owner, &setup.strategy_vault_authority],
+    );
+    assert!(process(&mut setup, transaction).await.is_ok());
+
+    let reserve = setup
+        .usdc_test_reserve
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        reserve.liquidity.available_amount,
+        USDC_RESERVE_LIQUIDITY_FRACTIONAL + STRATEGY_YIELD_FRACTIONAL
+    );
+    assert_eq!(reserve.liquidity.deployed_amount, 0);
+    assert_eq!(
+        reserve
+            .collateral_exchange_rate()
+            .unwrap()
+            .collateral_to_liquidity(USDC_RESERVE_COLLATERAL_AMOUNT)
+            .unwrap(),
+        USDC_RESERVE_LIQUIDITY_FRACTIONAL + STRATEGY_YIELD_FRACTIONAL
+    );
+
+    // the withdrawal now goes through, yield included
+    let transaction = after_refresh(
+        &setup,
+        redeem(&setup, USDC_RESERVE_COLLATERAL_AMOUNT),
+        &[&setup.user_accounts_owner],
+    );
+    assert!(process(&mut setup, transaction).await.is_ok());
+    assert_eq!(
+        get_token_balance(
+            &mut setup.banks_client,
+            setup.usdc_test_reserve.user_liquidity_pubkey
+        )
+        .await,
+        USDC_RESERVE_LIQUIDITY_FRACTIONAL + STRATEGY_YIELD_FRACTIONAL
+    );
+}
+
+#[tokio::test]
+async fn test_fail_deploy_over_max_deployed_pct() {
+    let mut setup = setup().await;
+
+    let transaction = after_refresh(
+        &setup,
+        deploy(
+            &setup,
+            800 * FRACTIONAL_TO_USDC + 1,
+            setup.lending_market.owner.pubkey(),
+        ),
+        &[&setup.lending_market.owner],
+    );
+    assert_eq!(
+        process(&mut setup, transaction).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::DeployLimitExceeded as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_deploy_not_market_owner() {
+    let mut setup = setup().await;
+
+    let transaction = after_refresh(
+        &setup,
+        deploy(
+            &setup,
+            FRACTIONAL_TO_USDC,
+            setup.user_accounts_owner.pubkey(),
+        ),
+        &[&setup.user_accounts_owner],
+    );
+    assert_eq!(
+        process(&mut setup, transaction).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+}
@@ -0,0 +1,203 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{deposit_collateral_and_assert_healthy, refresh_obligation, refresh_reserve},
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+// 100 SOL collateral at $20 and a 75% liquidation threshold -> $1500 unhealthy borrow value
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_600 * FRACTIONAL_TO_USDC;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let reserve_config = || {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.loan_to_value_ratio = 50;
+        reserve_config.liquidation_threshold = 75;
+        reserve_config
+    };
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+async fn top_up(setup: &mut TestSetup, collateral_amount: u64) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.sol_test_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![
+                    setup.sol_test_reserve.pubkey,
+                    setup.usdc_test_reserve.pubkey,
+                ],
+                setup.lending_market.pubkey,
+            ),
+            deposit_collateral_and_assert_healthy(
+                solend_program::id(),
+                collateral_amount,
+                setup.sol_test_reserve.user_collateral_pubkey,
+                setup.sol_test_reserve.collateral_supply_pubkey,
+                setup.sol_test_reserve.pubkey,
+                setup.test_obligation.pubkey,
+                setup.lending_market.pubkey,
+                setup.test_obligation.owner,
+                setup.user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    setup.banks_client.process_transaction(transaction).await
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup().await;
+
+    // 10 more SOL -> $1650 unhealthy borrow value
+    let top_up_amount = 10 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    assert!(top_up(&mut setup, top_up_amount).await.is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        SOL_DEPOSIT_AMOUNT_LAMPORTS + top_up_amount
+    );
+}
+
+#[tokio::test]
+async fn test_fail_insufficient_top_up() {
+    let mut setup = setup().await;
+
+    // 5 more SOL -> $1575 unhealthy borrow value, still below the $1600 borrowed
+    assert_eq!(
+        top_up(&mut setup, 5 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::ObligationUnhealthy as u32)
+        )
+    );
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        SOL_DEPOSIT_AMOUNT_LAMPORTS
+    );
+}
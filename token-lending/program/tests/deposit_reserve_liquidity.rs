@@ -4,8 +4,16 @@ mod helpers;
 
 use helpers::*;
 use solana_program_test::*;
-use solana_sdk::signature::Keypair;
-use solend_program::processor::process_instruction;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{deposit_reserve_liquidity, refresh_reserve},
+    processor::process_instruction,
+};
 
 #[tokio::test]
 async fn test_success() {
@@ -77,3 +85,105 @@ async fn test_success() {
 
     assert!(usdc_reserve.liquidity.cumulative_borrow_rate_wads > old_borrow_rate);
 }
+
+#[tokio::test]
+async fn test_deposit_limit_boundary() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 10_000 * FRACTIONAL_TO_USDC;
+    const USDC_DEPOSIT_ROOM_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.deposit_limit = USDC_RESERVE_LIQUIDITY_FRACTIONAL + USDC_DEPOSIT_ROOM_FRACTIONAL;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            user_liquidity_amount: 2 * USDC_DEPOSIT_ROOM_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let deposit = |liquidity_amount: u64| {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                refresh_reserve(
+                    solend_program::id(),
+                    usdc_test_reserve.pubkey,
+                    usdc_oracle.pyth_price_pubkey,
+                    usdc_oracle.switchboard_feed_pubkey,
+                ),
+                deposit_reserve_liquidity(
+                    solend_program::id(),
+                    liquidity_amount,
+                    usdc_test_reserve.user_liquidity_pubkey,
+                    usdc_test_reserve.user_collateral_pubkey,
+                    usdc_test_reserve.pubkey,
+                    usdc_test_reserve.liquidity_supply_pubkey,
+                    usdc_test_reserve.collateral_mint_pubkey,
+                    lending_market.pubkey,
+                    user_accounts_owner.pubkey(),
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+        transaction
+    };
+
+    // one unit above the limit fails
+    assert_eq!(
+        banks_client
+            .process_transaction(deposit(USDC_DEPOSIT_ROOM_FRACTIONAL + 1))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::DepositLimitExceeded as u32)
+        )
+    );
+
+    // depositing up to the limit succeeds
+    assert!(banks_client
+        .process_transaction(deposit(USDC_DEPOSIT_ROOM_FRACTIONAL))
+        .await
+        .is_ok());
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(
+        usdc_reserve.liquidity.available_amount,
+        reserve_config.deposit_limit
+    );
+
+    // and nothing more can be deposited after
+    assert_eq!(
+        banks_client
+            .process_transaction(deposit(1))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::DepositLimitExceeded as u32)
+        )
+    );
+}
@@ -5,7 +5,7 @@ mod helpers;
 use helpers::*;
 use solana_program_test::*;
 use solana_sdk::signature::Keypair;
-use solend_program::processor::process_instruction;
+use solend_program::{processor::process_instruction, state::INITIAL_COLLATERAL_RATIO};
 
 #[tokio::test]
 async fn test_success() {
@@ -50,6 +50,17 @@ async fn test_success() {
 
     test_obligation.validate_state(&mut banks_client).await;
 
+    let initial_reserve_state = usdc_test_reserve.get_state(&mut banks_client).await;
+    let initial_user_liquidity_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+    let initial_user_collateral_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_collateral_pubkey).await;
+    let initial_collateral_supply_balance = get_token_balance(
+        &mut banks_client,
+        usdc_test_reserve.collateral_supply_pubkey,
+    )
+    .await;
+
     lending_market
         .deposit_obligation_and_collateral(
             &mut banks_client,
@@ -61,6 +72,45 @@ async fn test_success() {
         )
         .await;
 
+    const COLLATERAL_AMOUNT: u64 = 100 * FRACTIONAL_TO_USDC * INITIAL_COLLATERAL_RATIO;
+
     let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
     assert!(usdc_reserve.last_update.stale);
+    assert_eq!(
+        usdc_reserve.liquidity.available_amount,
+        initial_reserve_state.liquidity.available_amount + 100 * FRACTIONAL_TO_USDC
+    );
+    assert_eq!(
+        usdc_reserve.collateral.mint_total_supply,
+        initial_reserve_state.collateral.mint_total_supply + COLLATERAL_AMOUNT
+    );
+
+    let user_liquidity_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+    assert_eq!(
+        user_liquidity_balance,
+        initial_user_liquidity_balance - 100 * FRACTIONAL_TO_USDC
+    );
+
+    // the minted collateral passes straight through the user's account into the obligation
+    let user_collateral_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_collateral_pubkey).await;
+    assert_eq!(user_collateral_balance, initial_user_collateral_balance);
+    let collateral_supply_balance = get_token_balance(
+        &mut banks_client,
+        usdc_test_reserve.collateral_supply_pubkey,
+    )
+    .await;
+    assert_eq!(
+        collateral_supply_balance,
+        initial_collateral_supply_balance + COLLATERAL_AMOUNT
+    );
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.deposits.len(), 1);
+    assert_eq!(
+        obligation.deposits[0].deposit_reserve,
+        usdc_test_reserve.pubkey
+    );
+    assert_eq!(obligation.deposits[0].deposited_amount, COLLATERAL_AMOUNT);
 }
@@ -0,0 +1,162 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    instruction::{refresh_reserve, update_reserve_config, LendingInstruction},
+    math::{Decimal, WAD},
+    processor::process_instruction,
+};
+
+/// RefreshReserve without any oracle accounts
+fn refresh_reserve_without_oracles(reserve_pubkey: solana_program::pubkey::Pubkey) -> Instruction {
+    Instruction {
+        program_id: solend_program::id(),
+        accounts: vec![AccountMeta::new(reserve_pubkey, false)],
+        data: LendingInstruction::RefreshReserve.pack(),
+    }
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.fixed_price = WAD;
+
+    // the oracle has the stablecoin depegged to $0.90
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_switchboardv2_oracle(&mut test, 9, 1);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(10).unwrap();
+
+    // no oracle accounts are needed, and the fixed price is used
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve_without_oracles(usdc_test_reserve.pubkey)],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(reserve.last_update.slot, 10);
+    assert_eq!(reserve.liquidity.market_price, Decimal::one());
+
+    // supplying the oracle accounts doesn't change the price either
+    test_context.warp_to_slot(20).unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve(
+            solend_program::id(),
+            usdc_test_reserve.pubkey,
+            usdc_oracle.pyth_price_pubkey,
+            usdc_oracle.switchboard_feed_pubkey,
+        )],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(reserve.last_update.slot, 20);
+    assert_eq!(reserve.liquidity.market_price, Decimal::one());
+
+    // clearing the fixed price goes back to the oracles straight away
+    let mut new_config = reserve_config;
+    new_config.fixed_price = 0;
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            update_reserve_config(
+                solend_program::id(),
+                new_config,
+                usdc_test_reserve.pubkey,
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                usdc_oracle.pyth_product_pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            ),
+        ],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&test_context.payer, &lending_market.owner],
+        test_context.last_blockhash,
+    );
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(reserve.config.fixed_price, 0);
+    assert_eq!(
+        reserve.liquidity.market_price,
+        Decimal::from_scaled_val(900_000_000_000_000_000)
+    );
+
+    // and the oracle accounts are required again
+    test_context.warp_to_slot(30).unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve_without_oracles(usdc_test_reserve.pubkey)],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert_eq!(
+        test_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+    );
+}
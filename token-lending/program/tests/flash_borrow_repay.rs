@@ -80,7 +80,7 @@ async fn test_success() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -113,6 +113,88 @@ async fn test_success() {
     assert_eq!(host_fee_balance, HOST_FEE_AMOUNT);
 }
 
+#[tokio::test]
+async fn test_success_without_host() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // limit to track compute unit increase
+    test.set_compute_max_units(60_000);
+
+    const FLASH_LOAN_AMOUNT: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    const FEE_AMOUNT: u64 = 3_000_000;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.fees.host_fee_percentage = 20;
+    reserve_config.fees.flash_loan_fee_wad = 3_000_000_000_000_000;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            user_liquidity_amount: FEE_AMOUNT,
+            liquidity_amount: FLASH_LOAN_AMOUNT,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            flash_borrow_reserve_liquidity(
+                solend_program::id(),
+                FLASH_LOAN_AMOUNT,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                lending_market.pubkey,
+            ),
+            flash_repay_reserve_liquidity(
+                solend_program::id(),
+                FLASH_LOAN_AMOUNT,
+                0,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                usdc_test_reserve.config.fee_receiver,
+                None,
+                usdc_test_reserve.pubkey,
+                lending_market.pubkey,
+                user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let token_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+    assert_eq!(token_balance, 0);
+
+    // the host's cut stays with the reserve
+    let fee_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.config.fee_receiver).await;
+    assert_eq!(fee_balance, FEE_AMOUNT);
+
+    let host_fee_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.liquidity_host_pubkey).await;
+    assert_eq!(host_fee_balance, 0);
+}
+
 #[tokio::test]
 async fn test_fail_disable_flash_loans() {
     let mut test = ProgramTest::new(
@@ -169,7 +251,7 @@ async fn test_fail_disable_flash_loans() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -249,7 +331,7 @@ async fn test_fail_borrow_over_borrow_limit() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -268,9 +350,90 @@ async fn test_fail_borrow_over_borrow_limit() {
             .unwrap(),
         TransactionError::InstructionError(
             0,
-            InstructionError::Custom(LendingError::InvalidAmount as u32)
+            InstructionError::Custom(LendingError::BorrowLimitExceeded as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_repay_to_wrong_fee_receiver() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const FLASH_LOAN_AMOUNT: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    const FEE_AMOUNT: u64 = 3_000_000;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.fees.flash_loan_fee_wad = 3_000_000_000_000_000;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            user_liquidity_amount: FEE_AMOUNT,
+            liquidity_amount: FLASH_LOAN_AMOUNT,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            flash_borrow_reserve_liquidity(
+                solend_program::id(),
+                FLASH_LOAN_AMOUNT,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                lending_market.pubkey,
+            ),
+            // the host account stands in for the reserve's fee receiver
+            flash_repay_reserve_liquidity(
+                solend_program::id(),
+                FLASH_LOAN_AMOUNT,
+                0,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                usdc_test_reserve.liquidity_host_pubkey,
+                None,
+                usdc_test_reserve.pubkey,
+                lending_market.pubkey,
+                user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidFeeReceiver as u32)
         )
     );
+
+    let host_fee_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.liquidity_host_pubkey).await;
+    assert_eq!(host_fee_balance, 0);
 }
 
 #[tokio::test]
@@ -338,7 +501,7 @@ async fn test_fail_double_borrow() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -419,7 +582,7 @@ async fn test_fail_double_repay() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -431,7 +594,7 @@ async fn test_fail_double_repay() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -512,7 +675,7 @@ async fn test_fail_only_one_flash_ix_pair_per_tx() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -532,7 +695,7 @@ async fn test_fail_only_one_flash_ix_pair_per_tx() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -620,7 +783,7 @@ async fn test_fail_invalid_repay_ix() {
                     usdc_test_reserve.user_liquidity_pubkey,
                     usdc_test_reserve.liquidity_supply_pubkey,
                     usdc_test_reserve.config.fee_receiver,
-                    usdc_test_reserve.liquidity_host_pubkey,
+                    Some(usdc_test_reserve.liquidity_host_pubkey),
                     Pubkey::new_unique(),
                     lending_market.pubkey,
                     user_accounts_owner.pubkey(),
@@ -662,7 +825,7 @@ async fn test_fail_invalid_repay_ix() {
                     usdc_test_reserve.user_liquidity_pubkey,
                     usdc_test_reserve.liquidity_supply_pubkey,
                     usdc_test_reserve.config.fee_receiver,
-                    usdc_test_reserve.liquidity_host_pubkey,
+                    Some(usdc_test_reserve.liquidity_host_pubkey),
                     usdc_test_reserve.pubkey,
                     lending_market.pubkey,
                     user_accounts_owner.pubkey(),
@@ -777,7 +940,7 @@ async fn test_fail_invalid_repay_ix() {
                     usdc_test_reserve.user_liquidity_pubkey,
                     usdc_test_reserve.liquidity_supply_pubkey,
                     usdc_test_reserve.config.fee_receiver,
-                    usdc_test_reserve.liquidity_host_pubkey,
+                    Some(usdc_test_reserve.liquidity_host_pubkey),
                     usdc_test_reserve.pubkey,
                     lending_market.pubkey,
                     user_accounts_owner.pubkey(),
@@ -816,7 +979,7 @@ async fn test_fail_invalid_repay_ix() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -857,7 +1020,7 @@ async fn test_fail_invalid_repay_ix() {
                     usdc_test_reserve.user_liquidity_pubkey,
                     usdc_test_reserve.liquidity_supply_pubkey,
                     usdc_test_reserve.config.fee_receiver,
-                    usdc_test_reserve.liquidity_host_pubkey,
+                    Some(usdc_test_reserve.liquidity_host_pubkey),
                     usdc_test_reserve.pubkey,
                     lending_market.pubkey,
                     user_accounts_owner.pubkey(),
@@ -901,7 +1064,7 @@ async fn test_fail_invalid_repay_ix() {
                     usdc_test_reserve.user_liquidity_pubkey,
                     usdc_test_reserve.liquidity_supply_pubkey,
                     usdc_test_reserve.config.fee_receiver,
-                    usdc_test_reserve.liquidity_host_pubkey,
+                    Some(usdc_test_reserve.liquidity_host_pubkey),
                     usdc_test_reserve.pubkey,
                     lending_market.pubkey,
                     user_accounts_owner.pubkey(),
@@ -934,7 +1097,7 @@ async fn test_fail_invalid_repay_ix() {
                     usdc_test_reserve.user_liquidity_pubkey,
                     usdc_test_reserve.liquidity_supply_pubkey,
                     usdc_test_reserve.config.fee_receiver,
-                    usdc_test_reserve.liquidity_host_pubkey,
+                    Some(usdc_test_reserve.liquidity_host_pubkey),
                     usdc_test_reserve.pubkey,
                     lending_market.pubkey,
                     user_accounts_owner.pubkey(),
@@ -954,7 +1117,7 @@ async fn test_fail_invalid_repay_ix() {
                     usdc_test_reserve.user_liquidity_pubkey,
                     usdc_test_reserve.liquidity_supply_pubkey,
                     usdc_test_reserve.config.fee_receiver,
-                    usdc_test_reserve.liquidity_host_pubkey,
+                    Some(usdc_test_reserve.liquidity_host_pubkey),
                     usdc_test_reserve.pubkey,
                     lending_market.pubkey,
                     user_accounts_owner.pubkey(),
@@ -1034,7 +1197,7 @@ async fn test_fail_insufficient_liquidity_for_borrow() {
                 usdc_test_reserve.user_liquidity_pubkey,
                 usdc_test_reserve.liquidity_supply_pubkey,
                 usdc_test_reserve.config.fee_receiver,
-                usdc_test_reserve.liquidity_host_pubkey,
+                Some(usdc_test_reserve.liquidity_host_pubkey),
                 usdc_test_reserve.pubkey,
                 lending_market.pubkey,
                 user_accounts_owner.pubkey(),
@@ -0,0 +1,130 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{deposit_reserve_liquidity, refresh_reserve},
+    processor::process_instruction,
+};
+
+const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+const FIRST_DEPOSIT_FRACTIONAL: u64 = 400 * FRACTIONAL_TO_USDC;
+const SECOND_DEPOSIT_FRACTIONAL: u64 = 600 * FRACTIONAL_TO_USDC;
+
+/// Deposits 400 and then 600 USDC into a reserve supplying 1,000 USDC with `deposit_limit`
+async fn deposit_twice(
+    fractional_limits: bool,
+    deposit_limit: u64,
+) -> (Result<(), TransportError>, Result<(), TransportError>) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.fractional_limits = fractional_limits;
+    reserve_config.deposit_limit = deposit_limit;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            user_liquidity_amount: FIRST_DEPOSIT_FRACTIONAL + SECOND_DEPOSIT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let deposit = |liquidity_amount: u64| {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                refresh_reserve(
+                    solend_program::id(),
+                    usdc_test_reserve.pubkey,
+                    usdc_oracle.pyth_price_pubkey,
+                    usdc_oracle.switchboard_feed_pubkey,
+                ),
+                deposit_reserve_liquidity(
+                    solend_program::id(),
+                    liquidity_amount,
+                    usdc_test_reserve.user_liquidity_pubkey,
+                    usdc_test_reserve.user_collateral_pubkey,
+                    usdc_test_reserve.pubkey,
+                    usdc_test_reserve.liquidity_supply_pubkey,
+                    usdc_test_reserve.collateral_mint_pubkey,
+                    lending_market.pubkey,
+                    user_accounts_owner.pubkey(),
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+        transaction
+    };
+
+    let first = banks_client
+        .process_transaction(deposit(FIRST_DEPOSIT_FRACTIONAL))
+        .await;
+    let second = banks_client
+        .process_transaction(deposit(SECOND_DEPOSIT_FRACTIONAL))
+        .await;
+    (first, second)
+}
+
+#[tokio::test]
+async fn test_fractional_limit_scales_with_supply() {
+    // 150% of the supply caps it at 1,500 USDC before the first deposit and 2,100 USDC after
+    let (first, second) = deposit_twice(true, 15_000).await;
+    assert!(first.is_ok());
+    assert!(second.is_ok());
+}
+
+#[tokio::test]
+async fn test_absolute_limit_stays_fixed() {
+    let (first, second) = deposit_twice(false, 1_500 * FRACTIONAL_TO_USDC).await;
+    assert!(first.is_ok());
+    assert_eq!(
+        second.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::DepositLimitExceeded as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_fractional_limit_exceeded() {
+    // 140% of the supply only leaves room for the first deposit
+    let (first, second) = deposit_twice(true, 14_000).await;
+    assert!(first.is_ok());
+    assert_eq!(
+        second.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::DepositLimitExceeded as u32)
+        )
+    );
+}
@@ -0,0 +1,221 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::{instruction::InstructionError, program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::get_market_reserves,
+    processor::process_instruction,
+    state::{ReserveConfigSummary, INITIAL_COLLATERAL_RATIO},
+};
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reader_program_id = Pubkey::new_unique();
+    test.prefer_bpf(false);
+    test.add_program(
+        "return_data_reader",
+        reader_program_id,
+        processor!(helpers::return_data_reader::process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut sol_reserve_config = test_reserve_config();
+    sol_reserve_config.loan_to_value_ratio = 75;
+    sol_reserve_config.liquidation_threshold = 80;
+    sol_reserve_config.deposit_limit = 1_000 * LAMPORTS_TO_SOL;
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: sol_reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut usdc_reserve_config = test_reserve_config();
+    usdc_reserve_config.loan_to_value_ratio = 85;
+    usdc_reserve_config.liquidation_threshold = 90;
+    usdc_reserve_config.liquidation_close_factor = 50;
+    usdc_reserve_config.borrow_limit = 5_000 * FRACTIONAL_TO_USDC;
+    usdc_reserve_config.fees.flash_loan_fee_wad = 3_000_000_000_000_000;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 10_000 * FRACTIONAL_TO_USDC,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: usdc_reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut supply_only_reserve_config = test_reserve_config();
+    supply_only_reserve_config.loan_to_value_ratio = 0;
+    supply_only_reserve_config.protocol_take_rate = 20;
+
+    let srm_oracle = add_switchboardv2_oracle(&mut test, 2, 0);
+    let srm_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &srm_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_decimals: 6,
+            liquidity_mint_pubkey: Pubkey::new_unique(),
+            config: supply_only_reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_reserves = [&usdc_test_reserve, &sol_test_reserve, &srm_test_reserve];
+
+    let return_data_pubkey = Pubkey::new_unique();
+    test.add_account(
+        return_data_pubkey,
+        Account::new(
+            u32::MAX as u64,
+            test_reserves.len() * ReserveConfigSummary::LEN,
+            &reader_program_id,
+        ),
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[helpers::return_data_reader::read_return_data(
+            reader_program_id,
+            return_data_pubkey,
+            get_market_reserves(
+                solend_program::id(),
+                lending_market.pubkey,
+                test_reserves.iter().map(|r| r.pubkey).collect(),
+            ),
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let return_data = banks_client
+        .get_account(return_data_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    for (test_reserve, summary_data) in test_reserves
+        .iter()
+        .zip(return_data.data.chunks_exact(ReserveConfigSummary::LEN))
+    {
+        let reserve = test_reserve.get_state(&mut banks_client).await;
+        let summary = ReserveConfigSummary::unpack_from_slice(summary_data).unwrap();
+        assert_eq!(summary.reserve, test_reserve.pubkey);
+        assert_eq!(summary.liquidity_mint, test_reserve.liquidity_mint_pubkey);
+        assert_eq!(
+            summary,
+            ReserveConfigSummary::new(test_reserve.pubkey, &reserve)
+        );
+    }
+
+    let usdc_summary = ReserveConfigSummary::unpack_from_slice(&return_data.data).unwrap();
+    assert_eq!(usdc_summary.loan_to_value_ratio, 85);
+    assert_eq!(usdc_summary.liquidation_threshold, 90);
+    assert_eq!(usdc_summary.liquidation_close_factor, 50);
+    assert_eq!(usdc_summary.borrow_limit, 5_000 * FRACTIONAL_TO_USDC);
+    assert_eq!(usdc_summary.flash_loan_fee_wad, 3_000_000_000_000_000);
+
+    let sol_summary =
+        ReserveConfigSummary::unpack_from_slice(&return_data.data[ReserveConfigSummary::LEN..])
+            .unwrap();
+    assert_eq!(sol_summary.loan_to_value_ratio, 75);
+    assert_eq!(sol_summary.deposit_limit, 1_000 * LAMPORTS_TO_SOL);
+
+    let srm_summary =
+        ReserveConfigSummary::unpack_from_slice(&return_data.data[2 * ReserveConfigSummary::LEN..])
+            .unwrap();
+    assert_eq!(srm_summary.loan_to_value_ratio, 0);
+    assert_eq!(srm_summary.protocol_take_rate, 20);
+}
+
+#[tokio::test]
+async fn test_fail_reserve_of_another_market() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+    let other_lending_market = add_lending_market(&mut test);
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &other_lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * FRACTIONAL_TO_USDC,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    for reserve_pubkeys in [vec![usdc_test_reserve.pubkey], vec![]] {
+        let mut transaction = Transaction::new_with_payer(
+            &[get_market_reserves(
+                solend_program::id(),
+                lending_market.pubkey,
+                reserve_pubkeys,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+            )
+        );
+    }
+}
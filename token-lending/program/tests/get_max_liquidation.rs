@@ -0,0 +1,216 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::{
+        get_max_liquidation, liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation,
+    },
+    math::{Decimal, TryMul, TrySub},
+    processor::process_instruction,
+    state::{MaxLiquidation, INITIAL_COLLATERAL_RATIO, LIQUIDATION_CLOSE_FACTOR},
+};
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reader_program_id = Pubkey::new_unique();
+    test.prefer_bpf(false);
+    test.add_program(
+        "return_data_reader",
+        reader_program_id,
+        processor!(helpers::return_data_reader::process_instruction),
+    );
+
+    // 100 SOL collateral
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    // just over the 1600 USDC liquidation threshold, with a close factor share that isn't a
+    // whole number of tokens
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_600 * FRACTIONAL_TO_USDC + 7;
+    // 20% of the borrow is 320.0000014 USDC, rounded up
+    const USDC_MAX_REPAY_AMOUNT_FRACTIONAL: u64 = 320 * FRACTIONAL_TO_USDC + 2;
+
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_bonus = 10;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_bonus = 10;
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let return_data_pubkey = Pubkey::new_unique();
+    test.add_account(
+        return_data_pubkey,
+        Account::new(u32::MAX as u64, MaxLiquidation::LEN, &reader_program_id),
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            helpers::return_data_reader::read_return_data(
+                reader_program_id,
+                return_data_pubkey,
+                get_max_liquidation(
+                    solend_program::id(),
+                    test_obligation.pubkey,
+                    usdc_test_reserve.pubkey,
+                    sol_test_reserve.pubkey,
+                    lending_market.pubkey,
+                ),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let return_data = banks_client
+        .get_account(return_data_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    let max_liquidation = MaxLiquidation::unpack_from_slice(&return_data.data).unwrap();
+    assert_eq!(
+        max_liquidation.repay_amount,
+        USDC_MAX_REPAY_AMOUNT_FRACTIONAL
+    );
+    assert!(max_liquidation.withdraw_amount > 0);
+
+    let initial_user_liquidity_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+    let initial_collateral_supply_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.collateral_supply_pubkey).await;
+
+    // liquidating exactly the quoted amount moves exactly the quoted amounts
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            liquidate_obligation_and_redeem_reserve_collateral(
+                solend_program::id(),
+                max_liquidation.repay_amount,
+                usdc_test_reserve.user_liquidity_pubkey,
+                sol_test_reserve.user_collateral_pubkey,
+                sol_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.pubkey,
+                sol_test_reserve.collateral_mint_pubkey,
+                sol_test_reserve.collateral_supply_pubkey,
+                sol_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.config.fee_receiver,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    assert_eq!(
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await,
+        initial_user_liquidity_balance - max_liquidation.repay_amount
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, sol_test_reserve.collateral_supply_pubkey).await,
+        initial_collateral_supply_balance - max_liquidation.withdraw_amount
+    );
+
+    // the full close factor share was settled, not a truncated repay amount
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    let borrowed_amount_wads = Decimal::from(USDC_BORROW_AMOUNT_FRACTIONAL);
+    assert_eq!(
+        obligation.borrows[0].borrowed_amount_wads,
+        borrowed_amount_wads
+            .try_sub(
+                borrowed_amount_wads
+                    .try_mul(Decimal::from_percent(LIQUIDATION_CLOSE_FACTOR))
+                    .unwrap()
+            )
+            .unwrap()
+    );
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        SOL_DEPOSIT_AMOUNT_LAMPORTS - max_liquidation.withdraw_amount
+    );
+}
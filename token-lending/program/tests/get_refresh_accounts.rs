@@ -0,0 +1,276 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::{program::MAX_RETURN_DATA, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{get_refresh_accounts, refresh_obligation, refresh_reserve},
+    processor::process_instruction,
+    state::{ReserveRefreshAccounts, INITIAL_COLLATERAL_RATIO},
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reader_program_id = Pubkey::new_unique();
+    test.prefer_bpf(false);
+    test.add_program(
+        "return_data_reader",
+        reader_program_id,
+        processor!(helpers::return_data_reader::process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: test_reserve_config(),
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_extra_oracles = [
+        add_pyth_oracle(&mut test, 1, 240),
+        add_pyth_oracle(&mut test, 1, 240),
+    ];
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: test_reserve_config(),
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            extra_pyth_oracles: usdc_extra_oracles
+                .iter()
+                .map(|oracle| oracle.pyth_price_pubkey)
+                .collect(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let return_data_pubkey = Pubkey::new_unique();
+    test.add_account(
+        return_data_pubkey,
+        Account::new(u32::MAX as u64, MAX_RETURN_DATA, &reader_program_id),
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[helpers::return_data_reader::read_return_data(
+            reader_program_id,
+            return_data_pubkey,
+            get_refresh_accounts(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+            ),
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let return_data = banks_client
+        .get_account(return_data_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    let expected_refresh_accounts = vec![
+        ReserveRefreshAccounts {
+            reserve: sol_test_reserve.pubkey,
+            pyth_oracle: sol_oracle.pyth_price_pubkey,
+            switchboard_oracle: sol_oracle.switchboard_feed_pubkey,
+            extra_pyth_oracles: vec![],
+        },
+        ReserveRefreshAccounts {
+            reserve: usdc_test_reserve.pubkey,
+            pyth_oracle: usdc_oracle.pyth_price_pubkey,
+            switchboard_oracle: usdc_oracle.switchboard_feed_pubkey,
+            extra_pyth_oracles: usdc_extra_oracles
+                .iter()
+                .map(|oracle| oracle.pyth_price_pubkey)
+                .collect(),
+        },
+    ];
+    let return_data_len = expected_refresh_accounts
+        .iter()
+        .map(|accounts| accounts.packed_len())
+        .sum::<usize>();
+    assert!(return_data.data[return_data_len..]
+        .iter()
+        .all(|byte| *byte == 0));
+    let refresh_accounts =
+        ReserveRefreshAccounts::unpack_all(&return_data.data[..return_data_len]).unwrap();
+    assert_eq!(refresh_accounts, expected_refresh_accounts);
+
+    // the returned accounts are all a client needs to refresh the obligation
+    let mut instructions: Vec<_> = refresh_accounts
+        .iter()
+        .map(|accounts| {
+            let mut instruction = refresh_reserve(
+                solend_program::id(),
+                accounts.reserve,
+                accounts.pyth_oracle,
+                accounts.switchboard_oracle,
+            );
+            instruction.accounts.extend(
+                accounts
+                    .extra_pyth_oracles
+                    .iter()
+                    .map(|pubkey| AccountMeta::new_readonly(*pubkey, false)),
+            );
+            instruction
+        })
+        .collect();
+    instructions.push(refresh_obligation(
+        solend_program::id(),
+        test_obligation.pubkey,
+        refresh_accounts
+            .iter()
+            .map(|accounts| accounts.reserve)
+            .collect(),
+        lending_market.pubkey,
+    ));
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.last_update.slot, 240);
+    assert!(!obligation.last_update.stale);
+    assert!(obligation.borrowed_value > 0u64.into());
+}
+
+#[tokio::test]
+async fn test_fail_reserve_mismatch() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // reserves must follow the obligation's deposits then borrows
+    let mut transaction = Transaction::new_with_payer(
+        &[get_refresh_accounts(
+            solend_program::id(),
+            test_obligation.pubkey,
+            vec![usdc_test_reserve.pubkey, sol_test_reserve.pubkey],
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+}
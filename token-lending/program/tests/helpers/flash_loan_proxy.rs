@@ -89,7 +89,7 @@ impl Processor {
                 *source_liquidity_info.key,
                 *destination_liquidity_info.key,
                 *reserve_liquidity_fee_receiver_info.key,
-                *host_fee_receiver_info.key,
+                Some(*host_fee_receiver_info.key),
                 *reserve_info.key,
                 *lending_market_info.key,
                 *user_transfer_authority_info.key,
@@ -0,0 +1,94 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+};
+use std::sync::{Mutex, Once, RwLock};
+
+/// Syscall stubs program-test installed before capturing started, which keep handling every
+/// syscall other than `sol_log_data`
+static INNER_STUBS: RwLock<Option<Box<dyn SyscallStubs>>> = RwLock::new(None);
+static LOGGED_DATA: Mutex<Vec<Vec<Vec<u8>>>> = Mutex::new(Vec::new());
+static LOGGED_MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Records the fields programs log with `sol_log_data`, which natively run programs would
+/// otherwise only print to stdout rather than record in the transaction log, along with the
+/// messages they log, which banks clients have no way to return.
+struct LogDataCapture;
+
+impl LogDataCapture {
+    fn with_inner<R>(f: impl FnOnce(&dyn SyscallStubs) -> R) -> R {
+        f(INNER_STUBS.read().unwrap().as_deref().unwrap())
+    }
+}
+
+impl SyscallStubs for LogDataCapture {
+    fn sol_log(&self, message: &str) {
+        LOGGED_MESSAGES.lock().unwrap().push(message.to_string());
+        Self::with_inner(|inner| inner.sol_log(message))
+    }
+    fn sol_log_compute_units(&self) {
+        Self::with_inner(|inner| inner.sol_log_compute_units())
+    }
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        Self::with_inner(|inner| inner.sol_invoke_signed(instruction, account_infos, signers_seeds))
+    }
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        Self::with_inner(|inner| inner.sol_get_clock_sysvar(var_addr))
+    }
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        Self::with_inner(|inner| inner.sol_get_epoch_schedule_sysvar(var_addr))
+    }
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        Self::with_inner(|inner| inner.sol_get_fees_sysvar(var_addr))
+    }
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        Self::with_inner(|inner| inner.sol_get_rent_sysvar(var_addr))
+    }
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        Self::with_inner(|inner| inner.sol_get_return_data())
+    }
+    fn sol_set_return_data(&self, data: &[u8]) {
+        Self::with_inner(|inner| inner.sol_set_return_data(data))
+    }
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        LOGGED_DATA
+            .lock()
+            .unwrap()
+            .push(fields.iter().map(|field| field.to_vec()).collect());
+    }
+    fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+        Self::with_inner(|inner| inner.sol_get_processed_sibling_instruction(index))
+    }
+    fn sol_get_stack_height(&self) -> u64 {
+        Self::with_inner(|inner| inner.sol_get_stack_height())
+    }
+}
+
+/// Starts recording data logged with `sol_log_data` and messages logged with `msg!`. Must be called once the test has started,
+/// after program-test has installed its own syscall stubs.
+pub fn capture_log_data() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        // syscalls made while swapping wait on the lock until the previous stubs are stored
+        let mut inner_stubs = INNER_STUBS.write().unwrap();
+        *inner_stubs = Some(set_syscall_stubs(Box::new(LogDataCapture)));
+    });
+}
+
+/// Fields of every `sol_log_data` call recorded since capturing started, oldest first
+pub fn logged_data() -> Vec<Vec<Vec<u8>>> {
+    LOGGED_DATA.lock().unwrap().clone()
+}
+
+/// Every message logged since capturing started, oldest first
+pub fn logged_messages() -> Vec<String> {
+    LOGGED_MESSAGES.lock().unwrap().clone()
+}
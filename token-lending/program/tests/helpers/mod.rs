@@ -3,17 +3,21 @@
 pub mod flash_loan_proxy;
 pub mod flash_loan_receiver;
 pub mod genesis;
+pub mod log_data_capture;
+pub mod return_data_reader;
 
 use assert_matches::*;
 use bytemuck::{cast_slice_mut, from_bytes_mut, try_cast_slice_mut, Pod, PodCastError};
-use pyth_sdk_solana::state::PriceAccount;
+use pyth_sdk_solana::state::{PriceAccount, PriceStatus, ProductAccount};
 use solana_program::{program_option::COption, program_pack::Pack, pubkey::Pubkey};
 use solana_program_test::*;
 use solana_sdk::{
     account::Account,
+    hash::Hash,
     signature::{read_keypair_file, Keypair, Signer},
     system_instruction::create_account,
     transaction::{Transaction, TransactionError},
+    transport::TransportError,
 };
 use solend_program::{
     instruction::{
@@ -22,13 +26,14 @@ use solend_program::{
         init_reserve, liquidate_obligation, refresh_obligation, refresh_reserve,
         withdraw_obligation_collateral_and_redeem_reserve_collateral,
     },
-    math::{Decimal, Rate, TryAdd, TryMul},
-    processor::switchboard_v2_mainnet,
+    math::{Decimal, Rate, TryAdd, TryDiv, TryMul},
+    processor::{process_instruction, switchboard_v2_mainnet},
     state::{
         InitLendingMarketParams, InitObligationParams, InitReserveParams, LendingMarket,
         NewReserveCollateralParams, NewReserveLiquidityParams, Obligation, ObligationCollateral,
         ObligationLiquidity, Reserve, ReserveCollateral, ReserveConfig, ReserveFees,
-        ReserveLiquidity, INITIAL_COLLATERAL_RATIO, PROGRAM_VERSION,
+        ReserveLiquidity, ReserveType, INITIAL_COLLATERAL_RATIO, LIQUIDATION_CLOSE_FACTOR,
+        PROGRAM_VERSION,
     },
 };
 use spl_token::{
@@ -40,13 +45,14 @@ use std::{
     mem::size_of,
     time::{SystemTime, UNIX_EPOCH},
 };
-use switchboard_v2::AggregatorAccountData;
+use switchboard_v2::{AggregatorAccountData, SwitchboardDecimal};
 
 pub const QUOTE_CURRENCY: [u8; 32] =
     *b"USD\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
 
 pub const LAMPORTS_TO_SOL: u64 = 1_000_000_000;
 pub const FRACTIONAL_TO_USDC: u64 = 1_000_000;
+pub const FRACTIONAL_TO_SRM: u64 = 1_000_000;
 
 pub fn test_reserve_config() -> ReserveConfig {
     ReserveConfig {
@@ -67,6 +73,33 @@ pub fn test_reserve_config() -> ReserveConfig {
         fee_receiver: Keypair::new().pubkey(),
         protocol_liquidation_fee: 30,
         protocol_take_rate: 10,
+        liquidation_threshold_ramp_slots: 0,
+        protocol_fees_cap: 0,
+        permissioned: false,
+        allow_zero_price: false,
+        max_unchanged_price_slots: 0,
+        auto_refresh: false,
+        usable_as_collateral: true,
+        max_borrow_price_increase_pct: 0,
+        tranche: 0,
+        extreme_utilization_rate: 100,
+        extreme_borrow_rate: 0,
+        use_ema_price: false,
+        liquidation_close_factor: LIQUIDATION_CLOSE_FACTOR,
+        round_supplier_interest_up: false,
+        max_price_confidence_bps: 0,
+        reject_self_borrow: false,
+        max_stale_price_slots: 0,
+        stale_price_decay_bps: 0,
+        fixed_price: 0,
+        max_liquidation_bonus: 0,
+        pause_interest_on_oracle_error: false,
+        reserve_type: ReserveType::Regular,
+        strategy_vault: Pubkey::default(),
+        max_deployed_pct: 0,
+        max_price_age_slots: 0,
+        max_liquidation_price_age_slots: 0,
+        fractional_limits: false,
     }
 }
 
@@ -84,7 +117,7 @@ pub const SRM_SWITCHBOARDV2_FEED: &str = "CUgoqwiQ4wCt6Tthkrgx5saAEpLBjPCdHshVa4
 
 pub const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
 
-trait AddPacked {
+pub trait AddPacked {
     fn add_packable_account<T: Pack>(
         &mut self,
         pubkey: Pubkey,
@@ -149,6 +182,7 @@ pub struct AddObligationArgs<'a> {
     pub borrows: &'a [(&'a TestReserve, u64)],
     pub mark_fresh: bool,
     pub slots_elapsed: u64,
+    pub tranche: u8,
 }
 
 pub fn add_obligation(
@@ -162,6 +196,7 @@ pub fn add_obligation(
         borrows,
         mark_fresh,
         slots_elapsed,
+        tranche,
     } = args;
 
     let obligation_keypair = Keypair::new();
@@ -212,6 +247,7 @@ pub fn add_obligation(
         owner: user_accounts_owner.pubkey(),
         deposits: obligation_deposits,
         borrows: obligation_borrows,
+        tranche,
     });
 
     if mark_fresh {
@@ -248,6 +284,7 @@ pub struct AddReserveArgs {
     pub collateral_amount: u64,
     pub mark_fresh: bool,
     pub slots_elapsed: u64,
+    pub extra_pyth_oracles: Vec<Pubkey>,
 }
 
 pub fn add_reserve(
@@ -269,6 +306,7 @@ pub fn add_reserve(
         collateral_amount,
         mark_fresh,
         slots_elapsed,
+        extra_pyth_oracles,
     } = args;
 
     let is_native = if liquidity_mint_pubkey == spl_token::native_mint::id() {
@@ -381,6 +419,10 @@ pub fn add_reserve(
         }),
         config,
     });
+    reserve
+        .liquidity
+        .set_extra_pyth_oracles(&extra_pyth_oracles)
+        .unwrap();
     reserve.deposit_liquidity(liquidity_amount).unwrap();
     reserve.liquidity.borrow(borrow_amount.into()).unwrap();
     let borrow_rate_multiplier = Rate::one()
@@ -453,6 +495,179 @@ pub fn add_reserve(
     }
 }
 
+/// 100 SOL collateral at $20, deposited by the obligation of `SolCollateralTestSetup`
+pub const SOL_COLLATERAL_DEPOSIT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+
+/// Index of each reserve in `SolCollateralTestSetup::reserves`
+pub const SOL_COLLATERAL_RESERVE: usize = 0;
+pub const USDC_BORROW_RESERVE: usize = 1;
+pub const SRM_BORROW_RESERVE: usize = 2;
+
+/// A started test with an obligation depositing SOL collateral and two reserves to borrow from,
+/// $1 USDC and $2 SRM
+pub struct SolCollateralTestSetup {
+    pub banks_client: BanksClient,
+    pub payer: Keypair,
+    pub recent_blockhash: Hash,
+    pub user_accounts_owner: Keypair,
+    pub lending_market: TestLendingMarket,
+    pub reserves: Vec<(TestReserve, TestOracle)>,
+    pub test_obligation: TestObligation,
+}
+
+pub async fn setup_sol_collateral(
+    usdc_config: ReserveConfig,
+    srm_config: ReserveConfig,
+) -> SolCollateralTestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_COLLATERAL_DEPOSIT_LAMPORTS,
+            liquidity_amount: SOL_COLLATERAL_DEPOSIT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: usdc_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let srm_oracle = add_switchboardv2_oracle(&mut test, 2, 0);
+    let srm_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &srm_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 1_000 * FRACTIONAL_TO_SRM,
+            liquidity_mint_pubkey: Pubkey::new_unique(),
+            liquidity_mint_decimals: 6,
+            config: srm_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_COLLATERAL_DEPOSIT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    SolCollateralTestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        reserves: vec![
+            (sol_test_reserve, sol_oracle),
+            (usdc_test_reserve, usdc_oracle),
+            (srm_test_reserve, srm_oracle),
+        ],
+        test_obligation,
+    }
+}
+
+impl SolCollateralTestSetup {
+    /// Refreshes every reserve and the obligation, then borrows `liquidity_amount` from the
+    /// reserve at `reserve_index`
+    pub async fn borrow(
+        &mut self,
+        reserve_index: usize,
+        liquidity_amount: u64,
+    ) -> Result<(), TransportError> {
+        let obligation = self.test_obligation.get_state(&mut self.banks_client).await;
+        let reserve_pubkeys = obligation
+            .deposits
+            .iter()
+            .map(|collateral| collateral.deposit_reserve)
+            .chain(
+                obligation
+                    .borrows
+                    .iter()
+                    .map(|liquidity| liquidity.borrow_reserve),
+            )
+            .collect();
+
+        let mut instructions: Vec<_> = self
+            .reserves
+            .iter()
+            .map(|(reserve, oracle)| {
+                refresh_reserve(
+                    solend_program::id(),
+                    reserve.pubkey,
+                    oracle.pyth_price_pubkey,
+                    oracle.switchboard_feed_pubkey,
+                )
+            })
+            .collect();
+        let borrow_reserve = &self.reserves[reserve_index].0;
+        instructions.push(refresh_obligation(
+            solend_program::id(),
+            self.test_obligation.pubkey,
+            reserve_pubkeys,
+            self.lending_market.pubkey,
+        ));
+        instructions.push(borrow_obligation_liquidity(
+            solend_program::id(),
+            liquidity_amount,
+            borrow_reserve.liquidity_supply_pubkey,
+            borrow_reserve.user_liquidity_pubkey,
+            borrow_reserve.pubkey,
+            borrow_reserve.config.fee_receiver,
+            self.test_obligation.pubkey,
+            self.lending_market.pubkey,
+            self.user_accounts_owner.pubkey(),
+            None,
+        ));
+
+        let mut transaction =
+            Transaction::new_with_payer(&instructions, Some(&self.payer.pubkey()));
+        transaction.sign(
+            &[&self.payer, &self.user_accounts_owner],
+            self.recent_blockhash,
+        );
+        self.banks_client.process_transaction(transaction).await
+    }
+}
+
 pub fn add_account_for_program(
     test: &mut ProgramTest,
     program_derived_account: &Pubkey,
@@ -701,6 +916,7 @@ impl TestLendingMarket {
                     solend_program::id(),
                     obligation.pubkey,
                     vec![reserve.pubkey],
+                    self.pubkey,
                 ),
                 withdraw_obligation_collateral_and_redeem_reserve_collateral(
                     solend_program::id(),
@@ -863,6 +1079,38 @@ impl TestReserve {
         liquidity_fee_receiver_keypair: &Keypair,
         payer: &Keypair,
         user_accounts_owner: &Keypair,
+    ) -> Result<Self, TransactionError> {
+        Self::init_with_collateral_exchange_rate(
+            name,
+            banks_client,
+            lending_market,
+            oracle,
+            liquidity_amount,
+            config,
+            0,
+            liquidity_mint_pubkey,
+            user_liquidity_pubkey,
+            liquidity_fee_receiver_keypair,
+            payer,
+            user_accounts_owner,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn init_with_collateral_exchange_rate(
+        name: String,
+        banks_client: &mut BanksClient,
+        lending_market: &TestLendingMarket,
+        oracle: &TestOracle,
+        liquidity_amount: u64,
+        config: ReserveConfig,
+        collateral_exchange_rate_wads: u64,
+        liquidity_mint_pubkey: Pubkey,
+        user_liquidity_pubkey: Pubkey,
+        liquidity_fee_receiver_keypair: &Keypair,
+        payer: &Keypair,
+        user_accounts_owner: &Keypair,
     ) -> Result<Self, TransactionError> {
         let reserve_keypair = Keypair::new();
         let reserve_pubkey = reserve_keypair.pubkey();
@@ -945,6 +1193,7 @@ impl TestReserve {
                     solend_program::id(),
                     liquidity_amount,
                     config,
+                    collateral_exchange_rate_wads,
                     user_liquidity_pubkey,
                     user_collateral_token_keypair.pubkey(),
                     reserve_pubkey,
@@ -1086,6 +1335,7 @@ impl TestObligation {
                 ),
                 init_obligation(
                     solend_program::id(),
+                    0,
                     obligation.pubkey,
                     lending_market.pubkey,
                     user_accounts_owner.pubkey(),
@@ -1266,6 +1516,130 @@ pub fn add_usdc_oracle_switchboardv2(test: &mut ProgramTest) -> TestOracle {
     )
 }
 
+/// Adds a switchboard v2 feed with no pyth oracle, reporting `mantissa * 10^-scale`
+pub fn add_switchboardv2_oracle(test: &mut ProgramTest, mantissa: i128, scale: u32) -> TestOracle {
+    add_switchboardv2_oracle_with_heartbeat(test, mantissa, scale, 0)
+}
+
+/// Adds a switchboard v2 feed like `add_switchboardv2_oracle`, declaring a heartbeat of
+/// `heartbeat_seconds`
+pub fn add_switchboardv2_oracle_with_heartbeat(
+    test: &mut ProgramTest,
+    mantissa: i128,
+    scale: u32,
+    heartbeat_seconds: i64,
+) -> TestOracle {
+    add_switchboardv2_feed(test, mantissa, scale, 0, heartbeat_seconds)
+}
+
+/// Adds a switchboard v2 feed like `add_switchboardv2_oracle`, whose latest round has a standard
+/// deviation of `std_deviation_mantissa * 10^-scale`
+pub fn add_switchboardv2_oracle_with_std_deviation(
+    test: &mut ProgramTest,
+    mantissa: i128,
+    scale: u32,
+    std_deviation_mantissa: i128,
+) -> TestOracle {
+    add_switchboardv2_feed(test, mantissa, scale, std_deviation_mantissa, 0)
+}
+
+fn add_switchboardv2_feed(
+    test: &mut ProgramTest,
+    mantissa: i128,
+    scale: u32,
+    std_deviation_mantissa: i128,
+    heartbeat_seconds: i64,
+) -> TestOracle {
+    let switchboard_feed_pubkey = Pubkey::new_unique();
+    let filename = &format!("{}.bin", SOL_SWITCHBOARDV2_FEED);
+    let mut switchboard_feed_data = read_file(find_file(filename).unwrap_or_else(|| {
+        panic!("Unable to locate {}", filename);
+    }));
+
+    let agg_state =
+        bytemuck::from_bytes_mut::<AggregatorAccountData>(&mut switchboard_feed_data[8..]);
+    agg_state.latest_confirmed_round.round_open_slot = 0;
+    agg_state.latest_confirmed_round.result = SwitchboardDecimal { mantissa, scale };
+    agg_state.latest_confirmed_round.std_deviation = SwitchboardDecimal {
+        mantissa: std_deviation_mantissa,
+        scale,
+    };
+    agg_state.force_report_period = heartbeat_seconds;
+    test.add_account(
+        switchboard_feed_pubkey,
+        Account {
+            lamports: u32::MAX as u64,
+            data: switchboard_feed_data,
+            owner: switchboard_v2_mainnet::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    TestOracle {
+        pyth_product_pubkey: Pubkey::from_str(NULL_PUBKEY).unwrap(),
+        pyth_price_pubkey: Pubkey::from_str(NULL_PUBKEY).unwrap(),
+        switchboard_feed_pubkey,
+        price: Decimal::from(mantissa.max(0) as u128)
+            .try_div(Decimal::from(10u128.pow(scale)))
+            .unwrap(),
+    }
+}
+
+/// Adds a pyth product and price account pair with no switchboard feed, the price account
+/// reporting `price` as last published at `pub_slot`
+pub fn add_pyth_oracle(test: &mut ProgramTest, price: u64, pub_slot: u64) -> TestOracle {
+    let oracle_program_id = read_keypair_file("tests/fixtures/oracle_program_id.json").unwrap();
+    let pyth_product_pubkey = Pubkey::new_unique();
+    let pyth_price_pubkey = Pubkey::new_unique();
+
+    let filename = &format!("{}.bin", SOL_PYTH_PRODUCT);
+    let mut pyth_product_data = read_file(find_file(filename).unwrap_or_else(|| {
+        panic!("Unable to locate {}", filename);
+    }));
+    load_mut::<ProductAccount>(pyth_product_data.as_mut_slice())
+        .unwrap()
+        .px_acc = pyth_price_pubkey;
+
+    let filename = &format!("{}.bin", SOL_PYTH_PRICE);
+    let mut pyth_price_data = read_file(find_file(filename).unwrap_or_else(|| {
+        panic!("Unable to locate {}", filename);
+    }));
+    let pyth_price = load_mut::<PriceAccount>(pyth_price_data.as_mut_slice()).unwrap();
+    let decimals = 10u64
+        .checked_pow(pyth_price.expo.checked_abs().unwrap().try_into().unwrap())
+        .unwrap();
+    pyth_price.valid_slot = pub_slot;
+    pyth_price.agg.price = price.checked_mul(decimals).unwrap().try_into().unwrap();
+    pyth_price.agg.conf = 0;
+    pyth_price.agg.status = PriceStatus::Trading;
+    pyth_price.agg.pub_slot = pub_slot;
+    pyth_price.prev_slot = 0;
+
+    for (pubkey, data) in [
+        (pyth_product_pubkey, pyth_product_data),
+        (pyth_price_pubkey, pyth_price_data),
+    ] {
+        test.add_account(
+            pubkey,
+            Account {
+                lamports: u32::MAX as u64,
+                data,
+                owner: oracle_program_id.pubkey(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    TestOracle {
+        pyth_product_pubkey,
+        pyth_price_pubkey,
+        switchboard_feed_pubkey: Pubkey::from_str(NULL_PUBKEY).unwrap(),
+        price: Decimal::from(price),
+    }
+}
+
 pub fn load_mut<T: Pod>(data: &mut [u8]) -> Result<&mut T, PodCastError> {
     let size = size_of::<T>();
     Ok(from_bytes_mut(cast_slice_mut::<u8, u8>(
@@ -1280,6 +1654,28 @@ pub fn add_oracle(
     switchboard_feed_pubkey: Pubkey,
     price: Decimal,
     valid_slot: u64,
+) -> TestOracle {
+    add_oracle_with_ema_price(
+        test,
+        pyth_product_pubkey,
+        pyth_price_pubkey,
+        switchboard_feed_pubkey,
+        price,
+        price,
+        valid_slot,
+    )
+}
+
+/// Adds oracles like `add_oracle`, with the Pyth feed's exponential moving average reporting
+/// `ema_price` rather than the aggregate price
+pub fn add_oracle_with_ema_price(
+    test: &mut ProgramTest,
+    pyth_product_pubkey: Pubkey,
+    pyth_price_pubkey: Pubkey,
+    switchboard_feed_pubkey: Pubkey,
+    price: Decimal,
+    ema_price: Decimal,
+    valid_slot: u64,
 ) -> TestOracle {
     let oracle_program_id = read_keypair_file("tests/fixtures/oracle_program_id.json").unwrap();
 
@@ -1315,6 +1711,18 @@ pub fn add_oracle(
             .unwrap();
 
         pyth_price.agg.pub_slot = valid_slot;
+        pyth_price.ema_price.val = ema_price
+            .try_round_u64()
+            .unwrap()
+            .checked_mul(decimals)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        pyth_price.ema_price.numer = pyth_price.ema_price.val;
+        pyth_price.ema_price.denom = 1;
+        pyth_price.ema_conf.val = pyth_price.agg.conf.try_into().unwrap();
+        pyth_price.ema_conf.numer = pyth_price.ema_conf.val;
+        pyth_price.ema_conf.denom = 1;
         pyth_price.timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -1346,6 +1754,10 @@ pub fn add_oracle(
         let agg_state =
             bytemuck::from_bytes_mut::<AggregatorAccountData>(&mut switchboard_feed_data[8..]);
         agg_state.latest_confirmed_round.round_open_slot = 0;
+        agg_state.latest_confirmed_round.result = SwitchboardDecimal {
+            mantissa: price.try_round_u64().unwrap().into(),
+            scale: 0,
+        };
         test.add_account(
             switchboard_feed_pubkey,
             Account {
@@ -0,0 +1,77 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Forwards the instruction data to the target program and copies whatever it returns into
+/// the destination account, so tests can read return data back out of the bank.
+///
+/// Accounts:
+///   0. `[writable]` Destination account, owned by this program.
+///   1. `[]` Target program id.
+///   .. Accounts forwarded to the target program.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let destination_info = next_account_info(account_info_iter)?;
+    let target_program_info = next_account_info(account_info_iter)?;
+    if destination_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let forwarded_accounts: Vec<AccountInfo> = account_info_iter.cloned().collect();
+    let mut invoked_accounts = forwarded_accounts.clone();
+    invoked_accounts.push(target_program_info.clone());
+    invoke(
+        &Instruction {
+            program_id: *target_program_info.key,
+            accounts: forwarded_accounts
+                .iter()
+                .map(|a| AccountMeta {
+                    pubkey: *a.key,
+                    is_signer: a.is_signer,
+                    is_writable: a.is_writable,
+                })
+                .collect(),
+            data: instruction_data.to_vec(),
+        },
+        &invoked_accounts,
+    )?;
+
+    let (returning_program_id, data) = get_return_data().ok_or(ProgramError::InvalidArgument)?;
+    if returning_program_id != *target_program_info.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+    destination_info
+        .try_borrow_mut_data()?
+        .get_mut(..data.len())
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .copy_from_slice(&data);
+
+    Ok(())
+}
+
+/// Wraps an instruction so its return data is captured in `destination_pubkey`.
+pub fn read_return_data(
+    program_id: Pubkey,
+    destination_pubkey: Pubkey,
+    instruction: Instruction,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(instruction.program_id, false),
+    ];
+    accounts.extend(instruction.accounts);
+    Instruction {
+        program_id,
+        accounts,
+        data: instruction.data,
+    }
+}
@@ -65,6 +65,7 @@ async fn test_already_initialized() {
     let mut transaction = Transaction::new_with_payer(
         &[init_obligation(
             solend_program::id(),
+            0,
             usdc_obligation.pubkey,
             lending_market.pubkey,
             user_accounts_owner.pubkey(),
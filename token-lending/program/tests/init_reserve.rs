@@ -3,19 +3,25 @@
 mod helpers;
 
 use helpers::*;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
 use solana_program_test::*;
 use solana_sdk::{
     instruction::InstructionError,
     signature::{Keypair, Signer},
+    system_instruction::create_account,
     transaction::{Transaction, TransactionError},
 };
 use solend_program::{
     error::LendingError,
     instruction::{init_reserve, update_reserve_config},
-    math::Decimal,
+    math::{Decimal, WAD},
     processor::process_instruction,
-    state::{ReserveConfig, ReserveFees, INITIAL_COLLATERAL_RATIO},
+    state::{
+        Reserve, ReserveConfig, ReserveFees, ReserveType, INITIAL_COLLATERAL_RATIO,
+        MAX_INITIAL_COLLATERAL_RATE, MAX_TOTAL_FEE_WAD,
+    },
 };
+use spl_token::state::{Account as Token, Mint};
 
 #[tokio::test]
 async fn test_success() {
@@ -90,6 +96,159 @@ async fn test_success() {
     );
 }
 
+#[tokio::test]
+async fn test_success_collateral_exchange_rate() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+    let sol_oracle = add_sol_oracle(&mut test);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: _recent_blockhash,
+        ..
+    } = test_context;
+
+    const RESERVE_AMOUNT: u64 = 42;
+    const DEPOSIT_AMOUNT: u64 = 10;
+    // 2 collateral tokens for every liquidity token
+    const COLLATERAL_EXCHANGE_RATE_WADS: u64 = 2 * WAD;
+
+    let sol_user_liquidity_account = create_and_mint_to_token_account(
+        &mut banks_client,
+        spl_token::native_mint::id(),
+        None,
+        &payer,
+        user_accounts_owner.pubkey(),
+        RESERVE_AMOUNT + DEPOSIT_AMOUNT,
+    )
+    .await;
+
+    let mut config = test_reserve_config();
+    let fee_receiver_keypair = Keypair::new();
+    config.fee_receiver = fee_receiver_keypair.pubkey();
+
+    let sol_reserve = TestReserve::init_with_collateral_exchange_rate(
+        "sol".to_owned(),
+        &mut banks_client,
+        &lending_market,
+        &sol_oracle,
+        RESERVE_AMOUNT,
+        config,
+        COLLATERAL_EXCHANGE_RATE_WADS,
+        spl_token::native_mint::id(),
+        sol_user_liquidity_account,
+        &fee_receiver_keypair,
+        &payer,
+        &user_accounts_owner,
+    )
+    .await
+    .unwrap();
+
+    let reserve = sol_reserve.get_state(&mut banks_client).await;
+    assert_eq!(reserve.liquidity.available_amount, RESERVE_AMOUNT);
+    assert_eq!(reserve.collateral.mint_total_supply, 2 * RESERVE_AMOUNT);
+    let collateral_mint_account = banks_client
+        .get_account(sol_reserve.collateral_mint_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    let collateral_mint = Mint::unpack(&collateral_mint_account.data[..]).unwrap();
+    assert_eq!(collateral_mint.supply, reserve.collateral.mint_total_supply);
+    let user_sol_collateral_balance =
+        get_token_balance(&mut banks_client, sol_reserve.user_collateral_pubkey).await;
+    assert_eq!(user_sol_collateral_balance, 2 * RESERVE_AMOUNT);
+
+    // later deposits mint collateral at the rate the reserve started at
+    lending_market
+        .deposit(
+            &mut banks_client,
+            &user_accounts_owner,
+            &payer,
+            &sol_reserve,
+            DEPOSIT_AMOUNT,
+        )
+        .await;
+
+    let user_sol_collateral_balance =
+        get_token_balance(&mut banks_client, sol_reserve.user_collateral_pubkey).await;
+    assert_eq!(
+        user_sol_collateral_balance,
+        2 * (RESERVE_AMOUNT + DEPOSIT_AMOUNT)
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_collateral_exchange_rate() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+    let sol_oracle = add_sol_oracle(&mut test);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: _recent_blockhash,
+        ..
+    } = test_context;
+
+    const RESERVE_AMOUNT: u64 = 42;
+
+    let sol_user_liquidity_account = create_and_mint_to_token_account(
+        &mut banks_client,
+        spl_token::native_mint::id(),
+        None,
+        &payer,
+        user_accounts_owner.pubkey(),
+        RESERVE_AMOUNT,
+    )
+    .await;
+
+    let mut config = test_reserve_config();
+    let fee_receiver_keypair = Keypair::new();
+    config.fee_receiver = fee_receiver_keypair.pubkey();
+
+    assert_eq!(
+        TestReserve::init_with_collateral_exchange_rate(
+            "sol".to_owned(),
+            &mut banks_client,
+            &lending_market,
+            &sol_oracle,
+            RESERVE_AMOUNT,
+            config,
+            MAX_INITIAL_COLLATERAL_RATE + 1,
+            spl_token::native_mint::id(),
+            sol_user_liquidity_account,
+            &fee_receiver_keypair,
+            &payer,
+            &user_accounts_owner,
+        )
+        .await
+        .unwrap_err(),
+        TransactionError::InstructionError(
+            8,
+            InstructionError::Custom(LendingError::InvalidConfig as u32)
+        )
+    );
+}
+
 #[tokio::test]
 async fn test_init_reserve_null_oracles() {
     let mut test = ProgramTest::new(
@@ -260,6 +419,7 @@ async fn test_already_initialized() {
             solend_program::id(),
             42,
             usdc_test_reserve.config,
+            0,
             usdc_test_reserve.user_liquidity_pubkey,
             usdc_test_reserve.user_collateral_pubkey,
             usdc_test_reserve.pubkey,
@@ -293,6 +453,78 @@ async fn test_already_initialized() {
     );
 }
 
+#[tokio::test]
+async fn test_fees_at_cap() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+    let sol_oracle = add_sol_oracle(&mut test);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        ..
+    } = test_context;
+
+    const RESERVE_AMOUNT: u64 = 42;
+
+    let sol_user_liquidity_account = create_and_mint_to_token_account(
+        &mut banks_client,
+        spl_token::native_mint::id(),
+        None,
+        &payer,
+        user_accounts_owner.pubkey(),
+        2 * RESERVE_AMOUNT,
+    )
+    .await;
+
+    for fees in [
+        // combined fees exactly at the cap
+        ReserveFees {
+            borrow_fee_wad: MAX_TOTAL_FEE_WAD / 2,
+            flash_loan_fee_wad: MAX_TOTAL_FEE_WAD / 2,
+            host_fee_percentage: 20,
+        },
+        // a disabled flash loan fee doesn't count towards the cap
+        ReserveFees {
+            borrow_fee_wad: MAX_TOTAL_FEE_WAD,
+            flash_loan_fee_wad: u64::MAX,
+            host_fee_percentage: 20,
+        },
+    ] {
+        let mut config = test_reserve_config();
+        config.fees = fees;
+        let fee_receiver_keypair = Keypair::new();
+        config.fee_receiver = fee_receiver_keypair.pubkey();
+
+        let test_reserve = TestReserve::init(
+            "sol".to_owned(),
+            &mut banks_client,
+            &lending_market,
+            &sol_oracle,
+            RESERVE_AMOUNT,
+            config,
+            spl_token::native_mint::id(),
+            sol_user_liquidity_account,
+            &fee_receiver_keypair,
+            &payer,
+            &user_accounts_owner,
+        )
+        .await
+        .unwrap();
+        let reserve = test_reserve.get_state(&mut banks_client).await;
+        assert_eq!(reserve.config.fees, fees);
+    }
+}
+
 #[tokio::test]
 async fn test_invalid_fees() {
     let mut test = ProgramTest::new(
@@ -354,6 +586,40 @@ async fn test_invalid_fees() {
         );
     }
 
+    // each fee is under the cap on its own but not combined
+    {
+        let mut config = test_reserve_config();
+        config.fees = ReserveFees {
+            borrow_fee_wad: MAX_TOTAL_FEE_WAD / 2,
+            flash_loan_fee_wad: MAX_TOTAL_FEE_WAD / 2 + 1,
+            host_fee_percentage: 0,
+        };
+        let fee_receiver_keypair = Keypair::new();
+        config.fee_receiver = fee_receiver_keypair.pubkey();
+
+        assert_eq!(
+            TestReserve::init(
+                "sol".to_owned(),
+                &mut banks_client,
+                &lending_market,
+                &sol_oracle,
+                RESERVE_AMOUNT,
+                config,
+                spl_token::native_mint::id(),
+                sol_user_liquidity_account,
+                &fee_receiver_keypair,
+                &payer,
+                &user_accounts_owner,
+            )
+            .await
+            .unwrap_err(),
+            TransactionError::InstructionError(
+                8,
+                InstructionError::Custom(LendingError::InvalidConfig as u32)
+            )
+        );
+    }
+
     // host fee above 100%
     {
         let mut config = test_reserve_config();
@@ -419,9 +685,9 @@ async fn test_update_reserve_config() {
     // Update the reserve config
     let new_config: ReserveConfig = ReserveConfig {
         optimal_utilization_rate: 75,
-        loan_to_value_ratio: 45,
+        loan_to_value_ratio: 50,
         liquidation_bonus: 10,
-        liquidation_threshold: 65,
+        liquidation_threshold: 55,
         min_borrow_rate: 1,
         optimal_borrow_rate: 5,
         max_borrow_rate: 45,
@@ -435,6 +701,33 @@ async fn test_update_reserve_config() {
         fee_receiver: Keypair::new().pubkey(),
         protocol_liquidation_fee: 30,
         protocol_take_rate: 10,
+        liquidation_threshold_ramp_slots: 1000,
+        protocol_fees_cap: 50,
+        permissioned: true,
+        allow_zero_price: false,
+        max_unchanged_price_slots: 0,
+        auto_refresh: false,
+        usable_as_collateral: true,
+        max_borrow_price_increase_pct: 0,
+        tranche: 0,
+        extreme_utilization_rate: 90,
+        extreme_borrow_rate: 300,
+        use_ema_price: true,
+        liquidation_close_factor: 50,
+        round_supplier_interest_up: true,
+        max_price_confidence_bps: 200,
+        reject_self_borrow: true,
+        max_stale_price_slots: 100,
+        stale_price_decay_bps: 10,
+        fixed_price: 0,
+        max_liquidation_bonus: 0,
+        pause_interest_on_oracle_error: true,
+        reserve_type: ReserveType::Isolated,
+        strategy_vault: Pubkey::new_unique(),
+        max_deployed_pct: 50,
+        max_price_age_slots: 120,
+        max_liquidation_price_age_slots: 5,
+        fractional_limits: true,
     };
 
     let (mut banks_client, payer, recent_blockhash) = test.start().await;
@@ -571,3 +864,127 @@ async fn test_update_invalid_oracle_config() {
         )
     );
 }
+
+#[tokio::test]
+async fn test_same_liquidity_mint_in_two_markets() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market_a = add_lending_market(&mut test);
+    let lending_market_b = add_lending_market(&mut test);
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let reserve_args = || AddReserveArgs {
+        liquidity_amount: 42,
+        liquidity_mint_decimals: usdc_mint.decimals,
+        liquidity_mint_pubkey: usdc_mint.pubkey,
+        user_liquidity_amount: 42,
+        config: test_reserve_config(),
+        ..AddReserveArgs::default()
+    };
+    let usdc_reserve_a = add_reserve(
+        &mut test,
+        &lending_market_a,
+        &usdc_oracle,
+        &user_accounts_owner,
+        reserve_args(),
+    );
+    let usdc_reserve_b = add_reserve(
+        &mut test,
+        &lending_market_b,
+        &usdc_oracle,
+        &user_accounts_owner,
+        reserve_args(),
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    assert_ne!(lending_market_a.authority, lending_market_b.authority);
+    for (reserve, lending_market) in [
+        (&usdc_reserve_a, &lending_market_a),
+        (&usdc_reserve_b, &lending_market_b),
+    ] {
+        for vault in [
+            reserve.liquidity_supply_pubkey,
+            reserve.collateral_supply_pubkey,
+        ] {
+            let account = banks_client.get_account(vault).await.unwrap().unwrap();
+            let token = Token::unpack(&account.data[..]).unwrap();
+            assert_eq!(token.owner, lending_market.authority);
+        }
+    }
+    let vaults = [
+        usdc_reserve_a.liquidity_supply_pubkey,
+        usdc_reserve_a.collateral_mint_pubkey,
+        usdc_reserve_a.collateral_supply_pubkey,
+        usdc_reserve_a.config.fee_receiver,
+        usdc_reserve_b.liquidity_supply_pubkey,
+        usdc_reserve_b.collateral_mint_pubkey,
+        usdc_reserve_b.collateral_supply_pubkey,
+        usdc_reserve_b.config.fee_receiver,
+    ];
+    for (i, vault) in vaults.iter().enumerate() {
+        assert!(!vaults[i + 1..].contains(vault));
+    }
+
+    // a second reserve in market b cannot take over the liquidity supply of market a's reserve
+    let reserve_keypair = Keypair::new();
+    let user_transfer_authority = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            create_account(
+                &payer.pubkey(),
+                &reserve_keypair.pubkey(),
+                rent.minimum_balance(Reserve::LEN),
+                Reserve::LEN as u64,
+                &solend_program::id(),
+            ),
+            init_reserve(
+                solend_program::id(),
+                42,
+                usdc_reserve_b.config,
+                0,
+                usdc_reserve_b.user_liquidity_pubkey,
+                usdc_reserve_b.user_collateral_pubkey,
+                reserve_keypair.pubkey(),
+                usdc_mint.pubkey,
+                usdc_reserve_a.liquidity_supply_pubkey,
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                usdc_oracle.pyth_product_pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+                lending_market_b.pubkey,
+                lending_market_b.owner.pubkey(),
+                user_transfer_authority.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(
+        &[
+            &payer,
+            &reserve_keypair,
+            &lending_market_b.owner,
+            &user_transfer_authority,
+        ],
+        recent_blockhash,
+    );
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::AlreadyInitialized as u32)
+        )
+    );
+}
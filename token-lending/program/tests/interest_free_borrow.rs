@@ -0,0 +1,267 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::{refresh_obligation, repay_obligation_liquidity, update_lending_market},
+    math::{Decimal, Rate, TryAdd, TryMul},
+    processor::process_instruction,
+    state::LendingMarketConfigUpdate,
+};
+use spl_token::instruction::approve;
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // obligations borrowing less than $1 don't accrue interest
+    const INTEREST_FREE_BORROW_VALUE_USD: u64 = 1;
+    const SMALL_BORROW_AMOUNT_FRACTIONAL: u64 = FRACTIONAL_TO_USDC / 2;
+    const LARGE_BORROW_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+    const USDC_RESERVE_BORROW_RATE: u8 = 110;
+    // the reserve has already accrued 110% interest on both borrows
+    const USDC_RESERVE_BORROW_AMOUNT_FRACTIONAL: u64 =
+        (SMALL_BORROW_AMOUNT_FRACTIONAL + LARGE_BORROW_AMOUNT_FRACTIONAL) * 21 / 10;
+
+    let user_accounts_owner = Keypair::new();
+    let user_transfer_authority = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_RESERVE_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: FRACTIONAL_TO_USDC,
+            liquidity_amount: USDC_RESERVE_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            initial_borrow_rate: USDC_RESERVE_BORROW_RATE,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let small_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            borrows: &[(&usdc_test_reserve, SMALL_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+    let large_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            borrows: &[(&usdc_test_reserve, LARGE_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let refresh = |obligation: &TestObligation| {
+        refresh_obligation(
+            solend_program::id(),
+            obligation.pubkey,
+            vec![usdc_test_reserve.pubkey],
+            lending_market.pubkey,
+        )
+    };
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            update_lending_market(
+                solend_program::id(),
+                LendingMarketConfigUpdate {
+                    interest_free_borrow_value_usd: Some(INTEREST_FREE_BORROW_VALUE_USD),
+                    ..LendingMarketConfigUpdate::default()
+                },
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                vec![],
+            ),
+            refresh(&small_obligation),
+            refresh(&large_obligation),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let interest_rate = Rate::one()
+        .try_add(Rate::from_percent(USDC_RESERVE_BORROW_RATE))
+        .unwrap();
+
+    let small = small_obligation.get_state(&mut banks_client).await;
+    assert_eq!(
+        small.borrows[0].borrowed_amount_wads,
+        Decimal::from(SMALL_BORROW_AMOUNT_FRACTIONAL)
+    );
+    assert_eq!(
+        small.borrows[0].forgiven_interest_wads,
+        Decimal::from(SMALL_BORROW_AMOUNT_FRACTIONAL * 11 / 10)
+    );
+
+    let large = large_obligation.get_state(&mut banks_client).await;
+    assert_eq!(
+        large.borrows[0].borrowed_amount_wads,
+        Decimal::from(LARGE_BORROW_AMOUNT_FRACTIONAL)
+            .try_mul(interest_rate)
+            .unwrap()
+    );
+    assert_eq!(large.borrows[0].forgiven_interest_wads, Decimal::zero());
+
+    // the reserve keeps counting the forgiven interest until the borrow is repaid
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(
+        usdc_reserve.liquidity.borrowed_amount_wads,
+        small.borrows[0]
+            .borrowed_amount_wads
+            .try_add(small.borrows[0].forgiven_interest_wads)
+            .unwrap()
+            .try_add(large.borrows[0].borrowed_amount_wads)
+            .unwrap()
+    );
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            approve(
+                &spl_token::id(),
+                &usdc_test_reserve.user_liquidity_pubkey,
+                &user_transfer_authority.pubkey(),
+                &user_accounts_owner.pubkey(),
+                &[],
+                SMALL_BORROW_AMOUNT_FRACTIONAL,
+            )
+            .unwrap(),
+            repay_obligation_liquidity(
+                solend_program::id(),
+                u64::MAX,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                usdc_test_reserve.pubkey,
+                small_obligation.pubkey,
+                lending_market.pubkey,
+                user_transfer_authority.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(
+        &[&payer, &user_accounts_owner, &user_transfer_authority],
+        recent_blockhash,
+    );
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    // repaying the small borrow writes its forgiven interest off the reserve
+    let small = small_obligation.get_state(&mut banks_client).await;
+    assert!(small.borrows.is_empty());
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(
+        usdc_reserve.liquidity.borrowed_amount_wads,
+        large.borrows[0].borrowed_amount_wads
+    );
+}
+
+#[tokio::test]
+async fn test_accrues_without_lending_market() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SMALL_BORROW_AMOUNT_FRACTIONAL: u64 = FRACTIONAL_TO_USDC / 2;
+    const USDC_RESERVE_BORROW_RATE: u8 = 110;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: SMALL_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: FRACTIONAL_TO_USDC,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            initial_borrow_rate: USDC_RESERVE_BORROW_RATE,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            borrows: &[(&usdc_test_reserve, SMALL_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // the lending market account is optional, without it the allowance doesn't apply
+    let mut refresh_obligation_instruction = refresh_obligation(
+        solend_program::id(),
+        test_obligation.pubkey,
+        vec![usdc_test_reserve.pubkey],
+        lending_market.pubkey,
+    );
+    refresh_obligation_instruction.accounts.pop();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            update_lending_market(
+                solend_program::id(),
+                LendingMarketConfigUpdate {
+                    interest_free_borrow_value_usd: Some(1),
+                    ..LendingMarketConfigUpdate::default()
+                },
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                vec![],
+            ),
+            refresh_obligation_instruction,
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(
+        obligation.borrows[0].borrowed_amount_wads,
+        Decimal::from(SMALL_BORROW_AMOUNT_FRACTIONAL * 21 / 10)
+    );
+    assert_eq!(
+        obligation.borrows[0].forgiven_interest_wads,
+        Decimal::zero()
+    );
+}
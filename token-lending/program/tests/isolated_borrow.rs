@@ -0,0 +1,70 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError, transaction::TransactionError, transport::TransportError,
+};
+use solend_program::{error::LendingError, state::ReserveType};
+
+/// Adds SOL collateral and two reserves to borrow from, regular USDC and isolated SRM
+async fn setup() -> SolCollateralTestSetup {
+    let mut srm_reserve_config = test_reserve_config();
+    srm_reserve_config.reserve_type = ReserveType::Isolated;
+
+    setup_sol_collateral(test_reserve_config(), srm_reserve_config).await
+}
+
+fn assert_isolation_violated(result: Result<(), TransportError>) {
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            4,
+            InstructionError::Custom(LendingError::IsolatedBorrowViolation as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_isolated_borrow_blocks_other_borrows() {
+    let mut setup = setup().await;
+
+    assert!(setup
+        .borrow(SRM_BORROW_RESERVE, 10 * FRACTIONAL_TO_SRM)
+        .await
+        .is_ok());
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert!(obligation.borrows[0].isolated);
+
+    assert_isolation_violated(
+        setup
+            .borrow(USDC_BORROW_RESERVE, 10 * FRACTIONAL_TO_USDC)
+            .await,
+    );
+
+    // the isolated asset itself can still be borrowed more of
+    assert!(setup
+        .borrow(SRM_BORROW_RESERVE, 20 * FRACTIONAL_TO_SRM)
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn test_fail_isolated_borrow_alongside_other_borrows() {
+    let mut setup = setup().await;
+
+    assert!(setup
+        .borrow(USDC_BORROW_RESERVE, 10 * FRACTIONAL_TO_USDC)
+        .await
+        .is_ok());
+    assert_isolation_violated(
+        setup
+            .borrow(SRM_BORROW_RESERVE, 10 * FRACTIONAL_TO_SRM)
+            .await,
+    );
+}
@@ -112,6 +112,7 @@ async fn test_fail_deprecated() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             liquidate_obligation(
                 solend_program::id(),
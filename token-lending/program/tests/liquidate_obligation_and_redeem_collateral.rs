@@ -5,13 +5,18 @@ mod helpers;
 use helpers::*;
 use solana_program_test::*;
 use solana_sdk::{
+    instruction::InstructionError,
     signature::{Keypair, Signer},
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
 };
 use solend_program::{
-    instruction::{liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation},
+    error::LendingError,
+    instruction::{
+        liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation,
+        update_lending_market,
+    },
     processor::process_instruction,
-    state::{INITIAL_COLLATERAL_RATIO, LIQUIDATION_CLOSE_FACTOR},
+    state::{LendingMarketConfigUpdate, INITIAL_COLLATERAL_RATIO, LIQUIDATION_CLOSE_FACTOR},
 };
 use std::cmp::{max, min};
 
@@ -120,6 +125,7 @@ async fn test_success() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             liquidate_obligation_and_redeem_reserve_collateral(
                 solend_program::id(),
@@ -305,6 +311,7 @@ async fn test_success_insufficent_liquidity() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             liquidate_obligation_and_redeem_reserve_collateral(
                 solend_program::id(),
@@ -389,3 +396,298 @@ async fn test_success_insufficent_liquidity() {
         (USDC_BORROW_AMOUNT_FRACTIONAL - USDC_LIQUIDATION_AMOUNT_FRACTIONAL).into()
     )
 }
+
+#[tokio::test]
+async fn test_success_bonus_capped() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // 100 SOL collateral
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    // 100 SOL * 80% LTV -> 80 SOL * 20 USDC -> 1600 USDC borrow
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_600 * FRACTIONAL_TO_USDC;
+    // 1600 USDC * 20% -> 320 USDC liquidation
+    const USDC_LIQUIDATION_AMOUNT_FRACTIONAL: u64 =
+        USDC_BORROW_AMOUNT_FRACTIONAL * (LIQUIDATION_CLOSE_FACTOR as u64) / 100;
+    // a 10% bonus would be worth $32, the market pays out at most $10
+    const MAX_LIQUIDATION_BONUS_USD: u64 = 10;
+    // 320 USDC + 10 USDC bonus / 20 USDC per SOL -> 16.5 SOL
+    const SOL_LIQUIDATION_AMOUNT_LAMPORTS: u64 =
+        LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO * 33 / 2;
+    // 10 USDC bonus / 20 USDC per SOL -> 0.5 SOL
+    const SOL_BONUS_LAMPORTS: u64 = LAMPORTS_TO_SOL / 2;
+
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_bonus = 10;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_bonus = 10;
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let initial_user_withdraw_liquidity_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.user_liquidity_pubkey).await;
+    let initial_fee_receiver_withdraw_liquidity_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.config.fee_receiver).await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            update_lending_market(
+                solend_program::id(),
+                LendingMarketConfigUpdate {
+                    max_liquidation_bonus_usd: Some(MAX_LIQUIDATION_BONUS_USD),
+                    ..LendingMarketConfigUpdate::default()
+                },
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                vec![],
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            liquidate_obligation_and_redeem_reserve_collateral(
+                solend_program::id(),
+                USDC_LIQUIDATION_AMOUNT_FRACTIONAL,
+                usdc_test_reserve.user_liquidity_pubkey,
+                sol_test_reserve.user_collateral_pubkey,
+                sol_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.pubkey,
+                sol_test_reserve.collateral_mint_pubkey,
+                sol_test_reserve.collateral_supply_pubkey,
+                sol_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.config.fee_receiver,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+
+    transaction.sign(
+        &[&payer, &lending_market.owner, &user_accounts_owner],
+        recent_blockhash,
+    );
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let lending_market_state = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(
+        lending_market_state.config.max_liquidation_bonus_usd,
+        MAX_LIQUIDATION_BONUS_USD
+    );
+
+    let user_withdraw_liquidity_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.user_liquidity_pubkey).await;
+    let fee_receiver_withdraw_liquidity_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.config.fee_receiver).await;
+    assert_eq!(
+        user_withdraw_liquidity_balance + fee_receiver_withdraw_liquidity_balance,
+        initial_user_withdraw_liquidity_balance
+            + initial_fee_receiver_withdraw_liquidity_balance
+            + SOL_LIQUIDATION_AMOUNT_LAMPORTS
+    );
+
+    assert_eq!(
+        // 30% of the capped bonus
+        SOL_BONUS_LAMPORTS * 3 / 10,
+        (fee_receiver_withdraw_liquidity_balance - initial_fee_receiver_withdraw_liquidity_balance)
+    );
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        SOL_DEPOSIT_AMOUNT_LAMPORTS - SOL_LIQUIDATION_AMOUNT_LAMPORTS
+    );
+    assert_eq!(
+        obligation.borrows[0].borrowed_amount_wads,
+        (USDC_BORROW_AMOUNT_FRACTIONAL - USDC_LIQUIDATION_AMOUNT_FRACTIONAL).into()
+    )
+}
+
+#[tokio::test]
+async fn test_fail_wrong_fee_receiver() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // 100 SOL collateral
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    // 100 SOL * 80% LTV -> 80 SOL * 20 USDC -> 1600 USDC borrow
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_600 * FRACTIONAL_TO_USDC;
+    // 1600 USDC * 20% -> 320 USDC liquidation
+    const USDC_LIQUIDATION_AMOUNT_FRACTIONAL: u64 =
+        USDC_BORROW_AMOUNT_FRACTIONAL * (LIQUIDATION_CLOSE_FACTOR as u64) / 100;
+
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_bonus = 10;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_bonus = 10;
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            liquidate_obligation_and_redeem_reserve_collateral(
+                solend_program::id(),
+                USDC_LIQUIDATION_AMOUNT_FRACTIONAL,
+                usdc_test_reserve.user_liquidity_pubkey,
+                sol_test_reserve.user_collateral_pubkey,
+                sol_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.pubkey,
+                sol_test_reserve.collateral_mint_pubkey,
+                sol_test_reserve.collateral_supply_pubkey,
+                sol_test_reserve.liquidity_supply_pubkey,
+                // the liquidator's own account stands in for the reserve's fee receiver
+                sol_test_reserve.user_liquidity_pubkey,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidFeeReceiver as u32)
+        )
+    );
+}
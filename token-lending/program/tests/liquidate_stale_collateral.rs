@@ -0,0 +1,179 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation, refresh_reserve,
+    },
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+#[tokio::test]
+async fn test_fail_stale_collateral_reserve() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // 100 SOL collateral
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    // above the 1600 USDC liquidation threshold
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_700 * FRACTIONAL_TO_USDC;
+    const USDC_LIQUIDATION_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(100).unwrap();
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let refresh_sol_reserve = refresh_reserve(
+        solend_program::id(),
+        sol_test_reserve.pubkey,
+        sol_oracle.pyth_price_pubkey,
+        sol_oracle.switchboard_feed_pubkey,
+    );
+    let refresh_usdc_reserve = refresh_reserve(
+        solend_program::id(),
+        usdc_test_reserve.pubkey,
+        usdc_oracle.pyth_price_pubkey,
+        usdc_oracle.switchboard_feed_pubkey,
+    );
+    let liquidate = liquidate_obligation_and_redeem_reserve_collateral(
+        solend_program::id(),
+        USDC_LIQUIDATION_AMOUNT_FRACTIONAL,
+        usdc_test_reserve.user_liquidity_pubkey,
+        sol_test_reserve.user_collateral_pubkey,
+        sol_test_reserve.user_liquidity_pubkey,
+        usdc_test_reserve.pubkey,
+        usdc_test_reserve.liquidity_supply_pubkey,
+        sol_test_reserve.pubkey,
+        sol_test_reserve.collateral_mint_pubkey,
+        sol_test_reserve.collateral_supply_pubkey,
+        sol_test_reserve.liquidity_supply_pubkey,
+        sol_test_reserve.config.fee_receiver,
+        test_obligation.pubkey,
+        lending_market.pubkey,
+        user_accounts_owner.pubkey(),
+    );
+
+    // only the borrow reserve is refreshed, the collateral is still valued at its old price
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_usdc_reserve.clone(), liquidate.clone()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ReserveStale as u32)
+        )
+    );
+
+    let initial_user_liquidity_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+
+    // refreshing the collateral reserve and the obligation first lets the liquidation through
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_sol_reserve,
+            refresh_usdc_reserve,
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            liquidate,
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    assert_eq!(
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await,
+        initial_user_liquidity_balance - USDC_LIQUIDATION_AMOUNT_FRACTIONAL
+    );
+}
@@ -0,0 +1,247 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::{
+        liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation, refresh_reserve,
+        update_lending_market,
+    },
+    math::Decimal,
+    processor::process_instruction,
+    state::{
+        LendingMarketConfigUpdate, INITIAL_COLLATERAL_RATIO, LIQUIDATION_BONUS_DEFERRAL_SLOTS,
+    },
+};
+
+// 100 SOL collateral at $20 and a 75% liquidation threshold -> $1500 unhealthy borrow value
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_600 * FRACTIONAL_TO_USDC;
+// 1600 USDC * 20% -> 320 USDC liquidation
+const FIRST_LIQUIDATION_AMOUNT_FRACTIONAL: u64 = 320 * FRACTIONAL_TO_USDC;
+// 1280 USDC * 20% -> 256 USDC liquidation
+const SECOND_LIQUIDATION_AMOUNT_FRACTIONAL: u64 = 256 * FRACTIONAL_TO_USDC;
+const LIQUIDATION_BONUS_CLAWBACK_PCT: u8 = 50;
+
+/// SOL received by a liquidator, including the protocol's share of the bonus
+async fn sol_balance(banks_client: &mut BanksClient, sol_test_reserve: &TestReserve) -> u64 {
+    get_token_balance(banks_client, sol_test_reserve.user_liquidity_pubkey).await
+        + get_token_balance(banks_client, sol_test_reserve.config.fee_receiver).await
+}
+
+/// Liquidate the obligation twice, the second time at `second_liquidation_slot`, returning the
+/// SOL received by each liquidation.
+async fn liquidate_twice(second_liquidation_slot: Option<u64>) -> (u64, u64) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let reserve_config = || {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.loan_to_value_ratio = 50;
+        reserve_config.liquidation_threshold = 75;
+        reserve_config.liquidation_bonus = 10;
+        reserve_config
+    };
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+
+    let liquidate = |liquidity_amount: u64| {
+        [
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            liquidate_obligation_and_redeem_reserve_collateral(
+                solend_program::id(),
+                liquidity_amount,
+                usdc_test_reserve.user_liquidity_pubkey,
+                sol_test_reserve.user_collateral_pubkey,
+                sol_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.pubkey,
+                sol_test_reserve.collateral_mint_pubkey,
+                sol_test_reserve.collateral_supply_pubkey,
+                sol_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.config.fee_receiver,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                user_accounts_owner.pubkey(),
+            ),
+        ]
+    };
+    let initial_sol_balance = sol_balance(&mut test_context.banks_client, &sol_test_reserve).await;
+
+    let mut instructions = vec![update_lending_market(
+        solend_program::id(),
+        LendingMarketConfigUpdate {
+            liquidation_bonus_clawback_pct: Some(LIQUIDATION_BONUS_CLAWBACK_PCT),
+            ..LendingMarketConfigUpdate::default()
+        },
+        lending_market.pubkey,
+        lending_market.owner.pubkey(),
+        vec![],
+    )];
+    instructions.extend(liquidate(FIRST_LIQUIDATION_AMOUNT_FRACTIONAL));
+    let mut transaction =
+        Transaction::new_with_payer(&instructions, Some(&test_context.payer.pubkey()));
+    transaction.sign(
+        &[
+            &test_context.payer,
+            &lending_market.owner,
+            &user_accounts_owner,
+        ],
+        test_context.last_blockhash,
+    );
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    // the first liquidation leaves the obligation unhealthy
+    let obligation = test_obligation
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(
+        obligation.deferred_liquidation_bonus_value,
+        Decimal::from(16u64)
+    );
+
+    let first_sol_balance = sol_balance(&mut test_context.banks_client, &sol_test_reserve).await;
+
+    if let Some(slot) = second_liquidation_slot {
+        test_context.warp_to_slot(slot).unwrap();
+    }
+
+    let mut transaction = Transaction::new_with_payer(
+        &liquidate(SECOND_LIQUIDATION_AMOUNT_FRACTIONAL),
+        Some(&test_context.payer.pubkey()),
+    );
+    let recent_blockhash = test_context
+        .banks_client
+        .get_latest_blockhash()
+        .await
+        .unwrap();
+    transaction.sign(
+        &[&test_context.payer, &user_accounts_owner],
+        recent_blockhash,
+    );
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let obligation = test_obligation
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(obligation.deferred_liquidation_bonus_value, Decimal::zero());
+
+    let second_sol_balance = sol_balance(&mut test_context.banks_client, &sol_test_reserve).await;
+
+    (
+        first_sol_balance - initial_sol_balance,
+        second_sol_balance - first_sol_balance,
+    )
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (first_liquidation_lamports, second_liquidation_lamports) = liquidate_twice(None).await;
+
+    // 320 USDC + half of the 32 USDC bonus / 20 USDC per SOL -> 16.8 SOL
+    assert_eq!(first_liquidation_lamports, LAMPORTS_TO_SOL * 168 / 10);
+    // the second liquidation restores health:
+    // 256 USDC + 25.6 USDC bonus + 16 USDC withheld / 20 USDC per SOL -> 14.88 SOL, rounded down
+    assert_eq!(
+        second_liquidation_lamports,
+        LAMPORTS_TO_SOL * 1488 / 100 - 1
+    );
+}
+
+#[tokio::test]
+async fn test_success_deferral_expired() {
+    let (first_liquidation_lamports, second_liquidation_lamports) =
+        liquidate_twice(Some(2 + LIQUIDATION_BONUS_DEFERRAL_SLOTS)).await;
+
+    assert_eq!(first_liquidation_lamports, LAMPORTS_TO_SOL * 168 / 10);
+    // 256 USDC + 25.6 USDC bonus / 20 USDC per SOL -> 14.08 SOL, rounded down
+    assert_eq!(
+        second_liquidation_lamports,
+        LAMPORTS_TO_SOL * 1408 / 100 - 1
+    );
+}
@@ -0,0 +1,174 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::{liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation},
+    processor::process_instruction,
+    state::{INITIAL_COLLATERAL_RATIO, LIQUIDATION_CLOSE_FACTOR},
+};
+
+// 100 SOL collateral worth $2,000, unhealthy once $1,600 is borrowed against it
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+
+/// Liquidates as much of an obligation borrowing `usdc_borrow_amount` against 100 SOL as a
+/// single liquidation can, returning the collateral withdrawn and the protocol's cut of the bonus
+async fn liquidate(usdc_borrow_amount: u64) -> (u64, u64) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let usdc_liquidation_amount = usdc_borrow_amount * (LIQUIDATION_CLOSE_FACTOR as u64) / 100;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    // a 10% bonus at the liquidation threshold, ramping up to 20% once insolvent
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_bonus = 10;
+    reserve_config.max_liquidation_bonus = 20;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: usdc_borrow_amount,
+            user_liquidity_amount: usdc_borrow_amount,
+            liquidity_amount: 2 * usdc_borrow_amount,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, usdc_borrow_amount)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let initial_collateral_supply_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.collateral_supply_pubkey).await;
+    let initial_fee_receiver_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.config.fee_receiver).await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            liquidate_obligation_and_redeem_reserve_collateral(
+                solend_program::id(),
+                usdc_liquidation_amount,
+                usdc_test_reserve.user_liquidity_pubkey,
+                sol_test_reserve.user_collateral_pubkey,
+                sol_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.pubkey,
+                sol_test_reserve.collateral_mint_pubkey,
+                sol_test_reserve.collateral_supply_pubkey,
+                sol_test_reserve.liquidity_supply_pubkey,
+                sol_test_reserve.config.fee_receiver,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let collateral_supply_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.collateral_supply_pubkey).await;
+    let fee_receiver_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.config.fee_receiver).await;
+    (
+        initial_collateral_supply_balance - collateral_supply_balance,
+        fee_receiver_balance - initial_fee_receiver_balance,
+    )
+}
+
+#[tokio::test]
+async fn test_min_bonus_at_liquidation_threshold() {
+    // 1600 USDC * 20% -> 320 USDC / 20 USDC per SOL -> 16 SOL + 10% bonus -> 17.6 SOL
+    let (withdrawn_collateral, protocol_fee) = liquidate(1_600 * FRACTIONAL_TO_USDC).await;
+    assert_eq!(
+        withdrawn_collateral,
+        17_600_000_000 * INITIAL_COLLATERAL_RATIO
+    );
+    // 30% of the 1.6 SOL bonus
+    assert_eq!(protocol_fee, 480_000_000);
+}
+
+#[tokio::test]
+async fn test_max_bonus_when_insolvent() {
+    // 2000 USDC * 20% -> 400 USDC / 20 USDC per SOL -> 20 SOL + 20% bonus -> 24 SOL
+    let (withdrawn_collateral, protocol_fee) = liquidate(2_000 * FRACTIONAL_TO_USDC).await;
+    assert_eq!(
+        withdrawn_collateral,
+        24_000_000_000 * INITIAL_COLLATERAL_RATIO
+    );
+    // 30% of the 4 SOL bonus
+    assert_eq!(protocol_fee, 1_200_000_000);
+}
+
+#[tokio::test]
+async fn test_ramped_bonus_when_underwater() {
+    // halfway from the $1,600 threshold to insolvency at $2,000
+    // 1800 USDC * 20% -> 360 USDC / 20 USDC per SOL -> 18 SOL + 15% bonus -> 20.7 SOL
+    let (withdrawn_collateral, protocol_fee) = liquidate(1_800 * FRACTIONAL_TO_USDC).await;
+    assert_eq!(
+        withdrawn_collateral,
+        20_700_000_000 * INITIAL_COLLATERAL_RATIO
+    );
+    // 30% of the 2.7 SOL bonus
+    assert_eq!(protocol_fee, 810_000_000);
+}
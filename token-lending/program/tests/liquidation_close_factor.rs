@@ -0,0 +1,173 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::{
+        liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation, refresh_reserve,
+    },
+    processor::process_instruction,
+    state::{INITIAL_COLLATERAL_RATIO, LIQUIDATION_CLOSE_FACTOR},
+};
+
+/// Liquidates as much as possible of an obligation borrowing 1700 USDC against 100 SOL and returns
+/// the amount of USDC repaid
+async fn liquidate_max_with_close_factor(liquidation_close_factor: u8) -> u64 {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // 100 SOL collateral
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    // above the 1600 USDC liquidation threshold
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_700 * FRACTIONAL_TO_USDC;
+
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_close_factor = liquidation_close_factor;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let refresh_sol_reserve = refresh_reserve(
+        solend_program::id(),
+        sol_test_reserve.pubkey,
+        sol_oracle.pyth_price_pubkey,
+        sol_oracle.switchboard_feed_pubkey,
+    );
+    let refresh_usdc_reserve = refresh_reserve(
+        solend_program::id(),
+        usdc_test_reserve.pubkey,
+        usdc_oracle.pyth_price_pubkey,
+        usdc_oracle.switchboard_feed_pubkey,
+    );
+    let liquidate = liquidate_obligation_and_redeem_reserve_collateral(
+        solend_program::id(),
+        u64::MAX,
+        usdc_test_reserve.user_liquidity_pubkey,
+        sol_test_reserve.user_collateral_pubkey,
+        sol_test_reserve.user_liquidity_pubkey,
+        usdc_test_reserve.pubkey,
+        usdc_test_reserve.liquidity_supply_pubkey,
+        sol_test_reserve.pubkey,
+        sol_test_reserve.collateral_mint_pubkey,
+        sol_test_reserve.collateral_supply_pubkey,
+        sol_test_reserve.liquidity_supply_pubkey,
+        sol_test_reserve.config.fee_receiver,
+        test_obligation.pubkey,
+        lending_market.pubkey,
+        user_accounts_owner.pubkey(),
+    );
+
+    let initial_user_liquidity_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_sol_reserve,
+            refresh_usdc_reserve,
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            liquidate,
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    initial_user_liquidity_balance
+        - get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await
+}
+
+#[tokio::test]
+async fn test_default_close_factor() {
+    assert_eq!(
+        liquidate_max_with_close_factor(LIQUIDATION_CLOSE_FACTOR).await,
+        340 * FRACTIONAL_TO_USDC
+    );
+}
+
+#[tokio::test]
+async fn test_half_close_factor() {
+    assert_eq!(
+        liquidate_max_with_close_factor(50).await,
+        850 * FRACTIONAL_TO_USDC
+    );
+}
+
+#[tokio::test]
+async fn test_full_close_factor() {
+    assert_eq!(
+        liquidate_max_with_close_factor(100).await,
+        1_700 * FRACTIONAL_TO_USDC
+    );
+}
@@ -0,0 +1,266 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation, refresh_reserve,
+        update_lending_market,
+    },
+    processor::process_instruction,
+    state::{LendingMarketConfigUpdate, INITIAL_COLLATERAL_RATIO},
+};
+
+// 100 SOL collateral at $20 and a 75% liquidation threshold -> $1500 unhealthy borrow value
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_600 * FRACTIONAL_TO_USDC;
+// the obligation is still unhealthy after either liquidation
+const FIRST_LIQUIDATION_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+const SECOND_LIQUIDATION_AMOUNT_FRACTIONAL: u64 = 90 * FRACTIONAL_TO_USDC;
+const LIQUIDATION_COOLDOWN_SLOTS: u64 = 50;
+
+struct TestSetup {
+    test_context: ProgramTestContext,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let reserve_config = || {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.loan_to_value_ratio = 50;
+        reserve_config.liquidation_threshold = 75;
+        reserve_config.liquidation_bonus = 10;
+        reserve_config
+    };
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[update_lending_market(
+            solend_program::id(),
+            LendingMarketConfigUpdate {
+                liquidation_cooldown_slots: Some(LIQUIDATION_COOLDOWN_SLOTS),
+                ..LendingMarketConfigUpdate::default()
+            },
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+            vec![],
+        )],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&test_context.payer, &lending_market.owner],
+        test_context.last_blockhash,
+    );
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let market = lending_market
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(
+        market.config.liquidation_cooldown_slots,
+        LIQUIDATION_COOLDOWN_SLOTS
+    );
+
+    TestSetup {
+        test_context,
+        user_accounts_owner,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+fn liquidate_instructions(setup: &TestSetup, liquidity_amount: u64) -> [Instruction; 4] {
+    [
+        refresh_reserve(
+            solend_program::id(),
+            setup.sol_test_reserve.pubkey,
+            setup.sol_oracle.pyth_price_pubkey,
+            setup.sol_oracle.switchboard_feed_pubkey,
+        ),
+        refresh_reserve(
+            solend_program::id(),
+            setup.usdc_test_reserve.pubkey,
+            setup.usdc_oracle.pyth_price_pubkey,
+            setup.usdc_oracle.switchboard_feed_pubkey,
+        ),
+        refresh_obligation(
+            solend_program::id(),
+            setup.test_obligation.pubkey,
+            vec![
+                setup.sol_test_reserve.pubkey,
+                setup.usdc_test_reserve.pubkey,
+            ],
+            setup.lending_market.pubkey,
+        ),
+        liquidate_obligation_and_redeem_reserve_collateral(
+            solend_program::id(),
+            liquidity_amount,
+            setup.usdc_test_reserve.user_liquidity_pubkey,
+            setup.sol_test_reserve.user_collateral_pubkey,
+            setup.sol_test_reserve.user_liquidity_pubkey,
+            setup.usdc_test_reserve.pubkey,
+            setup.usdc_test_reserve.liquidity_supply_pubkey,
+            setup.sol_test_reserve.pubkey,
+            setup.sol_test_reserve.collateral_mint_pubkey,
+            setup.sol_test_reserve.collateral_supply_pubkey,
+            setup.sol_test_reserve.liquidity_supply_pubkey,
+            setup.sol_test_reserve.config.fee_receiver,
+            setup.test_obligation.pubkey,
+            setup.lending_market.pubkey,
+            setup.user_accounts_owner.pubkey(),
+        ),
+    ]
+}
+
+async fn liquidate(setup: &mut TestSetup, liquidity_amount: u64) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(
+        &liquidate_instructions(setup, liquidity_amount),
+        Some(&setup.test_context.payer.pubkey()),
+    );
+    let recent_blockhash = setup
+        .test_context
+        .banks_client
+        .get_latest_blockhash()
+        .await
+        .unwrap();
+    transaction.sign(
+        &[&setup.test_context.payer, &setup.user_accounts_owner],
+        recent_blockhash,
+    );
+    setup
+        .test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup().await;
+
+    assert!(liquidate(&mut setup, FIRST_LIQUIDATION_AMOUNT_FRACTIONAL)
+        .await
+        .is_ok());
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.test_context.banks_client)
+        .await;
+    let liquidation_slot = obligation.last_liquidation_slot;
+    assert!(liquidation_slot > 0);
+
+    // an immediate second liquidation falls within the cooldown
+    assert_eq!(
+        liquidate(&mut setup, SECOND_LIQUIDATION_AMOUNT_FRACTIONAL)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::LiquidationCooldown as u32)
+        )
+    );
+
+    setup
+        .test_context
+        .warp_to_slot(liquidation_slot + LIQUIDATION_COOLDOWN_SLOTS)
+        .unwrap();
+    assert!(liquidate(&mut setup, SECOND_LIQUIDATION_AMOUNT_FRACTIONAL)
+        .await
+        .is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.test_context.banks_client)
+        .await;
+    assert!(obligation.last_liquidation_slot >= liquidation_slot + LIQUIDATION_COOLDOWN_SLOTS);
+    assert_eq!(
+        get_token_balance(
+            &mut setup.test_context.banks_client,
+            setup.usdc_test_reserve.user_liquidity_pubkey
+        )
+        .await,
+        USDC_BORROW_AMOUNT_FRACTIONAL
+            - FIRST_LIQUIDATION_AMOUNT_FRACTIONAL
+            - SECOND_LIQUIDATION_AMOUNT_FRACTIONAL
+    );
+}
@@ -0,0 +1,251 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation, refresh_reserve,
+        repay_obligation_liquidity,
+    },
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+// 100 SOL collateral at $20 and a 75% liquidation threshold -> $1500 unhealthy borrow value
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_600 * FRACTIONAL_TO_USDC;
+const LIQUIDATION_AMOUNT_FRACTIONAL: u64 = 10 * FRACTIONAL_TO_USDC;
+const MAX_LIQUIDATION_PRICE_AGE_SLOTS: u64 = 10;
+
+struct TestSetup {
+    test_context: ProgramTestContext,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    // cached prices keep the reserves refreshing for 100 slots after the oracles were last read,
+    // but they can only be liquidated against for the first 10
+    let reserve_config = || {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.loan_to_value_ratio = 50;
+        reserve_config.liquidation_threshold = 75;
+        reserve_config.liquidation_bonus = 10;
+        reserve_config.max_stale_price_slots = 100;
+        reserve_config.max_liquidation_price_age_slots = MAX_LIQUIDATION_PRICE_AGE_SLOTS;
+        reserve_config
+    };
+
+    // both feeds last updated at slot 0 and go stale at slot 60, after their 30 second heartbeat
+    let sol_oracle = add_switchboardv2_oracle_with_heartbeat(&mut test, 20, 0, 30);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_switchboardv2_oracle_with_heartbeat(&mut test, 1, 0, 30);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let test_context = test.start_with_context().await;
+
+    TestSetup {
+        test_context,
+        user_accounts_owner,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+/// Refreshes both reserves and the obligation at `slot` before `instruction`, so the instruction
+/// is at index 3
+async fn process_at_slot(
+    setup: &mut TestSetup,
+    slot: u64,
+    instruction: Instruction,
+) -> Result<(), TransportError> {
+    setup.test_context.warp_to_slot(slot).unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.sol_test_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![
+                    setup.sol_test_reserve.pubkey,
+                    setup.usdc_test_reserve.pubkey,
+                ],
+                setup.lending_market.pubkey,
+            ),
+            instruction,
+        ],
+        Some(&setup.test_context.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.test_context.payer, &setup.user_accounts_owner],
+        setup.test_context.last_blockhash,
+    );
+    setup
+        .test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+}
+
+fn liquidate(setup: &TestSetup) -> Instruction {
+    liquidate_obligation_and_redeem_reserve_collateral(
+        solend_program::id(),
+        LIQUIDATION_AMOUNT_FRACTIONAL,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.sol_test_reserve.user_collateral_pubkey,
+        setup.sol_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.sol_test_reserve.pubkey,
+        setup.sol_test_reserve.collateral_mint_pubkey,
+        setup.sol_test_reserve.collateral_supply_pubkey,
+        setup.sol_test_reserve.liquidity_supply_pubkey,
+        setup.sol_test_reserve.config.fee_receiver,
+        setup.test_obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    )
+}
+
+fn repay(setup: &TestSetup) -> Instruction {
+    repay_obligation_liquidity(
+        solend_program::id(),
+        FRACTIONAL_TO_USDC,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        setup.test_obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn test_liquidate_on_recent_price() {
+    let mut setup = setup().await;
+
+    // the oracles are live
+    let instruction = liquidate(&setup);
+    assert!(process_at_slot(&mut setup, 55, instruction).await.is_ok());
+
+    // the oracles are down, but the cached prices were read recently enough
+    let instruction = liquidate(&setup);
+    assert!(process_at_slot(&mut setup, 64, instruction).await.is_ok());
+
+    let sol_reserve = setup
+        .sol_test_reserve
+        .get_state(&mut setup.test_context.banks_client)
+        .await;
+    assert_eq!(sol_reserve.last_update.slot, 64);
+    assert_eq!(sol_reserve.liquidity.oracle_price_slot, 55);
+}
+
+#[tokio::test]
+async fn test_fail_liquidate_on_old_cached_price() {
+    let mut setup = setup().await;
+
+    let instruction = repay(&setup);
+    assert!(process_at_slot(&mut setup, 55, instruction).await.is_ok());
+
+    // the cached prices are too old to liquidate against
+    let instruction = liquidate(&setup);
+    assert_eq!(
+        process_at_slot(&mut setup, 66, instruction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::ReserveStale as u32)
+        )
+    );
+
+    // but still fresh enough to repay against
+    let instruction = repay(&setup);
+    assert!(process_at_slot(&mut setup, 75, instruction).await.is_ok());
+
+    let usdc_reserve = setup
+        .usdc_test_reserve
+        .get_state(&mut setup.test_context.banks_client)
+        .await;
+    assert_eq!(usdc_reserve.last_update.slot, 75);
+    assert_eq!(usdc_reserve.liquidity.oracle_price_slot, 55);
+}
@@ -0,0 +1,380 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        borrow_obligation_liquidity, deposit_obligation_collateral, deposit_reserve_liquidity,
+        liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation, refresh_reserve,
+        repay_obligation_liquidity, update_lending_market, withdraw_obligation_collateral,
+    },
+    processor::process_instruction,
+    state::{LendingMarketConfigUpdate, INITIAL_COLLATERAL_RATIO},
+};
+
+// 100 SOL collateral at $20 and a 75% liquidation threshold -> $1500 unhealthy borrow value
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const HEALTHY_BORROW_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+const UNHEALTHY_BORROW_AMOUNT_FRACTIONAL: u64 = 1_600 * FRACTIONAL_TO_USDC;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 =
+    HEALTHY_BORROW_AMOUNT_FRACTIONAL + UNHEALTHY_BORROW_AMOUNT_FRACTIONAL;
+// 1600 USDC * 20% -> 320 USDC liquidation
+const LIQUIDATION_AMOUNT_FRACTIONAL: u64 = 320 * FRACTIONAL_TO_USDC;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    healthy_obligation: TestObligation,
+    unhealthy_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let reserve_config = || {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.loan_to_value_ratio = 50;
+        reserve_config.liquidation_threshold = 75;
+        reserve_config
+    };
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 3 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: 3 * SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let healthy_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, HEALTHY_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+    let unhealthy_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, UNHEALTHY_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        sol_test_reserve,
+        usdc_test_reserve,
+        healthy_obligation,
+        unhealthy_obligation,
+    }
+}
+
+async fn set_deleveraging(setup: &mut TestSetup, deleveraging: bool) {
+    let mut transaction = Transaction::new_with_payer(
+        &[update_lending_market(
+            solend_program::id(),
+            LendingMarketConfigUpdate {
+                deleveraging: Some(deleveraging),
+                ..LendingMarketConfigUpdate::default()
+            },
+            setup.lending_market.pubkey,
+            setup.lending_market.owner.pubkey(),
+            vec![],
+        )],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.lending_market.owner],
+        setup.recent_blockhash,
+    );
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let lending_market = setup
+        .lending_market
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(lending_market.config.deleveraging, deleveraging);
+}
+
+/// Refresh both reserves and the obligation, then run `instruction` signed by the user
+async fn process_refreshed(
+    setup: &mut TestSetup,
+    obligation_pubkey: Pubkey,
+    instruction: Instruction,
+) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.sol_test_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                obligation_pubkey,
+                vec![
+                    setup.sol_test_reserve.pubkey,
+                    setup.usdc_test_reserve.pubkey,
+                ],
+                setup.lending_market.pubkey,
+            ),
+            instruction,
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    setup.banks_client.process_transaction(transaction).await
+}
+
+fn borrow_instruction(setup: &TestSetup, liquidity_amount: u64) -> Instruction {
+    borrow_obligation_liquidity(
+        solend_program::id(),
+        liquidity_amount,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        setup.usdc_test_reserve.config.fee_receiver,
+        setup.healthy_obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+        None,
+    )
+}
+
+fn assert_deleveraging(result: Result<(), TransportError>) {
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::MarketDeleveraging as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_blocks_borrows_and_deposits() {
+    let mut setup = setup().await;
+    set_deleveraging(&mut setup, true).await;
+    let healthy_obligation = setup.healthy_obligation.pubkey;
+
+    let instruction = borrow_instruction(&setup, FRACTIONAL_TO_USDC);
+    assert_deleveraging(process_refreshed(&mut setup, healthy_obligation, instruction).await);
+
+    let instruction = deposit_reserve_liquidity(
+        solend_program::id(),
+        FRACTIONAL_TO_USDC,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.user_collateral_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.usdc_test_reserve.collateral_mint_pubkey,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    );
+    assert_deleveraging(process_refreshed(&mut setup, healthy_obligation, instruction).await);
+
+    let instruction = deposit_obligation_collateral(
+        solend_program::id(),
+        INITIAL_COLLATERAL_RATIO,
+        setup.sol_test_reserve.user_collateral_pubkey,
+        setup.sol_test_reserve.collateral_supply_pubkey,
+        setup.sol_test_reserve.pubkey,
+        healthy_obligation,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+        setup.user_accounts_owner.pubkey(),
+    );
+    assert_deleveraging(process_refreshed(&mut setup, healthy_obligation, instruction).await);
+
+    // borrowing resumes once the market stops deleveraging
+    set_deleveraging(&mut setup, false).await;
+    let instruction = borrow_instruction(&setup, 2 * FRACTIONAL_TO_USDC);
+    assert!(
+        process_refreshed(&mut setup, healthy_obligation, instruction)
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn test_allows_unwinding() {
+    let mut setup = setup().await;
+    set_deleveraging(&mut setup, true).await;
+    let healthy_obligation = setup.healthy_obligation.pubkey;
+    let unhealthy_obligation = setup.unhealthy_obligation.pubkey;
+
+    let initial_user_liquidity_balance = get_token_balance(
+        &mut setup.banks_client,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+    )
+    .await;
+    let instruction = repay_obligation_liquidity(
+        solend_program::id(),
+        HEALTHY_BORROW_AMOUNT_FRACTIONAL / 2,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        healthy_obligation,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    );
+    assert!(
+        process_refreshed(&mut setup, healthy_obligation, instruction)
+            .await
+            .is_ok()
+    );
+
+    let user_liquidity_balance = get_token_balance(
+        &mut setup.banks_client,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+    )
+    .await;
+    assert_eq!(
+        user_liquidity_balance,
+        initial_user_liquidity_balance - HEALTHY_BORROW_AMOUNT_FRACTIONAL / 2
+    );
+
+    let initial_user_collateral_balance = get_token_balance(
+        &mut setup.banks_client,
+        setup.sol_test_reserve.user_collateral_pubkey,
+    )
+    .await;
+    let instruction = withdraw_obligation_collateral(
+        solend_program::id(),
+        SOL_DEPOSIT_AMOUNT_LAMPORTS / 2,
+        setup.sol_test_reserve.collateral_supply_pubkey,
+        setup.sol_test_reserve.user_collateral_pubkey,
+        setup.sol_test_reserve.pubkey,
+        healthy_obligation,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    );
+    assert!(
+        process_refreshed(&mut setup, healthy_obligation, instruction)
+            .await
+            .is_ok()
+    );
+
+    let user_collateral_balance = get_token_balance(
+        &mut setup.banks_client,
+        setup.sol_test_reserve.user_collateral_pubkey,
+    )
+    .await;
+    assert_eq!(
+        user_collateral_balance,
+        initial_user_collateral_balance + SOL_DEPOSIT_AMOUNT_LAMPORTS / 2
+    );
+
+    let instruction = liquidate_obligation_and_redeem_reserve_collateral(
+        solend_program::id(),
+        LIQUIDATION_AMOUNT_FRACTIONAL,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.sol_test_reserve.user_collateral_pubkey,
+        setup.sol_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.sol_test_reserve.pubkey,
+        setup.sol_test_reserve.collateral_mint_pubkey,
+        setup.sol_test_reserve.collateral_supply_pubkey,
+        setup.sol_test_reserve.liquidity_supply_pubkey,
+        setup.sol_test_reserve.config.fee_receiver,
+        unhealthy_obligation,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    );
+    assert!(
+        process_refreshed(&mut setup, unhealthy_obligation, instruction)
+            .await
+            .is_ok()
+    );
+
+    let obligation = setup
+        .unhealthy_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        obligation.borrows[0].borrowed_amount_wads,
+        (UNHEALTHY_BORROW_AMOUNT_FRACTIONAL - LIQUIDATION_AMOUNT_FRACTIONAL).into()
+    );
+}
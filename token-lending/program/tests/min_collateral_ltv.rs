@@ -0,0 +1,161 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{update_lending_market, update_reserve_config},
+    processor::process_instruction,
+    state::{LendingMarketConfigUpdate, ReserveConfig},
+};
+
+const MIN_COLLATERAL_LTV_PCT: u8 = 60;
+
+/// Raises the market's collateral floor to 60% and then reconfigures its USDC reserve with
+/// `config`
+async fn update_below_floor(config: ReserveConfig) -> (Result<(), TransportError>, ReserveConfig) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[update_lending_market(
+            solend_program::id(),
+            LendingMarketConfigUpdate {
+                min_collateral_ltv_pct: Some(MIN_COLLATERAL_LTV_PCT),
+                ..LendingMarketConfigUpdate::default()
+            },
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+            vec![],
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(market.config.min_collateral_ltv_pct, MIN_COLLATERAL_LTV_PCT);
+
+    let mut transaction = Transaction::new_with_payer(
+        &[update_reserve_config(
+            solend_program::id(),
+            config,
+            usdc_test_reserve.pubkey,
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+            usdc_oracle.pyth_product_pubkey,
+            usdc_oracle.pyth_price_pubkey,
+            usdc_oracle.switchboard_feed_pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    let result = banks_client.process_transaction(transaction).await;
+
+    let reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    (result, reserve.config)
+}
+
+#[tokio::test]
+async fn test_fail_collateral_below_floor() {
+    let mut config = test_reserve_config();
+    config.usable_as_collateral = true;
+    assert!(config.loan_to_value_ratio < MIN_COLLATERAL_LTV_PCT);
+
+    let (result, reserve_config) = update_below_floor(config).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::CollateralLtvTooLow as u32)
+        )
+    );
+    assert!(reserve_config.usable_as_collateral);
+    assert_eq!(
+        reserve_config.loan_to_value_ratio,
+        test_reserve_config().loan_to_value_ratio
+    );
+}
+
+#[tokio::test]
+async fn test_success_supply_only_below_floor() {
+    let mut config = test_reserve_config();
+    config.usable_as_collateral = false;
+
+    let (result, reserve_config) = update_below_floor(config).await;
+    assert!(result.is_ok());
+    assert!(!reserve_config.usable_as_collateral);
+    assert!(reserve_config.loan_to_value_ratio < MIN_COLLATERAL_LTV_PCT);
+}
+
+#[tokio::test]
+async fn test_other_updates_keep_floor() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let lending_market = add_lending_market(&mut test);
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    for update in [
+        LendingMarketConfigUpdate {
+            min_collateral_ltv_pct: Some(MIN_COLLATERAL_LTV_PCT),
+            ..LendingMarketConfigUpdate::default()
+        },
+        LendingMarketConfigUpdate {
+            deleveraging: Some(true),
+            ..LendingMarketConfigUpdate::default()
+        },
+    ] {
+        let mut transaction = Transaction::new_with_payer(
+            &[update_lending_market(
+                solend_program::id(),
+                update,
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                vec![],
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(market.config.min_collateral_ltv_pct, MIN_COLLATERAL_LTV_PCT);
+    assert!(market.config.deleveraging);
+}
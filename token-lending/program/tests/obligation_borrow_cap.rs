@@ -0,0 +1,111 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::Signer,
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError, instruction::update_lending_market, state::LendingMarketConfigUpdate,
+};
+
+// 100 SOL collateral at $20 and a 50% loan to value ratio -> $1000 allowed borrow value, capped
+// well below that
+const MAX_OBLIGATION_BORROW_USD: u64 = 600;
+
+async fn setup() -> SolCollateralTestSetup {
+    let mut borrow_reserve_config = test_reserve_config();
+    borrow_reserve_config.fees.borrow_fee_wad = 0;
+
+    let mut setup = setup_sol_collateral(borrow_reserve_config, borrow_reserve_config).await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[update_lending_market(
+            solend_program::id(),
+            LendingMarketConfigUpdate {
+                max_obligation_borrow_usd: Some(MAX_OBLIGATION_BORROW_USD),
+                ..LendingMarketConfigUpdate::default()
+            },
+            setup.lending_market.pubkey,
+            setup.lending_market.owner.pubkey(),
+            vec![],
+        )],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.lending_market.owner],
+        setup.recent_blockhash,
+    );
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let market = setup
+        .lending_market
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        market.config.max_obligation_borrow_usd,
+        MAX_OBLIGATION_BORROW_USD
+    );
+
+    setup
+}
+
+fn assert_capped(result: Result<(), TransportError>) {
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            4,
+            InstructionError::Custom(LendingError::ObligationValueLimit as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_borrow_across_reserves_up_to_cap() {
+    let mut setup = setup().await;
+
+    // $400 of USDC and $200 of SRM fill the $600 cap exactly
+    assert!(setup
+        .borrow(USDC_BORROW_RESERVE, 400 * FRACTIONAL_TO_USDC)
+        .await
+        .is_ok());
+    assert!(setup
+        .borrow(SRM_BORROW_RESERVE, 100 * FRACTIONAL_TO_SRM)
+        .await
+        .is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(obligation.borrows.len(), 2);
+
+    // the obligation is still well within its allowed borrow value, but neither reserve lends
+    // any more
+    assert_capped(setup.borrow(USDC_BORROW_RESERVE, 1).await);
+    assert_capped(setup.borrow(SRM_BORROW_RESERVE, 1).await);
+}
+
+#[tokio::test]
+async fn test_fail_single_borrow_over_cap() {
+    let mut setup = setup().await;
+
+    assert_capped(
+        setup
+            .borrow(SRM_BORROW_RESERVE, 301 * FRACTIONAL_TO_SRM)
+            .await,
+    );
+    assert!(setup
+        .borrow(SRM_BORROW_RESERVE, 300 * FRACTIONAL_TO_SRM)
+        .await
+        .is_ok());
+}
@@ -125,6 +125,7 @@ async fn test_success() {
             // 1
             init_obligation(
                 solend_program::id(),
+                0,
                 obligation_pubkey,
                 lending_market.pubkey,
                 user_accounts_owner_pubkey,
@@ -170,6 +171,7 @@ async fn test_success() {
                 solend_program::id(),
                 obligation_pubkey,
                 vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             // 7
             borrow_obligation_liquidity(
@@ -217,6 +219,7 @@ async fn test_success() {
                 solend_program::id(),
                 obligation_pubkey,
                 vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             // 12
             withdraw_obligation_collateral(
@@ -384,6 +387,7 @@ async fn test_success2() {
             // 1
             init_obligation(
                 solend_program::id(),
+                0,
                 obligation_pubkey,
                 lending_market.pubkey,
                 user_accounts_owner_pubkey,
@@ -429,6 +433,7 @@ async fn test_success2() {
                 solend_program::id(),
                 obligation_pubkey,
                 vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             // 7
             borrow_obligation_liquidity(
@@ -476,6 +481,7 @@ async fn test_success2() {
                 solend_program::id(),
                 obligation_pubkey,
                 vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             // 12
             withdraw_obligation_collateral(
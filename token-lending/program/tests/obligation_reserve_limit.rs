@@ -0,0 +1,218 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{borrow_obligation_liquidity, deposit_obligation_collateral, refresh_obligation},
+    processor::process_instruction,
+    state::{INITIAL_COLLATERAL_RATIO, MAX_OBLIGATION_RESERVES},
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 10 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 10 * FRACTIONAL_TO_USDC;
+
+/// Adds `count` SOL reserves, each with collateral for the user to deposit
+fn add_sol_reserves(
+    test: &mut ProgramTest,
+    lending_market: &TestLendingMarket,
+    user_accounts_owner: &Keypair,
+    count: usize,
+) -> Vec<TestReserve> {
+    let sol_oracle = add_sol_oracle(test);
+    (0..count)
+        .map(|_| {
+            add_reserve(
+                test,
+                lending_market,
+                &sol_oracle,
+                user_accounts_owner,
+                AddReserveArgs {
+                    liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+                    collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+                    liquidity_mint_pubkey: spl_token::native_mint::id(),
+                    liquidity_mint_decimals: 9,
+                    config: test_reserve_config(),
+                    mark_fresh: true,
+                    ..AddReserveArgs::default()
+                },
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_deposit_reserve_limit() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_test_reserves = add_sol_reserves(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        MAX_OBLIGATION_RESERVES + 1,
+    );
+    let deposits: Vec<_> = sol_test_reserves[..MAX_OBLIGATION_RESERVES - 1]
+        .iter()
+        .map(|reserve| (reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS))
+        .collect();
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &deposits,
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let deposit = |sol_test_reserve: &TestReserve| {
+        let mut transaction = Transaction::new_with_payer(
+            &[deposit_obligation_collateral(
+                solend_program::id(),
+                SOL_DEPOSIT_AMOUNT_LAMPORTS,
+                sol_test_reserve.user_collateral_pubkey,
+                sol_test_reserve.collateral_supply_pubkey,
+                sol_test_reserve.pubkey,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                test_obligation.owner,
+                user_accounts_owner.pubkey(),
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+        transaction
+    };
+
+    // the last position up to the limit can still be opened
+    assert!(banks_client
+        .process_transaction(deposit(&sol_test_reserves[MAX_OBLIGATION_RESERVES - 1]))
+        .await
+        .is_ok());
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.deposits.len(), MAX_OBLIGATION_RESERVES);
+
+    assert_eq!(
+        banks_client
+            .process_transaction(deposit(&sol_test_reserves[MAX_OBLIGATION_RESERVES]))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ObligationReserveLimit as u32)
+        )
+    );
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.deposits.len(), MAX_OBLIGATION_RESERVES);
+}
+
+#[tokio::test]
+async fn test_borrow_reserve_limit() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_test_reserves = add_sol_reserves(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        MAX_OBLIGATION_RESERVES,
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let deposits: Vec<_> = sol_test_reserves
+        .iter()
+        .map(|reserve| (reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS))
+        .collect();
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &deposits,
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                sol_test_reserves
+                    .iter()
+                    .map(|reserve| reserve.pubkey)
+                    .collect(),
+                lending_market.pubkey,
+            ),
+            borrow_obligation_liquidity(
+                solend_program::id(),
+                USDC_BORROW_AMOUNT_FRACTIONAL,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.config.fee_receiver,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                test_obligation.owner,
+                None,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ObligationReserveLimit as u32)
+        )
+    );
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert!(obligation.borrows.is_empty());
+}
@@ -0,0 +1,323 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::program_pack::Pack;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    system_instruction::create_account,
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        borrow_obligation_liquidity, deposit_obligation_collateral, init_obligation,
+        refresh_obligation, refresh_reserve,
+    },
+    processor::process_instruction,
+    state::{Obligation, INITIAL_COLLATERAL_RATIO},
+};
+
+const TRANCHE: u8 = 1;
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 10 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const SOL_RESERVE_LIQUIDITY_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL;
+const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 10 * FRACTIONAL_TO_USDC;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    tranche_sol_reserve: TestReserve,
+    default_sol_reserve: TestReserve,
+    tranche_usdc_reserve: TestReserve,
+    default_usdc_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut tranche_reserve_config = test_reserve_config();
+    tranche_reserve_config.tranche = TRANCHE;
+    tranche_reserve_config.fees.borrow_fee_wad = 0;
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let add_sol_reserve = |test: &mut ProgramTest, config| {
+        add_reserve(
+            test,
+            &lending_market,
+            &sol_oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+                liquidity_amount: SOL_RESERVE_LIQUIDITY_LAMPORTS,
+                liquidity_mint_pubkey: spl_token::native_mint::id(),
+                liquidity_mint_decimals: 9,
+                config,
+                mark_fresh: true,
+                ..AddReserveArgs::default()
+            },
+        )
+    };
+    let tranche_sol_reserve = add_sol_reserve(&mut test, tranche_reserve_config);
+    let default_sol_reserve = add_sol_reserve(&mut test, test_reserve_config());
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let add_usdc_reserve = |test: &mut ProgramTest, config| {
+        add_reserve(
+            test,
+            &lending_market,
+            &usdc_oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+                liquidity_mint_pubkey: usdc_mint.pubkey,
+                liquidity_mint_decimals: usdc_mint.decimals,
+                config,
+                mark_fresh: true,
+                ..AddReserveArgs::default()
+            },
+        )
+    };
+    let tranche_usdc_reserve = add_usdc_reserve(&mut test, tranche_reserve_config);
+    let default_usdc_reserve = add_usdc_reserve(&mut test, test_reserve_config());
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&tranche_sol_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            tranche: TRANCHE,
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        tranche_sol_reserve,
+        default_sol_reserve,
+        tranche_usdc_reserve,
+        default_usdc_reserve,
+        test_obligation,
+    }
+}
+
+fn deposit(setup: &TestSetup, reserve: &TestReserve) -> Transaction {
+    let mut transaction = Transaction::new_with_payer(
+        &[deposit_obligation_collateral(
+            solend_program::id(),
+            SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            reserve.user_collateral_pubkey,
+            reserve.collateral_supply_pubkey,
+            reserve.pubkey,
+            setup.test_obligation.pubkey,
+            setup.lending_market.pubkey,
+            setup.test_obligation.owner,
+            setup.user_accounts_owner.pubkey(),
+        )],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    transaction
+}
+
+fn refresh_and_borrow(setup: &TestSetup, borrow_reserve: &TestReserve) -> Transaction {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.tranche_sol_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                borrow_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![setup.tranche_sol_reserve.pubkey],
+                setup.lending_market.pubkey,
+            ),
+            borrow_obligation_liquidity(
+                solend_program::id(),
+                USDC_BORROW_AMOUNT_FRACTIONAL,
+                borrow_reserve.liquidity_supply_pubkey,
+                borrow_reserve.user_liquidity_pubkey,
+                borrow_reserve.pubkey,
+                borrow_reserve.config.fee_receiver,
+                setup.test_obligation.pubkey,
+                setup.lending_market.pubkey,
+                setup.user_accounts_owner.pubkey(),
+                None,
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    transaction
+}
+
+#[tokio::test]
+async fn test_init_obligation_with_tranche() {
+    let mut setup = setup().await;
+
+    let obligation_keypair = Keypair::new();
+    let rent = setup.banks_client.get_rent().await.unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            create_account(
+                &setup.payer.pubkey(),
+                &obligation_keypair.pubkey(),
+                rent.minimum_balance(Obligation::LEN),
+                Obligation::LEN as u64,
+                &solend_program::id(),
+            ),
+            init_obligation(
+                solend_program::id(),
+                TRANCHE,
+                obligation_keypair.pubkey(),
+                setup.lending_market.pubkey,
+                setup.user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[
+            &setup.payer,
+            &obligation_keypair,
+            &setup.user_accounts_owner,
+        ],
+        setup.recent_blockhash,
+    );
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let obligation_account = setup
+        .banks_client
+        .get_account(obligation_keypair.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    let obligation = Obligation::unpack(&obligation_account.data[..]).unwrap();
+    assert_eq!(obligation.tranche, TRANCHE);
+}
+
+#[tokio::test]
+async fn test_success_same_tranche() {
+    let mut setup = setup().await;
+
+    let transaction = deposit(&setup, &setup.tranche_sol_reserve);
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let transaction = refresh_and_borrow(&setup, &setup.tranche_usdc_reserve);
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        2 * SOL_DEPOSIT_AMOUNT_LAMPORTS
+    );
+    assert_eq!(
+        obligation.borrows[0].borrow_reserve,
+        setup.tranche_usdc_reserve.pubkey
+    );
+    assert_eq!(
+        get_token_balance(
+            &mut setup.banks_client,
+            setup.tranche_usdc_reserve.user_liquidity_pubkey
+        )
+        .await,
+        USDC_BORROW_AMOUNT_FRACTIONAL
+    );
+}
+
+#[tokio::test]
+async fn test_fail_cross_tranche() {
+    let mut setup = setup().await;
+
+    let transaction = deposit(&setup, &setup.default_sol_reserve);
+    assert_eq!(
+        setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::TrancheMismatch as u32)
+        )
+    );
+
+    let transaction = refresh_and_borrow(&setup, &setup.default_usdc_reserve);
+    assert_eq!(
+        setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(LendingError::TrancheMismatch as u32)
+        )
+    );
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(obligation.deposits.len(), 1);
+    assert!(obligation.borrows.is_empty());
+}
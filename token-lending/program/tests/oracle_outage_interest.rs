@@ -0,0 +1,224 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    account::AccountSharedData,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{instruction::refresh_reserve, processor::process_instruction};
+use switchboard_v2::AggregatorAccountData;
+
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 500 * FRACTIONAL_TO_USDC;
+
+/// Adds a USDC reserve half lent out, priced by a feed with a 30 second heartbeat that was last
+/// updated at slot 0, so it goes stale at slot 60
+fn add_borrowed_usdc_reserve(
+    test: &mut ProgramTest,
+    lending_market: &TestLendingMarket,
+    pause_interest_on_oracle_error: bool,
+    max_stale_price_slots: u64,
+) -> (TestReserve, TestOracle) {
+    let mut reserve_config = test_reserve_config();
+    reserve_config.pause_interest_on_oracle_error = pause_interest_on_oracle_error;
+    reserve_config.max_stale_price_slots = max_stale_price_slots;
+
+    let usdc_mint = add_usdc_mint(test);
+    let usdc_oracle = add_switchboardv2_oracle_with_heartbeat(test, 1, 0, 30);
+    let usdc_test_reserve = add_reserve(
+        test,
+        lending_market,
+        &usdc_oracle,
+        &Keypair::new(),
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+    (usdc_test_reserve, usdc_oracle)
+}
+
+async fn refresh_at_slot(
+    test_context: &mut ProgramTestContext,
+    slot: u64,
+    reserves: &[(&TestReserve, &TestOracle)],
+) {
+    test_context.warp_to_slot(slot).unwrap();
+    let instructions: Vec<_> = reserves
+        .iter()
+        .map(|(reserve, oracle)| {
+            refresh_reserve(
+                solend_program::id(),
+                reserve.pubkey,
+                oracle.pyth_price_pubkey,
+                oracle.switchboard_feed_pubkey,
+            )
+        })
+        .collect();
+    let mut transaction =
+        Transaction::new_with_payer(&instructions, Some(&test_context.payer.pubkey()));
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+}
+
+/// Brings the feed back by confirming a new round at `slot`
+async fn recover_feed(test_context: &mut ProgramTestContext, feed_pubkey: Pubkey, slot: u64) {
+    let mut feed_account = test_context
+        .banks_client
+        .get_account(feed_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    let agg_state = bytemuck::from_bytes_mut::<AggregatorAccountData>(&mut feed_account.data[8..]);
+    agg_state.latest_confirmed_round.round_open_slot = slot;
+    test_context.set_account(&feed_pubkey, &AccountSharedData::from(feed_account));
+}
+
+#[tokio::test]
+async fn test_outage_freezes_accrual_until_recovery() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let lending_market = add_lending_market(&mut test);
+    let (usdc_test_reserve, usdc_oracle) =
+        add_borrowed_usdc_reserve(&mut test, &lending_market, true, 0);
+
+    let mut test_context = test.start_with_context().await;
+    let reserves = [(&usdc_test_reserve, &usdc_oracle)];
+
+    refresh_at_slot(&mut test_context, 50, &reserves).await;
+    let reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert!(!reserve.liquidity.interest_paused);
+
+    // the outage is found, the slots before it still accrue
+    refresh_at_slot(&mut test_context, 62, &reserves).await;
+    let outage_reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert!(
+        outage_reserve.liquidity.cumulative_borrow_rate_wads
+            > reserve.liquidity.cumulative_borrow_rate_wads
+    );
+    assert!(outage_reserve.liquidity.interest_paused);
+    assert!(outage_reserve.last_update.stale);
+
+    // nothing accrues through the outage, and the reserve can't be used at its old price
+    refresh_at_slot(&mut test_context, 200, &reserves).await;
+    let reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(
+        reserve.liquidity.cumulative_borrow_rate_wads,
+        outage_reserve.liquidity.cumulative_borrow_rate_wads
+    );
+    assert_eq!(
+        reserve.liquidity.borrowed_amount_wads,
+        outage_reserve.liquidity.borrowed_amount_wads
+    );
+    assert_eq!(reserve.last_update.slot, 200);
+    assert!(reserve.last_update.stale);
+
+    // the slots of the outage are skipped once the oracle recovers
+    recover_feed(&mut test_context, usdc_oracle.switchboard_feed_pubkey, 300).await;
+    refresh_at_slot(&mut test_context, 302, &reserves).await;
+    let recovered_reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(
+        recovered_reserve.liquidity.cumulative_borrow_rate_wads,
+        outage_reserve.liquidity.cumulative_borrow_rate_wads
+    );
+    assert!(!recovered_reserve.liquidity.interest_paused);
+    assert!(!recovered_reserve.last_update.stale);
+    assert_eq!(recovered_reserve.liquidity.oracle_price_slot, 302);
+
+    // and accrual resumes from the recovery
+    refresh_at_slot(&mut test_context, 340, &reserves).await;
+    let reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    let mut expected_reserve = recovered_reserve;
+    expected_reserve.accrue_interest(340).unwrap();
+    assert!(
+        reserve.liquidity.cumulative_borrow_rate_wads
+            > outage_reserve.liquidity.cumulative_borrow_rate_wads
+    );
+    assert_eq!(
+        reserve.liquidity.cumulative_borrow_rate_wads,
+        expected_reserve.liquidity.cumulative_borrow_rate_wads
+    );
+}
+
+#[tokio::test]
+async fn test_outage_on_cached_price() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let lending_market = add_lending_market(&mut test);
+    let (paused_test_reserve, paused_oracle) =
+        add_borrowed_usdc_reserve(&mut test, &lending_market, true, 1_000);
+    let (accruing_test_reserve, accruing_oracle) =
+        add_borrowed_usdc_reserve(&mut test, &lending_market, false, 1_000);
+
+    let mut test_context = test.start_with_context().await;
+    let reserves = [
+        (&paused_test_reserve, &paused_oracle),
+        (&accruing_test_reserve, &accruing_oracle),
+    ];
+
+    refresh_at_slot(&mut test_context, 62, &reserves).await;
+    let paused_outage_reserve = paused_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    let accruing_outage_reserve = accruing_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(
+        paused_outage_reserve.liquidity.cumulative_borrow_rate_wads,
+        accruing_outage_reserve
+            .liquidity
+            .cumulative_borrow_rate_wads
+    );
+
+    // both keep refreshing on their cached prices, only one keeps accruing
+    refresh_at_slot(&mut test_context, 200, &reserves).await;
+    let paused_reserve = paused_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    let accruing_reserve = accruing_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert!(!paused_reserve.last_update.stale);
+    assert!(paused_reserve.liquidity.interest_paused);
+    assert_eq!(
+        paused_reserve.liquidity.cumulative_borrow_rate_wads,
+        paused_outage_reserve.liquidity.cumulative_borrow_rate_wads
+    );
+    assert!(!accruing_reserve.liquidity.interest_paused);
+    assert!(
+        accruing_reserve.liquidity.cumulative_borrow_rate_wads
+            > accruing_outage_reserve
+                .liquidity
+                .cumulative_borrow_rate_wads
+    );
+}
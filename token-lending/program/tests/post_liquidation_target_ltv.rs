@@ -0,0 +1,218 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::{
+        liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation, refresh_reserve,
+        update_lending_market,
+    },
+    math::{Decimal, Rate, TryDiv, TrySub},
+    processor::process_instruction,
+    state::{
+        LendingMarketConfigUpdate, Obligation, INITIAL_COLLATERAL_RATIO, LIQUIDATION_CLOSE_FACTOR,
+    },
+};
+use std::convert::TryFrom;
+
+const POST_LIQUIDATION_TARGET_LTV: u8 = 75;
+
+/// Liquidates up to `liquidity_amount` of an obligation borrowing 1700 USDC against 100 SOL at $20
+/// in a market that stops liquidations at a 75% loan to value ratio. Returns the amount of USDC
+/// repaid and the obligation refreshed afterwards
+async fn liquidate_to_target(
+    liquidation_close_factor: u8,
+    liquidity_amount: u64,
+) -> (u64, Obligation) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // 100 SOL collateral
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    // above the 1600 USDC liquidation threshold
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_700 * FRACTIONAL_TO_USDC;
+
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+    reserve_config.liquidation_close_factor = liquidation_close_factor;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let refresh = || {
+        vec![
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+        ]
+    };
+
+    let mut transaction = Transaction::new_with_payer(
+        &[update_lending_market(
+            solend_program::id(),
+            LendingMarketConfigUpdate {
+                post_liquidation_target_ltv: Some(POST_LIQUIDATION_TARGET_LTV),
+                ..LendingMarketConfigUpdate::default()
+            },
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+            vec![],
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(
+        market.config.post_liquidation_target_ltv,
+        POST_LIQUIDATION_TARGET_LTV
+    );
+
+    let initial_user_liquidity_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+
+    let mut instructions = refresh();
+    instructions.push(liquidate_obligation_and_redeem_reserve_collateral(
+        solend_program::id(),
+        liquidity_amount,
+        usdc_test_reserve.user_liquidity_pubkey,
+        sol_test_reserve.user_collateral_pubkey,
+        sol_test_reserve.user_liquidity_pubkey,
+        usdc_test_reserve.pubkey,
+        usdc_test_reserve.liquidity_supply_pubkey,
+        sol_test_reserve.pubkey,
+        sol_test_reserve.collateral_mint_pubkey,
+        sol_test_reserve.collateral_supply_pubkey,
+        sol_test_reserve.liquidity_supply_pubkey,
+        sol_test_reserve.config.fee_receiver,
+        test_obligation.pubkey,
+        lending_market.pubkey,
+        user_accounts_owner.pubkey(),
+    ));
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let repaid = initial_user_liquidity_balance
+        - get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+
+    let mut transaction = Transaction::new_with_payer(&refresh(), Some(&payer.pubkey()));
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    (repaid, test_obligation.get_state(&mut banks_client).await)
+}
+
+#[tokio::test]
+async fn test_liquidation_truncated_to_target() {
+    let (repaid, obligation) = liquidate_to_target(100, u64::MAX).await;
+
+    // (1700 - 75% * 2000) / (1 - 75% * 105%) USDC, rounded up
+    assert_eq!(repaid, 941_176_471);
+
+    let ltv = Rate::try_from(
+        obligation
+            .borrowed_value
+            .try_div(obligation.deposited_value)
+            .unwrap(),
+    )
+    .unwrap();
+    let target_ltv = Rate::from_percent(POST_LIQUIDATION_TARGET_LTV);
+    assert!(ltv <= target_ltv);
+    assert!(target_ltv.try_sub(ltv).unwrap() < Rate::from_scaled_val(1_000_000_000));
+    assert!(obligation.borrowed_value > Decimal::zero());
+}
+
+#[tokio::test]
+async fn test_smaller_liquidation_unchanged() {
+    let (repaid, _) = liquidate_to_target(100, 500 * FRACTIONAL_TO_USDC).await;
+    assert_eq!(repaid, 500 * FRACTIONAL_TO_USDC);
+}
+
+#[tokio::test]
+async fn test_close_factor_still_applies() {
+    let (repaid, _) = liquidate_to_target(LIQUIDATION_CLOSE_FACTOR, u64::MAX).await;
+    assert_eq!(repaid, 340 * FRACTIONAL_TO_USDC);
+}
@@ -0,0 +1,208 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    instruction::{refresh_obligation, refresh_reserve, update_lending_market},
+    math::{Decimal, TryDiv, TryMul},
+    processor::process_instruction,
+    state::{LendingMarketConfigUpdate, Obligation, INITIAL_COLLATERAL_RATIO},
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 500 * FRACTIONAL_TO_USDC;
+
+/// Refreshes an obligation holding 100 SOL at $20 +/- $2 against 500 USDC at $1 +/- $0.10,
+/// leaving the lending market out of the refresh unless `include_lending_market` is set
+async fn refresh(
+    use_price_confidence: bool,
+    include_lending_market: bool,
+) -> (Result<(), TransportError>, Obligation) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.liquidation_threshold = 80;
+
+    let sol_oracle = add_switchboardv2_oracle_with_std_deviation(&mut test, 200, 1, 20);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_switchboardv2_oracle_with_std_deviation(&mut test, 10, 1, 1);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            update_lending_market(
+                solend_program::id(),
+                LendingMarketConfigUpdate {
+                    use_price_confidence: Some(use_price_confidence),
+                    ..LendingMarketConfigUpdate::default()
+                },
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(market.config.use_price_confidence, use_price_confidence);
+
+    let sol_reserve = sol_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(sol_reserve.use_price_confidence, use_price_confidence);
+    assert_eq!(sol_reserve.liquidity.market_price, Decimal::from(20u64));
+    assert_eq!(
+        sol_reserve.liquidity.market_price_confidence,
+        Decimal::from(2u64)
+    );
+
+    let mut refresh_obligation_instruction = refresh_obligation(
+        solend_program::id(),
+        test_obligation.pubkey,
+        vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+        lending_market.pubkey,
+    );
+    if !include_lending_market {
+        refresh_obligation_instruction.accounts.pop();
+    }
+    let mut transaction =
+        Transaction::new_with_payer(&[refresh_obligation_instruction], Some(&payer.pubkey()));
+    transaction.sign(&[&payer], recent_blockhash);
+    let result = banks_client.process_transaction(transaction).await;
+
+    (result, test_obligation.get_state(&mut banks_client).await)
+}
+
+/// Value of `obligation`'s USDC borrow at `price`
+fn borrowed_value_at(obligation: &Obligation, price: Decimal) -> Decimal {
+    obligation.borrows[0]
+        .borrowed_amount_wads
+        .try_mul(price)
+        .unwrap()
+        .try_div(FRACTIONAL_TO_USDC)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_point_estimate_prices() {
+    let (result, obligation) = refresh(false, true).await;
+    assert!(result.is_ok());
+
+    assert_eq!(obligation.deposited_value, Decimal::from(2_000u64));
+    assert_eq!(obligation.allowed_borrow_value, Decimal::from(1_000u64));
+    assert_eq!(obligation.unhealthy_borrow_value, Decimal::from(1_600u64));
+    assert_eq!(
+        obligation.borrowed_value,
+        borrowed_value_at(&obligation, Decimal::one())
+    );
+}
+
+#[tokio::test]
+async fn test_confidence_adjusted_prices() {
+    let (result, obligation) = refresh(true, true).await;
+    assert!(result.is_ok());
+
+    // collateral is valued at $18 per SOL
+    assert_eq!(obligation.deposited_value, Decimal::from(1_800u64));
+    assert_eq!(obligation.deposits[0].market_value, Decimal::from(1_800u64));
+    assert_eq!(obligation.allowed_borrow_value, Decimal::from(900u64));
+    assert_eq!(obligation.unhealthy_borrow_value, Decimal::from(1_440u64));
+
+    // and debt at $1.10 per USDC
+    let debt_price = Decimal::from(11u64).try_div(10u64).unwrap();
+    assert_eq!(
+        obligation.borrowed_value,
+        borrowed_value_at(&obligation, debt_price)
+    );
+}
+
+#[tokio::test]
+async fn test_fail_without_lending_market() {
+    // leaving the market out can't be used to dodge its valuation policy
+    let (result, obligation) = refresh(true, false).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+    );
+    assert_eq!(obligation.deposited_value, Decimal::zero());
+}
+
+#[tokio::test]
+async fn test_point_estimate_prices_without_lending_market() {
+    // the market stays optional for reserves that don't use confidence pricing
+    let (result, obligation) = refresh(false, false).await;
+    assert!(result.is_ok());
+
+    assert_eq!(obligation.deposited_value, Decimal::from(2_000u64));
+    assert_eq!(obligation.allowed_borrow_value, Decimal::from(1_000u64));
+}
@@ -0,0 +1,226 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::{program_pack::Pack, pubkey::PUBKEY_BYTES};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{AccountMeta, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::refresh_reserve,
+    instruction::set_reserve_oracles,
+    math::Decimal,
+    processor::process_instruction,
+    state::{Reserve, MAX_EXTRA_PYTH_ORACLES, MAX_ORACLE_PRICE_DIVERGENCE_BPS},
+};
+
+// pyth prices older than the default max price age of 240 slots are stale by then
+const REFRESH_SLOT: u64 = 1_000;
+
+struct TestSetup {
+    test_context: ProgramTestContext,
+    lending_market: TestLendingMarket,
+    oracles: Vec<TestOracle>,
+    test_reserve: TestReserve,
+}
+
+/// Adds a reserve priced by a pyth feed for each of `feeds`, a price and the slot it was
+/// published at, the first being the reserve's pyth oracle
+async fn setup(feeds: &[(u64, u64)]) -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let oracles: Vec<_> = feeds
+        .iter()
+        .map(|(price, pub_slot)| add_pyth_oracle(&mut test, *price, *pub_slot))
+        .collect();
+    let test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &oracles[0],
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(REFRESH_SLOT).unwrap();
+
+    TestSetup {
+        test_context,
+        lending_market,
+        oracles,
+        test_reserve,
+    }
+}
+
+/// Sets the reserve's extra pyth oracles to all but its first feed
+async fn set_extra_pyth_oracles(setup: &mut TestSetup) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(
+        &[set_reserve_oracles(
+            solend_program::id(),
+            MAX_ORACLE_PRICE_DIVERGENCE_BPS,
+            setup.test_reserve.pubkey,
+            setup.lending_market.pubkey,
+            setup.lending_market.owner.pubkey(),
+            setup.oracles[0].pyth_product_pubkey,
+            setup.oracles[0].pyth_price_pubkey,
+            setup.oracles[0].switchboard_feed_pubkey,
+            setup.oracles[1..]
+                .iter()
+                .map(|oracle| (oracle.pyth_product_pubkey, oracle.pyth_price_pubkey))
+                .collect(),
+        )],
+        Some(&setup.test_context.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.test_context.payer, &setup.lending_market.owner],
+        setup.test_context.last_blockhash,
+    );
+    setup
+        .test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+}
+
+/// Refreshes the reserve with the pyth price accounts of its first `feed_count` feeds
+async fn refresh(setup: &mut TestSetup, feed_count: usize) -> Result<(), TransportError> {
+    let mut instruction = refresh_reserve(
+        solend_program::id(),
+        setup.test_reserve.pubkey,
+        setup.oracles[0].pyth_price_pubkey,
+        setup.oracles[0].switchboard_feed_pubkey,
+    );
+    instruction.accounts.extend(
+        setup.oracles[1..feed_count]
+            .iter()
+            .map(|oracle| AccountMeta::new_readonly(oracle.pyth_price_pubkey, false)),
+    );
+
+    let mut transaction =
+        Transaction::new_with_payer(&[instruction], Some(&setup.test_context.payer.pubkey()));
+    transaction.sign(
+        &[&setup.test_context.payer],
+        setup.test_context.last_blockhash,
+    );
+    setup
+        .test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+}
+
+#[tokio::test]
+async fn test_outlier_ignored() {
+    let mut setup = setup(&[(20, REFRESH_SLOT), (21, REFRESH_SLOT), (200, REFRESH_SLOT)]).await;
+    assert!(set_extra_pyth_oracles(&mut setup).await.is_ok());
+
+    let reserve = setup
+        .test_reserve
+        .get_state(&mut setup.test_context.banks_client)
+        .await;
+    assert_eq!(
+        reserve.liquidity.extra_pyth_oracles(),
+        vec![
+            setup.oracles[1].pyth_price_pubkey,
+            setup.oracles[2].pyth_price_pubkey
+        ]
+    );
+
+    assert!(refresh(&mut setup, 3).await.is_ok());
+
+    let reserve = setup
+        .test_reserve
+        .get_state(&mut setup.test_context.banks_client)
+        .await;
+    assert_eq!(reserve.last_update.slot, REFRESH_SLOT);
+    assert_eq!(reserve.liquidity.market_price, Decimal::from(21u64));
+}
+
+#[tokio::test]
+async fn test_stale_feeds_ignored() {
+    // the reserve's pyth oracle and the outlier both stopped publishing
+    let mut setup = setup(&[(20, 0), (21, REFRESH_SLOT), (200, 0)]).await;
+    assert!(set_extra_pyth_oracles(&mut setup).await.is_ok());
+    assert!(refresh(&mut setup, 3).await.is_ok());
+
+    let reserve = setup
+        .test_reserve
+        .get_state(&mut setup.test_context.banks_client)
+        .await;
+    assert_eq!(reserve.last_update.slot, REFRESH_SLOT);
+    assert_eq!(reserve.liquidity.market_price, Decimal::from(21u64));
+}
+
+#[tokio::test]
+async fn test_fail_without_extra_pyth_oracles() {
+    let mut setup = setup(&[(20, REFRESH_SLOT), (21, REFRESH_SLOT), (200, REFRESH_SLOT)]).await;
+    assert!(set_extra_pyth_oracles(&mut setup).await.is_ok());
+
+    // leaving out the outlier's feed isn't allowed either
+    for feed_count in [1, 2] {
+        assert_eq!(
+            refresh(&mut setup, feed_count).await.unwrap_err().unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
+            )
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_unmigrated_reserve() {
+    let mut setup = setup(&[(20, REFRESH_SLOT), (21, REFRESH_SLOT)]).await;
+
+    // reserve accounts created before extra pyth oracles end where they start
+    let mut account = setup
+        .test_context
+        .banks_client
+        .get_account(setup.test_reserve.pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    account
+        .data
+        .truncate(Reserve::LEN - PUBKEY_BYTES * MAX_EXTRA_PYTH_ORACLES);
+    setup
+        .test_context
+        .set_account(&setup.test_reserve.pubkey, &account.into());
+
+    // they refresh as before
+    assert!(refresh(&mut setup, 1).await.is_ok());
+    let reserve = setup
+        .test_reserve
+        .get_state(&mut setup.test_context.banks_client)
+        .await;
+    assert_eq!(reserve.last_update.slot, REFRESH_SLOT);
+    assert_eq!(reserve.liquidity.market_price, Decimal::from(20u64));
+
+    // but have no room for extra pyth oracles until migrated
+    assert_eq!(
+        set_extra_pyth_oracles(&mut setup)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(0, InstructionError::AccountDataTooSmall)
+    );
+}
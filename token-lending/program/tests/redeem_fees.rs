@@ -7,11 +7,13 @@ use std::str::FromStr;
 use helpers::*;
 use solana_program_test::*;
 use solana_sdk::{
+    instruction::InstructionError,
     pubkey::Pubkey,
     signature::{Keypair, Signer},
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
 };
 use solend_program::{
+    error::LendingError,
     instruction::{redeem_fees, refresh_reserve},
     math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
     processor::process_instruction,
@@ -293,3 +295,116 @@ async fn test_success() {
         usdc_test_reserve.market_price
     );
 }
+
+/// A USDC reserve that has lent out liquidity and has not been refreshed yet
+async fn setup_usdc_reserve() -> (
+    BanksClient,
+    Keypair,
+    solana_program::hash::Hash,
+    TestLendingMarket,
+    TestOracle,
+    TestReserve,
+) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: 100 * FRACTIONAL_TO_USDC,
+            user_liquidity_amount: 100 * FRACTIONAL_TO_USDC,
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+    (
+        banks_client,
+        payer,
+        recent_blockhash,
+        lending_market,
+        usdc_oracle,
+        usdc_test_reserve,
+    )
+}
+
+#[tokio::test]
+async fn test_fail_stale_reserve() {
+    let (mut banks_client, payer, recent_blockhash, lending_market, _, usdc_test_reserve) =
+        setup_usdc_reserve().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[redeem_fees(
+            solend_program::id(),
+            usdc_test_reserve.pubkey,
+            usdc_test_reserve.config.fee_receiver,
+            usdc_test_reserve.liquidity_supply_pubkey,
+            lending_market.pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ReserveStale as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_destination_not_fee_receiver() {
+    let (mut banks_client, payer, recent_blockhash, lending_market, usdc_oracle, usdc_test_reserve) =
+        setup_usdc_reserve().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            ),
+            redeem_fees(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                lending_market.pubkey,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidFeeReceiver as u32)
+        )
+    );
+}
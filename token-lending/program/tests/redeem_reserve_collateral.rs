@@ -9,7 +9,9 @@ use solana_sdk::{
     transaction::Transaction,
 };
 use solend_program::{
-    instruction::redeem_reserve_collateral, processor::process_instruction,
+    instruction::redeem_reserve_collateral,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+    processor::process_instruction,
     state::INITIAL_COLLATERAL_RATIO,
 };
 use spl_token::instruction::approve;
@@ -102,3 +104,129 @@ async fn test_success() {
 
     assert!(usdc_reserve.liquidity.cumulative_borrow_rate_wads > old_borrow_rate);
 }
+
+#[tokio::test]
+async fn test_success_accrued_interest() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 200 * FRACTIONAL_TO_USDC;
+    const BORROWED_AMOUNT: u64 = 150 * FRACTIONAL_TO_USDC;
+    const COLLATERAL_AMOUNT: u64 = 10 * FRACTIONAL_TO_USDC * INITIAL_COLLATERAL_RATIO;
+
+    // 75% utilization on a steep curve so interest is visible after a few hundred slots
+    let mut reserve_config = test_reserve_config();
+    reserve_config.optimal_utilization_rate = 50;
+    reserve_config.max_borrow_rate = 250;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: COLLATERAL_AMOUNT,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            borrow_amount: BORROWED_AMOUNT,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(300).unwrap(); // clock.slot = 300
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let pre_usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    let initial_user_liquidity_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await;
+
+    let user_transfer_authority = Keypair::new();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            approve(
+                &spl_token::id(),
+                &usdc_test_reserve.user_collateral_pubkey,
+                &user_transfer_authority.pubkey(),
+                &user_accounts_owner.pubkey(),
+                &[],
+                COLLATERAL_AMOUNT,
+            )
+            .unwrap(),
+            redeem_reserve_collateral(
+                solend_program::id(),
+                COLLATERAL_AMOUNT,
+                usdc_test_reserve.user_collateral_pubkey,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.collateral_mint_pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                lending_market.pubkey,
+                user_transfer_authority.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+
+    transaction.sign(
+        &[&payer, &user_accounts_owner, &user_transfer_authority],
+        recent_blockhash,
+    );
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    let redeemed_amount =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_liquidity_pubkey).await
+            - initial_user_liquidity_balance;
+
+    // grow the debt by the borrow index accrued during the redeem, net of protocol fees
+    let index_growth = usdc_reserve
+        .liquidity
+        .cumulative_borrow_rate_wads
+        .try_div(pre_usdc_reserve.liquidity.cumulative_borrow_rate_wads)
+        .unwrap();
+    let accrued_supply = Decimal::from(pre_usdc_reserve.liquidity.available_amount)
+        .try_add(
+            pre_usdc_reserve
+                .liquidity
+                .borrowed_amount_wads
+                .try_mul(index_growth)
+                .unwrap(),
+        )
+        .unwrap()
+        .try_sub(usdc_reserve.liquidity.accumulated_protocol_fees_wads)
+        .unwrap();
+    let expected_amount = Decimal::from(COLLATERAL_AMOUNT)
+        .try_mul(accrued_supply)
+        .unwrap()
+        .try_div(pre_usdc_reserve.collateral.mint_total_supply)
+        .unwrap()
+        .try_floor_u64()
+        .unwrap();
+
+    assert!(index_growth > Decimal::one());
+    assert!(redeemed_amount > COLLATERAL_AMOUNT / INITIAL_COLLATERAL_RATIO);
+    assert!(
+        redeemed_amount.max(expected_amount) - redeemed_amount.min(expected_amount) <= 1,
+        "redeemed {} expected {}",
+        redeemed_amount,
+        expected_amount
+    );
+}
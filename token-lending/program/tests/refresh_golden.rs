@@ -0,0 +1,517 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::*;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::{refresh_obligation, refresh_reserve},
+    math::Decimal,
+    processor::process_instruction,
+    state::{Obligation, Reserve, ReserveConfig, INITIAL_COLLATERAL_RATIO, SLOTS_PER_YEAR},
+};
+use std::str::FromStr;
+
+// Golden master for the refresh accounting math. Each fixture records a set of inputs and the
+// exact values the program produced after refreshing them. A change to any of these numbers
+// must be deliberate: re-record the fixture from the assertion output and explain the
+// difference in review.
+
+/// Slot the reserves and obligations were last updated at, past the first pyth staleness window
+const LAST_UPDATE_SLOT: u64 = 240;
+
+struct RateFixture {
+    min_borrow_rate: u8,
+    optimal_borrow_rate: u8,
+    max_borrow_rate: u8,
+    optimal_utilization_rate: u8,
+    protocol_take_rate: u8,
+}
+
+struct ReserveFixture {
+    name: &'static str,
+    rates: RateFixture,
+    liquidity_amount: u64,
+    borrow_amount: u64,
+    initial_borrow_rate: u8,
+    price: u64,
+    slots_elapsed: u64,
+    expected: ReserveGolden,
+}
+
+/// Reserve values after refresh, as raw wads
+#[derive(Debug, PartialEq)]
+struct ReserveGolden {
+    cumulative_borrow_rate_wads: u128,
+    borrowed_amount_wads: u128,
+    accumulated_protocol_fees_wads: u128,
+    market_price: u128,
+}
+
+impl From<&Reserve> for ReserveGolden {
+    fn from(reserve: &Reserve) -> Self {
+        Self {
+            cumulative_borrow_rate_wads: scaled(reserve.liquidity.cumulative_borrow_rate_wads),
+            borrowed_amount_wads: scaled(reserve.liquidity.borrowed_amount_wads),
+            accumulated_protocol_fees_wads: scaled(
+                reserve.liquidity.accumulated_protocol_fees_wads,
+            ),
+            market_price: scaled(reserve.liquidity.market_price),
+        }
+    }
+}
+
+struct ObligationFixture {
+    name: &'static str,
+    loan_to_value_ratio: u8,
+    liquidation_threshold: u8,
+    borrow_rates: RateFixture,
+    sol_deposit_lamports: u64,
+    usdc_borrow_fractional: u64,
+    sol_price: u64,
+    slots_elapsed: u64,
+    expected: ObligationGolden,
+}
+
+/// Obligation values after refresh, as raw wads
+#[derive(Debug, PartialEq)]
+struct ObligationGolden {
+    deposited_value: u128,
+    borrowed_value: u128,
+    allowed_borrow_value: u128,
+    unhealthy_borrow_value: u128,
+    borrowed_amount_wads: u128,
+    cumulative_borrow_rate_wads: u128,
+}
+
+impl From<&Obligation> for ObligationGolden {
+    fn from(obligation: &Obligation) -> Self {
+        Self {
+            deposited_value: scaled(obligation.deposited_value),
+            borrowed_value: scaled(obligation.borrowed_value),
+            allowed_borrow_value: scaled(obligation.allowed_borrow_value),
+            unhealthy_borrow_value: scaled(obligation.unhealthy_borrow_value),
+            borrowed_amount_wads: scaled(obligation.borrows[0].borrowed_amount_wads),
+            cumulative_borrow_rate_wads: scaled(obligation.borrows[0].cumulative_borrow_rate_wads),
+        }
+    }
+}
+
+fn scaled(value: Decimal) -> u128 {
+    value.to_scaled_val().unwrap()
+}
+
+fn reserve_fixtures() -> Vec<ReserveFixture> {
+    vec![
+        ReserveFixture {
+            name: "below optimal utilization",
+            rates: RateFixture {
+                min_borrow_rate: 0,
+                optimal_borrow_rate: 4,
+                max_borrow_rate: 30,
+                optimal_utilization_rate: 80,
+                protocol_take_rate: 10,
+            },
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            borrow_amount: 500 * FRACTIONAL_TO_USDC,
+            initial_borrow_rate: 0,
+            price: 1,
+            slots_elapsed: 100,
+            expected: ReserveGolden {
+                cumulative_borrow_rate_wads: 1_000_000_039_637_240_645,
+                borrowed_amount_wads: 500_000_019_818_620_322_500_000_000,
+                accumulated_protocol_fees_wads: 1_981_862_032_250_000_000,
+                market_price: 1_000_000_000_000_000_000,
+            },
+        },
+        ReserveFixture {
+            name: "above optimal utilization",
+            rates: RateFixture {
+                min_borrow_rate: 0,
+                optimal_borrow_rate: 4,
+                max_borrow_rate: 30,
+                optimal_utilization_rate: 80,
+                protocol_take_rate: 10,
+            },
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            borrow_amount: 900 * FRACTIONAL_TO_USDC,
+            initial_borrow_rate: 0,
+            price: 1,
+            slots_elapsed: 1_000,
+            expected: ReserveGolden {
+                cumulative_borrow_rate_wads: 1_000_002_695_335_946_506,
+                borrowed_amount_wads: 900_002_425_802_351_855_400_000_000,
+                accumulated_protocol_fees_wads: 242_580_235_185_540_000_000,
+                market_price: 1_000_000_000_000_000_000,
+            },
+        },
+        ReserveFixture {
+            name: "fixed rate over a quarter year",
+            rates: RateFixture {
+                min_borrow_rate: 1,
+                optimal_borrow_rate: 1,
+                max_borrow_rate: 30,
+                optimal_utilization_rate: 100,
+                protocol_take_rate: 0,
+            },
+            liquidity_amount: 100 * FRACTIONAL_TO_USDC,
+            borrow_amount: 100 * FRACTIONAL_TO_USDC,
+            initial_borrow_rate: 0,
+            price: 1,
+            slots_elapsed: SLOTS_PER_YEAR / 4,
+            expected: ReserveGolden {
+                cumulative_borrow_rate_wads: 1_002_503_127_588_475_360,
+                borrowed_amount_wads: 100_250_312_758_847_536_000_000_000,
+                accumulated_protocol_fees_wads: 0,
+                market_price: 1_000_000_000_000_000_000,
+            },
+        },
+        ReserveFixture {
+            name: "compounded index with protocol take",
+            rates: RateFixture {
+                min_borrow_rate: 2,
+                optimal_borrow_rate: 10,
+                max_borrow_rate: 150,
+                optimal_utilization_rate: 60,
+                protocol_take_rate: 25,
+            },
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            borrow_amount: 700 * FRACTIONAL_TO_USDC,
+            initial_borrow_rate: 5,
+            price: 3,
+            slots_elapsed: 10_000,
+            expected: ReserveGolden {
+                cumulative_borrow_rate_wads: 1_050_074_917_055_805_841,
+                borrowed_amount_wads: 700_049_944_703_870_560_800_000_000,
+                accumulated_protocol_fees_wads: 12_486_175_967_640_200_000_000,
+                market_price: 3_000_000_000_000_000_000,
+            },
+        },
+        ReserveFixture {
+            name: "no borrows",
+            rates: RateFixture {
+                min_borrow_rate: 0,
+                optimal_borrow_rate: 4,
+                max_borrow_rate: 30,
+                optimal_utilization_rate: 80,
+                protocol_take_rate: 10,
+            },
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            borrow_amount: 0,
+            initial_borrow_rate: 0,
+            price: 1,
+            slots_elapsed: 500,
+            expected: ReserveGolden {
+                cumulative_borrow_rate_wads: 1_000_000_000_000_000_000,
+                borrowed_amount_wads: 0,
+                accumulated_protocol_fees_wads: 0,
+                market_price: 1_000_000_000_000_000_000,
+            },
+        },
+    ]
+}
+
+fn obligation_fixtures() -> Vec<ObligationFixture> {
+    vec![
+        ObligationFixture {
+            name: "healthy",
+            loan_to_value_ratio: 50,
+            liquidation_threshold: 55,
+            borrow_rates: RateFixture {
+                min_borrow_rate: 0,
+                optimal_borrow_rate: 4,
+                max_borrow_rate: 30,
+                optimal_utilization_rate: 80,
+                protocol_take_rate: 10,
+            },
+            sol_deposit_lamports: 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO,
+            usdc_borrow_fractional: 1_000 * FRACTIONAL_TO_USDC,
+            sol_price: 20,
+            slots_elapsed: 100,
+            expected: ObligationGolden {
+                deposited_value: 2_000_000_000_000_000_000_000,
+                borrowed_value: 1_000_000_039_637_240_645_000,
+                allowed_borrow_value: 1_000_000_000_000_000_000_000,
+                unhealthy_borrow_value: 1_100_000_000_000_000_000_000,
+                borrowed_amount_wads: 1_000_000_039_637_240_645_000_000_000,
+                cumulative_borrow_rate_wads: 1_000_000_039_637_240_645,
+            },
+        },
+        ObligationFixture {
+            name: "over the borrow limit after interest",
+            loan_to_value_ratio: 75,
+            liquidation_threshold: 80,
+            borrow_rates: RateFixture {
+                min_borrow_rate: 5,
+                optimal_borrow_rate: 20,
+                max_borrow_rate: 200,
+                optimal_utilization_rate: 50,
+                protocol_take_rate: 20,
+            },
+            sol_deposit_lamports: 10 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO,
+            usdc_borrow_fractional: 150 * FRACTIONAL_TO_USDC,
+            sol_price: 20,
+            slots_elapsed: 100_000,
+            expected: ObligationGolden {
+                deposited_value: 200_000_000_000_000_000_000,
+                borrowed_value: 150_047_572_230_019_838_700,
+                allowed_borrow_value: 150_000_000_000_000_000_000,
+                unhealthy_borrow_value: 160_000_000_000_000_000_000,
+                borrowed_amount_wads: 150_047_572_230_019_838_700_000_000,
+                cumulative_borrow_rate_wads: 1_000_317_148_200_132_258,
+            },
+        },
+        ObligationFixture {
+            name: "fixed rate over a month",
+            loan_to_value_ratio: 60,
+            liquidation_threshold: 70,
+            borrow_rates: RateFixture {
+                min_borrow_rate: 3,
+                optimal_borrow_rate: 3,
+                max_borrow_rate: 30,
+                optimal_utilization_rate: 100,
+                protocol_take_rate: 10,
+            },
+            sol_deposit_lamports: LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO,
+            usdc_borrow_fractional: 5 * FRACTIONAL_TO_USDC,
+            sol_price: 37,
+            slots_elapsed: SLOTS_PER_YEAR / 12,
+            expected: ObligationGolden {
+                deposited_value: 37_000_000_000_000_000_000,
+                borrowed_value: 5_012_515_637_992_977_165,
+                allowed_borrow_value: 22_200_000_000_000_000_000,
+                unhealthy_borrow_value: 25_900_000_000_000_000_000,
+                borrowed_amount_wads: 5_012_515_637_992_977_165_000_000,
+                cumulative_borrow_rate_wads: 1_002_503_127_598_595_433,
+            },
+        },
+    ]
+}
+
+fn add_oracle_at(
+    test: &mut ProgramTest,
+    product: &str,
+    price_account: &str,
+    switchboard_feed: &str,
+    price: u64,
+    valid_slot: u64,
+) -> TestOracle {
+    add_oracle(
+        test,
+        Pubkey::from_str(product).unwrap(),
+        Pubkey::from_str(price_account).unwrap(),
+        Pubkey::from_str(switchboard_feed).unwrap(),
+        Decimal::from(price),
+        valid_slot,
+    )
+}
+
+fn config_with_rates(rates: &RateFixture) -> ReserveConfig {
+    let mut config = test_reserve_config();
+    config.min_borrow_rate = rates.min_borrow_rate;
+    config.optimal_borrow_rate = rates.optimal_borrow_rate;
+    config.max_borrow_rate = rates.max_borrow_rate;
+    config.optimal_utilization_rate = rates.optimal_utilization_rate;
+    config.protocol_take_rate = rates.protocol_take_rate;
+    config
+}
+
+#[tokio::test]
+async fn test_refresh_reserve_golden() {
+    for fixture in reserve_fixtures() {
+        let mut test = ProgramTest::new(
+            "solend_program",
+            solend_program::id(),
+            processor!(process_instruction),
+        );
+
+        let refresh_slot = LAST_UPDATE_SLOT + fixture.slots_elapsed;
+        let user_accounts_owner = Keypair::new();
+        let lending_market = add_lending_market(&mut test);
+
+        let usdc_mint = add_usdc_mint(&mut test);
+        let usdc_oracle = add_oracle_at(
+            &mut test,
+            SRM_PYTH_PRODUCT,
+            SRM_PYTH_PRICE,
+            SRM_SWITCHBOARD_FEED,
+            fixture.price,
+            refresh_slot,
+        );
+        let usdc_test_reserve = add_reserve(
+            &mut test,
+            &lending_market,
+            &usdc_oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                liquidity_amount: fixture.liquidity_amount,
+                borrow_amount: fixture.borrow_amount,
+                initial_borrow_rate: fixture.initial_borrow_rate,
+                liquidity_mint_decimals: usdc_mint.decimals,
+                liquidity_mint_pubkey: usdc_mint.pubkey,
+                config: config_with_rates(&fixture.rates),
+                slots_elapsed: LAST_UPDATE_SLOT - 1,
+                ..AddReserveArgs::default()
+            },
+        );
+
+        let mut test_context = test.start_with_context().await;
+        test_context.warp_to_slot(refresh_slot).unwrap();
+
+        let ProgramTestContext {
+            mut banks_client,
+            payer,
+            last_blockhash: recent_blockhash,
+            ..
+        } = test_context;
+
+        let mut transaction = Transaction::new_with_payer(
+            &[refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+        let reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+        assert_eq!(
+            ReserveGolden::from(&reserve),
+            fixture.expected,
+            "reserve fixture {}",
+            fixture.name
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_refresh_obligation_golden() {
+    for fixture in obligation_fixtures() {
+        let mut test = ProgramTest::new(
+            "solend_program",
+            solend_program::id(),
+            processor!(process_instruction),
+        );
+
+        let refresh_slot = LAST_UPDATE_SLOT + fixture.slots_elapsed;
+        let user_accounts_owner = Keypair::new();
+        let lending_market = add_lending_market(&mut test);
+
+        let mut sol_config = test_reserve_config();
+        sol_config.loan_to_value_ratio = fixture.loan_to_value_ratio;
+        sol_config.liquidation_threshold = fixture.liquidation_threshold;
+
+        let sol_oracle = add_oracle_at(
+            &mut test,
+            SOL_PYTH_PRODUCT,
+            SOL_PYTH_PRICE,
+            SOL_SWITCHBOARD_FEED,
+            fixture.sol_price,
+            refresh_slot,
+        );
+        let sol_test_reserve = add_reserve(
+            &mut test,
+            &lending_market,
+            &sol_oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                collateral_amount: fixture.sol_deposit_lamports,
+                liquidity_amount: fixture.sol_deposit_lamports / INITIAL_COLLATERAL_RATIO,
+                liquidity_mint_decimals: 9,
+                liquidity_mint_pubkey: spl_token::native_mint::id(),
+                config: sol_config,
+                slots_elapsed: LAST_UPDATE_SLOT - 1,
+                ..AddReserveArgs::default()
+            },
+        );
+
+        let usdc_mint = add_usdc_mint(&mut test);
+        let usdc_oracle = add_oracle_at(
+            &mut test,
+            SRM_PYTH_PRODUCT,
+            SRM_PYTH_PRICE,
+            SRM_SWITCHBOARD_FEED,
+            1,
+            refresh_slot,
+        );
+        let usdc_test_reserve = add_reserve(
+            &mut test,
+            &lending_market,
+            &usdc_oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                liquidity_amount: 2 * fixture.usdc_borrow_fractional,
+                borrow_amount: fixture.usdc_borrow_fractional,
+                liquidity_mint_decimals: usdc_mint.decimals,
+                liquidity_mint_pubkey: usdc_mint.pubkey,
+                config: config_with_rates(&fixture.borrow_rates),
+                slots_elapsed: LAST_UPDATE_SLOT - 1,
+                ..AddReserveArgs::default()
+            },
+        );
+
+        let test_obligation = add_obligation(
+            &mut test,
+            &lending_market,
+            &user_accounts_owner,
+            AddObligationArgs {
+                deposits: &[(&sol_test_reserve, fixture.sol_deposit_lamports)],
+                borrows: &[(&usdc_test_reserve, fixture.usdc_borrow_fractional)],
+                slots_elapsed: LAST_UPDATE_SLOT - 1,
+                ..AddObligationArgs::default()
+            },
+        );
+
+        let mut test_context = test.start_with_context().await;
+        test_context.warp_to_slot(refresh_slot).unwrap();
+
+        let ProgramTestContext {
+            mut banks_client,
+            payer,
+            last_blockhash: recent_blockhash,
+            ..
+        } = test_context;
+
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                refresh_reserve(
+                    solend_program::id(),
+                    sol_test_reserve.pubkey,
+                    sol_oracle.pyth_price_pubkey,
+                    sol_oracle.switchboard_feed_pubkey,
+                ),
+                refresh_reserve(
+                    solend_program::id(),
+                    usdc_test_reserve.pubkey,
+                    usdc_oracle.pyth_price_pubkey,
+                    usdc_oracle.switchboard_feed_pubkey,
+                ),
+                refresh_obligation(
+                    solend_program::id(),
+                    test_obligation.pubkey,
+                    vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                    lending_market.pubkey,
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+        let obligation = test_obligation.get_state(&mut banks_client).await;
+        assert_eq!(
+            ObligationGolden::from(&obligation),
+            fixture.expected,
+            "obligation fixture {}",
+            fixture.name
+        );
+    }
+}
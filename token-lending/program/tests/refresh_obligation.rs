@@ -5,16 +5,22 @@ mod helpers;
 use helpers::*;
 use solana_program_test::*;
 use solana_sdk::{
+    account::AccountSharedData,
+    instruction::InstructionError,
     signature::{Keypair, Signer},
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
 };
 use solend_program::math::{Rate, TryAdd, TryMul, TrySub};
 use solend_program::state::SLOTS_PER_YEAR;
 use solend_program::{
-    instruction::{refresh_obligation, refresh_reserve},
+    error::LendingError,
+    instruction::{
+        apply_reserve_config_update, queue_reserve_config_update, refresh_obligation,
+        refresh_reserve, update_reserve_config, withdraw_obligation_collateral,
+    },
     math::{Decimal, TryDiv},
     processor::process_instruction,
-    state::INITIAL_COLLATERAL_RATIO,
+    state::{INITIAL_COLLATERAL_RATIO, PROGRAM_VERSION, RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS},
 };
 
 #[tokio::test]
@@ -122,6 +128,7 @@ async fn test_success() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
         ],
         Some(&payer.pubkey()),
@@ -181,3 +188,668 @@ async fn test_success() {
     assert_eq!(sol_reserve.liquidity.market_price, collateral_price,);
     assert_eq!(usdc_reserve.liquidity.market_price, liquidity_price,);
 }
+
+#[tokio::test]
+async fn test_liquidation_threshold_ramp() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 30;
+    reserve_config.liquidation_threshold = 55;
+
+    // priced when the refresh halfway through the ramp happens
+    let apply_slot = 100 + RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS;
+    let sol_oracle = add_pyth_oracle(&mut test, 20, apply_slot + 140);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(100).unwrap(); // clock.slot = 100
+
+    // lower the threshold from 55% to 35%, phased in over 280 slots once applied
+    let mut new_config = reserve_config;
+    new_config.liquidation_threshold_ramp_slots = 280;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            update_reserve_config(
+                solend_program::id(),
+                new_config,
+                sol_test_reserve.pubkey,
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                sol_oracle.pyth_product_pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            queue_reserve_config_update(
+                solend_program::id(),
+                30,
+                35,
+                reserve_config.protocol_take_rate,
+                sol_test_reserve.pubkey,
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+            ),
+        ],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&test_context.payer, &lending_market.owner],
+        test_context.last_blockhash,
+    );
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    test_context.warp_to_slot(apply_slot).unwrap();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[apply_reserve_config_update(
+            solend_program::id(),
+            sol_test_reserve.pubkey,
+        )],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    // halfway through the ramp
+    test_context.warp_to_slot(apply_slot + 140).unwrap();
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        ..
+    } = test_context;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+
+    let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let sol_reserve = sol_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(sol_reserve.config.liquidation_threshold, 35);
+    assert_eq!(sol_reserve.liquidation_threshold_ramp.start_threshold, 55);
+    assert_eq!(
+        sol_reserve.liquidation_threshold_ramp.start_slot,
+        apply_slot
+    );
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(
+        obligation.unhealthy_borrow_value,
+        obligation
+            .deposited_value
+            .try_mul(Rate::from_percent(45))
+            .unwrap()
+    );
+    assert_eq!(
+        obligation.allowed_borrow_value,
+        obligation
+            .deposited_value
+            .try_mul(Rate::from_percent(30))
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_refresh_twice_in_same_slot() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.min_borrow_rate = 1;
+    reserve_config.optimal_borrow_rate = 1;
+    reserve_config.optimal_utilization_rate = 100;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let refresh_instructions = [
+        refresh_reserve(
+            solend_program::id(),
+            usdc_test_reserve.pubkey,
+            usdc_oracle.pyth_price_pubkey,
+            usdc_oracle.switchboard_feed_pubkey,
+        ),
+        refresh_reserve(
+            solend_program::id(),
+            sol_test_reserve.pubkey,
+            sol_oracle.pyth_price_pubkey,
+            sol_oracle.switchboard_feed_pubkey,
+        ),
+        refresh_obligation(
+            solend_program::id(),
+            test_obligation.pubkey,
+            vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+            lending_market.pubkey,
+        ),
+    ];
+
+    let mut transaction = Transaction::new_with_payer(&refresh_instructions, Some(&payer.pubkey()));
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let sol_reserve = sol_test_reserve.get_state(&mut banks_client).await;
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(usdc_reserve.last_update.slot, 240);
+    assert_eq!(obligation.last_update.slot, 240);
+
+    // refresh everything again, twice over, without leaving the slot
+    let mut transaction = Transaction::new_with_payer(
+        &[&refresh_instructions[..], &refresh_instructions[..]].concat(),
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    assert_eq!(
+        sol_test_reserve.get_state(&mut banks_client).await,
+        sol_reserve
+    );
+    assert_eq!(
+        usdc_test_reserve.get_state(&mut banks_client).await,
+        usdc_reserve
+    );
+    assert_eq!(
+        test_obligation.get_state(&mut banks_client).await,
+        obligation
+    );
+}
+
+#[tokio::test]
+async fn test_deposits_without_borrows() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.deposited_value, Decimal::from(2_000u64));
+    assert_eq!(obligation.allowed_borrow_value, Decimal::from(1_000u64));
+    assert_eq!(obligation.borrowed_value, Decimal::zero());
+    assert_eq!(obligation.loan_to_value().unwrap(), Decimal::zero());
+    assert!(obligation.borrowed_value < obligation.unhealthy_borrow_value);
+
+    let initial_user_collateral_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.user_collateral_pubkey).await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[withdraw_obligation_collateral(
+            solend_program::id(),
+            u64::MAX,
+            sol_test_reserve.collateral_supply_pubkey,
+            sol_test_reserve.user_collateral_pubkey,
+            sol_test_reserve.pubkey,
+            test_obligation.pubkey,
+            lending_market.pubkey,
+            test_obligation.owner,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let user_collateral_balance =
+        get_token_balance(&mut banks_client, sol_test_reserve.user_collateral_pubkey).await;
+    assert_eq!(
+        user_collateral_balance,
+        initial_user_collateral_balance + SOL_DEPOSIT_AMOUNT_LAMPORTS
+    );
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert!(obligation.deposits.is_empty());
+}
+
+#[tokio::test]
+async fn test_fail_stale_deposit_reserve() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    // the deposit reserve is left unrefreshed
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ReserveStale as u32)
+        )
+    );
+}
+
+/// Adds a SOL deposit reserve and a USDC borrow reserve, and an obligation using both
+fn add_obligation_reserves(
+    test: &mut ProgramTest,
+) -> (TestObligation, [(TestReserve, TestOracle); 2]) {
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(test);
+
+    let sol_oracle = add_sol_oracle_switchboardv2(test);
+    let sol_test_reserve = add_reserve(
+        test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(test);
+    let usdc_test_reserve = add_reserve(
+        test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    (
+        test_obligation,
+        [
+            (sol_test_reserve, sol_oracle),
+            (usdc_test_reserve, usdc_oracle),
+        ],
+    )
+}
+
+#[tokio::test]
+async fn test_all_current_reserve_versions() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let (test_obligation, reserves) = add_obligation_reserves(&mut test);
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut instructions: Vec<_> = reserves
+        .iter()
+        .map(|(reserve, oracle)| {
+            refresh_reserve(
+                solend_program::id(),
+                reserve.pubkey,
+                oracle.pyth_price_pubkey,
+                oracle.switchboard_feed_pubkey,
+            )
+        })
+        .collect();
+    instructions.push(refresh_obligation(
+        solend_program::id(),
+        test_obligation.pubkey,
+        reserves.iter().map(|(reserve, _)| reserve.pubkey).collect(),
+        test_obligation.lending_market,
+    ));
+
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert!(!obligation.last_update.stale);
+    assert!(obligation.borrowed_value > Decimal::zero());
+}
+
+#[tokio::test]
+async fn test_fail_mismatched_reserve_version() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let (test_obligation, reserves) = add_obligation_reserves(&mut test);
+    let [(sol_test_reserve, sol_oracle), (usdc_test_reserve, _)] = &reserves;
+
+    let mut test_context = test.start_with_context().await;
+
+    // the borrow reserve is on a layout this program doesn't know how to value
+    let mut reserve_account = test_context
+        .banks_client
+        .get_account(usdc_test_reserve.pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    reserve_account.data[0] = PROGRAM_VERSION + 1;
+    test_context.set_account(
+        &usdc_test_reserve.pubkey,
+        &AccountSharedData::from(reserve_account),
+    );
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                test_obligation.lending_market,
+            ),
+        ],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert_eq!(
+        test_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ReserveVersionMismatch as u32)
+        )
+    );
+}
@@ -0,0 +1,115 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::refresh_obligation,
+    math::{Decimal, TryDiv},
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // $1,000 of 6 decimal USDC backs $200 of 9 decimal SOL
+    const USDC_DEPOSIT_AMOUNT_FRACTIONAL: u64 =
+        1_000 * FRACTIONAL_TO_USDC * INITIAL_COLLATERAL_RATIO;
+    const SOL_BORROW_AMOUNT_LAMPORTS: u64 = 10 * LAMPORTS_TO_SOL;
+    const USDC_RESERVE_COLLATERAL_FRACTIONAL: u64 = 2 * USDC_DEPOSIT_AMOUNT_FRACTIONAL;
+    const SOL_RESERVE_LIQUIDITY_LAMPORTS: u64 = 2 * SOL_BORROW_AMOUNT_LAMPORTS;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut usdc_reserve_config = test_reserve_config();
+    usdc_reserve_config.loan_to_value_ratio = 50;
+    usdc_reserve_config.liquidation_threshold = 80;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: USDC_RESERVE_COLLATERAL_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: usdc_reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: SOL_BORROW_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_RESERVE_LIQUIDITY_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&usdc_test_reserve, USDC_DEPOSIT_AMOUNT_FRACTIONAL)],
+            borrows: &[(&sol_test_reserve, SOL_BORROW_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_obligation(
+            solend_program::id(),
+            test_obligation.pubkey,
+            vec![usdc_test_reserve.pubkey, sol_test_reserve.pubkey],
+            lending_market.pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.deposits[0].market_value, Decimal::from(1_000u64));
+    assert_eq!(obligation.borrows[0].market_value, Decimal::from(200u64));
+    assert_eq!(obligation.deposited_value, Decimal::from(1_000u64));
+    assert_eq!(obligation.borrowed_value, Decimal::from(200u64));
+    assert_eq!(obligation.allowed_borrow_value, Decimal::from(500u64));
+    assert_eq!(obligation.unhealthy_borrow_value, Decimal::from(800u64));
+
+    // the positions are valued relative to each other the same as their dollar values
+    assert_eq!(
+        obligation
+            .borrowed_value
+            .try_div(obligation.deposited_value)
+            .unwrap(),
+        Decimal::from_percent(20)
+    );
+}
@@ -3,6 +3,7 @@
 mod helpers;
 
 use helpers::*;
+use pyth_sdk_solana::state::PriceAccount;
 use solana_program::{
     instruction::{AccountMeta, Instruction, InstructionError},
     pubkey::Pubkey,
@@ -10,6 +11,7 @@ use solana_program::{
 };
 use solana_program_test::*;
 use solana_sdk::{
+    account::AccountSharedData,
     signature::{Keypair, Signer},
     transaction::{Transaction, TransactionError},
 };
@@ -388,3 +390,526 @@ async fn test_pyth_price_stale() {
         ),
     );
 }
+
+#[tokio::test]
+async fn test_pyth_price_stale_custom_max_price_age() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let add_usdc_reserve = |test: &mut ProgramTest, max_price_age_slots| {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.max_price_age_slots = max_price_age_slots;
+        add_reserve(
+            test,
+            &lending_market,
+            &usdc_oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                borrow_amount: 100,
+                liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+                liquidity_mint_decimals: usdc_mint.decimals,
+                liquidity_mint_pubkey: usdc_mint.pubkey,
+                config: reserve_config,
+                slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+                ..AddReserveArgs::default()
+            },
+        )
+    };
+    let default_usdc_reserve = add_usdc_reserve(&mut test, 0);
+    let strict_usdc_reserve = add_usdc_reserve(&mut test, 100);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    // only the latest aggregate counts, published at slot 0
+    let mut pyth_price_account = test_context
+        .banks_client
+        .get_account(usdc_oracle.pyth_price_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    load_mut::<PriceAccount>(pyth_price_account.data.as_mut_slice())
+        .unwrap()
+        .prev_slot = 0;
+    test_context.set_account(
+        &usdc_oracle.pyth_price_pubkey,
+        &AccountSharedData::from(pyth_price_account),
+    );
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    // the price is within the default max age
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve_no_switchboard(
+            solend_program::id(),
+            default_usdc_reserve.pubkey,
+            usdc_oracle.pyth_price_pubkey,
+            false,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    // but too old for a reserve that only accepts prices from the last 100 slots
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve_no_switchboard(
+            solend_program::id(),
+            strict_usdc_reserve.pubkey,
+            usdc_oracle.pyth_price_pubkey,
+            false,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidOracleConfig as u32),
+        ),
+    );
+}
+
+#[tokio::test]
+async fn test_too_many_price_sources() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // limit to track compute unit increase
+    test.set_compute_max_units(31_000);
+
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let reserve_config = test_reserve_config();
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let sol_oracle = add_sol_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: 100,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let mut instruction = refresh_reserve(
+        solend_program::id(),
+        usdc_test_reserve.pubkey,
+        usdc_oracle.pyth_price_pubkey,
+        usdc_oracle.switchboard_feed_pubkey,
+    );
+    instruction.accounts.extend([
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new_readonly(sol_oracle.pyth_price_pubkey, false),
+        AccountMeta::new_readonly(Pubkey::new_unique(), false),
+        AccountMeta::new_readonly(Pubkey::new_unique(), false),
+    ]);
+
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::TooManyPriceSources as u32),
+        ),
+    );
+
+    let usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(usdc_reserve.last_update.slot, 239);
+}
+
+#[tokio::test]
+async fn test_non_positive_price() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+    let usdc_mint = add_usdc_mint(&mut test);
+    let zero_oracle = add_switchboardv2_oracle(&mut test, 0, 0);
+    let negative_oracle = add_switchboardv2_oracle(&mut test, -1_000, 3);
+
+    let mut add_usdc_reserve = |oracle: &TestOracle, allow_zero_price: bool| {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.allow_zero_price = allow_zero_price;
+        add_reserve(
+            &mut test,
+            &lending_market,
+            oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                liquidity_amount: 100 * FRACTIONAL_TO_USDC,
+                liquidity_mint_decimals: usdc_mint.decimals,
+                liquidity_mint_pubkey: usdc_mint.pubkey,
+                config: reserve_config,
+                ..AddReserveArgs::default()
+            },
+        )
+    };
+
+    let zero_test_reserve = add_usdc_reserve(&zero_oracle, false);
+    let negative_test_reserve = add_usdc_reserve(&negative_oracle, false);
+    let allowed_zero_test_reserve = add_usdc_reserve(&zero_oracle, true);
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    for (test_reserve, oracle) in [
+        (&zero_test_reserve, &zero_oracle),
+        (&negative_test_reserve, &negative_oracle),
+    ] {
+        let mut transaction = Transaction::new_with_payer(
+            &[refresh_reserve(
+                solend_program::id(),
+                test_reserve.pubkey,
+                oracle.pyth_price_pubkey,
+                oracle.switchboard_feed_pubkey,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(LendingError::InvalidOracleConfig as u32),
+            ),
+        );
+    }
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve(
+            solend_program::id(),
+            allowed_zero_test_reserve.pubkey,
+            zero_oracle.pyth_price_pubkey,
+            zero_oracle.switchboard_feed_pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let reserve = allowed_zero_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(reserve.liquidity.market_price, Decimal::zero());
+}
+
+#[tokio::test]
+async fn test_oracle_price_stuck() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.max_unchanged_price_slots = 200;
+
+    // price last changed when the reserve was created at slot 1
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * FRACTIONAL_TO_USDC,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    // price last changed when the reserve was created at slot 239
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve(
+            solend_program::id(),
+            usdc_test_reserve.pubkey,
+            usdc_oracle.pyth_price_pubkey,
+            usdc_oracle.switchboard_feed_pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::OraclePriceStuck as u32)
+        )
+    );
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve(
+            solend_program::id(),
+            sol_test_reserve.pubkey,
+            sol_oracle.pyth_price_pubkey,
+            sol_oracle.switchboard_feed_pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let sol_reserve = sol_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(sol_reserve.last_update.slot, 240);
+    assert_eq!(sol_reserve.liquidity.market_price_last_changed_slot, 239);
+}
+
+/// Refresh a reserve priced by a switchboard v2 feed declaring a heartbeat of
+/// `heartbeat_seconds`, which last updated at slot 0. The refresh succeeds at `fresh_slot` and
+/// fails as stale at `stale_slot`.
+async fn assert_switchboard_heartbeat(heartbeat_seconds: i64, fresh_slot: u64, stale_slot: u64) {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_switchboardv2_oracle_with_heartbeat(&mut test, 1, 0, heartbeat_seconds);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * FRACTIONAL_TO_USDC,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+
+    let refresh_at_slot = |test_context: &mut ProgramTestContext, slot: u64| {
+        test_context.warp_to_slot(slot).unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            )],
+            Some(&test_context.payer.pubkey()),
+        );
+        transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+        transaction
+    };
+
+    let transaction = refresh_at_slot(&mut test_context, fresh_slot);
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let reserve = usdc_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(reserve.last_update.slot, fresh_slot);
+
+    let transaction = refresh_at_slot(&mut test_context, stale_slot);
+    assert_eq!(
+        test_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidOracleConfig as u32),
+        ),
+    );
+}
+
+#[tokio::test]
+async fn test_switchboard_heartbeat_shorter_than_default() {
+    // a 30 second heartbeat is 60 slots, well within the default 240 slot window
+    assert_switchboard_heartbeat(30, 58, 60).await;
+}
+
+#[tokio::test]
+async fn test_switchboard_heartbeat_longer_than_default() {
+    // a 5 minute heartbeat is 600 slots, past the default 240 slot window
+    assert_switchboard_heartbeat(300, 300, 600).await;
+}
+
+#[tokio::test]
+async fn test_switchboard_std_deviation_too_wide() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+    let usdc_mint = add_usdc_mint(&mut test);
+    // $1.000 with a std deviation of $0.030 and $0.010
+    let wide_oracle = add_switchboardv2_oracle_with_std_deviation(&mut test, 1_000, 3, 30);
+    let narrow_oracle = add_switchboardv2_oracle_with_std_deviation(&mut test, 1_000, 3, 10);
+
+    let mut add_usdc_reserve = |oracle: &TestOracle, max_price_confidence_bps: u16| {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.max_price_confidence_bps = max_price_confidence_bps;
+        add_reserve(
+            &mut test,
+            &lending_market,
+            oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                liquidity_amount: 100 * FRACTIONAL_TO_USDC,
+                liquidity_mint_decimals: usdc_mint.decimals,
+                liquidity_mint_pubkey: usdc_mint.pubkey,
+                config: reserve_config,
+                ..AddReserveArgs::default()
+            },
+        )
+    };
+
+    // 2% allowed
+    let wide_test_reserve = add_usdc_reserve(&wide_oracle, 200);
+    let narrow_test_reserve = add_usdc_reserve(&narrow_oracle, 200);
+    let unchecked_test_reserve = add_usdc_reserve(&wide_oracle, 0);
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserve(
+            solend_program::id(),
+            wide_test_reserve.pubkey,
+            wide_oracle.pyth_price_pubkey,
+            wide_oracle.switchboard_feed_pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidOracleConfig as u32),
+        ),
+    );
+
+    for (test_reserve, oracle) in [
+        (&narrow_test_reserve, &narrow_oracle),
+        (&unchecked_test_reserve, &wide_oracle),
+    ] {
+        let mut transaction = Transaction::new_with_payer(
+            &[refresh_reserve(
+                solend_program::id(),
+                test_reserve.pubkey,
+                oracle.pyth_price_pubkey,
+                oracle.switchboard_feed_pubkey,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+        let reserve = test_reserve.get_state(&mut banks_client).await;
+        assert_eq!(reserve.liquidity.market_price, Decimal::one());
+    }
+}
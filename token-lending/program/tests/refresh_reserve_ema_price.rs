@@ -0,0 +1,94 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{instruction::refresh_reserve, math::Decimal, processor::process_instruction};
+use std::str::FromStr;
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_RESERVE_LIQUIDITY_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    // SOL spot has spiked to $25 while its moving average is still $20
+    let sol_oracle = add_oracle_with_ema_price(
+        &mut test,
+        Pubkey::from_str(SOL_PYTH_PRODUCT).unwrap(),
+        Pubkey::from_str(SOL_PYTH_PRICE).unwrap(),
+        Pubkey::from_str(SOL_SWITCHBOARD_FEED).unwrap(),
+        Decimal::from(25u64),
+        Decimal::from(20u64),
+        0,
+    );
+    let add_sol_reserve = |test: &mut ProgramTest, use_ema_price| {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.use_ema_price = use_ema_price;
+        add_reserve(
+            test,
+            &lending_market,
+            &sol_oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                liquidity_amount: SOL_RESERVE_LIQUIDITY_LAMPORTS,
+                liquidity_mint_decimals: 9,
+                liquidity_mint_pubkey: spl_token::native_mint::id(),
+                config: reserve_config,
+                slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+                ..AddReserveArgs::default()
+            },
+        )
+    };
+    let ema_sol_reserve = add_sol_reserve(&mut test, true);
+    let spot_sol_reserve = add_sol_reserve(&mut test, false);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                ema_sol_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                spot_sol_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let ema_reserve = ema_sol_reserve.get_state(&mut banks_client).await;
+    assert_eq!(ema_reserve.liquidity.market_price, Decimal::from(20u64));
+
+    let spot_reserve = spot_sol_reserve.get_state(&mut banks_client).await;
+    assert_eq!(spot_reserve.liquidity.market_price, Decimal::from(25u64));
+}
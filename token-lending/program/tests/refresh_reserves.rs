@@ -0,0 +1,264 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError, instruction::refresh_reserves, math::Decimal,
+    processor::process_instruction,
+};
+
+const BORROW_AMOUNT: u64 = 100 * FRACTIONAL_TO_USDC;
+
+/// Adds three reserves with half their liquidity lent out, last refreshed at slot 1
+fn add_reserves(test: &mut ProgramTest) -> Vec<(TestReserve, TestOracle)> {
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(test);
+
+    vec![
+        add_sol_oracle_switchboardv2(test),
+        add_usdc_oracle_switchboardv2(test),
+        add_switchboardv2_oracle(test, 2, 0),
+    ]
+    .into_iter()
+    .map(|oracle| {
+        let reserve = add_reserve(
+            test,
+            &lending_market,
+            &oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                borrow_amount: BORROW_AMOUNT,
+                liquidity_amount: 2 * BORROW_AMOUNT,
+                liquidity_mint_pubkey: Pubkey::new_unique(),
+                liquidity_mint_decimals: 6,
+                config: test_reserve_config(),
+                ..AddReserveArgs::default()
+            },
+        );
+        (reserve, oracle)
+    })
+    .collect()
+}
+
+/// Adds a reserve priced at the median of three pyth feeds published at slot 100, returning it
+/// along with its feeds
+fn add_median_reserve(test: &mut ProgramTest) -> (TestReserve, Vec<TestOracle>) {
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(test);
+    let oracles: Vec<_> = [20, 21, 200]
+        .iter()
+        .map(|price| add_pyth_oracle(test, *price, 100))
+        .collect();
+    // pyth prices can't be read before the slot passes the max price age
+    let mut config = test_reserve_config();
+    config.max_price_age_slots = 100;
+    let reserve = add_reserve(
+        test,
+        &lending_market,
+        &oracles[0],
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config,
+            extra_pyth_oracles: oracles[1..]
+                .iter()
+                .map(|oracle| oracle.pyth_price_pubkey)
+                .collect(),
+            ..AddReserveArgs::default()
+        },
+    );
+    (reserve, oracles)
+}
+
+fn reserve_accounts(
+    reserves: &[(TestReserve, TestOracle)],
+) -> Vec<(Pubkey, Pubkey, Pubkey, Vec<Pubkey>)> {
+    reserves
+        .iter()
+        .map(|(reserve, oracle)| {
+            (
+                reserve.pubkey,
+                oracle.pyth_price_pubkey,
+                oracle.switchboard_feed_pubkey,
+                vec![],
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reserves = add_reserves(&mut test);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(100).unwrap();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserves(
+            solend_program::id(),
+            reserve_accounts(&reserves),
+        )],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    for (test_reserve, oracle) in &reserves {
+        let reserve = test_reserve.get_state(&mut test_context.banks_client).await;
+        assert_eq!(reserve.last_update.slot, 100);
+        assert!(!reserve.last_update.stale);
+        assert_eq!(reserve.liquidity.market_price, oracle.price);
+        assert!(reserve.liquidity.cumulative_borrow_rate_wads > Decimal::one());
+        assert!(reserve.liquidity.borrowed_amount_wads > Decimal::from(BORROW_AMOUNT));
+    }
+}
+
+#[tokio::test]
+async fn test_fail_invalid_oracle() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reserves = add_reserves(&mut test);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(100).unwrap();
+
+    // the second reserve is given the third reserve's oracles
+    let mut accounts = reserve_accounts(&reserves);
+    accounts[1].1 = accounts[2].1;
+    accounts[1].2 = accounts[2].2;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserves(solend_program::id(), accounts)],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert_eq!(
+        test_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
+        )
+    );
+
+    // none of the reserves were refreshed
+    for (test_reserve, _) in &reserves {
+        let reserve = test_reserve.get_state(&mut test_context.banks_client).await;
+        assert_eq!(reserve.last_update.slot, 1);
+    }
+}
+
+#[tokio::test]
+async fn test_success_extra_pyth_oracles() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reserves = add_reserves(&mut test);
+
+    // ahead of the others
+    let (median_reserve, oracles) = add_median_reserve(&mut test);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(100).unwrap();
+
+    let mut accounts = reserve_accounts(&reserves);
+    accounts.insert(
+        0,
+        (
+            median_reserve.pubkey,
+            oracles[0].pyth_price_pubkey,
+            oracles[0].switchboard_feed_pubkey,
+            vec![oracles[1].pyth_price_pubkey, oracles[2].pyth_price_pubkey],
+        ),
+    );
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserves(solend_program::id(), accounts)],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let reserve = median_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(reserve.last_update.slot, 100);
+    assert_eq!(reserve.liquidity.market_price, Decimal::from(21u64));
+    for (test_reserve, oracle) in &reserves {
+        let reserve = test_reserve.get_state(&mut test_context.banks_client).await;
+        assert_eq!(reserve.last_update.slot, 100);
+        assert_eq!(reserve.liquidity.market_price, oracle.price);
+    }
+}
+
+#[tokio::test]
+async fn test_fail_missing_extra_pyth_oracles() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let (median_reserve, oracles) = add_median_reserve(&mut test);
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(100).unwrap();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[refresh_reserves(
+            solend_program::id(),
+            vec![(
+                median_reserve.pubkey,
+                oracles[0].pyth_price_pubkey,
+                oracles[0].switchboard_feed_pubkey,
+                vec![oracles[1].pyth_price_pubkey],
+            )],
+        )],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+    assert_eq!(
+        test_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+    );
+}
@@ -0,0 +1,177 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{renounce_lending_market_owner, set_lending_market_owner, update_lending_market},
+    processor::process_instruction,
+    state::LendingMarketConfigUpdate,
+    NULL_PUBKEY,
+};
+
+async fn process(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    instruction: Instruction,
+    signer: &Keypair,
+) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+    transaction.sign(&[payer, signer], recent_blockhash);
+    banks_client.process_transaction(transaction).await
+}
+
+fn assert_error(result: Result<(), TransportError>, error: LendingError) {
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let lending_market = add_lending_market(&mut test);
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    assert!(process(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        renounce_lending_market_owner(
+            solend_program::id(),
+            NULL_PUBKEY,
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+        ),
+        &lending_market.owner,
+    )
+    .await
+    .is_ok());
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(market.owner, NULL_PUBKEY);
+    assert!(market.is_owner_renounced());
+
+    // the former owner can no longer change the market or hand it back to anyone
+    assert_error(
+        process(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            update_lending_market(
+                solend_program::id(),
+                LendingMarketConfigUpdate {
+                    deleveraging: Some(true),
+                    ..LendingMarketConfigUpdate::default()
+                },
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                vec![],
+            ),
+            &lending_market.owner,
+        )
+        .await,
+        LendingError::Unauthorized,
+    );
+    assert_error(
+        process(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            set_lending_market_owner(
+                solend_program::id(),
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                lending_market.owner.pubkey(),
+            ),
+            &lending_market.owner,
+        )
+        .await,
+        LendingError::Unauthorized,
+    );
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(market.owner, NULL_PUBKEY);
+    assert!(!market.config.deleveraging);
+}
+
+#[tokio::test]
+async fn test_fail_without_confirmation() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let lending_market = add_lending_market(&mut test);
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    assert_error(
+        process(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            renounce_lending_market_owner(
+                solend_program::id(),
+                Pubkey::new_unique(),
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+            ),
+            &lending_market.owner,
+        )
+        .await,
+        LendingError::InvalidConfig,
+    );
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(market.owner, lending_market.owner.pubkey());
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let lending_market = add_lending_market(&mut test);
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let invalid_owner = Keypair::new();
+    assert_error(
+        process(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            renounce_lending_market_owner(
+                solend_program::id(),
+                NULL_PUBKEY,
+                lending_market.pubkey,
+                invalid_owner.pubkey(),
+            ),
+            &invalid_owner,
+        )
+        .await,
+        LendingError::InvalidMarketOwner,
+    );
+
+    let market = lending_market.get_state(&mut banks_client).await;
+    assert_eq!(market.owner, lending_market.owner.pubkey());
+}
@@ -0,0 +1,361 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{refresh_obligation, refresh_reserve, repay_and_withdraw},
+    math::Decimal,
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 200 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+// $3000 of the $4000 deposited, only allowed once the borrow is below $500
+const WITHDRAW_AMOUNT: u64 = 150 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            slots_elapsed: 239, // elapsed from 1; clock.slot = 240
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            slots_elapsed: 239, // elapsed from 1; clock.slot = 240
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            slots_elapsed: 239, // elapsed from 1; clock.slot = 240
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+fn repay_and_withdraw_transaction(setup: &TestSetup, repay_amount: u64) -> Transaction {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![
+                    setup.sol_test_reserve.pubkey,
+                    setup.usdc_test_reserve.pubkey,
+                ],
+                setup.lending_market.pubkey,
+            ),
+            repay_and_withdraw(
+                solend_program::id(),
+                repay_amount,
+                WITHDRAW_AMOUNT,
+                setup.usdc_test_reserve.user_liquidity_pubkey,
+                setup.usdc_test_reserve.liquidity_supply_pubkey,
+                setup.usdc_test_reserve.pubkey,
+                setup.sol_test_reserve.collateral_supply_pubkey,
+                setup.sol_test_reserve.user_collateral_pubkey,
+                setup.sol_test_reserve.pubkey,
+                setup.test_obligation.pubkey,
+                setup.lending_market.pubkey,
+                setup.test_obligation.owner,
+                setup.user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    transaction
+}
+
+#[tokio::test]
+async fn test_success() {
+    const REPAY_AMOUNT: u64 = 600 * FRACTIONAL_TO_USDC;
+
+    let mut setup = setup().await;
+    let transaction = repay_and_withdraw_transaction(&setup, REPAY_AMOUNT);
+
+    let banks_client = &mut setup.banks_client;
+    let initial_user_liquidity_balance =
+        get_token_balance(banks_client, setup.usdc_test_reserve.user_liquidity_pubkey).await;
+    let initial_user_collateral_balance =
+        get_token_balance(banks_client, setup.sol_test_reserve.user_collateral_pubkey).await;
+
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let user_liquidity_balance =
+        get_token_balance(banks_client, setup.usdc_test_reserve.user_liquidity_pubkey).await;
+    assert_eq!(
+        user_liquidity_balance,
+        initial_user_liquidity_balance - REPAY_AMOUNT
+    );
+    let user_collateral_balance =
+        get_token_balance(banks_client, setup.sol_test_reserve.user_collateral_pubkey).await;
+    assert_eq!(
+        user_collateral_balance,
+        initial_user_collateral_balance + WITHDRAW_AMOUNT
+    );
+
+    let obligation = setup.test_obligation.get_state(banks_client).await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        SOL_DEPOSIT_AMOUNT_LAMPORTS - WITHDRAW_AMOUNT
+    );
+    assert_eq!(
+        obligation.borrows[0].borrowed_amount_wads,
+        Decimal::from(USDC_BORROW_AMOUNT_FRACTIONAL - REPAY_AMOUNT)
+    );
+    assert!(obligation.last_update.stale);
+
+    // a fresh look at the obligation agrees that it is still healthy
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_test_reserve.liquidity_pyth_oracle_pubkey,
+                setup.usdc_test_reserve.liquidity_switchboard_oracle_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![
+                    setup.sol_test_reserve.pubkey,
+                    setup.usdc_test_reserve.pubkey,
+                ],
+                setup.lending_market.pubkey,
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(&[&setup.payer], setup.recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = setup.test_obligation.get_state(banks_client).await;
+    assert_eq!(obligation.borrowed_value, Decimal::from(400u64));
+    assert_eq!(obligation.deposited_value, Decimal::from(1_000u64));
+    assert!(obligation.borrowed_value <= obligation.allowed_borrow_value);
+}
+
+#[tokio::test]
+async fn test_withdraw_too_large_after_repay() {
+    const REPAY_AMOUNT: u64 = 100 * FRACTIONAL_TO_USDC;
+
+    let mut setup = setup().await;
+    let transaction = repay_and_withdraw_transaction(&setup, REPAY_AMOUNT);
+
+    assert_eq!(
+        setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_success_same_reserve() {
+    const USDC_DEPOSIT_AMOUNT: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL * INITIAL_COLLATERAL_RATIO;
+    const REPAY_AMOUNT: u64 = 600 * FRACTIONAL_TO_USDC;
+    // $1000 of the $2000 deposited, only allowed once the borrow is below $500
+    const USDC_WITHDRAW_AMOUNT: u64 = USDC_DEPOSIT_AMOUNT / 2;
+
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    // the obligation borrows from the reserve it deposited into
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: USDC_DEPOSIT_AMOUNT,
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            slots_elapsed: 239, // elapsed from 1; clock.slot = 240
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&usdc_test_reserve, USDC_DEPOSIT_AMOUNT)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            slots_elapsed: 239, // elapsed from 1; clock.slot = 240
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let initial_user_collateral_balance =
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_collateral_pubkey).await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![usdc_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            repay_and_withdraw(
+                solend_program::id(),
+                REPAY_AMOUNT,
+                USDC_WITHDRAW_AMOUNT,
+                usdc_test_reserve.user_liquidity_pubkey,
+                usdc_test_reserve.liquidity_supply_pubkey,
+                usdc_test_reserve.pubkey,
+                usdc_test_reserve.collateral_supply_pubkey,
+                usdc_test_reserve.user_collateral_pubkey,
+                usdc_test_reserve.pubkey,
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                test_obligation.owner,
+                user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    assert_eq!(
+        get_token_balance(&mut banks_client, usdc_test_reserve.user_collateral_pubkey).await,
+        initial_user_collateral_balance + USDC_WITHDRAW_AMOUNT
+    );
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        USDC_DEPOSIT_AMOUNT - USDC_WITHDRAW_AMOUNT
+    );
+    assert_eq!(
+        obligation.borrows[0].borrowed_amount_wads,
+        Decimal::from(USDC_BORROW_AMOUNT_FRACTIONAL - REPAY_AMOUNT)
+    );
+
+    // the reserve is left stale, as after a plain repay
+    let reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+    assert!(reserve.last_update.stale);
+}
@@ -0,0 +1,188 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{refresh_obligation, refresh_reserve, set_reserve_collateral_floor_price},
+    math::{Decimal, TryMul},
+    processor::process_instruction,
+    state::{Obligation, INITIAL_COLLATERAL_RATIO},
+};
+
+const SOL_DEPOSIT_AMOUNT: u64 = 100;
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 =
+    SOL_DEPOSIT_AMOUNT * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+
+/// Set the SOL reserve's collateral floor price, then refresh the obligation depositing SOL
+/// (priced at $20 spot) and return it.
+async fn refresh_with_floor_price(floor_price: Decimal) -> Obligation {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: test_reserve_config(),
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            set_reserve_collateral_floor_price(
+                solend_program::id(),
+                floor_price.to_scaled_val().unwrap(),
+                sol_test_reserve.pubkey,
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let sol_reserve = sol_test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(sol_reserve.collateral_floor_price, floor_price);
+    assert_eq!(sol_reserve.liquidity.market_price, Decimal::from(20u64));
+
+    test_obligation.get_state(&mut banks_client).await
+}
+
+#[tokio::test]
+async fn test_floor_below_spot() {
+    let obligation = refresh_with_floor_price(Decimal::from(15u64)).await;
+
+    let expected_value = Decimal::from(SOL_DEPOSIT_AMOUNT)
+        .try_mul(Decimal::from(15u64))
+        .unwrap();
+    assert_eq!(obligation.deposits[0].market_value, expected_value);
+    assert_eq!(obligation.deposited_value, expected_value);
+}
+
+#[tokio::test]
+async fn test_floor_above_spot() {
+    let obligation = refresh_with_floor_price(Decimal::from(25u64)).await;
+
+    let expected_value = Decimal::from(SOL_DEPOSIT_AMOUNT)
+        .try_mul(Decimal::from(20u64))
+        .unwrap();
+    assert_eq!(obligation.deposits[0].market_value, expected_value);
+    assert_eq!(obligation.deposited_value, expected_value);
+}
+
+#[tokio::test]
+async fn test_no_floor() {
+    let obligation = refresh_with_floor_price(Decimal::zero()).await;
+
+    let expected_value = Decimal::from(SOL_DEPOSIT_AMOUNT)
+        .try_mul(Decimal::from(20u64))
+        .unwrap();
+    assert_eq!(obligation.deposited_value, expected_value);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[set_reserve_collateral_floor_price(
+            solend_program::id(),
+            Decimal::from(15u64).to_scaled_val().unwrap(),
+            sol_test_reserve.pubkey,
+            lending_market.pubkey,
+            user_accounts_owner.pubkey(),
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+}
@@ -0,0 +1,323 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        apply_reserve_config_update, queue_reserve_config_update, update_reserve_config,
+    },
+    processor::process_instruction,
+    state::{PendingReserveConfigUpdate, RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS},
+};
+
+const QUEUE_SLOT: u64 = 100;
+
+#[tokio::test]
+async fn test_fail_apply_before_timelock() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mint = add_usdc_mint(&mut test);
+    let oracle = add_usdc_oracle(&mut test);
+    let test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 42,
+            liquidity_mint_decimals: mint.decimals,
+            liquidity_mint_pubkey: mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(QUEUE_SLOT).unwrap(); // clock.slot = 100
+
+    // raise the loan to value ratio from 50% to 52%
+    let mut transaction = Transaction::new_with_payer(
+        &[queue_reserve_config_update(
+            solend_program::id(),
+            52,
+            55,
+            10,
+            test_reserve.pubkey,
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+        )],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&test_context.payer, &lending_market.owner],
+        test_context.last_blockhash,
+    );
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let reserve = test_reserve.get_state(&mut test_context.banks_client).await;
+    assert_eq!(reserve.config.loan_to_value_ratio, 50);
+    assert_eq!(
+        reserve.pending_config_update,
+        PendingReserveConfigUpdate {
+            loan_to_value_ratio: 52,
+            liquidation_threshold: 55,
+            protocol_take_rate: 10,
+            applicable_slot: QUEUE_SLOT + RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS,
+        }
+    );
+
+    // one slot before the timelock elapses
+    test_context
+        .warp_to_slot(QUEUE_SLOT + RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS - 1)
+        .unwrap();
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        ..
+    } = test_context;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[apply_reserve_config_update(
+            solend_program::id(),
+            test_reserve.pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ConfigUpdateTimelocked as u32)
+        )
+    );
+
+    let reserve = test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(reserve.config.loan_to_value_ratio, 50);
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mint = add_usdc_mint(&mut test);
+    let oracle = add_usdc_oracle(&mut test);
+    let test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 42,
+            liquidity_mint_decimals: mint.decimals,
+            liquidity_mint_pubkey: mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(QUEUE_SLOT).unwrap(); // clock.slot = 100
+
+    let mut transaction = Transaction::new_with_payer(
+        &[queue_reserve_config_update(
+            solend_program::id(),
+            52,
+            55,
+            10,
+            test_reserve.pubkey,
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+        )],
+        Some(&test_context.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&test_context.payer, &lending_market.owner],
+        test_context.last_blockhash,
+    );
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    test_context
+        .warp_to_slot(QUEUE_SLOT + RESERVE_CONFIG_UPDATE_TIMELOCK_SLOTS)
+        .unwrap();
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        ..
+    } = test_context;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[apply_reserve_config_update(
+            solend_program::id(),
+            test_reserve.pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let reserve = test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(reserve.config.loan_to_value_ratio, 52);
+    assert_eq!(reserve.config.liquidation_threshold, 55);
+    assert_eq!(reserve.config.protocol_take_rate, 10);
+    assert_eq!(
+        reserve.pending_config_update,
+        PendingReserveConfigUpdate::default()
+    );
+}
+
+#[tokio::test]
+async fn test_fail_invalid_config() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mint = add_usdc_mint(&mut test);
+    let oracle = add_usdc_oracle(&mut test);
+    let test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 42,
+            liquidity_mint_decimals: mint.decimals,
+            liquidity_mint_pubkey: mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // loan to value ratio above the liquidation threshold
+    let mut transaction = Transaction::new_with_payer(
+        &[queue_reserve_config_update(
+            solend_program::id(),
+            60,
+            55,
+            10,
+            test_reserve.pubkey,
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidConfig as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_update_risk_parameters_directly() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mint = add_usdc_mint(&mut test);
+    let oracle = add_usdc_oracle(&mut test);
+    let test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 42,
+            liquidity_mint_decimals: mint.decimals,
+            liquidity_mint_pubkey: mint.pubkey,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // raising the loan to value ratio without going through the timelock
+    let mut new_config = test_reserve.config;
+    new_config.loan_to_value_ratio = 52;
+    let mut transaction = Transaction::new_with_payer(
+        &[update_reserve_config(
+            solend_program::id(),
+            new_config,
+            test_reserve.pubkey,
+            lending_market.pubkey,
+            lending_market.owner.pubkey(),
+            oracle.pyth_product_pubkey,
+            oracle.pyth_price_pubkey,
+            oracle.switchboard_feed_pubkey,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::RiskParameterTimelocked as u32)
+        )
+    );
+
+    let reserve = test_reserve.get_state(&mut banks_client).await;
+    assert_eq!(reserve.config.loan_to_value_ratio, 50);
+}
@@ -0,0 +1,250 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{borrow_obligation_liquidity, refresh_obligation, refresh_reserve},
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 10 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const SOL_RESERVE_LIQUIDITY_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL;
+const SOL_BORROW_AMOUNT_LAMPORTS: u64 = LAMPORTS_TO_SOL;
+const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 10 * FRACTIONAL_TO_USDC;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    strict_sol_reserve: TestReserve,
+    lenient_sol_reserve: TestReserve,
+    usdc_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+/// An obligation with SOL deposited in a reserve that rejects self borrows and in one that
+/// allows them, next to a USDC reserve it has no collateral in
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let add_sol_reserve = |test: &mut ProgramTest, reject_self_borrow| {
+        let mut reserve_config = test_reserve_config();
+        reserve_config.reject_self_borrow = reject_self_borrow;
+        add_reserve(
+            test,
+            &lending_market,
+            &sol_oracle,
+            &user_accounts_owner,
+            AddReserveArgs {
+                collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+                liquidity_amount: SOL_RESERVE_LIQUIDITY_LAMPORTS,
+                liquidity_mint_pubkey: spl_token::native_mint::id(),
+                liquidity_mint_decimals: 9,
+                config: reserve_config,
+                mark_fresh: true,
+                ..AddReserveArgs::default()
+            },
+        )
+    };
+    let strict_sol_reserve = add_sol_reserve(&mut test, true);
+    let lenient_sol_reserve = add_sol_reserve(&mut test, false);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.reject_self_borrow = true;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[
+                (&strict_sol_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS),
+                (&lenient_sol_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS),
+            ],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        strict_sol_reserve,
+        lenient_sol_reserve,
+        usdc_reserve,
+        test_obligation,
+    }
+}
+
+fn refresh_and_borrow(
+    setup: &TestSetup,
+    borrow_reserve: &TestReserve,
+    liquidity_amount: u64,
+) -> Transaction {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.strict_sol_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                setup.lenient_sol_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![
+                    setup.strict_sol_reserve.pubkey,
+                    setup.lenient_sol_reserve.pubkey,
+                ],
+                setup.lending_market.pubkey,
+            ),
+            borrow_obligation_liquidity(
+                solend_program::id(),
+                liquidity_amount,
+                borrow_reserve.liquidity_supply_pubkey,
+                borrow_reserve.user_liquidity_pubkey,
+                borrow_reserve.pubkey,
+                borrow_reserve.config.fee_receiver,
+                setup.test_obligation.pubkey,
+                setup.lending_market.pubkey,
+                setup.user_accounts_owner.pubkey(),
+                None,
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    transaction
+}
+
+#[tokio::test]
+async fn test_fail_self_borrow() {
+    let mut setup = setup().await;
+
+    let transaction = refresh_and_borrow(
+        &setup,
+        &setup.strict_sol_reserve,
+        SOL_BORROW_AMOUNT_LAMPORTS,
+    );
+    assert_eq!(
+        setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            4,
+            InstructionError::Custom(LendingError::SelfBorrow as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_success_other_asset() {
+    let mut setup = setup().await;
+
+    let transaction =
+        refresh_and_borrow(&setup, &setup.usdc_reserve, USDC_BORROW_AMOUNT_FRACTIONAL);
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(obligation.borrows.len(), 1);
+    assert_eq!(
+        obligation.borrows[0].borrow_reserve,
+        setup.usdc_reserve.pubkey
+    );
+}
+
+#[tokio::test]
+async fn test_success_self_borrow_allowed_by_default() {
+    let mut setup = setup().await;
+
+    let transaction = refresh_and_borrow(
+        &setup,
+        &setup.lenient_sol_reserve,
+        SOL_BORROW_AMOUNT_LAMPORTS,
+    );
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(obligation.borrows.len(), 1);
+    assert_eq!(
+        obligation.borrows[0].borrow_reserve,
+        setup.lenient_sol_reserve.pubkey
+    );
+}
@@ -0,0 +1,257 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        refresh_obligation, set_obligation_collateral_protection, withdraw_obligation_collateral,
+    },
+    math::Decimal,
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 200 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const SOL_PROTECTED_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup(borrow_amount: u64) -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let borrows = if borrow_amount > 0 {
+        vec![(&usdc_test_reserve, borrow_amount)]
+    } else {
+        vec![]
+    };
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &borrows,
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+fn set_protection_instruction(setup: &TestSetup, protected_amount: u64) -> Instruction {
+    set_obligation_collateral_protection(
+        solend_program::id(),
+        protected_amount,
+        setup.test_obligation.pubkey,
+        setup.sol_test_reserve.pubkey,
+        setup.lending_market.pubkey,
+        setup.user_accounts_owner.pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup(USDC_BORROW_AMOUNT_FRACTIONAL).await;
+    let refresh = refresh_obligation(
+        solend_program::id(),
+        setup.test_obligation.pubkey,
+        vec![
+            setup.sol_test_reserve.pubkey,
+            setup.usdc_test_reserve.pubkey,
+        ],
+        setup.lending_market.pubkey,
+    );
+
+    let mut transaction =
+        Transaction::new_with_payer(&[refresh.clone()], Some(&setup.payer.pubkey()));
+    transaction.sign(&[&setup.payer], setup.recent_blockhash);
+    let banks_client = &mut setup.banks_client;
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    // $4000 of SOL at 50% LTV and a 55% liquidation threshold
+    let obligation = setup.test_obligation.get_state(banks_client).await;
+    assert_eq!(obligation.allowed_borrow_value, Decimal::from(2_000u64));
+    assert_eq!(obligation.unhealthy_borrow_value, Decimal::from(2_200u64));
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            set_protection_instruction(&setup, SOL_PROTECTED_AMOUNT_LAMPORTS),
+            refresh,
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    let banks_client = &mut setup.banks_client;
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    // half the collateral no longer backs borrows but still keeps liquidators away
+    let obligation = setup.test_obligation.get_state(banks_client).await;
+    assert_eq!(
+        obligation.deposits[0].protected_amount,
+        SOL_PROTECTED_AMOUNT_LAMPORTS
+    );
+    assert_eq!(obligation.deposited_value, Decimal::from(4_000u64));
+    assert_eq!(obligation.allowed_borrow_value, Decimal::from(1_000u64));
+    assert_eq!(obligation.unhealthy_borrow_value, Decimal::from(2_200u64));
+}
+
+#[tokio::test]
+async fn test_withdraw_skips_protected_collateral() {
+    let mut setup = setup(0).await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            set_protection_instruction(&setup, SOL_PROTECTED_AMOUNT_LAMPORTS),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![setup.sol_test_reserve.pubkey],
+                setup.lending_market.pubkey,
+            ),
+            withdraw_obligation_collateral(
+                solend_program::id(),
+                u64::MAX,
+                setup.sol_test_reserve.collateral_supply_pubkey,
+                setup.sol_test_reserve.user_collateral_pubkey,
+                setup.sol_test_reserve.pubkey,
+                setup.test_obligation.pubkey,
+                setup.lending_market.pubkey,
+                setup.user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+
+    let banks_client = &mut setup.banks_client;
+    let initial_user_collateral_balance =
+        get_token_balance(banks_client, setup.sol_test_reserve.user_collateral_pubkey).await;
+
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let user_collateral_balance =
+        get_token_balance(banks_client, setup.sol_test_reserve.user_collateral_pubkey).await;
+    assert_eq!(
+        user_collateral_balance,
+        initial_user_collateral_balance + SOL_DEPOSIT_AMOUNT_LAMPORTS
+            - SOL_PROTECTED_AMOUNT_LAMPORTS
+    );
+
+    let obligation = setup.test_obligation.get_state(banks_client).await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        SOL_PROTECTED_AMOUNT_LAMPORTS
+    );
+    assert_eq!(
+        obligation.deposits[0].protected_amount,
+        SOL_PROTECTED_AMOUNT_LAMPORTS
+    );
+}
+
+#[tokio::test]
+async fn test_protected_amount_too_large() {
+    let mut setup = setup(0).await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[set_protection_instruction(
+            &setup,
+            SOL_DEPOSIT_AMOUNT_LAMPORTS + 1,
+        )],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+
+    assert_eq!(
+        setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidAmount as u32)
+        )
+    );
+}
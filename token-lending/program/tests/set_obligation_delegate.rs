@@ -0,0 +1,290 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        borrow_obligation_liquidity, deposit_obligation_collateral, refresh_obligation,
+        repay_obligation_liquidity, set_obligation_delegate, withdraw_obligation_collateral,
+    },
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+use spl_token::instruction::approve;
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    delegate: Keypair,
+    lending_market: TestLendingMarket,
+    sol_test_reserve: TestReserve,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            user_liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 2 * USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+    let delegate = Keypair::new();
+
+    // the owner designates the delegate and lets it spend the owner's tokens
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            set_obligation_delegate(
+                solend_program::id(),
+                delegate.pubkey(),
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                user_accounts_owner.pubkey(),
+            ),
+            approve(
+                &spl_token::id(),
+                &sol_test_reserve.user_collateral_pubkey,
+                &delegate.pubkey(),
+                &user_accounts_owner.pubkey(),
+                &[],
+                SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            )
+            .unwrap(),
+            approve(
+                &spl_token::id(),
+                &usdc_test_reserve.user_liquidity_pubkey,
+                &delegate.pubkey(),
+                &user_accounts_owner.pubkey(),
+                &[],
+                USDC_BORROW_AMOUNT_FRACTIONAL,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.delegate, delegate.pubkey());
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        delegate,
+        lending_market,
+        sol_test_reserve,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+/// Refresh the obligation, then run `instructions` signed by the delegate
+async fn process_as_delegate(
+    setup: &mut TestSetup,
+    instructions: &[Instruction],
+) -> Result<(), TransportError> {
+    let mut all_instructions = vec![refresh_obligation(
+        solend_program::id(),
+        setup.test_obligation.pubkey,
+        vec![
+            setup.sol_test_reserve.pubkey,
+            setup.usdc_test_reserve.pubkey,
+        ],
+        setup.lending_market.pubkey,
+    )];
+    all_instructions.extend_from_slice(instructions);
+    let mut transaction =
+        Transaction::new_with_payer(&all_instructions, Some(&setup.payer.pubkey()));
+    transaction.sign(&[&setup.payer, &setup.delegate], setup.recent_blockhash);
+    setup.banks_client.process_transaction(transaction).await
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup().await;
+
+    let instructions = [
+        deposit_obligation_collateral(
+            solend_program::id(),
+            SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            setup.sol_test_reserve.user_collateral_pubkey,
+            setup.sol_test_reserve.collateral_supply_pubkey,
+            setup.sol_test_reserve.pubkey,
+            setup.test_obligation.pubkey,
+            setup.lending_market.pubkey,
+            setup.delegate.pubkey(),
+            setup.delegate.pubkey(),
+        ),
+        repay_obligation_liquidity(
+            solend_program::id(),
+            USDC_BORROW_AMOUNT_FRACTIONAL,
+            setup.usdc_test_reserve.user_liquidity_pubkey,
+            setup.usdc_test_reserve.liquidity_supply_pubkey,
+            setup.usdc_test_reserve.pubkey,
+            setup.test_obligation.pubkey,
+            setup.lending_market.pubkey,
+            setup.delegate.pubkey(),
+        ),
+    ];
+    assert!(process_as_delegate(&mut setup, &instructions).await.is_ok());
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        2 * SOL_DEPOSIT_AMOUNT_LAMPORTS
+    );
+    assert!(obligation.borrows.is_empty());
+}
+
+#[tokio::test]
+async fn test_delegate_cannot_borrow() {
+    let mut setup = setup().await;
+
+    let instruction = borrow_obligation_liquidity(
+        solend_program::id(),
+        USDC_BORROW_AMOUNT_FRACTIONAL,
+        setup.usdc_test_reserve.liquidity_supply_pubkey,
+        setup.usdc_test_reserve.user_liquidity_pubkey,
+        setup.usdc_test_reserve.pubkey,
+        setup.usdc_test_reserve.config.fee_receiver,
+        setup.test_obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.delegate.pubkey(),
+        None,
+    );
+    assert_eq!(
+        process_as_delegate(&mut setup, &[instruction])
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidObligationOwner as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_delegate_cannot_withdraw() {
+    let mut setup = setup().await;
+
+    let instruction = withdraw_obligation_collateral(
+        solend_program::id(),
+        1,
+        setup.sol_test_reserve.collateral_supply_pubkey,
+        setup.sol_test_reserve.user_collateral_pubkey,
+        setup.sol_test_reserve.pubkey,
+        setup.test_obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.delegate.pubkey(),
+    );
+    assert_eq!(
+        process_as_delegate(&mut setup, &[instruction])
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidObligationOwner as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_delegate_cannot_set_delegate() {
+    let mut setup = setup().await;
+
+    let instruction = set_obligation_delegate(
+        solend_program::id(),
+        Pubkey::default(),
+        setup.test_obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.delegate.pubkey(),
+    );
+    assert_eq!(
+        process_as_delegate(&mut setup, &[instruction])
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidObligationOwner as u32)
+        )
+    );
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(obligation.delegate, setup.delegate.pubkey());
+}
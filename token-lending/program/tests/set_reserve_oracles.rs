@@ -0,0 +1,252 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use solend_program::{
+    error::LendingError, instruction::set_reserve_oracles, processor::process_instruction,
+    state::MAX_ORACLE_PRICE_DIVERGENCE_BPS,
+};
+
+// the new feed may be off from the reserve's $20 market price by up to 5%
+const MAX_PRICE_DIVERGENCE_BPS: u16 = 500;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    agreeing_oracle: TestOracle,
+    divergent_oracle: TestOracle,
+    sol_test_reserve: TestReserve,
+    unpriced_test_reserve: TestReserve,
+    zero_price_test_reserve: TestReserve,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    // $20.50 and $25
+    let agreeing_oracle = add_switchboardv2_oracle(&mut test, 2_050, 2);
+    let divergent_oracle = add_switchboardv2_oracle(&mut test, 25, 0);
+
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    // reserves whose stored market price is zero, one never refreshed and one allowing it
+    let zero_oracle = add_switchboardv2_oracle(&mut test, 0, 0);
+    let unpriced_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &zero_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut zero_price_config = test_reserve_config();
+    zero_price_config.allow_zero_price = true;
+    let zero_price_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &zero_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: zero_price_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        lending_market,
+        sol_oracle,
+        agreeing_oracle,
+        divergent_oracle,
+        sol_test_reserve,
+        unpriced_test_reserve,
+        zero_price_test_reserve,
+    }
+}
+
+async fn switch_oracles(
+    setup: &mut TestSetup,
+    reserve_pubkey: Pubkey,
+    max_price_divergence_bps: u16,
+    switchboard_feed_pubkey: Pubkey,
+) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(
+        &[set_reserve_oracles(
+            solend_program::id(),
+            max_price_divergence_bps,
+            reserve_pubkey,
+            setup.lending_market.pubkey,
+            setup.lending_market.owner.pubkey(),
+            setup.sol_oracle.pyth_product_pubkey,
+            setup.sol_oracle.pyth_price_pubkey,
+            switchboard_feed_pubkey,
+            vec![],
+        )],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.lending_market.owner],
+        setup.recent_blockhash,
+    );
+    setup.banks_client.process_transaction(transaction).await
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup().await;
+
+    let reserve_pubkey = setup.sol_test_reserve.pubkey;
+    let switchboard_feed_pubkey = setup.agreeing_oracle.switchboard_feed_pubkey;
+    assert!(switch_oracles(
+        &mut setup,
+        reserve_pubkey,
+        MAX_PRICE_DIVERGENCE_BPS,
+        switchboard_feed_pubkey
+    )
+    .await
+    .is_ok());
+
+    let reserve = setup
+        .sol_test_reserve
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        reserve.liquidity.switchboard_oracle_pubkey,
+        switchboard_feed_pubkey
+    );
+    // the price is only taken from the new feed on the next refresh
+    assert_eq!(reserve.liquidity.market_price, setup.sol_oracle.price);
+    assert!(reserve.last_update.stale);
+}
+
+#[tokio::test]
+async fn test_fail_price_divergence() {
+    let mut setup = setup().await;
+
+    let reserve_pubkey = setup.sol_test_reserve.pubkey;
+    let switchboard_feed_pubkey = setup.divergent_oracle.switchboard_feed_pubkey;
+    assert_eq!(
+        switch_oracles(
+            &mut setup,
+            reserve_pubkey,
+            MAX_PRICE_DIVERGENCE_BPS,
+            switchboard_feed_pubkey
+        )
+        .await
+        .unwrap_err()
+        .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::OraclePriceDivergence as u32)
+        )
+    );
+
+    let reserve = setup
+        .sol_test_reserve
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(
+        reserve.liquidity.switchboard_oracle_pubkey,
+        setup.sol_oracle.switchboard_feed_pubkey
+    );
+}
+
+#[tokio::test]
+async fn test_fail_divergence_tolerance_too_large() {
+    let mut setup = setup().await;
+
+    let reserve_pubkey = setup.sol_test_reserve.pubkey;
+    let switchboard_feed_pubkey = setup.divergent_oracle.switchboard_feed_pubkey;
+    assert_eq!(
+        switch_oracles(
+            &mut setup,
+            reserve_pubkey,
+            MAX_ORACLE_PRICE_DIVERGENCE_BPS + 1,
+            switchboard_feed_pubkey
+        )
+        .await
+        .unwrap_err()
+        .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidConfig as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_zero_market_price() {
+    let mut setup = setup().await;
+
+    let switchboard_feed_pubkey = setup.agreeing_oracle.switchboard_feed_pubkey;
+    for (reserve_pubkey, error) in [
+        (
+            setup.unpriced_test_reserve.pubkey,
+            LendingError::ReserveStale,
+        ),
+        (
+            setup.zero_price_test_reserve.pubkey,
+            LendingError::OraclePriceDivergence,
+        ),
+    ] {
+        assert_eq!(
+            switch_oracles(
+                &mut setup,
+                reserve_pubkey,
+                MAX_ORACLE_PRICE_DIVERGENCE_BPS,
+                switchboard_feed_pubkey
+            )
+            .await
+            .unwrap_err()
+            .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+        );
+    }
+}
@@ -0,0 +1,209 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::program_pack::Pack;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{refresh_obligation, refresh_reserve, snapshot_obligation},
+    math::Decimal,
+    processor::process_instruction,
+    state::{ObligationSnapshot, INITIAL_COLLATERAL_RATIO, OBLIGATION_SNAPSHOT_VERSION},
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 10 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 50 * FRACTIONAL_TO_USDC;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    lending_market: TestLendingMarket,
+    sol_oracle: TestOracle,
+    usdc_oracle: TestOracle,
+    sol_reserve: TestReserve,
+    usdc_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+/// An obligation borrowing USDC against SOL, neither of which has been refreshed yet
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        lending_market,
+        sol_oracle,
+        usdc_oracle,
+        sol_reserve,
+        usdc_reserve,
+        test_obligation,
+    }
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup().await;
+    log_data_capture::capture_log_data();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                setup.sol_reserve.pubkey,
+                setup.sol_oracle.pyth_price_pubkey,
+                setup.sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                setup.usdc_reserve.pubkey,
+                setup.usdc_oracle.pyth_price_pubkey,
+                setup.usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                vec![setup.sol_reserve.pubkey, setup.usdc_reserve.pubkey],
+                setup.lending_market.pubkey,
+            ),
+            snapshot_obligation(
+                solend_program::id(),
+                setup.test_obligation.pubkey,
+                setup.lending_market.pubkey,
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(&[&setup.payer], setup.recent_blockhash);
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let snapshots: Vec<ObligationSnapshot> = log_data_capture::logged_data()
+        .into_iter()
+        .filter_map(|fields| match fields.as_slice() {
+            [data] if data.len() == ObligationSnapshot::LEN => {
+                ObligationSnapshot::unpack_from_slice(data).ok()
+            }
+            _ => None,
+        })
+        .filter(|snapshot| snapshot.obligation_pubkey == setup.test_obligation.pubkey)
+        .collect();
+    assert_eq!(snapshots.len(), 1);
+    let snapshot = &snapshots[0];
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert_eq!(snapshot.version, OBLIGATION_SNAPSHOT_VERSION);
+    assert_eq!(snapshot.slot, obligation.last_update.slot);
+    assert_eq!(snapshot.obligation, obligation);
+
+    // $200 of SOL deposited against $50 of USDC borrowed
+    assert_eq!(snapshot.obligation.deposits.len(), 1);
+    assert_eq!(snapshot.obligation.borrows.len(), 1);
+    assert_eq!(
+        snapshot.obligation.deposits[0].deposit_reserve,
+        setup.sol_reserve.pubkey
+    );
+    assert_eq!(
+        snapshot.obligation.borrows[0].borrow_reserve,
+        setup.usdc_reserve.pubkey
+    );
+    assert_eq!(snapshot.obligation.deposited_value, Decimal::from(200u64));
+    assert_eq!(snapshot.obligation.borrowed_value, Decimal::from(50u64));
+}
+
+#[tokio::test]
+async fn test_fail_stale_obligation() {
+    let mut setup = setup().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[snapshot_obligation(
+            solend_program::id(),
+            setup.test_obligation.pubkey,
+            setup.lending_market.pubkey,
+        )],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(&[&setup.payer], setup.recent_blockhash);
+    assert_eq!(
+        setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ObligationStale as u32)
+        )
+    );
+}
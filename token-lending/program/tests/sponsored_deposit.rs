@@ -0,0 +1,354 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        deposit_obligation_collateral, refresh_obligation, refresh_reserve, update_lending_market,
+        withdraw_obligation_collateral,
+    },
+    processor::process_instruction,
+    state::{LendingMarketConfigUpdate, INITIAL_COLLATERAL_RATIO},
+};
+
+const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 10 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+
+/// Deposits the funder's collateral into an obligation without its owner signing
+fn sponsored_deposit(
+    sol_test_reserve: &TestReserve,
+    test_obligation: &TestObligation,
+    lending_market: &TestLendingMarket,
+    funder: Pubkey,
+) -> Instruction {
+    let mut instruction = deposit_obligation_collateral(
+        solend_program::id(),
+        SOL_DEPOSIT_AMOUNT_LAMPORTS,
+        sol_test_reserve.user_collateral_pubkey,
+        sol_test_reserve.collateral_supply_pubkey,
+        sol_test_reserve.pubkey,
+        test_obligation.pubkey,
+        lending_market.pubkey,
+        test_obligation.owner,
+        funder,
+    );
+    instruction.accounts[5].is_signer = false;
+    instruction
+}
+
+/// Refreshes the obligation and withdraws the sponsored deposit, signed by `signer`
+fn withdraw(
+    sol_test_reserve: &TestReserve,
+    sol_oracle: &TestOracle,
+    test_obligation: &TestObligation,
+    lending_market: &TestLendingMarket,
+    destination_collateral_pubkey: Pubkey,
+    signer: Pubkey,
+) -> Vec<Instruction> {
+    vec![
+        refresh_reserve(
+            solend_program::id(),
+            sol_test_reserve.pubkey,
+            sol_oracle.pyth_price_pubkey,
+            sol_oracle.switchboard_feed_pubkey,
+        ),
+        refresh_obligation(
+            solend_program::id(),
+            test_obligation.pubkey,
+            vec![sol_test_reserve.pubkey],
+            lending_market.pubkey,
+        ),
+        withdraw_obligation_collateral(
+            solend_program::id(),
+            SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            sol_test_reserve.collateral_supply_pubkey,
+            destination_collateral_pubkey,
+            sol_test_reserve.pubkey,
+            test_obligation.pubkey,
+            lending_market.pubkey,
+            signer,
+        ),
+    ]
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let funder = Keypair::new();
+    let obligation_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &funder,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    // sponsors can only add to collateral the obligation already has
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &obligation_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            update_lending_market(
+                solend_program::id(),
+                LendingMarketConfigUpdate {
+                    allow_sponsored_deposits: Some(true),
+                    ..LendingMarketConfigUpdate::default()
+                },
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                vec![],
+            ),
+            sponsored_deposit(
+                &sol_test_reserve,
+                &test_obligation,
+                &lending_market,
+                funder.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner, &funder], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.deposits.len(), 1);
+    assert_eq!(
+        obligation.deposits[0].deposit_reserve,
+        sol_test_reserve.pubkey
+    );
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        2 * SOL_DEPOSIT_AMOUNT_LAMPORTS
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, sol_test_reserve.user_collateral_pubkey).await,
+        0
+    );
+
+    // the funder has no claim on the deposit
+    let mut transaction = Transaction::new_with_payer(
+        &withdraw(
+            &sol_test_reserve,
+            &sol_oracle,
+            &test_obligation,
+            &lending_market,
+            sol_test_reserve.user_collateral_pubkey,
+            funder.pubkey(),
+        ),
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &funder], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            2,
+            InstructionError::Custom(LendingError::InvalidObligationOwner as u32)
+        )
+    );
+
+    // but the owner can withdraw it
+    let owner_collateral_pubkey = create_token_account(
+        &mut banks_client,
+        sol_test_reserve.collateral_mint_pubkey,
+        &payer,
+        Some(obligation_owner.pubkey()),
+        None,
+    )
+    .await;
+    let mut transaction = Transaction::new_with_payer(
+        &withdraw(
+            &sol_test_reserve,
+            &sol_oracle,
+            &test_obligation,
+            &lending_market,
+            owner_collateral_pubkey,
+            obligation_owner.pubkey(),
+        ),
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &obligation_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+    assert_eq!(
+        get_token_balance(&mut banks_client, owner_collateral_pubkey).await,
+        SOL_DEPOSIT_AMOUNT_LAMPORTS
+    );
+}
+
+#[tokio::test]
+async fn test_fail_sponsored_deposits_not_allowed() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let funder = Keypair::new();
+    let obligation_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &funder,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &obligation_owner,
+        AddObligationArgs::default(),
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[sponsored_deposit(
+            &sol_test_reserve,
+            &test_obligation,
+            &lending_market,
+            funder.pubkey(),
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &funder], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidSigner as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_sponsored_deposit_into_new_reserve() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let funder = Keypair::new();
+    let obligation_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &funder,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: test_reserve_config(),
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &obligation_owner,
+        AddObligationArgs::default(),
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // the obligation has no SOL collateral for a sponsor to add to
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            update_lending_market(
+                solend_program::id(),
+                LendingMarketConfigUpdate {
+                    allow_sponsored_deposits: Some(true),
+                    ..LendingMarketConfigUpdate::default()
+                },
+                lending_market.pubkey,
+                lending_market.owner.pubkey(),
+                vec![],
+            ),
+            sponsored_deposit(
+                &sol_test_reserve,
+                &test_obligation,
+                &lending_market,
+                funder.pubkey(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &lending_market.owner, &funder], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidSigner as u32)
+        )
+    );
+    assert!(test_obligation
+        .get_state(&mut banks_client)
+        .await
+        .deposits
+        .is_empty());
+}
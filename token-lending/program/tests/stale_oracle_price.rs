@@ -0,0 +1,190 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{refresh_obligation, refresh_reserve},
+    math::{Decimal, TryDiv, TryMul},
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+#[tokio::test]
+async fn test_cached_price_decays_then_fails() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 10 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 50 * FRACTIONAL_TO_USDC;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    // both feeds last updated at slot 0 and go stale at slot 60, after their 30 second heartbeat
+    let mut reserve_config = test_reserve_config();
+    reserve_config.max_stale_price_slots = 100;
+    reserve_config.stale_price_decay_bps = 10;
+
+    let sol_oracle = add_switchboardv2_oracle_with_heartbeat(&mut test, 20, 0, 30);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: 100 * LAMPORTS_TO_SOL,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.max_stale_price_slots = 100;
+    reserve_config.stale_price_decay_bps = 10;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_switchboardv2_oracle_with_heartbeat(&mut test, 1, 0, 30);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let mut test_context = test.start_with_context().await;
+
+    let refresh_at_slot = |test_context: &mut ProgramTestContext, slot: u64| {
+        test_context.warp_to_slot(slot).unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                refresh_reserve(
+                    solend_program::id(),
+                    sol_test_reserve.pubkey,
+                    sol_oracle.pyth_price_pubkey,
+                    sol_oracle.switchboard_feed_pubkey,
+                ),
+                refresh_reserve(
+                    solend_program::id(),
+                    usdc_test_reserve.pubkey,
+                    usdc_oracle.pyth_price_pubkey,
+                    usdc_oracle.switchboard_feed_pubkey,
+                ),
+                refresh_obligation(
+                    solend_program::id(),
+                    test_obligation.pubkey,
+                    vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                    lending_market.pubkey,
+                ),
+            ],
+            Some(&test_context.payer.pubkey()),
+        );
+        transaction.sign(&[&test_context.payer], test_context.last_blockhash);
+        transaction
+    };
+
+    // oracles are live, the obligation is valued at market
+    let transaction = refresh_at_slot(&mut test_context, 50);
+    assert!(test_context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    let sol_reserve = sol_test_reserve
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(sol_reserve.liquidity.oracle_price_slot, 50);
+    let obligation = test_obligation
+        .get_state(&mut test_context.banks_client)
+        .await;
+    assert_eq!(obligation.deposited_value, Decimal::from(200u64));
+    assert_eq!(
+        obligation.borrowed_value,
+        obligation.borrows[0]
+            .borrowed_amount_wads
+            .try_div(FRACTIONAL_TO_USDC)
+            .unwrap()
+    );
+
+    // through the outage the cached prices are used, 0.1% more conservatively every slot
+    for (slot, decay_percent) in [(70, 2u64), (100, 5), (150, 10)] {
+        let transaction = refresh_at_slot(&mut test_context, slot);
+        assert!(test_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .is_ok());
+
+        let sol_reserve = sol_test_reserve
+            .get_state(&mut test_context.banks_client)
+            .await;
+        assert_eq!(sol_reserve.last_update.slot, slot);
+        assert_eq!(sol_reserve.liquidity.market_price, Decimal::from(20u64));
+        assert_eq!(sol_reserve.liquidity.oracle_price_slot, 50);
+
+        let obligation = test_obligation
+            .get_state(&mut test_context.banks_client)
+            .await;
+        assert_eq!(
+            obligation.deposited_value,
+            Decimal::from(2 * (100 - decay_percent))
+        );
+        assert_eq!(
+            obligation.borrowed_value,
+            obligation.borrows[0]
+                .borrowed_amount_wads
+                .try_mul(100 + decay_percent)
+                .unwrap()
+                .try_div(100 * FRACTIONAL_TO_USDC)
+                .unwrap()
+        );
+    }
+
+    // past the window the refresh fails as it would without a fallback
+    let transaction = refresh_at_slot(&mut test_context, 152);
+    assert_eq!(
+        test_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
+        )
+    );
+}
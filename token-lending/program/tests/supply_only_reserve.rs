@@ -0,0 +1,231 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        deposit_obligation_collateral, deposit_reserve_liquidity,
+        deposit_reserve_liquidity_and_obligation_collateral, redeem_reserve_collateral,
+    },
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
+
+const USDC_DEPOSIT_AMOUNT_FRACTIONAL: u64 = 100 * FRACTIONAL_TO_USDC;
+const USDC_COLLATERAL_AMOUNT_FRACTIONAL: u64 =
+    USDC_DEPOSIT_AMOUNT_FRACTIONAL * INITIAL_COLLATERAL_RATIO;
+
+struct TestSetup {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: solana_program::hash::Hash,
+    user_accounts_owner: Keypair,
+    lending_market: TestLendingMarket,
+    usdc_oracle: TestOracle,
+    usdc_test_reserve: TestReserve,
+    test_obligation: TestObligation,
+}
+
+async fn setup() -> TestSetup {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut usdc_reserve_config = test_reserve_config();
+    usdc_reserve_config.usable_as_collateral = false;
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            user_liquidity_amount: USDC_DEPOSIT_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_DEPOSIT_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: usdc_reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs::default(),
+    );
+
+    let (banks_client, payer, recent_blockhash) = test.start().await;
+
+    TestSetup {
+        banks_client,
+        payer,
+        recent_blockhash,
+        user_accounts_owner,
+        lending_market,
+        usdc_oracle,
+        usdc_test_reserve,
+        test_obligation,
+    }
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup().await;
+    let initial_user_collateral_balance = get_token_balance(
+        &mut setup.banks_client,
+        setup.usdc_test_reserve.user_collateral_pubkey,
+    )
+    .await;
+
+    // supply-only reserves still take deposits and redemptions
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            deposit_reserve_liquidity(
+                solend_program::id(),
+                USDC_DEPOSIT_AMOUNT_FRACTIONAL,
+                setup.usdc_test_reserve.user_liquidity_pubkey,
+                setup.usdc_test_reserve.user_collateral_pubkey,
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_test_reserve.liquidity_supply_pubkey,
+                setup.usdc_test_reserve.collateral_mint_pubkey,
+                setup.lending_market.pubkey,
+                setup.user_accounts_owner.pubkey(),
+            ),
+            redeem_reserve_collateral(
+                solend_program::id(),
+                USDC_COLLATERAL_AMOUNT_FRACTIONAL / 2,
+                setup.usdc_test_reserve.user_collateral_pubkey,
+                setup.usdc_test_reserve.user_liquidity_pubkey,
+                setup.usdc_test_reserve.pubkey,
+                setup.usdc_test_reserve.collateral_mint_pubkey,
+                setup.usdc_test_reserve.liquidity_supply_pubkey,
+                setup.lending_market.pubkey,
+                setup.user_accounts_owner.pubkey(),
+            ),
+        ],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    assert!(setup
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .is_ok());
+
+    assert_eq!(
+        get_token_balance(
+            &mut setup.banks_client,
+            setup.usdc_test_reserve.user_collateral_pubkey
+        )
+        .await,
+        initial_user_collateral_balance + USDC_COLLATERAL_AMOUNT_FRACTIONAL / 2
+    );
+    assert_eq!(
+        get_token_balance(
+            &mut setup.banks_client,
+            setup.usdc_test_reserve.user_liquidity_pubkey
+        )
+        .await,
+        USDC_DEPOSIT_AMOUNT_FRACTIONAL / 2
+    );
+}
+
+#[tokio::test]
+async fn test_fail_deposit_obligation_collateral() {
+    let mut setup = setup().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[deposit_obligation_collateral(
+            solend_program::id(),
+            USDC_COLLATERAL_AMOUNT_FRACTIONAL,
+            setup.usdc_test_reserve.user_collateral_pubkey,
+            setup.usdc_test_reserve.collateral_supply_pubkey,
+            setup.usdc_test_reserve.pubkey,
+            setup.test_obligation.pubkey,
+            setup.lending_market.pubkey,
+            setup.test_obligation.owner,
+            setup.user_accounts_owner.pubkey(),
+        )],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    assert_eq!(
+        setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::CollateralNotAllowed as u32)
+        )
+    );
+
+    // nor through the combined deposit
+    let mut transaction = Transaction::new_with_payer(
+        &[deposit_reserve_liquidity_and_obligation_collateral(
+            solend_program::id(),
+            USDC_DEPOSIT_AMOUNT_FRACTIONAL,
+            setup.usdc_test_reserve.user_liquidity_pubkey,
+            setup.usdc_test_reserve.user_collateral_pubkey,
+            setup.usdc_test_reserve.pubkey,
+            setup.usdc_test_reserve.liquidity_supply_pubkey,
+            setup.usdc_test_reserve.collateral_mint_pubkey,
+            setup.lending_market.pubkey,
+            setup.usdc_test_reserve.collateral_supply_pubkey,
+            setup.test_obligation.pubkey,
+            setup.test_obligation.owner,
+            setup.usdc_oracle.pyth_price_pubkey,
+            setup.usdc_oracle.switchboard_feed_pubkey,
+            setup.user_accounts_owner.pubkey(),
+        )],
+        Some(&setup.payer.pubkey()),
+    );
+    transaction.sign(
+        &[&setup.payer, &setup.user_accounts_owner],
+        setup.recent_blockhash,
+    );
+    assert_eq!(
+        setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::CollateralNotAllowed as u32)
+        )
+    );
+
+    let obligation = setup
+        .test_obligation
+        .get_state(&mut setup.banks_client)
+        .await;
+    assert!(obligation.deposits.is_empty());
+}
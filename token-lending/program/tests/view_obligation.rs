@@ -0,0 +1,202 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::*;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solend_program::{
+    instruction::{refresh_obligation, refresh_reserve, view_obligation},
+    processor::process_instruction,
+    state::{ObligationSummary, INITIAL_COLLATERAL_RATIO},
+};
+
+#[tokio::test]
+async fn test_success() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    let reader_program_id = Pubkey::new_unique();
+    test.prefer_bpf(false);
+    test.add_program(
+        "return_data_reader",
+        reader_program_id,
+        processor!(helpers::return_data_reader::process_instruction),
+    );
+
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    const SOL_RESERVE_COLLATERAL_LAMPORTS: u64 = 2 * SOL_DEPOSIT_AMOUNT_LAMPORTS;
+    const USDC_RESERVE_LIQUIDITY_FRACTIONAL: u64 = 2 * USDC_BORROW_AMOUNT_FRACTIONAL;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+    reserve_config.min_borrow_rate = 1;
+    reserve_config.optimal_borrow_rate = 1;
+    reserve_config.optimal_utilization_rate = 100;
+
+    let sol_oracle = add_sol_oracle(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_RESERVE_COLLATERAL_LAMPORTS,
+            liquidity_mint_decimals: 9,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_RESERVE_LIQUIDITY_FRACTIONAL,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            config: reserve_config,
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            slots_elapsed: 238, // elapsed from 1; clock.slot = 239
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let return_data_pubkey = Pubkey::new_unique();
+    test.add_account(
+        return_data_pubkey,
+        Account::new(u32::MAX as u64, ObligationSummary::LEN, &reader_program_id),
+    );
+
+    let mut test_context = test.start_with_context().await;
+    test_context.warp_to_slot(240).unwrap(); // clock.slot = 240
+
+    let ProgramTestContext {
+        mut banks_client,
+        payer,
+        last_blockhash: recent_blockhash,
+        ..
+    } = test_context;
+
+    let stale_obligation = test_obligation.get_state(&mut banks_client).await;
+    let stale_sol_reserve = sol_test_reserve.get_state(&mut banks_client).await;
+    let stale_usdc_reserve = usdc_test_reserve.get_state(&mut banks_client).await;
+
+    // neither the obligation nor its reserves are refreshed beforehand
+    let mut transaction = Transaction::new_with_payer(
+        &[helpers::return_data_reader::read_return_data(
+            reader_program_id,
+            return_data_pubkey,
+            view_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                lending_market.pubkey,
+                vec![
+                    (
+                        sol_test_reserve.pubkey,
+                        sol_oracle.pyth_price_pubkey,
+                        sol_oracle.switchboard_feed_pubkey,
+                        vec![],
+                    ),
+                    (
+                        usdc_test_reserve.pubkey,
+                        usdc_oracle.pyth_price_pubkey,
+                        usdc_oracle.switchboard_feed_pubkey,
+                        vec![],
+                    ),
+                ],
+            ),
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    // the view must not persist anything
+    assert_eq!(
+        test_obligation.get_state(&mut banks_client).await,
+        stale_obligation
+    );
+    assert_eq!(
+        sol_test_reserve.get_state(&mut banks_client).await,
+        stale_sol_reserve
+    );
+    assert_eq!(
+        usdc_test_reserve.get_state(&mut banks_client).await,
+        stale_usdc_reserve
+    );
+
+    let return_data = banks_client
+        .get_account(return_data_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    let viewed_summary = ObligationSummary::unpack_from_slice(&return_data.data).unwrap();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                sol_test_reserve.pubkey,
+                sol_oracle.pyth_price_pubkey,
+                sol_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_reserve(
+                solend_program::id(),
+                usdc_test_reserve.pubkey,
+                usdc_oracle.pyth_price_pubkey,
+                usdc_oracle.switchboard_feed_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let refreshed_obligation = test_obligation.get_state(&mut banks_client).await;
+    assert!(refreshed_obligation.borrowed_value > 0u64.into());
+    assert_ne!(
+        ObligationSummary::new(test_obligation.pubkey, &stale_obligation),
+        viewed_summary
+    );
+    assert_eq!(
+        ObligationSummary::new(test_obligation.pubkey, &refreshed_obligation),
+        viewed_summary
+    );
+}
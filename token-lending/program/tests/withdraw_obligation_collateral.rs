@@ -104,6 +104,7 @@ async fn test_withdraw_fixed_amount() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             withdraw_obligation_collateral(
                 solend_program::id(),
@@ -214,6 +215,7 @@ async fn test_withdraw_max_amount() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             withdraw_obligation_collateral(
                 solend_program::id(),
@@ -325,6 +327,7 @@ async fn test_withdraw_too_large() {
                 solend_program::id(),
                 test_obligation.pubkey,
                 vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
             ),
             withdraw_obligation_collateral(
                 solend_program::id(),
@@ -4,8 +4,20 @@ mod helpers;
 
 use helpers::*;
 use solana_program_test::*;
-use solana_sdk::signature::Keypair;
-use solend_program::processor::process_instruction;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        refresh_obligation, refresh_reserve,
+        withdraw_obligation_collateral_and_redeem_reserve_collateral,
+    },
+    processor::process_instruction,
+    state::INITIAL_COLLATERAL_RATIO,
+};
 
 #[tokio::test]
 async fn test_success() {
@@ -104,3 +116,158 @@ async fn test_success() {
         get_token_balance(&mut banks_client, usdc_test_reserve.liquidity_supply_pubkey).await;
     assert_eq!(liquidity_supply, 10_050 * FRACTIONAL_TO_USDC);
 }
+
+#[tokio::test]
+async fn test_withdraw_max_keeps_obligation_healthy() {
+    let mut test = ProgramTest::new(
+        "solend_program",
+        solend_program::id(),
+        processor!(process_instruction),
+    );
+
+    // 200 SOL at $20 and a 50% loan to value ratio -> $2,000 allowed borrow value, so with
+    // $1,000 borrowed half of the collateral can be withdrawn
+    const SOL_DEPOSIT_AMOUNT_LAMPORTS: u64 = 200 * LAMPORTS_TO_SOL * INITIAL_COLLATERAL_RATIO;
+    const SOL_MAX_WITHDRAW_LAMPORTS: u64 = 100 * LAMPORTS_TO_SOL;
+    const USDC_BORROW_AMOUNT_FRACTIONAL: u64 = 1_000 * FRACTIONAL_TO_USDC;
+
+    let user_accounts_owner = Keypair::new();
+    let lending_market = add_lending_market(&mut test);
+
+    let mut reserve_config = test_reserve_config();
+    reserve_config.loan_to_value_ratio = 50;
+
+    let sol_oracle = add_sol_oracle_switchboardv2(&mut test);
+    let sol_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &sol_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            collateral_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS,
+            liquidity_amount: SOL_DEPOSIT_AMOUNT_LAMPORTS / INITIAL_COLLATERAL_RATIO,
+            liquidity_mint_pubkey: spl_token::native_mint::id(),
+            liquidity_mint_decimals: 9,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let usdc_mint = add_usdc_mint(&mut test);
+    let usdc_oracle = add_usdc_oracle_switchboardv2(&mut test);
+    let usdc_test_reserve = add_reserve(
+        &mut test,
+        &lending_market,
+        &usdc_oracle,
+        &user_accounts_owner,
+        AddReserveArgs {
+            borrow_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_amount: USDC_BORROW_AMOUNT_FRACTIONAL,
+            liquidity_mint_pubkey: usdc_mint.pubkey,
+            liquidity_mint_decimals: usdc_mint.decimals,
+            config: reserve_config,
+            mark_fresh: true,
+            ..AddReserveArgs::default()
+        },
+    );
+
+    let test_obligation = add_obligation(
+        &mut test,
+        &lending_market,
+        &user_accounts_owner,
+        AddObligationArgs {
+            deposits: &[(&sol_test_reserve, SOL_DEPOSIT_AMOUNT_LAMPORTS)],
+            borrows: &[(&usdc_test_reserve, USDC_BORROW_AMOUNT_FRACTIONAL)],
+            ..AddObligationArgs::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let refresh_instructions = vec![
+        refresh_reserve(
+            solend_program::id(),
+            sol_test_reserve.pubkey,
+            sol_oracle.pyth_price_pubkey,
+            sol_oracle.switchboard_feed_pubkey,
+        ),
+        refresh_reserve(
+            solend_program::id(),
+            usdc_test_reserve.pubkey,
+            usdc_oracle.pyth_price_pubkey,
+            usdc_oracle.switchboard_feed_pubkey,
+        ),
+        refresh_obligation(
+            solend_program::id(),
+            test_obligation.pubkey,
+            vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+            lending_market.pubkey,
+        ),
+    ];
+    let withdraw_and_redeem = |collateral_amount| {
+        withdraw_obligation_collateral_and_redeem_reserve_collateral(
+            solend_program::id(),
+            collateral_amount,
+            sol_test_reserve.collateral_supply_pubkey,
+            sol_test_reserve.user_collateral_pubkey,
+            sol_test_reserve.pubkey,
+            test_obligation.pubkey,
+            lending_market.pubkey,
+            sol_test_reserve.user_liquidity_pubkey,
+            sol_test_reserve.collateral_mint_pubkey,
+            sol_test_reserve.liquidity_supply_pubkey,
+            test_obligation.owner,
+            user_accounts_owner.pubkey(),
+        )
+    };
+
+    let mut instructions = refresh_instructions.clone();
+    instructions.push(withdraw_and_redeem(u64::MAX));
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    assert_eq!(
+        get_token_balance(&mut banks_client, sol_test_reserve.user_liquidity_pubkey).await,
+        SOL_MAX_WITHDRAW_LAMPORTS
+    );
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(
+        obligation.deposits[0].deposited_amount,
+        SOL_DEPOSIT_AMOUNT_LAMPORTS - SOL_MAX_WITHDRAW_LAMPORTS * INITIAL_COLLATERAL_RATIO
+    );
+
+    // the obligation is left exactly at its allowed borrow value
+    let mut transaction = Transaction::new_with_payer(&refresh_instructions, Some(&payer.pubkey()));
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+    let obligation = test_obligation.get_state(&mut banks_client).await;
+    assert_eq!(obligation.borrowed_value, obligation.allowed_borrow_value);
+
+    // so nothing more can be withdrawn
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            refresh_obligation(
+                solend_program::id(),
+                test_obligation.pubkey,
+                vec![sol_test_reserve.pubkey, usdc_test_reserve.pubkey],
+                lending_market.pubkey,
+            ),
+            withdraw_and_redeem(1),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_accounts_owner], recent_blockhash);
+    assert_eq!(
+        banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
+        )
+    );
+}